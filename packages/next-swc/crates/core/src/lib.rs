@@ -128,6 +128,11 @@ pub struct TransformOptions {
 
     #[serde(default)]
     pub server_actions: Option<server_actions::Config>,
+
+    /// The route table to validate links against in development. Only set
+    /// when `experimental.validateLinks` is enabled.
+    #[serde(default)]
+    pub link_validation: Option<next_transform_link_validation::Config>,
 }
 
 pub fn custom_before_pass<'a, C: Comments + 'a>(
@@ -268,6 +273,13 @@ where
             )),
             None => Either::Right(noop()),
         },
+        match &opts.link_validation {
+            Some(config) => Either::Left(next_transform_link_validation::link_validation(
+                config.clone(),
+                opts.is_development
+            )),
+            None => Either::Right(noop()),
+        },
     )
 }
 
@@ -19,6 +19,7 @@ use next_swc::{
     server_actions::{self, server_actions},
 };
 use next_transform_font::{next_font_loaders, Config as FontLoaderConfig};
+use next_transform_link_validation::{link_validation, Config as LinkValidationConfig};
 use std::path::PathBuf;
 
 fn syntax() -> Syntax {
@@ -172,3 +173,53 @@ fn react_server_actions_errors(input: PathBuf) {
         },
     );
 }
+
+fn link_validation_config() -> LinkValidationConfig {
+    LinkValidationConfig {
+        patterns: ["/", "/about", "/blog/[slug]", "/docs/[[...path]]"]
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[fixture("tests/errors/link-validation/enabled/**/input.js")]
+fn link_validation_errors(input: PathBuf) {
+    let output = input.parent().unwrap().join("output.js");
+    test_fixture(
+        syntax(),
+        &|_tr| {
+            chain!(
+                resolver(Mark::new(), Mark::new(), false),
+                link_validation(link_validation_config(), true)
+            )
+        },
+        &input,
+        &output,
+        FixtureTestConfig {
+            allow_error: true,
+            ..Default::default()
+        },
+    );
+}
+
+#[fixture("tests/errors/link-validation/disabled/**/input.js")]
+fn link_validation_disabled_errors(input: PathBuf) {
+    let output = input.parent().unwrap().join("output.js");
+    test_fixture(
+        syntax(),
+        &|_tr| {
+            chain!(
+                resolver(Mark::new(), Mark::new(), false),
+                link_validation(link_validation_config(), false)
+            )
+        },
+        &input,
+        &output,
+        FixtureTestConfig {
+            allow_error: true,
+            ..Default::default()
+        },
+    );
+}
@@ -0,0 +1,7 @@
+import Link from 'next/link'
+import Router from 'next/router'
+
+export default function Page() {
+  Router.push('/abuot')
+  return <Link href="/blgo/hello">Blog</Link>
+}
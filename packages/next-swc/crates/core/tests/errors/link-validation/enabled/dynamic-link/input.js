@@ -0,0 +1,16 @@
+import Link from 'next/link'
+import { useRouter } from 'next/router'
+
+export default function Post({ slug }) {
+  const router = useRouter()
+  router.replace(`/blgo/${slug}?edit`)
+  return (
+    <>
+      <Link href="/blog/hello">Post</Link>
+      <Link href={`/blgo/${slug}`}>Post</Link>
+      <Link href={{ pathname: '/docs', query: { slug } }}>Docs</Link>
+      <Link href="https://nextjs.org/abuot">Next.js</Link>
+      <Link href="#comments">Comments</Link>
+    </>
+  )
+}
@@ -0,0 +1,8 @@
+import { useRouter as useNextRouter } from 'next/router'
+const router = require('express').Router()
+router.push('/abuot')
+export function Nav() {
+  const nav = useNextRouter()
+  nav.push('/abuot')
+  useNextRouter().replace('/abuot')
+}
@@ -0,0 +1,65 @@
+//! Feeds arbitrary route patterns and pathnames through the segment-walking
+//! matcher and checks that it agrees with the `dynamicRoutes` regex of the
+//! pattern.
+//!
+//! Run from `crates/next-core` with
+//! `cargo +nightly fuzz run route_matcher -- -timeout=1`.
+
+#![no_main]
+
+use libfuzzer_sys::{arbitrary, fuzz_target};
+use next_core::pages_structure::{
+    dynamic_route_manifest_entry, match_route_segments, resolve_route, RouteSegment,
+};
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    pattern: &'a str,
+    /// The segments of the pathname.
+    parts: Vec<&'a str>,
+}
+
+fuzz_target!(|input: Input<'_>| {
+    let Input { pattern, parts } = input;
+    let segments = pattern
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .map(RouteSegment::parse)
+        .collect::<Vec<_>>();
+
+    // Pathnames of requests are normalized: no empty segments and no newlines,
+    // which `.` doesn't match in the regex.
+    let parts = parts
+        .into_iter()
+        .filter(|part| !part.is_empty() && !part.contains(['/', '\n']))
+        .collect::<Vec<_>>();
+    let pathname = format!("/{}", parts.join("/"));
+
+    let matched = match_route_segments(&segments, &pathname);
+    let resolved = resolve_route([pattern], &pathname);
+    assert_eq!(matched.is_some(), resolved.is_some());
+
+    // Catch-alls are only valid as the last segment, and the regex can't
+    // match like the segment walker otherwise.
+    let catch_all_is_last = segments.iter().rev().skip(1).all(|segment| {
+        !matches!(
+            segment,
+            RouteSegment::CatchAll(_) | RouteSegment::OptionalCatchAll(_)
+        )
+    });
+    if !catch_all_is_last {
+        return;
+    }
+    let Some(entry) = dynamic_route_manifest_entry(pattern) else {
+        return;
+    };
+    let Ok(regex) = regex::Regex::new(&entry.regex) else {
+        return;
+    };
+    assert_eq!(
+        regex.is_match(&pathname),
+        matched.is_some(),
+        "{pattern:?} and {pathname:?} with {:?}",
+        entry.regex
+    );
+});
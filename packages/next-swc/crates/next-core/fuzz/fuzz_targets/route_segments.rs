@@ -0,0 +1,61 @@
+//! Feeds arbitrary file and directory names through the route segment
+//! parser and the `dynamicRoutes` regex generation.
+//!
+//! Run from `crates/next-core` with
+//! `cargo +nightly fuzz run route_segments -- -timeout=1`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use next_core::pages_structure::{
+    dynamic_params, dynamic_route_manifest_entry, format_route_pattern, is_dynamic_route_pattern,
+    RouteSegment,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let pattern = String::from_utf8_lossy(data);
+
+    let segments = pattern
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let segment = RouteSegment::parse(name);
+            assert_eq!(
+                segment.to_string(),
+                name,
+                "segments format as they are named"
+            );
+            segment
+        })
+        .collect::<Vec<_>>();
+
+    let formatted = format_route_pattern(&segments);
+    let reparsed = formatted
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .map(RouteSegment::parse)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reparsed, segments,
+        "formatted patterns parse to the same segments"
+    );
+
+    let params = dynamic_params(&pattern);
+    assert_eq!(is_dynamic_route_pattern(&pattern), !params.is_empty());
+
+    if let Some(entry) = dynamic_route_manifest_entry(&pattern) {
+        match regex::Regex::new(&entry.regex) {
+            Ok(_) | Err(regex::Error::CompiledTooBig(_)) => {}
+            Err(err) => panic!("invalid regex {:?} for {pattern:?}: {err}", entry.regex),
+        }
+        let dynamic_segments = segments
+            .iter()
+            .filter(|segment| !matches!(segment, RouteSegment::Static(_)))
+            .count();
+        assert_eq!(
+            entry.route_keys.len(),
+            dynamic_segments,
+            "every param gets its own route key in {pattern:?}"
+        );
+    }
+});
@@ -397,6 +397,7 @@ async fn create_app_source_for_directory(
         item,
         ref children,
         directory,
+        ..
     } = *app_structure.await?;
     let mut sources = Vec::new();
 
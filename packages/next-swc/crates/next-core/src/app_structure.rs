@@ -1,12 +1,12 @@
 use std::collections::HashMap;
 
 use anyhow::{bail, Result};
-use turbo_tasks::{
-    primitives::{StringVc, StringsVc},
-    CompletionVc, ValueToString,
-};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use turbo_tasks::{primitives::StringVc, CompletionVc, ValueToString};
 use turbo_tasks_fs::{
-    DirectoryContent, DirectoryEntry, File, FileContentVc, FileSystemEntryType, FileSystemPathVc,
+    DirectoryContent, DirectoryEntry, File, FileContent, FileContentVc, FileSystemEntryType,
+    FileSystemPathVc,
 };
 use turbopack_core::issue::{Issue, IssueSeverity, IssueSeverityVc, IssueVc};
 use turbopack_dev_server::source::specificity::SpecificityVc;
@@ -14,6 +14,8 @@ use turbopack_dev_server::source::specificity::SpecificityVc;
 use crate::{
     app_render::{LayoutSegment, LayoutSegmentVc, LayoutSegmentsVc},
     next_config::NextConfigVc,
+    page_extensions::{PageExtensions, PageExtensionsVc},
+    pages_structure::{format_route_pattern, route_segments_for_url_path},
 };
 
 /// A final route in the app directory.
@@ -46,11 +48,23 @@ impl AppStructureItemVc {
     }
 }
 
+/// A metadata image of a route segment, like `opengraph-image.tsx`, with its
+/// alt text file (`opengraph-image.alt.txt`) if present.
+#[turbo_tasks::value]
+pub struct MetadataImage {
+    /// The convention of the image: `opengraph-image` or `twitter-image`.
+    pub name: String,
+    pub image: FileSystemPathVc,
+    pub alt: Option<FileSystemPathVc>,
+}
+
 /// A (sub)directory in the app directory with all analyzed routes and folders.
 #[turbo_tasks::value]
 pub struct AppStructure {
     pub directory: FileSystemPathVc,
     pub item: Option<AppStructureItemVc>,
+    /// The metadata images of this segment, sorted by image file name.
+    pub metadata_images: Vec<MetadataImageVc>,
     pub children: Vec<AppStructureVc>,
 }
 
@@ -62,6 +76,39 @@ impl AppStructureVc {
         Ok(self.await?.directory)
     }
 
+    /// Returns the nearest `template` file up the tree from the page serving
+    /// `route` (e.g. `/blog/[slug]`). Unlike layouts, templates are remounted
+    /// on navigation.
+    #[turbo_tasks::function]
+    pub async fn template_for(self, route: String) -> Result<OptionTemplateFileVc> {
+        let template = self
+            .find_page(&route)
+            .await?
+            .and_then(|(_, files)| nearest_file(&files, "template"));
+        Ok(OptionTemplateFileVc::cell(template))
+    }
+
+    /// Returns the page serving `route` (e.g. `/blog/[slug]`) with all the
+    /// special files that apply to it: its layouts and the nearest template,
+    /// error, loading and not found files.
+    #[turbo_tasks::function]
+    pub async fn resolve_full(self, route: String) -> Result<OptionRouteResolutionVc> {
+        let Some((page, files)) = self.find_page(&route).await? else {
+            return Ok(OptionRouteResolutionVc::cell(None));
+        };
+        Ok(OptionRouteResolutionVc::cell(Some(
+            RouteResolution {
+                page,
+                layouts: layout_chain(&files),
+                template: nearest_file(&files, "template"),
+                error: nearest_file(&files, "error"),
+                loading: nearest_file(&files, "loading"),
+                not_found: nearest_file(&files, "not-found"),
+            }
+            .cell(),
+        )))
+    }
+
     /// Returns a completion that changes when any route in the whole tree
     /// changes.
     #[turbo_tasks::function]
@@ -76,9 +123,119 @@ impl AppStructureVc {
     }
 }
 
+impl AppStructureVc {
+    /// Finds the page serving `route` and returns it with the files of its
+    /// layout segments, from the root down.
+    async fn find_page(
+        self,
+        route: &str,
+    ) -> Result<Option<(FileSystemPathVc, Vec<HashMap<String, FileSystemPathVc>>)>> {
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            if let Some(item) = structure.item {
+                if let AppStructureItem::Page {
+                    url,
+                    segments,
+                    page,
+                    ..
+                } = *item.await?
+                {
+                    let url = url.await?;
+                    if format_route_pattern(&route_segments_for_url_path(&url.path)) == route {
+                        let mut files = vec![];
+                        for segment in segments.await?.iter() {
+                            files.push(segment.await?.files.clone());
+                        }
+                        return Ok(Some((page, files)));
+                    }
+                }
+            }
+            queue.extend(structure.children.iter().copied());
+        }
+        Ok(None)
+    }
+}
+
+/// A page with all the special files that apply to it, as returned by
+/// [AppStructureVc::resolve_full].
+#[turbo_tasks::value(shared)]
+pub struct RouteResolution {
+    pub page: FileSystemPathVc,
+    /// The layouts wrapping the page, from the root down.
+    pub layouts: Vec<FileSystemPathVc>,
+    pub template: Option<FileSystemPathVc>,
+    pub error: Option<FileSystemPathVc>,
+    pub loading: Option<FileSystemPathVc>,
+    pub not_found: Option<FileSystemPathVc>,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionRouteResolution(Option<RouteResolutionVc>);
+
 #[turbo_tasks::value(transparent)]
 pub struct OptionAppStructure(Option<AppStructureVc>);
 
+#[turbo_tasks::value(transparent)]
+pub struct OptionTemplateFile(Option<FileSystemPathVc>);
+
+/// The metadata image conventions that can have an alt text file.
+const METADATA_IMAGES: [&str; 2] = ["opengraph-image", "twitter-image"];
+
+/// The extensions of static metadata images. Generated images use the page
+/// extensions.
+const METADATA_IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "gif"];
+
+/// Returns `(convention, image file name, alt text file name)` for every
+/// metadata image among the file names of a directory, sorted by image file
+/// name. The alt text of `opengraph-image.png` is `opengraph-image.alt.txt`.
+fn find_metadata_images<'a>(
+    file_names: &[&'a str],
+    page_extensions: &PageExtensions,
+) -> Vec<(&'static str, &'a str, Option<&'a str>)> {
+    let mut images = file_names
+        .iter()
+        .filter_map(|&file_name| {
+            let basename = match page_extensions.matches(file_name) {
+                Some((basename, _)) => basename,
+                None => match file_name.rsplit_once('.') {
+                    Some((basename, extension))
+                        if METADATA_IMAGE_EXTENSIONS.contains(&extension) =>
+                    {
+                        basename
+                    }
+                    _ => return None,
+                },
+            };
+            let convention = METADATA_IMAGES
+                .into_iter()
+                .find(|convention| *convention == basename)?;
+            let alt_name = format!("{convention}.alt.txt");
+            let alt = file_names.iter().copied().find(|name| *name == alt_name);
+            Some((convention, file_name, alt))
+        })
+        .collect::<Vec<_>>();
+    images.sort_by_key(|(_, file_name, _)| *file_name);
+    images
+}
+
+/// Returns the layouts of the segments, from the root down.
+fn layout_chain<T: Copy>(segments: &[HashMap<String, T>]) -> Vec<T> {
+    segments
+        .iter()
+        .filter_map(|files| files.get("layout").copied())
+        .collect()
+}
+
+/// Returns the file called `name` of the innermost segment that has one.
+/// `segments` are the files of each layout segment, from the root down.
+fn nearest_file<T: Copy>(segments: &[HashMap<String, T>], name: &str) -> Option<T> {
+    segments
+        .iter()
+        .rev()
+        .find_map(|files| files.get(name).copied())
+}
+
 #[turbo_tasks::value_impl]
 impl OptionAppStructureVc {
     /// Returns a completion that changes when any route in the whole tree
@@ -119,16 +276,20 @@ pub async fn find_app_structure(
     Ok(OptionAppStructureVc::cell(Some(get_app_structure(
         app_dir,
         server_root,
-        next_config.page_extensions(),
+        PageExtensionsVc::from_config(next_config),
+        *next_config.check_layout_imports().await?,
     ))))
 }
 
 /// Parses a directory as app directory and returns the [AppStructure].
+/// `check_layout_imports` warns about layouts importing the layout of one of
+/// their child segments.
 #[turbo_tasks::function]
 pub fn get_app_structure(
     app_dir: FileSystemPathVc,
     server_root: FileSystemPathVc,
-    page_extensions: StringsVc,
+    page_extensions: PageExtensionsVc,
+    check_layout_imports: bool,
 ) -> AppStructureVc {
     get_app_structure_for_directory(
         app_dir,
@@ -139,6 +300,7 @@ pub fn get_app_structure(
         server_root,
         LayoutSegmentsVc::cell(Vec::new()),
         page_extensions,
+        check_layout_imports,
     )
 }
 
@@ -152,7 +314,8 @@ async fn get_app_structure_for_directory(
     target: FileSystemPathVc,
     url: FileSystemPathVc,
     layouts: LayoutSegmentsVc,
-    page_extensions: StringsVc,
+    page_extensions: PageExtensionsVc,
+    check_layout_imports: bool,
 ) -> Result<AppStructureVc> {
     let mut layouts = layouts;
     let mut page = None;
@@ -163,15 +326,11 @@ async fn get_app_structure_for_directory(
         bail!("{} is not a directory", input_dir.to_string().await?)
     };
 
-    let allowed_extensions = &*page_extensions.await?;
+    let page_extensions_ref = page_extensions.await?;
 
     for (name, entry) in entries.iter() {
         if let &DirectoryEntry::File(file) = entry {
-            if let Some((name, ext)) = name.rsplit_once('.') {
-                if !allowed_extensions.iter().any(|allowed| allowed == ext) {
-                    continue;
-                }
-
+            if let Some((name, _)) = page_extensions_ref.matches(name) {
                 match name {
                     "page" => {
                         page = Some(file);
@@ -190,17 +349,37 @@ async fn get_app_structure_for_directory(
         }
     }
 
+    let file_names = entries
+        .iter()
+        .filter(|(_, entry)| matches!(entry, DirectoryEntry::File(_)))
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    let metadata_images = find_metadata_images(&file_names, &page_extensions_ref)
+        .into_iter()
+        .map(|(convention, image, alt)| {
+            MetadataImage {
+                name: convention.to_string(),
+                image: input_dir.join(image),
+                alt: alt.map(|alt| input_dir.join(alt)),
+            }
+            .cell()
+        })
+        .collect();
+
     let layout = files.get("layout");
 
-    if let (Some(_), Some(route_path)) = (page, route) {
+    if let Some(layout) = layout.filter(|_| check_layout_imports) {
+        check_child_layout_imports(*layout).await?;
+    }
+
+    if let (Some(page_path), Some(route_path)) = (page, route) {
         AppStructureIssue {
             severity: IssueSeverity::Error.into(),
             path: route_path,
-            message: StringVc::cell(
-                "It's not possible to have a page and a route in the same directory. The route \
-                 will be ignored in favor of the page."
-                    .to_string(),
-            ),
+            message: StringVc::cell(page_route_conflict_message(
+                &page_path.await?.path,
+                &route_path.await?.path,
+            )),
         }
         .cell()
         .as_issue()
@@ -287,6 +466,7 @@ async fn get_app_structure_for_directory(
                 new_url,
                 layouts,
                 page_extensions,
+                check_layout_imports,
             ),
         ));
     }
@@ -319,12 +499,76 @@ async fn get_app_structure_for_directory(
 
     Ok(AppStructure {
         item,
+        metadata_images,
         directory: input_dir,
         children: children.into_iter().map(|(_, v)| v).collect(),
     }
     .cell())
 }
 
+/// Matches the specifiers of `import`, `export ... from` and `require`.
+static IMPORT_SPECIFIER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?:\bfrom|\bimport|\brequire)\s*\(?\s*["']([^"']+)["']"#).unwrap());
+
+/// Returns true if an import specifier of a layout points to the layout of
+/// one of its child segments, like `./dashboard/layout`.
+fn is_child_layout_specifier(specifier: &str) -> bool {
+    let Some(rest) = specifier.strip_prefix("./") else {
+        return false;
+    };
+    let Some((directory, file)) = rest.rsplit_once('/') else {
+        return false;
+    };
+    let basename = file.split('.').next().unwrap_or(file);
+    basename == "layout" && !directory.split('/').any(|segment| segment == "..")
+}
+
+/// Returns the import specifiers of a layout's source that point to the
+/// layout of one of its child segments.
+fn child_layout_imports(source: &str) -> Vec<&str> {
+    IMPORT_SPECIFIER
+        .captures_iter(source)
+        .filter_map(|captures| captures.get(1))
+        .map(|specifier| specifier.as_str())
+        .filter(|specifier| is_child_layout_specifier(specifier))
+        .collect()
+}
+
+/// The message for a directory with both a `page` and a `route` file.
+fn page_route_conflict_message(page_path: &str, route_path: &str) -> String {
+    format!(
+        "It's not possible to have a page and a route in the same directory: {page_path} and \
+         {route_path}. The route will be ignored in favor of the page. Remove {route_path} to \
+         keep the page, or remove {page_path} to serve the route."
+    )
+}
+
+/// Warns when a layout imports the layout of one of its child segments, which
+/// may render the child layout within itself. This is a heuristic over the
+/// relative imports of the file and doesn't follow the import graph, so it's
+/// only enabled with `experimental.checkLayoutImports`.
+#[turbo_tasks::function]
+async fn check_child_layout_imports(layout: FileSystemPathVc) -> Result<CompletionVc> {
+    if let FileContent::Content(file) = &*layout.read().await? {
+        let source = file.content().to_str()?;
+        for specifier in child_layout_imports(&source) {
+            AppStructureIssue {
+                severity: IssueSeverity::Warning.into(),
+                path: layout,
+                message: StringVc::cell(format!(
+                    "The layout imports `{specifier}`, the layout of one of its child segments. \
+                     If it renders that layout, the child layout may end up rendered within \
+                     itself. Consider moving the shared code into a separate module."
+                )),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+        }
+    }
+    Ok(CompletionVc::new())
+}
+
 #[turbo_tasks::value(shared)]
 struct AppStructureIssue {
     pub severity: IssueSeverityVc,
@@ -361,3 +605,165 @@ impl Issue for AppStructureIssue {
         self.message
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        next_config::NextConfigBuilder,
+        testing::{project_root, reported_issues, TestProject, TestRunner},
+    };
+
+    #[test]
+    fn pairs_metadata_images_with_alt_text() {
+        let page_extensions = PageExtensions::new(["tsx".to_string(), "ts".to_string()]);
+        let file_names = [
+            "page.tsx",
+            "twitter-image.png",
+            "opengraph-image.tsx",
+            "opengraph-image.alt.txt",
+            "icon.png",
+            "twitter-image.txt",
+        ];
+        assert_eq!(
+            find_metadata_images(&file_names, &page_extensions),
+            vec![
+                (
+                    "opengraph-image",
+                    "opengraph-image.tsx",
+                    Some("opengraph-image.alt.txt")
+                ),
+                ("twitter-image", "twitter-image.png", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_both_files_of_a_page_and_route_conflict() {
+        let message = page_route_conflict_message("app/blog/page.tsx", "app/blog/route.ts");
+        assert!(message.contains("app/blog/page.tsx"));
+        assert!(message.contains("app/blog/route.ts"));
+        assert!(message.contains("Remove app/blog/route.ts"));
+    }
+
+    #[test]
+    fn finds_child_layout_imports() {
+        // `app/layout.tsx`
+        let source = r#"
+            import './globals.css';
+            import { Nav } from "../components/nav";
+            import DashboardLayout from './dashboard/layout';
+            const Settings = require("./dashboard/settings/layout.tsx");
+            export { metadata } from './metadata';
+        "#;
+        assert_eq!(
+            child_layout_imports(source),
+            vec!["./dashboard/layout", "./dashboard/settings/layout.tsx"]
+        );
+
+        assert!(!is_child_layout_specifier("./layout"));
+        assert!(!is_child_layout_specifier("../layout"));
+        assert!(!is_child_layout_specifier("./dashboard/../layout"));
+        assert!(!is_child_layout_specifier("./dashboard/layouts"));
+        assert!(!is_child_layout_specifier("dashboard/layout"));
+    }
+
+    #[test]
+    fn finds_nearest_template() {
+        // `app/template.tsx`, `app/blog/layout.tsx`, `app/blog/[slug]/page.tsx`
+        let segments = vec![
+            HashMap::from([
+                ("layout".to_string(), "app/layout.tsx"),
+                ("template".to_string(), "app/template.tsx"),
+            ]),
+            HashMap::from([("layout".to_string(), "app/blog/layout.tsx")]),
+            HashMap::new(),
+        ];
+        assert_eq!(
+            nearest_file(&segments, "template"),
+            Some("app/template.tsx")
+        );
+
+        let mut nested = segments.clone();
+        nested[1].insert("template".to_string(), "app/blog/template.tsx");
+        assert_eq!(
+            nearest_file(&nested, "template"),
+            Some("app/blog/template.tsx")
+        );
+
+        assert_eq!(nearest_file(&segments[1..], "template"), None);
+    }
+
+    #[test]
+    fn collects_inherited_special_files() {
+        // `app/layout.tsx`, `app/error.tsx`, `app/not-found.tsx`,
+        // `app/blog/layout.tsx`, `app/blog/loading.tsx`,
+        // `app/blog/[slug]/error.tsx` and `app/blog/[slug]/page.tsx`
+        let segments = vec![
+            HashMap::from([
+                ("layout".to_string(), "app/layout.tsx"),
+                ("error".to_string(), "app/error.tsx"),
+                ("not-found".to_string(), "app/not-found.tsx"),
+            ]),
+            HashMap::from([
+                ("layout".to_string(), "app/blog/layout.tsx"),
+                ("loading".to_string(), "app/blog/loading.tsx"),
+            ]),
+            HashMap::from([("error".to_string(), "app/blog/[slug]/error.tsx")]),
+        ];
+        assert_eq!(
+            layout_chain(&segments),
+            vec!["app/layout.tsx", "app/blog/layout.tsx"]
+        );
+        assert_eq!(
+            nearest_file(&segments, "error"),
+            Some("app/blog/[slug]/error.tsx")
+        );
+        assert_eq!(
+            nearest_file(&segments, "loading"),
+            Some("app/blog/loading.tsx")
+        );
+        assert_eq!(
+            nearest_file(&segments, "not-found"),
+            Some("app/not-found.tsx")
+        );
+        assert_eq!(nearest_file(&segments, "template"), None);
+    }
+
+    #[test]
+    fn reports_child_layout_imports_only_when_enabled() -> Result<()> {
+        let project = TestProject::new(&[
+            (
+                "app/layout.tsx",
+                "import DashboardLayout from './dashboard/layout';",
+            ),
+            ("app/page.tsx", ""),
+            ("app/dashboard/layout.tsx", ""),
+            ("app/dashboard/page.tsx", ""),
+        ])?;
+        let runner = TestRunner::new()?;
+        let root_dir = project.root_dir();
+        let scan = |check_layout_imports: bool| {
+            let root_dir = root_dir.clone();
+            runner.run(async move {
+                let root = project_root(&root_dir);
+                let next_config = NextConfigBuilder::default()
+                    .app_dir(true)
+                    .check_layout_imports(check_layout_imports)
+                    .cell();
+                let Some(app_structure) = *find_app_structure(root, root, next_config).await?
+                else {
+                    bail!("the app directory wasn't found");
+                };
+                reported_issues(app_structure).await
+            })
+        };
+
+        assert_eq!(scan(false)?, vec![]);
+        let issues = scan(true)?;
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!(issues[0].severity, "warning");
+        assert!(issues[0].description.contains("`./dashboard/layout`"));
+        Ok(())
+    }
+}
@@ -23,18 +23,25 @@ mod next_route_matcher;
 pub mod next_server;
 pub mod next_shared;
 mod page_loader;
+pub mod page_extensions;
 mod page_source;
 pub mod pages_structure;
 pub mod react_refresh;
+pub mod rewrites;
+pub mod route_events;
 pub mod router;
 pub mod router_source;
+pub mod routing_report;
 mod runtime;
+pub mod sorted_routes;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod typescript;
 mod util;
 mod web_entry_source;
 
 pub use app_source::create_app_source;
-pub use page_source::create_page_source;
+pub use page_source::{create_page_source, prewarm_page_routes, PrewarmRoutesHook};
 pub use turbopack_node::source_map;
 pub use web_entry_source::create_web_entry_source;
 
@@ -20,6 +20,7 @@ use turbopack_node::render::{
 use crate::{
     embed_js::next_js_file,
     next_config::{NextConfigVc, RewritesReadRef},
+    pages_structure::{is_dynamic_route_pattern, OptionPagesStructureVc},
     util::get_asset_path_from_route,
 };
 
@@ -180,6 +181,64 @@ impl ContentSource for DevManifestContentSource {
     }
 }
 
+/// A content source which serves a JSON index of all API routes in the pages
+/// directory, for development tooling. It's only part of the dev server.
+#[turbo_tasks::value(shared)]
+pub struct DevApiRoutesContentSource {
+    pub pages_structure: OptionPagesStructureVc,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiRouteIndexEntry {
+    route: String,
+    file: String,
+    dynamic: bool,
+}
+
+#[turbo_tasks::value_impl]
+impl ContentSource for DevApiRoutesContentSource {
+    #[turbo_tasks::function]
+    async fn get(
+        &self,
+        path: &str,
+        _data: turbo_tasks::Value<ContentSourceData>,
+    ) -> Result<ContentSourceResultVc> {
+        if !path.is_empty() {
+            return Ok(ContentSourceResultVc::not_found());
+        }
+
+        let mut routes = vec![];
+        if let Some(pages_structure) = *self.pages_structure.await? {
+            let pages_dir = pages_structure.directory().await?;
+            for entry in pages_structure.route_entries().await?.iter() {
+                let entry = entry.await?;
+                if !entry.is_api {
+                    continue;
+                }
+                let file = entry.file.await?;
+                routes.push(ApiRouteIndexEntry {
+                    route: entry.pattern.clone(),
+                    file: pages_dir
+                        .get_path_to(&file)
+                        .unwrap_or(&file.path)
+                        .to_string(),
+                    dynamic: is_dynamic_route_pattern(&entry.pattern),
+                });
+            }
+        }
+
+        let index = File::from(serde_json::to_string(&serde_json::json!({
+            "routes": routes,
+        }))?)
+        .with_content_type(APPLICATION_JSON);
+
+        Ok(ContentSourceResultVc::exact(
+            ContentSourceContentVc::static_content(AssetContentVc::from(index).into()).into(),
+        ))
+    }
+}
+
 /// PageSortKey is necessary because the next.js client code looks for matches
 /// in the order the pages are sent in the manifest,if they're sorted
 /// alphabetically this means \[slug] and \[\[catchall]] routes are prioritized
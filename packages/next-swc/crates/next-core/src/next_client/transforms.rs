@@ -1,24 +1,35 @@
 use anyhow::Result;
 use next_transform_strip_page_exports::ExportFilter;
+use turbo_tasks_fs::FileSystemPathVc;
 use turbopack::module_options::ModuleRule;
 
 use crate::{
     next_client::context::ClientContextType,
+    next_config::NextConfigVc,
     next_shared::transforms::{
         get_next_dynamic_transform_rule, get_next_font_transform_rule,
-        get_next_pages_transforms_rule,
+        get_next_link_validation_transform_rule, get_next_pages_transforms_rule,
     },
+    route_events::link_validation_routes,
 };
 
 /// Returns a list of module rules which apply client-side, Next.js-specific
 /// transforms.
 pub async fn get_next_client_transforms_rules(
+    project_path: FileSystemPathVc,
+    next_config: NextConfigVc,
     context_ty: ClientContextType,
 ) -> Result<Vec<ModuleRule>> {
     let mut rules = vec![];
 
     rules.push(get_next_font_transform_rule());
 
+    if let Some(routes) = *link_validation_routes(project_path, next_config).await? {
+        rules.push(get_next_link_validation_transform_rule(
+            routes.await?.link_validation_config(),
+        ));
+    }
+
     let pages_dir = match context_ty {
         ClientContextType::Pages { pages_dir } => {
             rules.push(
@@ -3,13 +3,13 @@ use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use turbo_tasks::{
-    primitives::{BoolVc, StringsVc},
+    primitives::{BoolVc, StringVc, StringsVc},
     trace::TraceRawVcs,
     CompletionVc, Value,
 };
 use turbo_tasks_bytes::stream::SingleValue;
 use turbo_tasks_env::EnvMapVc;
-use turbo_tasks_fs::{json::parse_json_with_source_context, FileSystemPathVc};
+use turbo_tasks_fs::{glob::Glob, json::parse_json_with_source_context, FileSystemPathVc};
 use turbopack::evaluate_context::node_evaluate_asset_context;
 use turbopack_core::{
     asset::Asset,
@@ -17,7 +17,7 @@ use turbopack_core::{
     chunk::ChunkingContext,
     context::AssetContext,
     ident::AssetIdentVc,
-    issue::IssueContextExt,
+    issue::{Issue, IssueContextExt, IssueSeverity, IssueSeverityVc, IssueVc},
     reference_type::{EntryReferenceSubType, ReferenceType},
     resolve::{
         find_context_file,
@@ -92,6 +92,254 @@ pub struct NextConfig {
     webpack: Option<serde_json::Value>,
 }
 
+impl NextConfig {
+    pub fn base_path(&self) -> &str {
+        &self.base_path
+    }
+
+    /// Returns true if `trailingSlash` is set, i.e. the canonical urls of
+    /// routes end with a `/`.
+    pub fn trailing_slash(&self) -> bool {
+        self.trailing_slash
+    }
+
+    /// Returns the configured locales and the default locale, if i18n is
+    /// enabled.
+    pub fn i18n_locales(&self) -> Option<(&[String], &str)> {
+        self.i18n
+            .as_ref()
+            .map(|i18n| (i18n.locales.as_slice(), i18n.default_locale.as_str()))
+    }
+
+    /// Returns true if `experimental.mdxRs` is enabled.
+    pub fn mdx_rs_enabled(&self) -> bool {
+        !matches!(
+            self.experimental.mdx_rs,
+            None | Some(serde_json::Value::Null | serde_json::Value::Bool(false))
+        )
+    }
+
+    /// Returns true if `output: 'export'` is set, i.e. the app is exported as
+    /// static files.
+    pub fn is_static_export(&self) -> bool {
+        self.output == Some(OutputType::Export)
+    }
+
+    /// Returns true if `experimental.ampSuffixPages` is enabled.
+    pub fn amp_suffix_pages_enabled(&self) -> bool {
+        self.experimental.amp_suffix_pages.unwrap_or_default()
+    }
+
+    /// Returns the prefix of the top-level pages directories that are left
+    /// out of production builds, if `experimental.devOnlyRoutes` is enabled.
+    /// Defaults to `__debug`.
+    pub fn dev_only_routes_prefix(&self) -> Option<&str> {
+        if !self.experimental.dev_only_routes.unwrap_or_default() {
+            return None;
+        }
+        Some(
+            self.experimental
+                .dev_only_routes_prefix
+                .as_deref()
+                .unwrap_or("__debug"),
+        )
+    }
+
+    /// Returns true if `experimental.previewPages` is enabled.
+    pub fn preview_pages_enabled(&self) -> bool {
+        self.experimental.preview_pages.unwrap_or_default()
+    }
+
+    /// Returns true if `experimental.validateLinks` is enabled.
+    pub fn validate_links(&self) -> bool {
+        self.experimental.validate_links.unwrap_or_default()
+    }
+
+    /// Returns true if `experimental.strictRouting` is enabled.
+    pub fn strict_routing(&self) -> bool {
+        self.experimental.strict_routing.unwrap_or_default()
+    }
+
+    /// Returns the names that dynamic params must not use, from
+    /// `experimental.reservedParamNames` or else
+    /// [DEFAULT_RESERVED_PARAM_NAMES].
+    pub fn reserved_param_names(&self) -> Vec<String> {
+        match &self.experimental.reserved_param_names {
+            Some(names) => names.clone(),
+            None => DEFAULT_RESERVED_PARAM_NAMES
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+
+    /// Returns `experimental.maxPagesDirectoryEntries`, the number of entries
+    /// of a pages directory beyond which its routes are left out.
+    pub fn max_pages_directory_entries(&self) -> u32 {
+        self.experimental
+            .max_pages_directory_entries
+            .unwrap_or(DEFAULT_MAX_PAGES_DIRECTORY_ENTRIES)
+    }
+
+    /// Returns `experimental.maxApiDepth`, if API route nesting is limited.
+    pub fn max_api_depth(&self) -> Option<u32> {
+        self.experimental.max_api_depth
+    }
+}
+
+/// Query parameters that Next.js adds to requests itself. A dynamic param with
+/// one of these names (like `pages/[amp].tsx`) is shadowed by or shadows the
+/// query parameter.
+pub const DEFAULT_RESERVED_PARAM_NAMES: [&str; 6] = [
+    "amp",
+    "_rsc",
+    "__nextDataReq",
+    "__nextLocale",
+    "__nextDefaultLocale",
+    "__nextFallback",
+];
+
+/// The default of `experimental.maxPagesDirectoryEntries`.
+pub const DEFAULT_MAX_PAGES_DIRECTORY_ENTRIES: u32 = 100_000;
+
+/// Builds a [NextConfig] with only the routing-relevant fields set, without
+/// evaluating a `next.config.js`. Intended for tests.
+#[cfg(any(test, feature = "testing"))]
+pub struct NextConfigBuilder {
+    config: NextConfig,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Default for NextConfigBuilder {
+    fn default() -> Self {
+        Self {
+            config: NextConfig {
+                page_extensions: ["tsx", "ts", "jsx", "js"]
+                    .into_iter()
+                    .map(ToOwned::to_owned)
+                    .collect(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl NextConfigBuilder {
+    pub fn page_extensions(mut self, page_extensions: Vec<String>) -> Self {
+        self.config.page_extensions = page_extensions;
+        self
+    }
+
+    pub fn base_path(mut self, base_path: impl Into<String>) -> Self {
+        self.config.base_path = base_path.into();
+        self
+    }
+
+    pub fn i18n(mut self, locales: Vec<String>, default_locale: impl Into<String>) -> Self {
+        self.config.i18n = Some(I18NConfig {
+            default_locale: default_locale.into(),
+            domains: None,
+            locale_detection: None,
+            locales,
+        });
+        self
+    }
+
+    pub fn trailing_slash(mut self, trailing_slash: bool) -> Self {
+        self.config.trailing_slash = trailing_slash;
+        self
+    }
+
+    pub fn rewrites(mut self, rewrites: Rewrites) -> Self {
+        self.config.rewrites = rewrites;
+        self
+    }
+
+    pub fn amp_suffix_pages(mut self, amp_suffix_pages: bool) -> Self {
+        self.config.experimental.amp_suffix_pages = Some(amp_suffix_pages);
+        self
+    }
+
+    pub fn app_dir(mut self, app_dir: bool) -> Self {
+        self.config.experimental.app_dir = Some(app_dir);
+        self
+    }
+
+    pub fn dev_only_routes(mut self, dev_only_routes: bool) -> Self {
+        self.config.experimental.dev_only_routes = Some(dev_only_routes);
+        self
+    }
+
+    pub fn max_api_depth(mut self, max_api_depth: u32) -> Self {
+        self.config.experimental.max_api_depth = Some(max_api_depth);
+        self
+    }
+
+    pub fn max_pages_directory_entries(mut self, max_pages_directory_entries: u32) -> Self {
+        self.config.experimental.max_pages_directory_entries = Some(max_pages_directory_entries);
+        self
+    }
+
+    pub fn preview_page_extensions(mut self, preview_page_extensions: Vec<String>) -> Self {
+        self.config.experimental.preview_page_extensions = Some(preview_page_extensions);
+        self
+    }
+
+    pub fn preview_pages(mut self, preview_pages: bool) -> Self {
+        self.config.experimental.preview_pages = Some(preview_pages);
+        self
+    }
+
+    pub fn reserved_param_names(mut self, reserved_param_names: Vec<String>) -> Self {
+        self.config.experimental.reserved_param_names = Some(reserved_param_names);
+        self
+    }
+
+    pub fn strict_routing(mut self, strict_routing: bool) -> Self {
+        self.config.experimental.strict_routing = Some(strict_routing);
+        self
+    }
+
+    pub fn validate_links(mut self, validate_links: bool) -> Self {
+        self.config.experimental.validate_links = Some(validate_links);
+        self
+    }
+
+    pub fn check_static_paths_params(mut self, check_static_paths_params: bool) -> Self {
+        self.config.experimental.check_static_paths_params = Some(check_static_paths_params);
+        self
+    }
+
+    pub fn check_layout_imports(mut self, check_layout_imports: bool) -> Self {
+        self.config.experimental.check_layout_imports = Some(check_layout_imports);
+        self
+    }
+
+    pub fn static_export(mut self, static_export: bool) -> Self {
+        self.config.output = static_export.then_some(OutputType::Export);
+        self
+    }
+
+    pub fn output_file_tracing(
+        mut self,
+        includes: IndexMap<String, Vec<String>>,
+        excludes: IndexMap<String, Vec<String>>,
+    ) -> Self {
+        self.config.experimental.output_file_tracing_includes = Some(includes);
+        self.config.experimental.output_file_tracing_excludes = Some(excludes);
+        self
+    }
+
+    pub fn build(self) -> NextConfig {
+        self.config
+    }
+
+    pub fn cell(self) -> NextConfigVc {
+        self.build().cell()
+    }
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, TraceRawVcs)]
 #[serde(rename_all = "camelCase")]
 struct AmpConfig {
@@ -157,6 +405,7 @@ struct I18NConfig {
 #[serde(rename_all = "kebab-case")]
 enum OutputType {
     Standalone,
+    Export,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TraceRawVcs)]
@@ -344,9 +593,48 @@ pub struct ExperimentalTurboConfig {
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, TraceRawVcs)]
 #[serde(rename_all = "camelCase")]
 pub struct ExperimentalConfig {
+    /// Treats pages like `post.amp.tsx` as the AMP variant of `post.tsx`.
+    pub amp_suffix_pages: Option<bool>,
     pub app_dir: Option<bool>,
+    /// Warns about dynamic pages whose `getStaticPaths` returns params that
+    /// don't match the route. Reads the source of every dynamic page.
+    pub check_static_paths_params: Option<bool>,
+    /// Warns about app layouts that import the layout of one of their child
+    /// segments. Reads the source of every layout.
+    pub check_layout_imports: Option<bool>,
+    /// Leaves top-level pages directories starting with
+    /// `devOnlyRoutesPrefix` out of production builds.
+    pub dev_only_routes: Option<bool>,
+    pub dev_only_routes_prefix: Option<String>,
+    /// Reports API routes nested deeper than this many segments below `/api`.
+    pub max_api_depth: Option<u32>,
+    /// Leaves out the routes of pages directories with more entries than
+    /// this.
+    pub max_pages_directory_entries: Option<u32>,
+    pub output_file_tracing_includes: Option<IndexMap<String, Vec<String>>>,
+    pub output_file_tracing_excludes: Option<IndexMap<String, Vec<String>>>,
+    /// Maps page extensions to the loader their modules need, in addition to
+    /// the defaults for JavaScript, TypeScript and MDX.
+    pub page_extension_loaders: Option<IndexMap<String, String>>,
+    /// Page extensions (like `preview.tsx`) that are only routable when
+    /// `previewPages` is enabled, e.g. in preview deployments.
+    pub preview_page_extensions: Option<Vec<String>>,
+    /// Includes the `previewPageExtensions`, e.g. set from an environment
+    /// variable in preview deployments.
+    pub preview_pages: Option<bool>,
+    /// Reports API routes whose file names contain uppercase letters.
+    pub require_lowercase_api_routes: Option<bool>,
+    /// Names that dynamic params must not use, replacing the
+    /// [DEFAULT_RESERVED_PARAM_NAMES].
+    pub reserved_param_names: Option<Vec<String>>,
     pub server_components_external_packages: Option<Vec<String>>,
+    /// Reports routing diagnostics (e.g. route conflicts) as errors instead of
+    /// warnings, so they fail the build.
+    pub strict_routing: Option<bool>,
     pub turbo: Option<ExperimentalTurboConfig>,
+    /// Warns in development about `next/link` hrefs and router navigations
+    /// to routes that don't exist.
+    pub validate_links: Option<bool>,
 
     // unsupported
     adjust_font_fallbacks: Option<bool>,
@@ -447,6 +735,81 @@ pub enum RemoveConsoleConfig {
     Config { exclude: Option<Vec<String>> },
 }
 
+/// `experimental.outputFileTracingIncludes` and
+/// `experimental.outputFileTracingExcludes`, which map route globs to the file
+/// globs to add to or remove from the traces of matching routes.
+#[turbo_tasks::value]
+#[derive(Clone, Debug, Default)]
+pub struct OutputFileTracing {
+    pub includes: IndexMap<String, Vec<String>>,
+    pub excludes: IndexMap<String, Vec<String>>,
+}
+
+impl OutputFileTracing {
+    /// Collects the file globs that apply to the route with the given
+    /// pattern (e.g. `/api/[id]`).
+    pub fn for_route(&self, route: &str) -> Result<RouteFileTracing> {
+        fn matching(map: &IndexMap<String, Vec<String>>, route: &str) -> Result<Vec<String>> {
+            let mut file_globs = vec![];
+            for (route_glob, globs) in map {
+                if Glob::parse(route_glob)?.execute(route) {
+                    file_globs.extend(globs.iter().cloned());
+                }
+            }
+            Ok(file_globs)
+        }
+
+        Ok(RouteFileTracing {
+            includes: matching(&self.includes, route)?,
+            excludes: matching(&self.excludes, route)?,
+        })
+    }
+}
+
+/// The file globs to add to or remove from the trace of a single route.
+#[turbo_tasks::value]
+#[derive(Clone, Debug, Default)]
+pub struct RouteFileTracing {
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+}
+
+impl RouteFileTracing {
+    /// Returns true if the traced file at `path` should be removed from the
+    /// trace. A file that is both included and excluded is kept.
+    pub fn is_excluded(&self, path: &str) -> Result<bool> {
+        Ok(!matches_any(&self.includes, path)? && matches_any(&self.excludes, path)?)
+    }
+
+    /// Removes the excluded files from the files traced for `route`. Files
+    /// that are also included are kept, which is logged at debug level.
+    pub fn apply(&self, route: &str, traced: &[String]) -> Result<Vec<String>> {
+        let mut files = vec![];
+        for path in traced {
+            if matches_any(&self.excludes, path)? {
+                if !matches_any(&self.includes, path)? {
+                    continue;
+                }
+                tracing::debug!(
+                    "keeping `{path}` in the trace of `{route}`: it is both included and \
+                     excluded by the output file tracing config, and includes win"
+                );
+            }
+            files.push(path.clone());
+        }
+        Ok(files)
+    }
+}
+
+fn matches_any(globs: &[String], path: &str) -> Result<bool> {
+    for glob in globs {
+        if Glob::parse(glob)?.execute(path) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 #[derive(Default)]
 #[turbo_tasks::value(transparent)]
 pub struct WebpackExtensionToLoaders(IndexMap<String, WebpackLoaderConfigItemsVc>);
@@ -477,6 +840,46 @@ impl NextConfigVc {
         ))
     }
 
+    #[turbo_tasks::function]
+    pub async fn require_lowercase_api_routes(self) -> Result<BoolVc> {
+        Ok(BoolVc::cell(
+            self.await?
+                .experimental
+                .require_lowercase_api_routes
+                .unwrap_or_default(),
+        ))
+    }
+
+    #[turbo_tasks::function]
+    pub async fn check_static_paths_params(self) -> Result<BoolVc> {
+        Ok(BoolVc::cell(
+            self.await?
+                .experimental
+                .check_static_paths_params
+                .unwrap_or_default(),
+        ))
+    }
+
+    #[turbo_tasks::function]
+    pub async fn check_layout_imports(self) -> Result<BoolVc> {
+        Ok(BoolVc::cell(
+            self.await?
+                .experimental
+                .check_layout_imports
+                .unwrap_or_default(),
+        ))
+    }
+
+    #[turbo_tasks::function]
+    pub async fn is_static_export(self) -> Result<BoolVc> {
+        Ok(BoolVc::cell(self.await?.is_static_export()))
+    }
+
+    #[turbo_tasks::function]
+    pub async fn base_path(self) -> Result<StringVc> {
+        Ok(StringVc::cell(self.await?.base_path.clone()))
+    }
+
     #[turbo_tasks::function]
     pub async fn env(self) -> Result<EnvMapVc> {
         Ok(EnvMapVc::cell(self.await?.env.clone()))
@@ -492,6 +895,22 @@ impl NextConfigVc {
         Ok(StringsVc::cell(self.await?.page_extensions.clone()))
     }
 
+    #[turbo_tasks::function]
+    pub async fn output_file_tracing(self) -> Result<OutputFileTracingVc> {
+        let experimental = &self.await?.experimental;
+        Ok(OutputFileTracing {
+            includes: experimental
+                .output_file_tracing_includes
+                .clone()
+                .unwrap_or_default(),
+            excludes: experimental
+                .output_file_tracing_excludes
+                .clone()
+                .unwrap_or_default(),
+        }
+        .cell())
+    }
+
     #[turbo_tasks::function]
     pub async fn rewrites(self) -> Result<RewritesVc> {
         Ok(self.await?.rewrites.clone().cell())
@@ -531,6 +950,9 @@ impl NextConfigVc {
     }
 }
 
+/// The config files Next.js' config loader can evaluate, in order of
+/// precedence. Everything read from the config (e.g. `pageExtensions`) comes
+/// from whichever of these is found first.
 fn next_configs() -> StringsVc {
     StringsVc::cell(
         ["next.config.mjs", "next.config.js"]
@@ -540,13 +962,60 @@ fn next_configs() -> StringsVc {
     )
 }
 
+/// Config files that Next.js' config loader refuses to evaluate, like
+/// `next.config.ts`.
+fn unsupported_next_configs() -> StringsVc {
+    StringsVc::cell(
+        [
+            "next.config.ts",
+            "next.config.tsx",
+            "next.config.jsx",
+            "next.config.json",
+        ]
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect(),
+    )
+}
+
+/// Reports a config file that Next.js can't load, like `next.config.ts`,
+/// which would otherwise be ignored in favor of the default config.
+#[turbo_tasks::function]
+pub async fn check_unsupported_next_config(project_path: FileSystemPathVc) -> Result<CompletionVc> {
+    if let FindContextFileResult::Found(config_path, _) =
+        &*find_context_file(project_path, unsupported_next_configs()).await?
+    {
+        let config_path_ref = config_path.await?;
+        let file_name = config_path_ref
+            .path
+            .rsplit('/')
+            .next()
+            .unwrap_or(&config_path_ref.path);
+        NextConfigIssue {
+            path: *config_path,
+            message: StringVc::cell(format!(
+                "Configuring Next.js via `{file_name}` is not supported, so its settings (like \
+                 `pageExtensions`) are ignored. Please replace the file with `next.config.js` or \
+                 `next.config.mjs`."
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+    Ok(CompletionVc::new())
+}
+
 #[turbo_tasks::function]
 pub async fn load_next_config(execution_context: ExecutionContextVc) -> Result<NextConfigVc> {
     let ExecutionContext { project_path, .. } = *execution_context.await?;
     let find_config_result = find_context_file(project_path, next_configs());
     let config_file = match &*find_config_result.await? {
         FindContextFileResult::Found(config_path, _) => Some(*config_path),
-        FindContextFileResult::NotFound(_) => None,
+        FindContextFileResult::NotFound(_) => {
+            check_unsupported_next_config(project_path).await?;
+            None
+        }
     };
     load_next_config_internal(execution_context, config_file)
         .issue_context(config_file, "Loading Next.js config")
@@ -619,3 +1088,103 @@ pub async fn has_next_config(context: FileSystemPathVc) -> Result<BoolVc> {
         FindContextFileResult::NotFound(_)
     )))
 }
+
+#[turbo_tasks::value(shared)]
+pub struct NextConfigIssue {
+    pub path: FileSystemPathVc,
+    pub message: StringVc,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for NextConfigIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> IssueSeverityVc {
+        IssueSeverity::Error.into()
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> StringVc {
+        StringVc::cell("Unsupported Next.js config file".to_string())
+    }
+
+    #[turbo_tasks::function]
+    fn category(&self) -> StringVc {
+        StringVc::cell("next config".to_string())
+    }
+
+    #[turbo_tasks::function]
+    fn context(&self) -> FileSystemPathVc {
+        self.path
+    }
+
+    #[turbo_tasks::function]
+    fn description(&self) -> StringVc {
+        self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::indexmap;
+
+    use super::*;
+    use crate::testing::{project_root, reported_issues, TestProject, TestRunner};
+
+    #[test]
+    fn route_specific_tracing_excludes() -> Result<()> {
+        let tracing = OutputFileTracing {
+            includes: indexmap! {
+                "/api/*".to_string() => vec!["node_modules/sharp/build/**".to_string()],
+            },
+            excludes: indexmap! {
+                "/api/image".to_string() => vec!["node_modules/sharp/**".to_string()],
+            },
+        };
+
+        let image = tracing.for_route("/api/image")?;
+        assert!(image.is_excluded("node_modules/sharp/lib/index.js")?);
+        // Includes win over excludes
+        assert!(!image.is_excluded("node_modules/sharp/build/sharp.node")?);
+
+        let other = tracing.for_route("/api/other")?;
+        assert!(!other.is_excluded("node_modules/sharp/lib/index.js")?);
+
+        let page = tracing.for_route("/about")?;
+        assert!(page.includes.is_empty());
+        assert!(page.excludes.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reports_typescript_config_files() -> Result<()> {
+        let check = |files: &[(&str, &str)]| {
+            let project = TestProject::new(files)?;
+            let root_dir = project.root_dir();
+            TestRunner::new()?.run(async move {
+                let completion = check_unsupported_next_config(project_root(&root_dir));
+                Ok(reported_issues(completion)
+                    .await?
+                    .into_iter()
+                    .map(|issue| issue.description)
+                    .collect::<Vec<_>>())
+            })
+        };
+        let issues = check(&[(
+            "next.config.ts",
+            "export default { pageExtensions: ['ts'] }",
+        )])?;
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("`next.config.ts` is not supported"));
+        assert!(check(&[("next.config.mjs", "export default {}")])?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn builds_routing_config() {
+        let config = NextConfigBuilder::default().base_path("/docs").build();
+        assert_eq!(config.base_path, "/docs");
+        assert_eq!(config.page_extensions, vec!["tsx", "ts", "jsx", "js"]);
+        assert!(config.i18n.is_none());
+        assert!(!config.trailing_slash);
+    }
+}
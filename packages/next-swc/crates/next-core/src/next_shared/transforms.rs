@@ -119,6 +119,36 @@ impl CustomTransformer for NextJsDynamic {
     }
 }
 
+/// Returns a rule which applies the Next.js link validation transform, which
+/// warns about links to routes that aren't in `routes`.
+pub fn get_next_link_validation_transform_rule(
+    routes: next_transform_link_validation::Config,
+) -> ModuleRule {
+    let transformer =
+        EcmascriptInputTransform::Custom(CustomTransformVc::cell(box NextJsLinkValidation { routes }));
+    ModuleRule::new(
+        module_rule_match_js_no_url(),
+        vec![ModuleRuleEffect::AddEcmascriptTransforms(
+            EcmascriptInputTransformsVc::cell(vec![transformer]),
+        )],
+    )
+}
+
+#[derive(Debug)]
+struct NextJsLinkValidation {
+    routes: next_transform_link_validation::Config,
+}
+
+impl CustomTransformer for NextJsLinkValidation {
+    fn transform(&self, program: &mut Program, _ctx: &TransformContext<'_>) -> Option<Program> {
+        let module_program = unwrap_module_program(program);
+        Some(module_program.fold_with(&mut next_transform_link_validation::link_validation(
+            self.routes.clone(),
+            true,
+        )))
+    }
+}
+
 /// Returns a rule which applies the Next.js font transform.
 pub fn get_next_font_transform_rule() -> ModuleRule {
     #[allow(unused_mut)] // This is mutated when next-font-local is enabled
@@ -0,0 +1,352 @@
+//! Decides which file names count as page modules.
+//!
+//! Every scanner (pages, API routes, the app directory and the middleware
+//! lookup) goes through [PageExtensions], so a file name can't be a page
+//! module for one of them and not for another.
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use turbo_tasks::primitives::StringsVc;
+
+use crate::next_config::{NextConfig, NextConfigVc};
+
+/// The extension added to `pageExtensions` when `experimental.mdxRs` is
+/// enabled.
+const MDX_EXTENSION: &str = "mdx";
+
+/// The loaders of the known page extensions, which can be extended and
+/// overridden with `experimental.pageExtensionLoaders`.
+const DEFAULT_LOADERS: [(&str, &str); 9] = [
+    ("js", "ecmascript"),
+    ("jsx", "ecmascript"),
+    ("mjs", "ecmascript"),
+    ("cjs", "ecmascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("mts", "typescript"),
+    ("cts", "typescript"),
+    ("mdx", "mdx"),
+];
+
+/// The effective `pageExtensions` of a project.
+///
+/// Extensions may be compound (like `page.tsx`), in which case the longest
+/// configured extension a file name ends with wins. Extensions are matched
+/// case-sensitively, like Next.js does, so `index.TSX` isn't a page module.
+/// TypeScript declaration files (`*.d.ts`) never are.
+///
+/// Each extension can have a loader (like `mdx`), the name of the transform
+/// that page modules with that extension need.
+///
+/// Extensions in `experimental.previewPageExtensions` (like `preview.tsx`)
+/// are only page extensions when `experimental.previewPages` is enabled.
+/// Otherwise files with them are excluded, instead of matching a shorter
+/// extension like `tsx`.
+#[turbo_tasks::value(shared)]
+#[derive(Debug, Clone, Default)]
+pub struct PageExtensions {
+    extensions: Vec<String>,
+    loaders: IndexMap<String, String>,
+    /// Whether the preview page extensions are included, kept for debugging.
+    preview_pages: bool,
+    excluded: Vec<String>,
+}
+
+impl PageExtensions {
+    /// Creates the matcher for the given extensions, in priority order.
+    /// Leading dots are ignored and duplicates are dropped.
+    pub fn new(extensions: impl IntoIterator<Item = String>) -> Self {
+        let mut result: Vec<String> = vec![];
+        for extension in extensions {
+            let extension = extension.trim_start_matches('.');
+            if !extension.is_empty() && !result.iter().any(|e| e == extension) {
+                result.push(extension.to_string());
+            }
+        }
+        PageExtensions {
+            extensions: result,
+            loaders: DEFAULT_LOADERS
+                .iter()
+                .map(|(extension, loader)| (extension.to_string(), loader.to_string()))
+                .collect(),
+            preview_pages: false,
+            excluded: vec![],
+        }
+    }
+
+    /// Returns the effective `pageExtensions` of the Next.js config. `mdx` is
+    /// added when `experimental.mdxRs` is enabled, and the preview page
+    /// extensions when `experimental.previewPages` is.
+    pub fn from_next_config(next_config: &NextConfig) -> Self {
+        let mut extensions = next_config.page_extensions.clone();
+        if next_config.mdx_rs_enabled() {
+            extensions.push(MDX_EXTENSION.to_string());
+        }
+        let preview_extensions = next_config
+            .experimental
+            .preview_page_extensions
+            .clone()
+            .unwrap_or_default();
+        let preview_pages = next_config.preview_pages_enabled();
+        if preview_pages {
+            extensions.extend(preview_extensions.iter().cloned());
+        }
+        let loaders = next_config
+            .experimental
+            .page_extension_loaders
+            .clone()
+            .unwrap_or_default();
+        let mut page_extensions = PageExtensions::new(extensions).with_loaders(loaders);
+        page_extensions.preview_pages = preview_pages;
+        if !preview_pages {
+            page_extensions.excluded = preview_extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_string())
+                .filter(|extension| !extension.is_empty())
+                .collect();
+        }
+        page_extensions
+    }
+
+    /// Returns true if the preview page extensions are included.
+    pub fn preview_pages(&self) -> bool {
+        self.preview_pages
+    }
+
+    /// Adds or overrides the loaders of extensions.
+    pub fn with_loaders(mut self, loaders: impl IntoIterator<Item = (String, String)>) -> Self {
+        for (extension, loader) in loaders {
+            self.loaders
+                .insert(extension.trim_start_matches('.').to_string(), loader);
+        }
+        self
+    }
+
+    /// Returns the extensions in priority order.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Splits `name` into its basename and page extension, or returns `None`
+    /// if `name` isn't a page module.
+    pub fn matches<'a>(&self, name: &'a str) -> Option<(&'a str, &'a str)> {
+        let (basename, extension) = self
+            .extensions
+            .iter()
+            .filter_map(|extension| {
+                let basename = name.strip_suffix(extension.as_str())?.strip_suffix('.')?;
+                Some((basename, &name[basename.len() + 1..]))
+            })
+            .filter(|(basename, _)| !basename.is_empty())
+            .max_by_key(|(_, extension)| extension.len())?;
+        if name.ends_with(".d.ts") && extension != "d.ts" {
+            return None;
+        }
+        let is_excluded = self.excluded.iter().any(|excluded| {
+            excluded.len() > extension.len()
+                && name
+                    .strip_suffix(excluded.as_str())
+                    .map_or(false, |basename| {
+                        basename.ends_with('.') && basename.len() > 1
+                    })
+        });
+        if is_excluded {
+            return None;
+        }
+        Some((basename, extension))
+    }
+
+    /// Returns the position of the page extension of `name` in the priority
+    /// order, or `None` if `name` isn't a page module.
+    pub fn priority(&self, name: &str) -> Option<usize> {
+        let (_, extension) = self.matches(name)?;
+        self.extensions.iter().position(|e| e == extension)
+    }
+
+    /// Returns the loader of the page module `name`, or `None` if it isn't a
+    /// page module or its extension has no loader. Compound extensions (like
+    /// `page.tsx`) fall back to the loader of their last part.
+    pub fn loader(&self, name: &str) -> Option<&str> {
+        let (_, extension) = self.matches(name)?;
+        let last = extension.rsplit('.').next().unwrap_or(extension);
+        self.loaders
+            .get(extension)
+            .or_else(|| self.loaders.get(last))
+            .map(String::as_str)
+    }
+
+    /// Returns the files whose classification differs from `previous`: files
+    /// that became or stopped being page modules, or whose basename changed
+    /// (e.g. when a compound extension like `page.tsx` was added). Only these
+    /// need to be reclassified when the extensions change.
+    pub fn reclassified_files<'a>(
+        &self,
+        previous: &PageExtensions,
+        files: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<&'a str> {
+        files
+            .into_iter()
+            .filter(|file| {
+                let name = file.rsplit('/').next().unwrap_or(file);
+                self.matches(name) != previous.matches(name)
+            })
+            .collect()
+    }
+
+    /// Returns the file names a page module named `basename` can have, in
+    /// priority order.
+    pub fn file_names(&self, basename: &str) -> Vec<String> {
+        self.extensions
+            .iter()
+            .map(|extension| format!("{basename}.{extension}"))
+            .collect()
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl PageExtensionsVc {
+    /// Returns the effective `pageExtensions` of the Next.js config. See
+    /// [PageExtensions::from_next_config].
+    #[turbo_tasks::function]
+    pub async fn from_config(next_config: NextConfigVc) -> Result<PageExtensionsVc> {
+        Ok(PageExtensions::from_next_config(&*next_config.await?).cell())
+    }
+
+    /// Returns the file names a page module named `basename` can have, in
+    /// priority order.
+    #[turbo_tasks::function]
+    pub async fn file_names(self, basename: &str) -> Result<StringsVc> {
+        Ok(StringsVc::cell(self.await?.file_names(basename)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{next_config::NextConfigBuilder, route_events::RouteTableSnapshot};
+
+    fn page_extensions(extensions: &[&str]) -> PageExtensions {
+        PageExtensions::new(extensions.iter().map(|e| e.to_string()))
+    }
+
+    #[test]
+    fn matches_configured_extensions() {
+        let extensions = page_extensions(&["tsx", "ts", "jsx", "js"]);
+        assert_eq!(extensions.matches("index.tsx"), Some(("index", "tsx")));
+        assert_eq!(extensions.matches("[slug].js"), Some(("[slug]", "js")));
+        assert_eq!(
+            extensions.matches("about.test.ts"),
+            Some(("about.test", "ts"))
+        );
+        assert_eq!(extensions.matches("styles.css"), None);
+        assert_eq!(extensions.matches("README"), None);
+        assert_eq!(extensions.matches(".tsx"), None);
+        assert_eq!(extensions.matches("tsx"), None);
+    }
+
+    #[test]
+    fn finds_files_reclassified_by_an_extension_change() {
+        let previous = page_extensions(&["tsx", "ts", "mdx"]);
+        let current = page_extensions(&["tsx", "ts"]);
+        let files = [
+            "index.tsx",
+            "blog/intro.mdx",
+            "blog/[slug].tsx",
+            "docs/guide.mdx",
+            "api/users.ts",
+            "styles.css",
+        ];
+        assert_eq!(
+            current.reclassified_files(&previous, files),
+            vec!["blog/intro.mdx", "docs/guide.mdx"]
+        );
+        assert!(previous.reclassified_files(&previous, files).is_empty());
+
+        let compound = page_extensions(&["tsx", "page.tsx"]);
+        assert_eq!(
+            compound.reclassified_files(&current, ["about.page.tsx", "about.tsx"]),
+            vec!["about.page.tsx"]
+        );
+    }
+
+    #[test]
+    fn prefers_the_longest_compound_extension() {
+        let extensions = page_extensions(&["tsx", "page.tsx"]);
+        assert_eq!(
+            extensions.matches("about.page.tsx"),
+            Some(("about", "page.tsx"))
+        );
+        assert_eq!(extensions.matches("about.tsx"), Some(("about", "tsx")));
+        assert_eq!(extensions.priority("about.page.tsx"), Some(1));
+        assert_eq!(extensions.priority("about.tsx"), Some(0));
+
+        let extensions = page_extensions(&["page.tsx"]);
+        assert_eq!(extensions.matches("about.tsx"), None);
+        assert_eq!(extensions.matches("page.tsx"), None);
+    }
+
+    #[test]
+    fn excludes_declaration_files() {
+        let extensions = page_extensions(&["ts"]);
+        assert_eq!(extensions.matches("routes.d.ts"), None);
+        assert_eq!(extensions.matches("routes.ts"), Some(("routes", "ts")));
+    }
+
+    #[test]
+    fn matches_case_sensitively() {
+        let extensions = page_extensions(&["tsx"]);
+        assert_eq!(extensions.matches("index.TSX"), None);
+        assert_eq!(extensions.matches("Index.tsx"), Some(("Index", "tsx")));
+    }
+
+    #[test]
+    fn finds_loaders() {
+        let extensions = page_extensions(&["mdx", "tsx", "page.js", "vue"])
+            .with_loaders([(".vue".to_string(), "vue".to_string())]);
+        assert_eq!(extensions.loader("post.mdx"), Some("mdx"));
+        assert_eq!(extensions.loader("about.tsx"), Some("typescript"));
+        assert_eq!(extensions.loader("about.page.js"), Some("ecmascript"));
+        assert_eq!(extensions.loader("about.vue"), Some("vue"));
+        assert_eq!(extensions.loader("about.ts"), None);
+    }
+
+    #[test]
+    fn includes_preview_pages_only_when_enabled() {
+        let files = ["index.tsx", "about.tsx", "draft.preview.tsx"];
+        let routes = |preview_pages: bool| {
+            let config = NextConfigBuilder::default()
+                .preview_page_extensions(vec!["preview.tsx".to_string()])
+                .preview_pages(preview_pages)
+                .build();
+            let extensions = PageExtensions::from_next_config(&config);
+            assert_eq!(extensions.preview_pages(), preview_pages);
+            files
+                .iter()
+                .filter_map(|file| extensions.matches(file))
+                .map(|(basename, _)| match basename {
+                    "index" => "/".to_string(),
+                    basename => format!("/{basename}"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let production = routes(false);
+        let preview = routes(true);
+        assert_eq!(production, vec!["/", "/about"]);
+        assert_eq!(preview, vec!["/", "/about", "/draft"]);
+        assert_ne!(
+            RouteTableSnapshot::new(production).fingerprint,
+            RouteTableSnapshot::new(preview).fingerprint
+        );
+    }
+
+    #[test]
+    fn normalizes_extensions() {
+        let extensions = page_extensions(&[".tsx", "tsx", "", "js"]);
+        assert_eq!(extensions.extensions(), ["tsx", "js"]);
+        assert_eq!(
+            extensions.file_names("middleware"),
+            vec!["middleware.tsx", "middleware.js"]
+        );
+    }
+}
@@ -2,9 +2,9 @@ use anyhow::Result;
 use indexmap::indexmap;
 use serde::{Deserialize, Serialize};
 use turbo_tasks::{
-    primitives::{OptionStringVc, StringVc, StringsVc},
+    primitives::{OptionStringVc, StringVc},
     trace::TraceRawVcs,
-    Value,
+    CompletionVc, TransientInstance, Value,
 };
 use turbo_tasks_env::{CustomProcessEnvVc, EnvMapVc, ProcessEnvVc};
 use turbo_tasks_fs::{FileContent, FileSystemPathVc};
@@ -14,6 +14,7 @@ use turbopack_core::{
     chunk::ChunkingContextVc,
     context::{AssetContext, AssetContextVc},
     environment::{EnvironmentIntention, ServerAddrVc},
+    issue::IssueSeverity,
     reference_type::{EntryReferenceSubType, ReferenceType},
     source_asset::SourceAssetVc,
 };
@@ -67,9 +68,11 @@ use crate::{
         get_server_compile_time_info, get_server_module_options_context,
         get_server_resolve_options_context, ServerContextType,
     },
+    page_extensions::PageExtensionsVc,
     page_loader::create_page_loader,
     pages_structure::{
-        OptionPagesStructureVc, PagesStructure, PagesStructureItem, PagesStructureVc,
+        OptionPagesStructureVc, PagesStructure, PagesStructureIssue, PagesStructureItem,
+        PagesStructureVc, RouteEntriesVc,
     },
     util::{parse_config_from_source, pathname_for_path, NextRuntime},
 };
@@ -232,7 +235,7 @@ pub async fn create_page_source(
         next_config,
     );
 
-    let page_extensions = next_config.page_extensions();
+    let page_extensions = PageExtensionsVc::from_config(next_config);
     let force_not_found_source = create_not_found_page_source(
         project_path,
         env,
@@ -294,6 +297,34 @@ pub async fn create_page_source(
     Ok(source)
 }
 
+/// Called with the patterns of the routes that are prewarmed.
+pub type PrewarmRoutesHook = Box<dyn Fn(&[String]) + Send + Sync>;
+
+/// Compiles the `routes` of the pages `source` ahead of their first request,
+/// one at a time. Await it from a task of its own (like the dev server does),
+/// so that requests are served in the meantime.
+#[turbo_tasks::function]
+pub async fn prewarm_page_routes(
+    source: ContentSourceVc,
+    routes: RouteEntriesVc,
+    on_prewarm: TransientInstance<PrewarmRoutesHook>,
+) -> Result<CompletionVc> {
+    let mut patterns = vec![];
+    for route in routes.await?.iter() {
+        patterns.push(route.await?.pattern.clone());
+    }
+    (*on_prewarm)(&patterns);
+    for pattern in patterns {
+        source
+            .get(
+                pattern.trim_start_matches('/'),
+                Value::new(ContentSourceData::default()),
+            )
+            .await?;
+    }
+    Ok(CompletionVc::new())
+}
+
 /// Handles a single page file in the pages directory
 #[turbo_tasks::function]
 async fn create_page_source_for_file(
@@ -438,10 +469,10 @@ async fn create_page_source_for_file(
 
 async fn get_not_found_page(
     pages_dir: FileSystemPathVc,
-    page_extensions: StringsVc,
+    page_extensions: PageExtensionsVc,
 ) -> Result<Option<AssetVc>> {
-    for ext in page_extensions.await?.iter() {
-        let not_found_path = pages_dir.join(&format!("404.{ext}"));
+    for file_name in page_extensions.file_names("404").await?.iter() {
+        let not_found_path = pages_dir.join(file_name);
         let content = not_found_path.read();
         if let FileContent::Content(_) = &*content.await? {
             return Ok(Some(SourceAssetVc::new(not_found_path).into()));
@@ -458,7 +489,7 @@ async fn create_not_found_page_source(
     server_context: AssetContextVc,
     client_context: AssetContextVc,
     pages_dir: FileSystemPathVc,
-    page_extensions: StringsVc,
+    page_extensions: PageExtensionsVc,
     runtime_entries: EcmascriptChunkPlaceablesVc,
     fallback_page: DevHtmlAssetVc,
     server_root: FileSystemPathVc,
@@ -567,11 +598,29 @@ async fn create_page_source_for_directory(
     let mut sources = vec![];
 
     for item in items.iter() {
-        let source = match *item.await? {
+        let item = item.await?;
+        if let Some(loader) = uncompiled_loader(&item) {
+            PagesStructureIssue {
+                severity: IssueSeverity::Error.into(),
+                path: item.file(),
+                message: StringVc::cell(format!(
+                    "The page needs the `{loader}` loader, which the pages directory can't \
+                     compile, so it isn't served. Remove its extension from `pageExtensions`, or \
+                     change the loader in `experimental.pageExtensionLoaders`."
+                )),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+            continue;
+        }
+        let asset = item.asset();
+        let source = match *item {
             PagesStructureItem::Page {
                 page,
                 specificity,
                 url,
+                ..
             } => create_page_source_for_file(
                 project_path,
                 env,
@@ -580,7 +629,7 @@ async fn create_page_source_for_directory(
                 client_context,
                 pages_dir,
                 specificity,
-                SourceAssetVc::new(page).into(),
+                asset,
                 runtime_entries,
                 fallback_page,
                 server_root,
@@ -594,6 +643,7 @@ async fn create_page_source_for_directory(
                 api,
                 specificity,
                 url,
+                ..
             } => create_page_source_for_file(
                 project_path,
                 env,
@@ -602,7 +652,7 @@ async fn create_page_source_for_directory(
                 client_context,
                 pages_dir,
                 specificity,
-                SourceAssetVc::new(api).into(),
+                asset,
                 runtime_entries,
                 fallback_page,
                 server_root,
@@ -612,6 +662,9 @@ async fn create_page_source_for_directory(
                 output_root,
             )
             .issue_context(api, "Next.js pages api directory"),
+            // Routes of custom kinds are served by the content sources of the
+            // frameworks that contributed them.
+            PagesStructureItem::Custom { .. } => continue,
         };
         sources.push(source);
     }
@@ -635,6 +688,21 @@ async fn create_page_source_for_directory(
     Ok(CombinedContentSource { sources }.cell().into())
 }
 
+/// The loaders (see [PagesStructureItem::transform_hint]) of the page modules
+/// that the server and client contexts of the pages directory can compile.
+const COMPILED_LOADERS: [&str; 2] = ["ecmascript", "typescript"];
+
+/// Returns the loader of a page or API route that the pages directory can't
+/// compile. Routes of custom kinds are compiled by the frameworks that
+/// contributed them.
+fn uncompiled_loader(item: &PagesStructureItem) -> Option<&str> {
+    if matches!(item, PagesStructureItem::Custom { .. }) {
+        return None;
+    }
+    item.transform_hint()
+        .filter(|loader| !COMPILED_LOADERS.contains(loader))
+}
+
 #[derive(
     Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, PartialOrd, Ord, TraceRawVcs,
 )]
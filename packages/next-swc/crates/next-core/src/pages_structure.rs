@@ -1,28 +1,285 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Display, Write},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
 use anyhow::Result;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use turbo_tasks::{
-    primitives::{BoolVc, StringsVc},
-    CompletionVc,
+    primitives::{BoolVc, OptionStringVc, StringVc, StringsVc, U32Vc},
+    trace::TraceRawVcs,
+    CollectiblesSource, CompletionVc, Value, ValueToString,
+};
+use turbo_tasks_fs::{
+    DirectoryContent, DirectoryEntry, File, FileContent, FileContentVc, FileSystemEntryType,
+    FileSystemPathVc,
+};
+use turbo_tasks_hash::hash_xxh3_hash64;
+use turbopack_core::{
+    asset::{Asset, AssetVc},
+    issue::{Issue, IssueSeverity, IssueSeverityVc, IssueVc},
+    source_asset::SourceAssetVc,
 };
-use turbo_tasks_fs::{DirectoryContent, DirectoryEntry, FileSystemEntryType, FileSystemPathVc};
 use turbopack_dev_server::source::specificity::SpecificityVc;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    app_structure::{find_app_structure, AppStructureItem, AppStructureVc, OptionAppStructureVc},
+    next_config::{
+        NextConfigVc, Rewrite, Rewrites, RouteFileTracingVc, DEFAULT_MAX_PAGES_DIRECTORY_ENTRIES,
+    },
+    page_extensions::{PageExtensions, PageExtensionsVc},
+    rewrites::{
+        check_rewrite_destination, find_rewrite_cycles, rewrite_pathname, DestinationProblem,
+        RouteRuleContext,
+    },
+    sorted_routes::get_sorted_routes,
+};
 
-use crate::next_config::NextConfigVc;
+/// Describes how a [PagesStructureItem] ended up in the structure.
+#[turbo_tasks::value(serialization = "auto_for_input")]
+#[derive(Debug, Copy, Clone, Hash)]
+pub enum PagesStructureItemProvenance {
+    /// Found while scanning the given pages root.
+    Scanned { root: FileSystemPathVc },
+    /// Generated by a plugin (see [VirtualPages]) and served from `asset`
+    /// instead of a file on disk. `replaced` is the scanned item for the same
+    /// route that the virtual route won over, see
+    /// [VirtualPagesPrecedence::VirtualPages].
+    Virtual {
+        asset: AssetVc,
+        replaced: Option<PagesStructureItemVc>,
+    },
+}
 
 /// A final route in the pages directory.
-#[turbo_tasks::value]
+///
+/// The provenance is ignored when comparing items, so rescanning the same
+/// root doesn't invalidate dependents. Only the asset of a virtual item is
+/// compared, since it is what gets served.
+#[turbo_tasks::value(eq = "manual")]
+#[derive(Clone, Debug)]
 pub enum PagesStructureItem {
     Page {
         url: FileSystemPathVc,
         specificity: SpecificityVc,
         page: FileSystemPathVc,
+        provenance: PagesStructureItemProvenance,
+        /// The loader for the file, from [PageExtensions::loader].
+        transform_hint: Option<String>,
+        /// The AMP variant of the page (like `post.amp.tsx` for `post.tsx`),
+        /// see [FileClassifier::amp_variant_of].
+        amp_variant: Option<FileSystemPathVc>,
     },
     Api {
         url: FileSystemPathVc,
         specificity: SpecificityVc,
         api: FileSystemPathVc,
+        provenance: PagesStructureItemProvenance,
+        /// The loader for the file, from [PageExtensions::loader].
+        transform_hint: Option<String>,
+    },
+    /// A route of a kind contributed by a framework built on top of Next.js
+    /// (see [FileClassification::Custom]). It isn't served by the pages
+    /// content source, and isn't part of the pages manifests.
+    Custom {
+        url: FileSystemPathVc,
+        specificity: SpecificityVc,
+        file: FileSystemPathVc,
+        kind: String,
+        provenance: PagesStructureItemProvenance,
+        /// The loader for the file, from [PageExtensions::loader].
+        transform_hint: Option<String>,
     },
 }
 
+impl PartialEq for PagesStructureItem {
+    fn eq(&self, other: &Self) -> bool {
+        let same_route = match (self, other) {
+            (
+                PagesStructureItem::Page {
+                    url,
+                    specificity,
+                    page,
+                    amp_variant,
+                    ..
+                },
+                PagesStructureItem::Page {
+                    url: other_url,
+                    specificity: other_specificity,
+                    page: other_page,
+                    amp_variant: other_amp_variant,
+                    ..
+                },
+            ) => {
+                url == other_url
+                    && specificity == other_specificity
+                    && page == other_page
+                    && amp_variant == other_amp_variant
+            }
+            (
+                PagesStructureItem::Api {
+                    url,
+                    specificity,
+                    api,
+                    ..
+                },
+                PagesStructureItem::Api {
+                    url: other_url,
+                    specificity: other_specificity,
+                    api: other_api,
+                    ..
+                },
+            ) => url == other_url && specificity == other_specificity && api == other_api,
+            (
+                PagesStructureItem::Custom {
+                    url,
+                    specificity,
+                    file,
+                    kind,
+                    ..
+                },
+                PagesStructureItem::Custom {
+                    url: other_url,
+                    specificity: other_specificity,
+                    file: other_file,
+                    kind: other_kind,
+                    ..
+                },
+            ) => {
+                url == other_url
+                    && specificity == other_specificity
+                    && file == other_file
+                    && kind == other_kind
+            }
+            _ => false,
+        };
+        same_route
+            && self.virtual_asset() == other.virtual_asset()
+            && self.transform_hint() == other.transform_hint()
+    }
+}
+
+impl Eq for PagesStructureItem {}
+
+impl PagesStructureItem {
+    /// Returns the url of the route in the server root.
+    pub fn url(&self) -> FileSystemPathVc {
+        match *self {
+            PagesStructureItem::Page { url, .. } => url,
+            PagesStructureItem::Api { url, .. } => url,
+            PagesStructureItem::Custom { url, .. } => url,
+        }
+    }
+
+    /// Returns the file backing the route.
+    pub fn file(&self) -> FileSystemPathVc {
+        match *self {
+            PagesStructureItem::Page { page, .. } => page,
+            PagesStructureItem::Api { api, .. } => api,
+            PagesStructureItem::Custom { file, .. } => file,
+        }
+    }
+
+    /// Returns the specificity of the route.
+    pub fn specificity(&self) -> SpecificityVc {
+        match *self {
+            PagesStructureItem::Page { specificity, .. } => specificity,
+            PagesStructureItem::Api { specificity, .. } => specificity,
+            PagesStructureItem::Custom { specificity, .. } => specificity,
+        }
+    }
+
+    /// Returns true if the route is an API route.
+    pub fn is_api(&self) -> bool {
+        matches!(self, PagesStructureItem::Api { .. })
+    }
+
+    /// Returns true if the route is a page.
+    pub fn is_page(&self) -> bool {
+        matches!(self, PagesStructureItem::Page { .. })
+    }
+
+    /// Returns the AMP variant of a page, like `post.amp.tsx` for `post.tsx`.
+    pub fn amp_variant(&self) -> Option<FileSystemPathVc> {
+        match *self {
+            PagesStructureItem::Page { amp_variant, .. } => amp_variant,
+            _ => None,
+        }
+    }
+
+    /// Returns the tag of a route of a custom kind.
+    pub fn custom_kind(&self) -> Option<&str> {
+        match self {
+            PagesStructureItem::Custom { kind, .. } => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the kind of the route for display: the tag of a
+    /// custom kind, `api` or `page`.
+    pub fn kind_name(&self) -> &str {
+        match self.custom_kind() {
+            Some(kind) => kind,
+            None if self.is_api() => "api",
+            None => "page",
+        }
+    }
+
+    /// Returns the name of the loader that transforms the file (e.g. `mdx`),
+    /// if known.
+    pub fn transform_hint(&self) -> Option<&str> {
+        match self {
+            PagesStructureItem::Page { transform_hint, .. }
+            | PagesStructureItem::Api { transform_hint, .. }
+            | PagesStructureItem::Custom { transform_hint, .. } => transform_hint.as_deref(),
+        }
+    }
+
+    /// Returns the asset serving the route: the asset of a virtual item, or
+    /// the file of any other item.
+    pub fn asset(&self) -> AssetVc {
+        self.virtual_asset()
+            .unwrap_or_else(|| SourceAssetVc::new(self.file()).into())
+    }
+
+    fn virtual_asset(&self) -> Option<AssetVc> {
+        match *self {
+            PagesStructureItem::Page { provenance, .. }
+            | PagesStructureItem::Api { provenance, .. }
+            | PagesStructureItem::Custom { provenance, .. } => match provenance {
+                PagesStructureItemProvenance::Virtual { asset, .. } => Some(asset),
+                _ => None,
+            },
+        }
+    }
+
+    fn url_mut(&mut self) -> &mut FileSystemPathVc {
+        match self {
+            PagesStructureItem::Page { url, .. } => url,
+            PagesStructureItem::Api { url, .. } => url,
+            PagesStructureItem::Custom { url, .. } => url,
+        }
+    }
+
+    fn specificity_mut(&mut self) -> &mut SpecificityVc {
+        match self {
+            PagesStructureItem::Page { specificity, .. } => specificity,
+            PagesStructureItem::Api { specificity, .. } => specificity,
+            PagesStructureItem::Custom { specificity, .. } => specificity,
+        }
+    }
+}
+
 #[turbo_tasks::value_impl]
 impl PagesStructureItemVc {
     #[turbo_tasks::function]
@@ -30,23 +287,179 @@ impl PagesStructureItemVc {
         url: FileSystemPathVc,
         specificity: SpecificityVc,
         file: FileSystemPathVc,
-        is_api: BoolVc,
+        is_api: bool,
+        custom_kind: OptionStringVc,
+        provenance: Value<PagesStructureItemProvenance>,
+        transform_hint: OptionStringVc,
     ) -> Result<Self> {
-        if *is_api.await? {
-            Ok(PagesStructureItem::Api {
+        let provenance = provenance.into_value();
+        let transform_hint = transform_hint.await?.clone_value();
+        Ok(if let Some(kind) = custom_kind.await?.clone_value() {
+            PagesStructureItem::Custom {
+                url,
+                specificity,
+                file,
+                kind,
+                provenance,
+                transform_hint,
+            }
+            .cell()
+        } else if is_api {
+            PagesStructureItem::Api {
                 url,
                 specificity,
                 api: file,
+                provenance,
+                transform_hint,
             }
-            .cell())
+            .cell()
         } else {
-            Ok(PagesStructureItem::Page {
+            PagesStructureItem::Page {
                 url,
                 specificity,
                 page: file,
+                provenance,
+                transform_hint,
+                amp_variant: None,
             }
-            .cell())
+            .cell()
+        })
+    }
+
+    /// Returns a copy of this page with `amp_variant` (an item for the same
+    /// route, like `post.amp.tsx` for `post.tsx`) as its AMP variant.
+    #[turbo_tasks::function]
+    async fn with_amp_variant(self, amp_variant: PagesStructureItemVc) -> Result<Self> {
+        let amp_file = amp_variant.await?.file();
+        let mut item = self.await?.clone_value();
+        if let PagesStructureItem::Page { amp_variant, .. } = &mut item {
+            *amp_variant = Some(amp_file);
+        }
+        Ok(item.cell())
+    }
+
+    /// Returns where this item came from.
+    #[turbo_tasks::function]
+    pub async fn provenance(self) -> Result<PagesStructureItemProvenanceVc> {
+        Ok(match *self.await? {
+            PagesStructureItem::Page { provenance, .. } => provenance,
+            PagesStructureItem::Api { provenance, .. } => provenance,
+            PagesStructureItem::Custom { provenance, .. } => provenance,
+        }
+        .cell())
+    }
+
+    /// Returns the segments of the route of this item. The other route
+    /// methods build on this, so the url is only split once per item.
+    #[turbo_tasks::function]
+    pub async fn route_segments(self) -> Result<RouteSegmentsVc> {
+        let url = self.await?.url().await?;
+        Ok(RouteSegmentsVc::cell(route_segments_for_url_path(
+            &url.path,
+        )))
+    }
+
+    /// Returns the route pattern of this item (e.g. `/blog/[slug]`), as used
+    /// for keys in manifests.
+    #[turbo_tasks::function]
+    pub async fn route_pattern(self) -> Result<StringVc> {
+        Ok(StringVc::cell(format_route_pattern(
+            &self.route_segments().await?,
+        )))
+    }
+
+    /// Returns the regex matching the pathnames of this item's route, like
+    /// Next.js' `getRouteRegex` (e.g. `^/blog/([^/]+?)(?:/)?$`).
+    #[turbo_tasks::function]
+    pub async fn route_regex(self) -> Result<StringVc> {
+        let (regex, _, _) = route_regexes(&self.route_segments().await?);
+        Ok(StringVc::cell(regex))
+    }
+
+    /// Returns the params of this item's route in order.
+    #[turbo_tasks::function]
+    pub async fn dynamic_params(self) -> Result<ParamInfosVc> {
+        Ok(ParamInfosVc::cell(dynamic_params_for_segments(
+            &self.route_segments().await?,
+        )))
+    }
+
+    /// Returns the other items of the directory of this item in `structure`
+    /// (the whole tree), sorted by route pattern. Useful for "previous" and
+    /// "next" links between pages, e.g. in docs. Items don't reference their
+    /// directory, so the tree is searched for it.
+    #[turbo_tasks::function]
+    pub async fn siblings(self, structure: PagesStructureVc) -> Result<PagesStructureItemsVc> {
+        let mut queue = vec![structure];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            let Some(index) = structure.items.iter().position(|item| *item == self) else {
+                queue.extend(structure.children.iter().copied());
+                continue;
+            };
+            let mut patterns = Vec::with_capacity(structure.items.len());
+            for item in structure.items.iter() {
+                patterns.push(item.route_pattern().await?.clone_value());
+            }
+            return Ok(PagesStructureItemsVc::cell(
+                sibling_indices(&patterns, index)
+                    .into_iter()
+                    .map(|index| structure.items[index])
+                    .collect(),
+            ));
+        }
+        Ok(PagesStructureItemsVc::cell(vec![]))
+    }
+
+    /// Returns every url this item's route is reachable at, with the
+    /// `basePath`, locale prefixes and `trailingSlash` of the Next.js config
+    /// applied. See [effective_urls] for the order.
+    #[turbo_tasks::function]
+    pub async fn effective_urls(self, next_config: NextConfigVc) -> Result<StringsVc> {
+        let pattern = self.route_pattern().await?;
+        let next_config = next_config.await?;
+        Ok(StringsVc::cell(effective_urls(
+            &pattern,
+            self.await?.is_api(),
+            next_config.base_path(),
+            next_config.i18n_locales(),
+            next_config.trailing_slash(),
+        )))
+    }
+
+    /// Returns true if the page may return `{ notFound: true }` from
+    /// `getStaticProps` or `getServerSideProps`. This is a heuristic on the
+    /// source: only a literal `notFound: true` is found.
+    #[turbo_tasks::function]
+    pub async fn can_return_not_found(self) -> Result<BoolVc> {
+        let item = self.await?;
+        if !item.is_page() {
+            return Ok(BoolVc::cell(false));
         }
+        let FileContent::Content(file) = &*item.file().read().await? else {
+            return Ok(BoolVc::cell(false));
+        };
+        Ok(BoolVc::cell(returns_not_found(&file.content().to_str()?)))
+    }
+
+    /// Returns true if the last segment of the route is a catch-all
+    /// (`[...slug]`) or an optional catch-all (`[[...slug]]`).
+    #[turbo_tasks::function]
+    pub async fn is_catch_all(self) -> Result<BoolVc> {
+        Ok(BoolVc::cell(matches!(
+            self.last_route_segment().await?,
+            Some(RouteSegment::CatchAll(_) | RouteSegment::OptionalCatchAll(_))
+        )))
+    }
+
+    /// Returns true if the last segment of the route is an optional catch-all
+    /// (`[[...slug]]`), which also serves the parent path.
+    #[turbo_tasks::function]
+    pub async fn is_optional_catch_all(self) -> Result<BoolVc> {
+        Ok(BoolVc::cell(matches!(
+            self.last_route_segment().await?,
+            Some(RouteSegment::OptionalCatchAll(_))
+        )))
     }
 
     /// Returns a completion that changes when any route in the whole tree
@@ -56,177 +469,8239 @@ impl PagesStructureItemVc {
         match *self.await? {
             PagesStructureItem::Page { url, .. } => url.await?,
             PagesStructureItem::Api { url, .. } => url.await?,
+            PagesStructureItem::Custom { url, .. } => url.await?,
         };
         Ok(CompletionVc::new())
     }
 }
 
-/// A (sub)directory in the pages directory with all analyzed routes and
-/// folders.
-#[turbo_tasks::value]
-pub struct PagesStructure {
-    pub directory: FileSystemPathVc,
-    pub items: Vec<PagesStructureItemVc>,
-    pub children: Vec<PagesStructureVc>,
+impl PagesStructureItemVc {
+    async fn last_route_segment(self) -> Result<Option<RouteSegment>> {
+        Ok(self.route_segments().await?.last().cloned())
+    }
 }
 
-#[turbo_tasks::value_impl]
-impl PagesStructureVc {
-    /// Returns the directory of this structure.
-    #[turbo_tasks::function]
-    pub async fn directory(self) -> Result<FileSystemPathVc> {
-        Ok(self.await?.directory)
+/// A single segment of a route, as written in the pages directory.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, TraceRawVcs)]
+pub enum RouteSegment {
+    /// A static segment like `about`.
+    Static(String),
+    /// A dynamic segment like `[slug]`.
+    Dynamic(String),
+    /// A catch-all segment like `[...slug]`.
+    CatchAll(String),
+    /// An optional catch-all segment like `[[...slug]]`.
+    OptionalCatchAll(String),
+}
+
+impl Display for RouteSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteSegment::Static(name) => write!(f, "{name}"),
+            RouteSegment::Dynamic(param) => write!(f, "[{param}]"),
+            RouteSegment::CatchAll(param) => write!(f, "[...{param}]"),
+            RouteSegment::OptionalCatchAll(param) => write!(f, "[[...{param}]]"),
+        }
     }
+}
 
-    /// Returns a completion that changes when any route in the whole tree
-    /// changes.
-    #[turbo_tasks::function]
-    pub async fn routes_changed(self) -> Result<CompletionVc> {
-        for item in self.await?.items.iter() {
-            item.routes_changed().await?;
+impl RouteSegment {
+    /// Returns the name of the param of a dynamic segment.
+    pub fn param_name(&self) -> Option<&str> {
+        match self {
+            RouteSegment::Static(_) => None,
+            RouteSegment::Dynamic(param)
+            | RouteSegment::CatchAll(param)
+            | RouteSegment::OptionalCatchAll(param) => Some(param),
         }
-        for child in self.await?.children.iter() {
-            child.routes_changed().await?;
+    }
+
+    /// Parses a file or directory name (without extension) into a segment.
+    pub fn parse(name: &str) -> Self {
+        if let Some(param) = name
+            .strip_prefix("[[...")
+            .and_then(|name| name.strip_suffix("]]"))
+        {
+            RouteSegment::OptionalCatchAll(param.to_string())
+        } else if let Some(param) = name
+            .strip_prefix("[...")
+            .and_then(|name| name.strip_suffix(']'))
+        {
+            RouteSegment::CatchAll(param.to_string())
+        } else if let Some(param) = name
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+        {
+            RouteSegment::Dynamic(param.to_string())
+        } else {
+            RouteSegment::Static(name.to_string())
         }
-        Ok(CompletionVc::new())
     }
 }
 
 #[turbo_tasks::value(transparent)]
-pub struct OptionPagesStructure(Option<PagesStructureVc>);
+pub struct RouteSegments(Vec<RouteSegment>);
 
-#[turbo_tasks::value_impl]
-impl OptionPagesStructureVc {
-    #[turbo_tasks::function]
-    pub async fn routes_changed(self) -> Result<CompletionVc> {
-        if let Some(pages_structure) = *self.await? {
-            pages_structure.routes_changed().await?;
-        }
-        Ok(CompletionVc::new())
-    }
+/// Splits a route pattern (e.g. `/blog/[slug]`) into its segments.
+fn route_segments_from_pattern(pattern: &str) -> Vec<RouteSegment> {
+    pattern
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(RouteSegment::parse)
+        .collect()
 }
 
-/// Finds and returns the [PagesStructure] of the pages directory if existing.
-#[turbo_tasks::function]
-pub async fn find_pages_structure(
-    project_path: FileSystemPathVc,
-    server_root: FileSystemPathVc,
-    next_config: NextConfigVc,
-) -> Result<OptionPagesStructureVc> {
-    let pages = project_path.join("pages");
-    let src_pages = project_path.join("src/pages");
-    let pages_dir = if *pages.get_type().await? == FileSystemEntryType::Directory {
-        pages
-    } else if *src_pages.get_type().await? == FileSystemEntryType::Directory {
-        src_pages
-    } else {
-        return Ok(OptionPagesStructureVc::cell(None));
-    }
-    .resolve()
-    .await?;
+/// Returns true if a route pattern contains any dynamic segment.
+pub fn is_dynamic_route_pattern(pattern: &str) -> bool {
+    route_segments_from_pattern(pattern)
+        .iter()
+        .any(|segment| segment.param_name().is_some())
+}
 
-    Ok(OptionPagesStructureVc::cell(Some(get_pages_structure(
-        pages_dir,
-        server_root,
-        next_config.page_extensions(),
-    ))))
+/// Splits the path of a route url (e.g. `blog/[slug]/index.html`) into its
+/// segments.
+pub(crate) fn route_segments_for_url_path(path: &str) -> Vec<RouteSegment> {
+    let path = path
+        .strip_suffix("index.html")
+        .unwrap_or(path)
+        .trim_end_matches('/');
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(RouteSegment::parse)
+        .collect()
 }
 
-/// Parses a directory as pages directory and returns the [PagesStructure].
-#[turbo_tasks::function]
-pub fn get_pages_structure(
-    pages_dir: FileSystemPathVc,
-    server_root: FileSystemPathVc,
-    page_extensions: StringsVc,
-) -> PagesStructureVc {
-    get_pages_structure_for_directory(
-        pages_dir,
-        SpecificityVc::exact(),
-        0,
-        server_root,
-        server_root.join("api"),
-        page_extensions,
-    )
+/// Formats route segments as a route pattern. This is the single place that
+/// decides how routes are keyed in manifests: the index route is `/`, and
+/// every other route is its segments joined with a leading `/` (e.g. `/a/b`,
+/// `/[x]`, `/[...x]`).
+pub fn format_route_pattern(segments: &[RouteSegment]) -> String {
+    if segments.is_empty() {
+        return "/".to_string();
+    }
+    let mut pattern = String::new();
+    for segment in segments {
+        pattern.push('/');
+        pattern.push_str(&segment.to_string());
+    }
+    pattern
 }
 
-/// Handles a directory in the pages directory (or the pages directory itself).
-/// Calls itself recursively for sub directories or the
-/// [create_page_source_for_file] method for files.
-#[turbo_tasks::function]
-async fn get_pages_structure_for_directory(
-    input_dir: FileSystemPathVc,
-    specificity: SpecificityVc,
-    position: u32,
-    url: FileSystemPathVc,
-    server_api_path: FileSystemPathVc,
-    page_extensions: StringsVc,
-) -> Result<PagesStructureVc> {
-    let page_extensions_raw = &*page_extensions.await?;
+/// The value of a route param captured when matching a pathname.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TraceRawVcs)]
+pub enum RouteParamValue {
+    /// The segment matched by a `[param]` segment.
+    Single(String),
+    /// The segments matched by a `[...param]` or `[[...param]]` segment.
+    Multiple(Vec<String>),
+}
 
-    let mut children = vec![];
-    let mut items = vec![];
-    let dir_content = input_dir.read_dir().await?;
-    if let DirectoryContent::Entries(entries) = &*dir_content {
-        for (name, entry) in entries.iter() {
-            let specificity = if name.starts_with("[[") || name.starts_with("[...") {
-                specificity.with_catch_all(position)
-            } else if name.starts_with('[') {
-                specificity.with_dynamic_segment(position)
-            } else {
-                specificity
-            };
-            match entry {
-                DirectoryEntry::File(file) => {
-                    if let Some((basename, extension)) = name.rsplit_once('.') {
-                        if page_extensions_raw
-                            .iter()
-                            .any(|allowed| allowed == extension)
-                        {
-                            let url = if basename == "index" {
-                                url.join("index.html")
-                            } else {
-                                url.join(basename).join("index.html")
-                            };
-                            items.push((
-                                name,
-                                PagesStructureItemVc::new(
-                                    url,
-                                    specificity,
-                                    *file,
-                                    url.is_inside(server_api_path),
-                                ),
-                            ))
-                        }
+pub type RouteParams = IndexMap<String, RouteParamValue>;
+
+/// Matches a pathname (e.g. `/blog/hello`) against the segments of a route
+/// pattern and returns the captured params.
+pub fn match_route_segments(segments: &[RouteSegment], pathname: &str) -> Option<RouteParams> {
+    let mut parts = pathname
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .peekable();
+    let mut params = RouteParams::new();
+    for segment in segments {
+        match segment {
+            RouteSegment::Static(name) => {
+                if parts.next()? != name.as_str() {
+                    return None;
+                }
+            }
+            RouteSegment::Dynamic(param) => {
+                params.insert(
+                    param.clone(),
+                    RouteParamValue::Single(parts.next()?.to_string()),
+                );
+            }
+            RouteSegment::CatchAll(param) | RouteSegment::OptionalCatchAll(param) => {
+                let rest = parts.by_ref().map(ToString::to_string).collect::<Vec<_>>();
+                if rest.is_empty() {
+                    if matches!(segment, RouteSegment::CatchAll(_)) {
+                        return None;
                     }
+                } else {
+                    params.insert(param.clone(), RouteParamValue::Multiple(rest));
                 }
-                DirectoryEntry::Directory(dir) => {
-                    children.push((
-                        name,
-                        get_pages_structure_for_directory(
-                            *dir,
-                            specificity,
-                            position + 1,
-                            url.join(name),
-                            server_api_path,
-                            page_extensions,
-                        ),
-                    ));
+            }
+        }
+    }
+    parts.peek().is_none().then_some(params)
+}
+
+/// Ranks a route pattern for resolution. Lower ranks win: at the first
+/// differing segment, static segments beat dynamic ones, which beat
+/// catch-alls, which beat optional catch-alls.
+fn route_rank(segments: &[RouteSegment]) -> Vec<u8> {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            RouteSegment::Static(_) => 0,
+            RouteSegment::Dynamic(_) => 1,
+            RouteSegment::CatchAll(_) => 2,
+            RouteSegment::OptionalCatchAll(_) => 3,
+        })
+        .collect()
+}
+
+/// Escapes a static segment like Next.js does (with `escape-string-regexp`).
+fn escape_route_regex(segment: &str) -> String {
+    let mut escaped = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        match c {
+            '-' => escaped.push_str("\\x2d"),
+            '|' | '\\' | '{' | '}' | '(' | ')' | '[' | ']' | '^' | '$' | '+' | '*' | '?' | '.' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the name of the capture group of a param, like Next.js'
+/// `getSafeKeyFromSegment`: non-word characters are removed. Returns `None`
+/// if the name ends up empty, too long or starting with a digit.
+fn safe_route_key(param: &str) -> Option<String> {
+    let key = param
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect::<String>();
+    if key.is_empty() || key.len() > 30 || key.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(key)
+}
+
+/// Returns the `n`th generated route key: `a`, `b`, ..., `z`, `aa`, `ab`, ...
+fn generated_route_key(mut n: usize) -> String {
+    let mut key = String::new();
+    loop {
+        key.insert(0, (b'a' + (n % 26) as u8) as char);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    key
+}
+
+/// Returns the route keys of the params of a route, see [safe_route_key].
+/// Params without a safe key, or whose safe key an earlier param already
+/// uses, get a generated key (see [generated_route_key]) that no other param
+/// of the route uses, so the keys are unique.
+fn route_keys<'a>(params: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let safe_keys = params.into_iter().map(safe_route_key).collect::<Vec<_>>();
+    let mut used = safe_keys.iter().flatten().cloned().collect::<HashSet<_>>();
+    let mut assigned = HashSet::new();
+    let mut generated = 0;
+    safe_keys
+        .into_iter()
+        .map(|key| match key {
+            Some(key) if assigned.insert(key.clone()) => key,
+            _ => loop {
+                let key = generated_route_key(generated);
+                generated += 1;
+                if used.insert(key.clone()) {
+                    assigned.insert(key.clone());
+                    break key;
                 }
-                _ => {}
+            },
+        })
+        .collect()
+}
+
+/// Builds the `dynamicRoutes` entry of a route pattern, or returns `None` if
+/// the route isn't dynamic. The regexes follow Next.js' `getRouteRegex` and
+/// `getNamedRouteRegex`, including the optional trailing slash.
+pub fn dynamic_route_manifest_entry(pattern: &str) -> Option<DynamicRouteManifestEntry> {
+    let segments = route_segments_from_pattern(pattern);
+    if segments
+        .iter()
+        .all(|segment| matches!(segment, RouteSegment::Static(_)))
+    {
+        return None;
+    }
+    let (regex, named_regex, route_keys) = route_regexes(&segments);
+    Some(DynamicRouteManifestEntry {
+        page: pattern.to_string(),
+        regex,
+        route_keys,
+        named_regex,
+    })
+}
+
+/// Builds the regex, the named regex and the route keys of the segments of a
+/// route, like Next.js' `getRouteRegex` and `getNamedRouteRegex`.
+fn route_regexes(segments: &[RouteSegment]) -> (String, String, IndexMap<String, String>) {
+    if segments.is_empty() {
+        return (
+            "^/(?:/)?$".to_string(),
+            "^/(?:/)?$".to_string(),
+            IndexMap::new(),
+        );
+    }
+    let mut regex = String::new();
+    let mut named_regex = String::new();
+    let mut keys = route_keys(segments.iter().filter_map(|segment| match segment {
+        RouteSegment::Static(_) => None,
+        RouteSegment::Dynamic(param)
+        | RouteSegment::CatchAll(param)
+        | RouteSegment::OptionalCatchAll(param) => Some(param.as_str()),
+    }))
+    .into_iter();
+    let mut route_keys = IndexMap::new();
+    for segment in segments {
+        let (group, param) = match segment {
+            RouteSegment::Static(name) => {
+                let escaped = format!("/{}", escape_route_regex(name));
+                regex.push_str(&escaped);
+                named_regex.push_str(&escaped);
+                continue;
             }
+            RouteSegment::Dynamic(param) => ("[^/]+?", param),
+            RouteSegment::CatchAll(param) | RouteSegment::OptionalCatchAll(param) => (".+?", param),
+        };
+        let key = keys.next().unwrap();
+        if matches!(segment, RouteSegment::OptionalCatchAll(_)) {
+            regex.push_str(&format!("(?:/({group}))?"));
+            named_regex.push_str(&format!("(?:/(?<{key}>{group}))?"));
+        } else {
+            regex.push_str(&format!("/({group})"));
+            named_regex.push_str(&format!("/(?<{key}>{group})"));
         }
+        route_keys.insert(key, param.clone());
     }
+    (
+        format!("^{regex}(?:/)?$"),
+        format!("^{named_regex}(?:/)?$"),
+        route_keys,
+    )
+}
 
-    // Ensure deterministic order since read_dir is not deterministic
-    items.sort_by_key(|(k, _)| *k);
+/// How [PagesStructureVc::sorted_routes] orders routes.
+#[turbo_tasks::value(serialization = "auto_for_input")]
+#[derive(Debug, Copy, Clone, Default, Hash, PartialOrd, Ord)]
+pub enum RouteOrdering {
+    /// By specificity, the order routes are resolved in: at the first
+    /// differing segment static segments come first, then dynamic segments,
+    /// catch-alls and optional catch-alls. Ties are sorted by pattern.
+    #[default]
+    Native,
+    /// The order of `getSortedRoutes` in the webpack pipeline, see
+    /// [get_sorted_routes]. It keeps each subtree together, so it differs
+    /// from [RouteOrdering::Native] for routes of different depths.
+    Webpack,
+}
 
-    // Ensure deterministic order since read_dir is not deterministic
-    children.sort_by_key(|(k, _)| *k);
+/// Sorts route patterns in the given order. Only the webpack ordering fails,
+/// for route sets that `getSortedRoutes` rejects.
+pub fn sort_routes<'a>(
+    patterns: impl IntoIterator<Item = &'a str>,
+    ordering: RouteOrdering,
+) -> Result<Vec<String>> {
+    match ordering {
+        RouteOrdering::Native => {
+            let mut patterns = patterns
+                .into_iter()
+                .map(|pattern| (route_rank(&route_segments_from_pattern(pattern)), pattern))
+                .collect::<Vec<_>>();
+            patterns.sort();
+            Ok(patterns
+                .into_iter()
+                .map(|(_, pattern)| pattern.to_string())
+                .collect())
+        }
+        RouteOrdering::Webpack => get_sorted_routes(patterns),
+    }
+}
 
-    Ok(PagesStructure {
-        directory: input_dir,
-        items: items.into_iter().map(|(_, v)| v).collect(),
-        children: children.into_iter().map(|(_, v)| v).collect(),
+/// How the entries of the manifests are ordered.
+#[turbo_tasks::value(serialization = "auto_for_input")]
+#[derive(Debug, Copy, Clone, Default, Hash, PartialOrd, Ord)]
+pub enum ManifestOrder {
+    /// The order routes are matched in, like Next.js writes its manifests:
+    /// more specific routes first, ties sorted by pattern.
+    #[default]
+    Specificity,
+    /// Sorted by route pattern. This is easier to diff against the output of
+    /// other tools.
+    Alphabetical,
+}
+
+/// Sorts manifest entries by their route pattern in the given order.
+fn sort_manifest_entries<T>(entries: &mut [T], pattern: impl Fn(&T) -> &str, order: ManifestOrder) {
+    match order {
+        ManifestOrder::Specificity => entries.sort_by_cached_key(|entry| {
+            let pattern = pattern(entry);
+            (
+                route_rank(&route_segments_from_pattern(pattern)),
+                pattern.to_string(),
+            )
+        }),
+        ManifestOrder::Alphabetical => entries.sort_by(|a, b| pattern(a).cmp(pattern(b))),
+    }
+}
+
+/// Builds the `dynamicRoutes` section of `routes-manifest.json`, in the
+/// given order.
+fn dynamic_routes_manifest<'a>(
+    patterns: impl IntoIterator<Item = &'a str>,
+    order: ManifestOrder,
+) -> Vec<DynamicRouteManifestEntry> {
+    let mut entries = patterns
+        .into_iter()
+        .filter_map(dynamic_route_manifest_entry)
+        .collect::<Vec<_>>();
+    sort_manifest_entries(&mut entries, |entry| entry.page.as_str(), order);
+    entries
+}
+
+fn provenance_name(provenance: &PagesStructureItemProvenance) -> &'static str {
+    match provenance {
+        PagesStructureItemProvenance::Scanned { .. } => "scanned",
+        PagesStructureItemProvenance::Virtual { replaced: None, .. } => "virtual",
+        PagesStructureItemProvenance::Virtual {
+            replaced: Some(_), ..
+        } => "virtual, replaces scanned",
+    }
+}
+
+/// Renders a route of [PagesStructureVc::print_tree]. The extension is the
+/// page extension the file matched (see [PageExtensions::matches]), so
+/// compound extensions like `.page.tsx` are shown completely and dots in
+/// params (like `[...slug].tsx`) aren't mistaken for one.
+fn tree_leaf_line(
+    pattern: &str,
+    kind: &str,
+    provenance: &str,
+    file_name: &str,
+    page_extensions: &PageExtensions,
+    verbose: bool,
+) -> String {
+    if !verbose {
+        return pattern.to_string();
+    }
+    match page_extensions.matches(file_name) {
+        Some((_, extension)) => format!("{pattern} ({kind}, {provenance}, .{extension})"),
+        None => format!("{pattern} ({kind}, {provenance})"),
+    }
+}
+
+/// Renders the routes of [PagesStructureVc::route_report]. Takes the
+/// directory of the file (relative to the pages directory), the pattern and
+/// the kind of every route. Directories are sorted by name and list their
+/// own routes before their subdirectories, and the columns are aligned.
+fn format_route_report(root_name: &str, mut routes: Vec<(String, String, String)>) -> String {
+    let directory_names = |directory: &str| {
+        directory
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+    };
+    routes.sort_by_cached_key(|(directory, pattern, _)| {
+        (directory_names(directory), pattern.clone())
+    });
+
+    let mut lines = vec![(0, format!("{root_name}/"), None)];
+    let mut current: Vec<String> = vec![];
+    for (directory, pattern, kind) in &routes {
+        let names = directory_names(directory);
+        let common = current
+            .iter()
+            .zip(&names)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for (depth, name) in names.iter().enumerate().skip(common) {
+            lines.push((depth + 1, format!("{name}/"), None));
+        }
+        let dynamic = if is_dynamic_route_pattern(pattern) {
+            "dynamic"
+        } else {
+            "static"
+        };
+        lines.push((names.len() + 1, pattern.clone(), Some((kind, dynamic))));
+        current = names;
+    }
+
+    let width = lines
+        .iter()
+        .filter(|(_, _, columns)| columns.is_some())
+        .map(|(depth, text, _)| depth * 2 + text.len())
+        .max()
+        .unwrap_or(0);
+    let kind_width = routes
+        .iter()
+        .map(|(_, _, kind)| kind.len())
+        .max()
+        .unwrap_or(0);
+    let mut report = String::new();
+    for (depth, text, columns) in lines {
+        let indent = depth * 2;
+        match columns {
+            Some((kind, dynamic)) => writeln!(
+                report,
+                "{:indent$}{text:<text_width$}  {kind:<kind_width$}  {dynamic}",
+                "",
+                text_width = width - indent,
+            ),
+            None => writeln!(report, "{:indent$}{text}", ""),
+        }
+        .unwrap();
+    }
+    report
+}
+
+/// Returns the params of a route pattern that use one of the `reserved`
+/// names.
+fn reserved_params(pattern: &str, reserved: &[String]) -> Vec<String> {
+    dynamic_params(pattern)
+        .into_iter()
+        .map(|param| param.name)
+        .filter(|name| reserved.contains(name))
+        .collect()
+}
+
+/// A route as compared by [PagesStructureVc::structurally_equal]: its pattern,
+/// kind, specificity rank and file path relative to the pages directory.
+type StructuralRoute = (String, String, Vec<u8>, String);
+
+/// Returns true if both lists have the same routes in any order.
+fn same_routes(mut a: Vec<StructuralRoute>, mut b: Vec<StructuralRoute>) -> bool {
+    a.sort();
+    b.sort();
+    a == b
+}
+
+/// Returns the indices of the routes other than the one at `index`, sorted
+/// by pattern.
+fn sibling_indices(patterns: &[String], index: usize) -> Vec<usize> {
+    let mut siblings = (0..patterns.len())
+        .filter(|sibling| *sibling != index)
+        .collect::<Vec<_>>();
+    siblings.sort_by(|a, b| patterns[*a].cmp(&patterns[*b]));
+    siblings
+}
+
+/// Returns the names of the directories leading from a structure serving
+/// `root_prefix` to the one serving `prefix` (like `["blog"]` from `/` to
+/// `/blog`), or None if `prefix` isn't below `root_prefix`.
+fn subtree_directories<'a>(root_prefix: &str, prefix: &'a str) -> Option<Vec<&'a str>> {
+    let mut segments = prefix.split('/').filter(|segment| !segment.is_empty());
+    for root_segment in root_prefix.split('/').filter(|segment| !segment.is_empty()) {
+        if segments.next() != Some(root_segment) {
+            return None;
+        }
+    }
+    Some(segments.collect())
+}
+
+/// Returns the index of the route pattern serving `/`: exactly `/`, or else,
+/// with `fallback_to_optional_catch_all`, a root optional catch-all.
+fn find_home_route<'a>(
+    patterns: impl IntoIterator<Item = &'a str>,
+    fallback_to_optional_catch_all: bool,
+) -> Option<usize> {
+    let mut optional_catch_all = None;
+    for (index, pattern) in patterns.into_iter().enumerate() {
+        match route_segments_from_pattern(pattern).as_slice() {
+            [] => return Some(index),
+            [RouteSegment::OptionalCatchAll(_)] if fallback_to_optional_catch_all => {
+                optional_catch_all = optional_catch_all.or(Some(index))
+            }
+            _ => {}
+        }
+    }
+    optional_catch_all
+}
+
+/// Resolves a pathname to the route pattern that serves it, following the
+/// Next.js route priority.
+pub fn resolve_route<'a>(
+    patterns: impl IntoIterator<Item = &'a str>,
+    pathname: &str,
+) -> Option<(&'a str, RouteParams)> {
+    patterns
+        .into_iter()
+        .filter_map(|pattern| {
+            let segments = route_segments_from_pattern(pattern);
+            let params = match_route_segments(&segments, pathname)?;
+            Some((route_rank(&segments), pattern, params))
+        })
+        .min_by(|(a, ..), (b, ..)| a.cmp(b))
+        .map(|(_, pattern, params)| (pattern, params))
+}
+
+/// Returns every url a route is reachable at: under the `base_path`, without
+/// a locale prefix and then with each of the `locales` (except for API
+/// routes, which aren't localized). An optional catch-all is also reachable
+/// at its parent path, which follows the route's own url. Each url comes in
+/// the canonical form for `trailing_slash` first, followed by the other form,
+/// which redirects to it.
+pub fn effective_urls(
+    pattern: &str,
+    is_api: bool,
+    base_path: &str,
+    i18n: Option<(&[String], &str)>,
+    trailing_slash: bool,
+) -> Vec<String> {
+    let mut paths = vec![pattern.to_string()];
+    let segments = route_segments_from_pattern(pattern);
+    if let Some((RouteSegment::OptionalCatchAll(_), parent)) = segments.split_last() {
+        paths.push(format_route_pattern(parent));
+    }
+    let mut prefixes = vec![String::new()];
+    if let (Some((locales, _)), false) = (i18n, is_api) {
+        prefixes.extend(locales.iter().map(|locale| format!("/{locale}")));
+    }
+
+    let mut urls = Vec::<String>::new();
+    for prefix in &prefixes {
+        for path in &paths {
+            let path = if path == "/" { "" } else { path.as_str() };
+            let url = format!("{base_path}{prefix}{path}");
+            let (canonical, other) = match (url.is_empty(), trailing_slash) {
+                (true, _) => ("/".to_string(), "/".to_string()),
+                (false, true) => (format!("{url}/"), url),
+                (false, false) => (url.clone(), format!("{url}/")),
+            };
+            for url in [canonical, other] {
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        }
+    }
+    urls
+}
+
+/// Like [resolve_route], but strips a leading locale of `context` first (see
+/// [RouteRuleContext::strip_locale]) and also returns the detected locale,
+/// which is the default locale when the pathname has no locale prefix.
+pub fn resolve_route_i18n<'a>(
+    patterns: impl IntoIterator<Item = &'a str>,
+    pathname: &str,
+    context: &RouteRuleContext,
+) -> Option<(&'a str, RouteParams, Option<String>)> {
+    let (locale, pathname) = context.strip_locale(pathname);
+    let (pattern, params) = resolve_route(patterns, pathname)?;
+    Some((pattern, params, locale.map(ToString::to_string)))
+}
+
+/// Rewrites a pathname with the first rule of `rules` that applies to it.
+/// Rules with `has` or `missing` conditions depend on the request and are
+/// skipped, and so are external destinations. The query and hash of the
+/// destination are dropped.
+fn apply_rewrites(rules: &[Rewrite], pathname: &str, context: &RouteRuleContext) -> Option<String> {
+    rules
+        .iter()
+        .filter(|rule| rule.has.is_none() && rule.missing.is_none())
+        .find_map(|rule| rewrite_pathname(rule, pathname, context))
+        .filter(|destination| destination.starts_with('/'))
+        .map(|destination| {
+            let end = destination.find(['?', '#']).unwrap_or(destination.len());
+            destination[..end].to_string()
+        })
+}
+
+/// Like [resolve_route], but applies `rewrites` in the order Next.js does:
+/// `beforeFiles` before any route is matched, `afterFiles` when no static
+/// route matches, and `fallback` when no route matches at all. Also returns
+/// the pathname the route was resolved for.
+///
+/// Unlike for [resolve_route], `pathname` is the requested pathname, including
+/// the base path and locale from `context`. Rewrites are matched the way
+/// Next.js matches them, so rules with `basePath: false` or `locale: false`
+/// see the prefixes, and routes are resolved after stripping them from the
+/// rewritten pathname.
+pub fn resolve_route_with_rewrites<'a>(
+    patterns: &[&'a str],
+    pathname: &str,
+    rewrites: &Rewrites,
+    context: &RouteRuleContext,
+) -> Option<(&'a str, RouteParams, String)> {
+    let resolve = |pathname: String| {
+        let (_, pathname) = context.strip_locale(context.strip_base_path(&pathname)?);
+        let (pattern, params) = resolve_route(patterns.iter().copied(), pathname)?;
+        Some((pattern, params, pathname.to_string()))
+    };
+
+    let pathname = apply_rewrites(&rewrites.before_files, pathname, context)
+        .unwrap_or_else(|| pathname.to_string());
+    let resolved = resolve(pathname.clone());
+    let is_static = matches!(&resolved, Some((pattern, ..)) if !is_dynamic_route_pattern(pattern));
+    if is_static {
+        return resolved;
+    }
+    if let Some(resolved) =
+        apply_rewrites(&rewrites.after_files, &pathname, context).and_then(resolve)
+    {
+        return Some(resolved);
+    }
+    if resolved.is_some() {
+        return resolved;
+    }
+    apply_rewrites(&rewrites.fallback, &pathname, context).and_then(resolve)
+}
+
+/// The special pages that are compiled first when prewarming, since every
+/// page is rendered with them.
+const PREWARM_SPECIAL_PAGES: [&str; 2] = ["/_app", "/_document"];
+
+/// Selects up to `count` routes to compile ahead of the first request and
+/// returns their indices. `_app` and `_document` come first, then the index
+/// route, followed by the other static page routes in the given order. API
+/// routes, dynamic routes and the other special pages (like `_error`) are
+/// skipped.
+fn select_prewarm_routes<'a>(
+    routes: impl IntoIterator<Item = (&'a str, bool)>,
+    count: usize,
+) -> Vec<usize> {
+    let mut candidates = routes
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, (pattern, is_api))| {
+            let rank = if PREWARM_SPECIAL_PAGES.contains(&pattern) {
+                0
+            } else if is_api || pattern.starts_with("/_") || is_dynamic_route_pattern(pattern) {
+                return None;
+            } else if pattern == "/" {
+                1
+            } else {
+                2
+            };
+            Some((rank, index))
+        })
+        .collect::<Vec<_>>();
+    // The sort is stable, so routes of the same rank keep their order.
+    candidates.sort_by_key(|(rank, _)| *rank);
+    candidates
+        .into_iter()
+        .take(count)
+        .map(|(_, index)| index)
+        .collect()
+}
+
+/// Groups routes by the first segment of their url path (e.g.
+/// `users/[id]/index.html`), keeping the order of the routes. Routes without
+/// a segment are grouped under `""`.
+fn group_api_routes<'a, T>(
+    routes: impl IntoIterator<Item = (&'a str, T)>,
+) -> IndexMap<String, Vec<T>> {
+    let mut groups = IndexMap::<String, Vec<T>>::new();
+    for (path, route) in routes {
+        let group = route_segments_for_url_path(path)
+            .first()
+            .map(|segment| segment.to_string())
+            .unwrap_or_default();
+        groups.entry(group).or_default().push(route);
+    }
+    groups
+}
+
+/// Describes a param of a dynamic route.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TraceRawVcs)]
+pub struct ParamInfo {
+    pub name: String,
+    /// True for `[...name]` and `[[...name]]`.
+    pub catch_all: bool,
+    /// True for `[[...name]]`.
+    pub optional: bool,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct ParamInfos(Vec<ParamInfo>);
+
+/// Returns the params of a route pattern in order.
+pub fn dynamic_params(pattern: &str) -> Vec<ParamInfo> {
+    dynamic_params_for_segments(&route_segments_from_pattern(pattern))
+}
+
+/// Returns the params of the segments of a route in order.
+fn dynamic_params_for_segments(segments: &[RouteSegment]) -> Vec<ParamInfo> {
+    segments
+        .iter()
+        .filter_map(|segment| {
+            let (catch_all, optional) = match segment {
+                RouteSegment::Static(_) => return None,
+                RouteSegment::Dynamic(_) => (false, false),
+                RouteSegment::CatchAll(_) => (true, false),
+                RouteSegment::OptionalCatchAll(_) => (true, true),
+            };
+            Some(ParamInfo {
+                name: segment.param_name()?.to_string(),
+                catch_all,
+                optional,
+            })
+        })
+        .collect()
+}
+
+/// Generates TypeScript declarations of the params of every dynamic route,
+/// keyed by route pattern. Routes are sorted to keep the output stable.
+fn route_params_type_declarations<'a>(patterns: impl IntoIterator<Item = &'a str>) -> String {
+    let mut routes = BTreeMap::new();
+    for pattern in patterns {
+        let params = dynamic_params(pattern)
+            .into_iter()
+            .map(|param| {
+                let name = ts_property_name(&param.name);
+                match (param.catch_all, param.optional) {
+                    (false, _) => format!("{name}: string"),
+                    (true, false) => format!("{name}: string[]"),
+                    (true, true) => format!("{name}: string[] | undefined"),
+                }
+            })
+            .collect::<Vec<_>>();
+        if !params.is_empty() {
+            routes.insert(pattern, params);
+        }
+    }
+
+    let mut declarations = "// This file is generated by Next.js and should not be \
+                            edited.\n\nexport interface RouteParams {\n"
+        .to_string();
+    for (pattern, params) in routes {
+        writeln!(
+            declarations,
+            "  {}: {{ {} }};",
+            ts_string_literal(pattern),
+            params.join("; ")
+        )
+        .unwrap();
+    }
+    declarations.push_str("}\n");
+    declarations
+}
+
+/// Returns `value` as a TypeScript string literal. JSON strings are valid
+/// TypeScript string literals.
+fn ts_string_literal(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Returns `name` as a TypeScript property name, quoting it unless it's an
+/// identifier.
+fn ts_property_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if is_identifier {
+        name.to_string()
+    } else {
+        ts_string_literal(name)
+    }
+}
+
+/// A node of a [RouteTrie], keyed by route segment (e.g. `blog`, `[slug]`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, TraceRawVcs)]
+pub struct RouteTrieNode {
+    /// The pattern of the route ending at this node.
+    route: Option<String>,
+    children: BTreeMap<String, RouteTrieNode>,
+}
+
+impl RouteTrieNode {
+    fn insert(&mut self, segments: &[RouteSegment], pattern: String) {
+        let mut node = self;
+        for segment in segments {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+        node.route = Some(pattern);
+    }
+
+    /// Returns the node at the end of the given route segments, which are
+    /// looked up literally (`[slug]` only matches a `[slug]` segment).
+    fn find(&self, prefix: &str) -> Option<&RouteTrieNode> {
+        prefix
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .try_fold(self, |node, segment| node.children.get(segment))
+    }
+
+    fn collect_routes<'a>(&'a self, routes: &mut Vec<&'a str>) {
+        routes.extend(self.route.as_deref());
+        for child in self.children.values() {
+            child.collect_routes(routes);
+        }
+    }
+
+    /// Matches the remaining pathname segments, trying static segments first,
+    /// then dynamic segments, catch-alls and optional catch-alls.
+    fn match_segments(&self, parts: &[&str]) -> Option<&str> {
+        let mut children = self
+            .children
+            .iter()
+            .map(|(key, child)| (RouteSegment::parse(key), child))
+            .collect::<Vec<_>>();
+        children.sort_by_key(|(segment, _)| route_rank(std::slice::from_ref(segment)));
+
+        let Some((first, rest)) = parts.split_first() else {
+            return self.route.as_deref().or_else(|| {
+                children.iter().find_map(|(segment, child)| {
+                    child
+                        .route
+                        .as_deref()
+                        .filter(|_| matches!(segment, RouteSegment::OptionalCatchAll(_)))
+                })
+            });
+        };
+        children.iter().find_map(|(segment, child)| match segment {
+            RouteSegment::Static(name) if name.as_str() == *first => child.match_segments(rest),
+            RouteSegment::Static(_) => None,
+            RouteSegment::Dynamic(_) => child.match_segments(rest),
+            RouteSegment::CatchAll(_) | RouteSegment::OptionalCatchAll(_) => child.route.as_deref(),
+        })
+    }
+}
+
+/// The routes of a [PagesStructure] as a trie of route segments, for fast
+/// prefix queries.
+#[turbo_tasks::value]
+#[derive(Debug, Default)]
+pub struct RouteTrie {
+    root: RouteTrieNode,
+}
+
+impl RouteTrie {
+    fn from_patterns<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut trie = RouteTrie::default();
+        for pattern in patterns {
+            trie.root
+                .insert(&route_segments_from_pattern(pattern), pattern.to_string());
+        }
+        trie
+    }
+
+    /// Returns true if any route starts with the segments of `prefix`.
+    pub fn has_routes_under(&self, prefix: &str) -> bool {
+        self.root.find(prefix).map_or(false, |node| {
+            node.route.is_some() || !node.children.is_empty()
+        })
+    }
+
+    /// Returns the patterns of all routes starting with the segments of
+    /// `prefix`, in segment order.
+    pub fn routes_under(&self, prefix: &str) -> Vec<&str> {
+        let mut routes = vec![];
+        if let Some(node) = self.root.find(prefix) {
+            node.collect_routes(&mut routes);
+        }
+        routes
+    }
+
+    /// Returns the pattern of the route serving `pathname`.
+    pub fn match_pathname(&self, pathname: &str) -> Option<&str> {
+        let parts = pathname
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        self.root.match_segments(&parts)
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl RouteTrieVc {
+    #[turbo_tasks::function]
+    pub async fn has_routes_under(self, prefix: String) -> Result<BoolVc> {
+        Ok(BoolVc::cell(self.await?.has_routes_under(&prefix)))
+    }
+
+    #[turbo_tasks::function]
+    pub async fn match_pathname(self, pathname: String) -> Result<OptionStringVc> {
+        Ok(OptionStringVc::cell(
+            self.await?
+                .match_pathname(&pathname)
+                .map(ToString::to_string),
+        ))
+    }
+}
+
+/// Counts of the routes in a [PagesStructure].
+#[turbo_tasks::value]
+#[derive(Debug, Default)]
+pub struct RouteStats {
+    pub total: u32,
+    pub dynamic: u32,
+    /// The greatest number of segments of any route.
+    pub max_depth: u32,
+}
+
+impl RouteStats {
+    fn from_patterns<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut stats = RouteStats::default();
+        for pattern in patterns {
+            let segments = route_segments_from_pattern(pattern);
+            stats.total += 1;
+            if segments
+                .iter()
+                .any(|segment| segment.param_name().is_some())
+            {
+                stats.dynamic += 1;
+            }
+            stats.max_depth = stats.max_depth.max(segments.len() as u32);
+        }
+        stats
+    }
+}
+
+/// How many directories a scan of the pages directory read. A large count with
+/// few levels points to a wide tree, few directories with many levels to a
+/// deep one.
+#[turbo_tasks::value]
+#[derive(Debug, Default)]
+pub struct PagesScanStats {
+    /// The number of directories read, including the pages directory itself.
+    pub directories: u32,
+    /// The number of directory levels, 1 if only the pages directory was read.
+    pub levels: u32,
+}
+
+impl PagesScanStats {
+    fn from_directory_depths(depths: impl IntoIterator<Item = u32>) -> Self {
+        let mut stats = PagesScanStats::default();
+        for depth in depths {
+            stats.directories += 1;
+            stats.levels = stats.levels.max(depth + 1);
+        }
+        stats
+    }
+}
+
+/// A route of the pages directory, flattened out of the [PagesStructure].
+#[turbo_tasks::value(shared)]
+pub struct RouteEntry {
+    pub pattern: String,
+    pub is_api: bool,
+    /// The tag of a route of a custom kind, which embedders serve with their
+    /// own content source. `None` for pages and API routes.
+    pub kind: Option<String>,
+    pub file: FileSystemPathVc,
+    pub specificity: SpecificityVc,
+}
+
+impl RouteEntry {
+    /// Returns true if the route is a page, i.e. neither an API route nor a
+    /// route of a custom kind.
+    pub fn is_page(&self) -> bool {
+        !self.is_api && self.kind.is_none()
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl RouteEntryVc {
+    /// Returns the file globs from `experimental.outputFileTracingIncludes`
+    /// and `experimental.outputFileTracingExcludes` that apply to this route's
+    /// trace.
+    #[turbo_tasks::function]
+    pub async fn file_tracing(self, next_config: NextConfigVc) -> Result<RouteFileTracingVc> {
+        let pattern = &self.await?.pattern;
+        Ok(next_config
+            .output_file_tracing()
+            .await?
+            .for_route(pattern)?
+            .cell())
+    }
+
+    /// Applies this route's [file tracing](Self::file_tracing) config to the
+    /// files traced for it (relative to the project), removing the excluded
+    /// ones.
+    #[turbo_tasks::function]
+    pub async fn trace_files(
+        self,
+        next_config: NextConfigVc,
+        traced: StringsVc,
+    ) -> Result<StringsVc> {
+        let pattern = &self.await?.pattern;
+        let file_tracing = self.file_tracing(next_config).await?;
+        Ok(StringsVc::cell(
+            file_tracing.apply(pattern, &traced.await?)?,
+        ))
+    }
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct RouteEntries(Vec<RouteEntryVc>);
+
+/// Special files of a [PagesStructure] with their kind.
+#[turbo_tasks::value(transparent)]
+pub struct SpecialFiles(Vec<(FileSystemPathVc, SpecialKind)>);
+
+/// The dynamic routes of a [PagesStructure] with their params.
+#[turbo_tasks::value(transparent)]
+pub struct DynamicRoutes(Vec<(String, Vec<ParamInfo>)>);
+
+/// API routes keyed by their first segment below the API root (e.g. `users`
+/// for `/api/users/[id]`). The API root's own index route is keyed by `""`.
+#[turbo_tasks::value(transparent)]
+pub struct ApiRouteGroups(IndexMap<String, Vec<PagesStructureItemVc>>);
+
+/// A route of the pages directory matched by a pathname.
+#[turbo_tasks::value(shared)]
+pub struct ResolvedRoute {
+    pub entry: RouteEntryVc,
+    pub params: RouteParams,
+    /// The locale detected from the pathname, if i18n is configured.
+    pub locale: Option<String>,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionResolvedRoute(Option<ResolvedRouteVc>);
+
+/// Pairs of route patterns.
+#[turbo_tasks::value(transparent)]
+pub struct RoutePairs(Vec<(String, String)>);
+
+/// Maps route patterns to the files serving them.
+#[turbo_tasks::value(transparent)]
+pub struct PagesManifest(IndexMap<String, FileSystemPathVc>);
+
+/// Maps route patterns to the paths of the files serving them, relative to
+/// the pages directory and with forward slashes. Unlike [PagesManifest], it is
+/// the same on every machine, so it can be checked into source control.
+#[turbo_tasks::value(transparent)]
+pub struct PortablePagesManifest(IndexMap<String, String>);
+
+#[turbo_tasks::value_impl]
+impl PortablePagesManifestVc {
+    #[turbo_tasks::function]
+    pub async fn to_json(self) -> Result<StringVc> {
+        Ok(StringVc::cell(serde_json::to_string_pretty(&*self.await?)?))
+    }
+}
+
+/// An entry of the `dynamicRoutes` section of Next.js' `routes-manifest.json`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TraceRawVcs)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicRouteManifestEntry {
+    pub page: String,
+    pub regex: String,
+    /// Maps the names of the capture groups of `named_regex` to the params.
+    pub route_keys: IndexMap<String, String>,
+    pub named_regex: String,
+}
+
+/// The `dynamicRoutes` section of Next.js' `routes-manifest.json`.
+#[turbo_tasks::value(transparent)]
+pub struct DynamicRoutesManifest(Vec<DynamicRouteManifestEntry>);
+
+/// A (sub)directory in the pages directory with all analyzed routes and
+/// folders.
+#[turbo_tasks::value]
+pub struct PagesStructure {
+    pub directory: FileSystemPathVc,
+    /// The root that the routes of the whole structure are served from.
+    pub router_root: FileSystemPathVc,
+    pub items: Vec<PagesStructureItemVc>,
+    /// The legacy `_middleware` files of this directory. They aren't routes.
+    pub middleware: Vec<FileSystemPathVc>,
+    pub children: Vec<PagesStructureVc>,
+}
+
+#[turbo_tasks::value_impl]
+impl PagesStructureVc {
+    /// Returns the directory of this structure.
+    #[turbo_tasks::function]
+    pub async fn directory(self) -> Result<FileSystemPathVc> {
+        Ok(self.await?.directory)
+    }
+
+    /// Returns the root that the routes of this structure are served from.
+    #[turbo_tasks::function]
+    pub async fn router_root(self) -> Result<FileSystemPathVc> {
+        Ok(self.await?.router_root)
+    }
+
+    /// Returns true if this directory or any directory below it contains a
+    /// route. Stops at the first route found.
+    #[turbo_tasks::function]
+    pub async fn has_routes(self) -> Result<BoolVc> {
+        let this = self.await?;
+        if !this.items.is_empty() {
+            return Ok(BoolVc::cell(true));
+        }
+        for child in this.children.iter() {
+            if *child.has_routes().await? {
+                return Ok(BoolVc::cell(true));
+            }
+        }
+        Ok(BoolVc::cell(false))
+    }
+
+    /// Returns true if this directory or any directory below it contains an
+    /// API route. Stops at the first API route found.
+    #[turbo_tasks::function]
+    pub async fn has_api_routes(self) -> Result<BoolVc> {
+        let this = self.await?;
+        for item in this.items.iter() {
+            if item.await?.is_api() {
+                return Ok(BoolVc::cell(true));
+            }
+        }
+        for child in this.children.iter() {
+            if *child.has_api_routes().await? {
+                return Ok(BoolVc::cell(true));
+            }
+        }
+        Ok(BoolVc::cell(false))
+    }
+
+    /// Returns the special files at any level of the tree, in traversal
+    /// order: `_app`, `_document` and `_error` at the root, and legacy
+    /// `_middleware` files in any directory.
+    #[turbo_tasks::function]
+    pub async fn all_special_files(self) -> Result<SpecialFilesVc> {
+        let mut special_files = vec![];
+        let mut queue = vec![(self, true)];
+        while let Some((structure, is_root)) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                let file = item.await?.file();
+                let file_path = file.await?;
+                let name = file_path.path.rsplit('/').next().unwrap_or(&file_path.path);
+                let basename = name.rsplit_once('.').map_or(name, |(basename, _)| basename);
+                if let Some(kind) = special_kind(basename, is_root) {
+                    special_files.push((file, kind));
+                }
+            }
+            special_files.extend(
+                structure
+                    .middleware
+                    .iter()
+                    .map(|file| (*file, SpecialKind::Middleware)),
+            );
+            queue.extend(structure.children.iter().map(|child| (*child, false)));
+        }
+        Ok(SpecialFilesVc::cell(special_files))
+    }
+
+    /// Returns all routes of the whole tree, in traversal order.
+    #[turbo_tasks::function]
+    pub async fn route_entries(self) -> Result<RouteEntriesVc> {
+        let mut entries = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                let pattern = item.route_pattern().await?.clone_value();
+                let item = item.await?;
+                entries.push(
+                    RouteEntry {
+                        pattern,
+                        is_api: item.is_api(),
+                        kind: item.custom_kind().map(ToString::to_string),
+                        file: item.file(),
+                        specificity: item.specificity(),
+                    }
+                    .cell(),
+                );
+            }
+            queue.extend(structure.children.iter().rev().copied());
+        }
+        Ok(RouteEntriesVc::cell(entries))
+    }
+
+    /// Returns the patterns of all routes of the whole tree, in traversal
+    /// order, see [PagesStructureVc::route_entries].
+    #[turbo_tasks::function]
+    pub async fn route_patterns(self) -> Result<StringsVc> {
+        let mut patterns = vec![];
+        for entry in self.route_entries().await?.iter() {
+            patterns.push(entry.await?.pattern.clone());
+        }
+        Ok(StringsVc::cell(patterns))
+    }
+
+    /// Returns the `/` route, i.e. the `index` page at the root. Unlike
+    /// [`home_item`](Self::home_item), this doesn't fall back to a root
+    /// optional catch-all.
+    #[turbo_tasks::function]
+    pub async fn home_route(self) -> Result<OptionPagesStructureItemVc> {
+        let mut items = vec![];
+        let mut patterns = vec![];
+        for item in self.await?.items.iter() {
+            if item.await?.is_page() {
+                patterns.push(item.route_pattern().await?.clone_value());
+                items.push(*item);
+            }
+        }
+        Ok(OptionPagesStructureItemVc::cell(
+            find_home_route(patterns.iter().map(|pattern| pattern.as_str()), false)
+                .map(|index| items[index]),
+        ))
+    }
+
+    /// Returns the page serving `/`: `index` at the root, or else a root
+    /// optional catch-all like `[[...slug]]`.
+    #[turbo_tasks::function]
+    pub async fn home_item(self) -> Result<OptionPagesStructureItemVc> {
+        let mut items = vec![];
+        let mut patterns = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                if item.await?.is_page() {
+                    patterns.push(item.route_pattern().await?.clone_value());
+                    items.push(*item);
+                }
+            }
+            queue.extend(structure.children.iter().rev().copied());
+        }
+        Ok(OptionPagesStructureItemVc::cell(
+            find_home_route(patterns.iter().map(|pattern| pattern.as_str()), true)
+                .map(|index| items[index]),
+        ))
+    }
+
+    /// Resolves a pathname to the route serving it.
+    #[turbo_tasks::function]
+    pub async fn resolve_route(self, pathname: String) -> Result<OptionResolvedRouteVc> {
+        self.resolve_route_with_locale(&pathname, &RouteRuleContext::default())
+            .await
+    }
+
+    /// Resolves a pathname to the route serving it, after stripping a leading
+    /// locale configured in `i18n.locales`. The detected locale falls back to
+    /// the default locale.
+    #[turbo_tasks::function]
+    pub async fn resolve_route_i18n(
+        self,
+        pathname: String,
+        next_config: NextConfigVc,
+    ) -> Result<OptionResolvedRouteVc> {
+        let context = RouteRuleContext::from_config(&*next_config.await?);
+        self.resolve_route_with_locale(&pathname, &context).await
+    }
+
+    /// Resolves a requested pathname to the route serving it, after applying
+    /// the configured rewrites with the configured base path and locales. See
+    /// [resolve_route_with_rewrites].
+    #[turbo_tasks::function]
+    pub async fn resolve_route_with_rewrites(
+        self,
+        pathname: String,
+        next_config: NextConfigVc,
+    ) -> Result<OptionResolvedRouteVc> {
+        let rewrites = next_config.rewrites().await?;
+        let context = RouteRuleContext::from_config(&*next_config.await?);
+        let entries = self.route_entries().await?;
+        let mut patterns = IndexMap::new();
+        for entry in entries.iter() {
+            patterns.insert(entry.await?.pattern.clone(), *entry);
+        }
+        let keys = patterns
+            .keys()
+            .map(|pattern| pattern.as_str())
+            .collect::<Vec<_>>();
+        Ok(OptionResolvedRouteVc::cell(
+            resolve_route_with_rewrites(&keys, &pathname, &rewrites, &context).map(
+                |(pattern, params, _)| {
+                    ResolvedRoute {
+                        entry: patterns[pattern],
+                        params,
+                        locale: None,
+                    }
+                    .cell()
+                },
+            ),
+        ))
+    }
+
+    /// Returns up to `count` routes to compile ahead of the first request.
+    /// See [select_prewarm_routes] for which routes are preferred.
+    #[turbo_tasks::function]
+    pub async fn prewarm_routes(self, count: u32) -> Result<RouteEntriesVc> {
+        let entries = self.route_entries().await?;
+        let mut routes = Vec::with_capacity(entries.len());
+        for entry in entries.iter() {
+            let entry_value = entry.await?;
+            routes.push((entry_value.pattern.clone(), entry_value.is_api));
+        }
+        let selected = select_prewarm_routes(
+            routes
+                .iter()
+                .map(|(pattern, is_api)| (pattern.as_str(), *is_api)),
+            count as usize,
+        );
+        Ok(RouteEntriesVc::cell(
+            selected.into_iter().map(|index| entries[index]).collect(),
+        ))
+    }
+
+    /// Returns every dynamic route of the whole tree with its params, in
+    /// traversal order.
+    #[turbo_tasks::function]
+    pub async fn dynamic_routes(self) -> Result<DynamicRoutesVc> {
+        let mut routes = vec![];
+        for entry in self.route_entries().await?.iter() {
+            let pattern = &entry.await?.pattern;
+            let params = dynamic_params(pattern);
+            if !params.is_empty() {
+                routes.push((pattern.clone(), params));
+            }
+        }
+        Ok(DynamicRoutesVc::cell(routes))
+    }
+
+    /// Returns a map of route patterns to the files serving them, in the
+    /// given order.
+    #[turbo_tasks::function]
+    pub async fn pages_manifest(self, order: Value<ManifestOrder>) -> Result<PagesManifestVc> {
+        let mut entries = vec![];
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            if entry.kind.is_none() {
+                entries.push((entry.pattern.clone(), entry.file));
+            }
+        }
+        sort_manifest_entries(
+            &mut entries,
+            |(pattern, _)| pattern.as_str(),
+            order.into_value(),
+        );
+        Ok(PagesManifestVc::cell(entries.into_iter().collect()))
+    }
+
+    /// Like [PagesStructureVc::pages_manifest], but with the file paths
+    /// relative to the pages directory. Files outside of it (like virtual
+    /// pages) are left out, since they have no portable path.
+    #[turbo_tasks::function]
+    pub async fn portable_pages_manifest(self) -> Result<PortablePagesManifestVc> {
+        let root = self.await?.directory.await?;
+        let mut manifest = IndexMap::new();
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            if entry.kind.is_some() {
+                continue;
+            }
+            if let Some(path) = root.get_path_to(&*entry.file.await?) {
+                manifest.insert(entry.pattern.clone(), path.to_string());
+            }
+        }
+        Ok(PortablePagesManifestVc::cell(manifest))
+    }
+
+    /// Renders the structure as an indented tree for debugging. With
+    /// `verbose`, each route also shows its kind, where it came from and the
+    /// page extension of its file, which helps to tell which file won a
+    /// conflict.
+    #[turbo_tasks::function]
+    pub async fn print_tree(
+        self,
+        page_extensions: PageExtensionsVc,
+        verbose: bool,
+    ) -> Result<StringVc> {
+        let page_extensions = page_extensions.await?;
+        let mut tree = String::new();
+        let mut stack = vec![(self, 0)];
+        while let Some((structure, depth)) = stack.pop() {
+            let structure = structure.await?;
+            let directory = structure.directory.await?;
+            let name = directory.path.rsplit('/').next().unwrap_or(&directory.path);
+            writeln!(tree, "{:indent$}{name}/", "", indent = depth * 2)?;
+            for item in structure.items.iter() {
+                let pattern = item.route_pattern().await?;
+                let item_ref = item.await?;
+                let kind = item_ref.kind_name();
+                let provenance = provenance_name(&*item.provenance().await?);
+                let file = item_ref.file().await?;
+                let file_name = file.path.rsplit('/').next().unwrap_or(&file.path);
+                writeln!(
+                    tree,
+                    "{:indent$}{}",
+                    "",
+                    tree_leaf_line(
+                        &pattern,
+                        kind,
+                        provenance,
+                        file_name,
+                        &page_extensions,
+                        verbose
+                    ),
+                    indent = (depth + 1) * 2
+                )?;
+            }
+            stack.extend(
+                structure
+                    .children
+                    .iter()
+                    .rev()
+                    .map(|child| (*child, depth + 1)),
+            );
+        }
+        Ok(StringVc::cell(tree))
+    }
+
+    /// Renders a report of the routes for users, like `next routes` prints
+    /// it: the pattern of every route relative to `router_root`, its kind and
+    /// whether it is dynamic, indented by the directory of its file. Unlike
+    /// [PagesStructureVc::print_tree], which is meant for snapshots, the
+    /// format may change to be more readable.
+    #[turbo_tasks::function]
+    pub async fn route_report(self, router_root: FileSystemPathVc) -> Result<StringVc> {
+        let root = self.await?.directory.await?;
+        let router_root = router_root.await?;
+        let mut routes = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            let directory = structure.directory.await?;
+            let Some(directory) = root.get_path_to(&directory) else {
+                continue;
+            };
+            for item in structure.items.iter() {
+                let item = item.await?;
+                let url = item.url().await?;
+                let Some(path) = router_root.get_path_to(&url) else {
+                    continue;
+                };
+                routes.push((
+                    directory.to_string(),
+                    format_route_pattern(&route_segments_for_url_path(path)),
+                    item.kind_name().to_string(),
+                ));
+            }
+            queue.extend(structure.children.iter().copied());
+        }
+        let root_name = root.path.rsplit('/').next().unwrap_or(&root.path);
+        Ok(StringVc::cell(format_route_report(root_name, routes)))
+    }
+
+    /// Returns the `dynamicRoutes` section of Next.js' `routes-manifest.json`
+    /// for the dynamic routes of the whole tree, in the given order.
+    #[turbo_tasks::function]
+    pub async fn dynamic_routes_manifest(
+        self,
+        order: Value<ManifestOrder>,
+    ) -> Result<DynamicRoutesManifestVc> {
+        let patterns = self.route_patterns().await?;
+        Ok(DynamicRoutesManifestVc::cell(dynamic_routes_manifest(
+            patterns.iter().map(|pattern| pattern.as_str()),
+            order.into_value(),
+        )))
+    }
+
+    /// Returns the patterns of all routes in the given order. Internal, for
+    /// comparing the turbopack ordering with the webpack one while migrating.
+    #[doc(hidden)]
+    #[turbo_tasks::function]
+    pub async fn sorted_routes(self, ordering: Value<RouteOrdering>) -> Result<StringsVc> {
+        let patterns = self.route_patterns().await?;
+        Ok(StringsVc::cell(sort_routes(
+            patterns.iter().map(|pattern| pattern.as_str()),
+            ordering.into_value(),
+        )?))
+    }
+
+    /// Returns the [RouteStats] of the whole tree: how many routes there are,
+    /// how many of them are dynamic and the depth of the deepest one.
+    #[turbo_tasks::function]
+    pub async fn route_stats(self) -> Result<RouteStatsVc> {
+        let patterns = self.route_patterns().await?;
+        Ok(RouteStats::from_patterns(patterns.iter().map(|pattern| pattern.as_str())).cell())
+    }
+
+    /// Returns the routes of the whole tree as a [RouteTrie], with patterns
+    /// relative to `router_root`.
+    #[turbo_tasks::function]
+    pub async fn route_trie(self, router_root: FileSystemPathVc) -> Result<RouteTrieVc> {
+        let patterns = self.route_patterns_relative_to(router_root).await?;
+        Ok(RouteTrie::from_patterns(patterns.iter().map(|pattern| pattern.as_str())).cell())
+    }
+
+    /// Returns true if both structures have the same routes, regardless of
+    /// their order. Routes are compared by pattern, kind, specificity rank and
+    /// file path relative to the pages directory, so unlike comparing
+    /// fingerprints, different structures never compare equal.
+    #[turbo_tasks::function]
+    pub async fn structurally_equal(self, other: PagesStructureVc) -> Result<BoolVc> {
+        Ok(BoolVc::cell(same_routes(
+            self.structural_routes().await?,
+            other.structural_routes().await?,
+        )))
+    }
+
+    /// Returns the structure of the directory serving the routes below
+    /// `prefix` (like `/admin`), relative to `router_root`, or None if there
+    /// is no such directory. Useful to serve or build only part of an app.
+    #[turbo_tasks::function]
+    pub async fn subtree(
+        self,
+        prefix: &str,
+        router_root: FileSystemPathVc,
+    ) -> Result<OptionPagesStructureVc> {
+        let router_root = router_root.await?;
+        let Some(root_prefix) = router_root
+            .get_path_to(&*self.await?.router_root.await?)
+            .map(ToString::to_string)
+        else {
+            return Ok(OptionPagesStructureVc::cell(None));
+        };
+        let Some(directories) = subtree_directories(&root_prefix, prefix) else {
+            return Ok(OptionPagesStructureVc::cell(None));
+        };
+        let mut structure = self;
+        for name in directories {
+            let mut found = None;
+            for child in structure.await?.children.iter() {
+                let directory = child.await?.directory.await?;
+                if directory.path.rsplit('/').next() == Some(name) {
+                    found = Some(*child);
+                    break;
+                }
+            }
+            let Some(child) = found else {
+                return Ok(OptionPagesStructureVc::cell(None));
+            };
+            structure = child;
+        }
+        Ok(OptionPagesStructureVc::cell(Some(structure)))
+    }
+
+    /// Returns the API routes of the whole tree grouped by their first
+    /// segment relative to `api_root`, in traversal order.
+    #[turbo_tasks::function]
+    pub async fn api_routes_by_group(self, api_root: FileSystemPathVc) -> Result<ApiRouteGroupsVc> {
+        let api_root = api_root.await?;
+        let mut routes = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                let item_value = item.await?;
+                if !item_value.is_api() {
+                    continue;
+                }
+                let url = item_value.url().await?;
+                if let Some(path) = api_root.get_path_to(&url) {
+                    routes.push((path.to_string(), *item));
+                }
+            }
+            queue.extend(structure.children.iter().rev().copied());
+        }
+        Ok(ApiRouteGroupsVc::cell(group_api_routes(
+            routes.iter().map(|(path, item)| (path.as_str(), *item)),
+        )))
+    }
+
+    /// Returns how many directories were read to build the whole tree. Every
+    /// structure in the tree is the result of reading one directory.
+    #[turbo_tasks::function]
+    pub async fn scan_stats(self) -> Result<PagesScanStatsVc> {
+        let mut depths = vec![];
+        let mut queue = vec![(self, 0)];
+        while let Some((structure, depth)) = queue.pop() {
+            depths.push(depth);
+            queue.extend(
+                structure
+                    .await?
+                    .children
+                    .iter()
+                    .map(|child| (*child, depth + 1)),
+            );
+        }
+        Ok(PagesScanStats::from_directory_depths(depths).cell())
+    }
+
+    /// Returns the greatest number of segments of any route, with routes
+    /// relative to `router_root`.
+    #[turbo_tasks::function]
+    pub async fn max_route_depth(self, router_root: FileSystemPathVc) -> Result<U32Vc> {
+        let patterns = self.route_patterns_relative_to(router_root).await?;
+        let stats = RouteStats::from_patterns(patterns.iter().map(|pattern| pattern.as_str()));
+        Ok(U32Vc::cell(stats.max_depth))
+    }
+
+    /// Returns TypeScript declarations of the params of every dynamic route
+    /// (e.g. `{ slug: string }`), keyed by route pattern.
+    #[turbo_tasks::function]
+    pub async fn route_params_types(self) -> Result<StringVc> {
+        let patterns = self.route_patterns().await?;
+        Ok(StringVc::cell(route_params_type_declarations(
+            patterns.iter().map(|pattern| pattern.as_str()),
+        )))
+    }
+
+    /// Writes the route params declarations to `routes.d.ts` in the types
+    /// output directory. The file is only rewritten when the declarations
+    /// change, not on every change of the routes.
+    #[turbo_tasks::function]
+    pub async fn write_route_params_types(
+        self,
+        types_dir: FileSystemPathVc,
+    ) -> Result<CompletionVc> {
+        let declarations = self.route_params_types().await?;
+        Ok(types_dir
+            .join("routes.d.ts")
+            .write(FileContentVc::from(File::from(declarations.as_str()))))
+    }
+
+    /// Returns a completion that changes when any route in the whole tree
+    /// changes.
+    #[turbo_tasks::function]
+    pub async fn routes_changed(self) -> Result<CompletionVc> {
+        for item in self.await?.items.iter() {
+            item.routes_changed().await?;
+        }
+        for child in self.await?.children.iter() {
+            child.routes_changed().await?;
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports when both `pages/api.ts` and `pages/api/index.ts` exist, which
+    /// both serve `/api`.
+    #[turbo_tasks::function]
+    pub async fn check_api_root_handlers(self, strict_routing: bool) -> Result<CompletionVc> {
+        let mut api_routes = vec![];
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            if entry.is_api {
+                api_routes.push((entry.pattern.clone(), entry.file));
+            }
+        }
+        if let Some(files) = find_api_root_conflict(&api_routes) {
+            let mut paths = vec![];
+            for file in files.iter() {
+                paths.push(file.await?.path.clone());
+            }
+            PagesStructureIssue {
+                severity: routing_issue_severity(strict_routing).into(),
+                path: files[0],
+                message: StringVc::cell(format!(
+                    "The files {} all serve `/{API_ROOT_NAME}`, so it's ambiguous which one \
+                     handles requests. Keep only `{API_ROOT_NAME}/index`, the conventional \
+                     handler of the API root.",
+                    paths.join(", ")
+                )),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports routes that use a param more than once (like `/[id]/[id]`),
+    /// since only one of the values ends up in the query.
+    #[turbo_tasks::function]
+    pub async fn check_repeated_params(self, strict_routing: bool) -> Result<CompletionVc> {
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            let segments = route_segments_from_pattern(&entry.pattern);
+            if let Some(param) = find_repeated_param(&segments) {
+                PagesStructureIssue {
+                    severity: routing_issue_severity(strict_routing).into(),
+                    path: entry.file,
+                    message: StringVc::cell(format!(
+                        "The param `{param}` is used more than once in the route `{}`. Params of \
+                         a route have to be unique.",
+                        entry.pattern
+                    )),
+                }
+                .cell()
+                .as_issue()
+                .emit();
+            }
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports routes with segments after a catch-all (like
+    /// `/[...a]/[b]`), which the catch-all swallows.
+    #[turbo_tasks::function]
+    pub async fn check_segments_after_catch_all(
+        self,
+        strict_routing: bool,
+    ) -> Result<CompletionVc> {
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            let segments = route_segments_from_pattern(&entry.pattern);
+            if let Some((catch_all, following)) = find_segment_after_catch_all(&segments) {
+                PagesStructureIssue {
+                    severity: routing_issue_severity(strict_routing).into(),
+                    path: entry.file,
+                    message: StringVc::cell(segment_after_catch_all_message(
+                        &entry.pattern,
+                        catch_all,
+                        following,
+                    )),
+                }
+                .cell()
+                .as_issue()
+                .emit();
+            }
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports pages that have an AMP variant (like `post.tsx` and
+    /// `post.amp.tsx`) when neither file is a hybrid AMP page. Both then serve
+    /// the same route. Enabled with `experimental.ampSuffixPages`.
+    #[turbo_tasks::function]
+    pub async fn check_amp_variants(self, strict_routing: bool) -> Result<CompletionVc> {
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                let item_value = item.await?;
+                let Some(amp_file) = item_value.amp_variant() else {
+                    continue;
+                };
+                let page_file = item_value.file();
+                if is_hybrid_amp_file(page_file).await? || is_hybrid_amp_file(amp_file).await? {
+                    continue;
+                }
+                PagesStructureIssue {
+                    severity: routing_issue_severity(strict_routing).into(),
+                    path: amp_file,
+                    message: StringVc::cell(format!(
+                        "The route `{}` has the AMP variant {}, but neither it nor {} is a hybrid \
+                         AMP page. Export `config = {{ amp: 'hybrid' }}` from one of them, or \
+                         remove one of the two pages.",
+                        item.route_pattern().await?,
+                        amp_file.await?.path,
+                        page_file.await?.path
+                    )),
+                }
+                .cell()
+                .as_issue()
+                .emit();
+            }
+            queue.extend(structure.children.iter().copied());
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports API routes whose file names contain uppercase letters. API
+    /// routes are often requested programmatically, where case mismatches
+    /// cause 404s on case-sensitive file systems. Enabled with
+    /// `experimental.requireLowercaseApiRoutes`.
+    #[turbo_tasks::function]
+    pub async fn check_lowercase_api_routes(self, strict_routing: bool) -> Result<CompletionVc> {
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            if !entry.is_api {
+                continue;
+            }
+            let file = entry.file.await?;
+            let file_name = file.path.rsplit('/').next().unwrap_or(&file.path);
+            if !is_lowercase_file_name(file_name) {
+                PagesStructureIssue {
+                    severity: routing_issue_severity(strict_routing).into(),
+                    path: entry.file,
+                    message: StringVc::cell(format!(
+                        "The API route file `{}` contains uppercase letters. Rename it to `{}` to \
+                         avoid case mismatches when it is requested.",
+                        file_name,
+                        file_name.to_lowercase()
+                    )),
+                }
+                .cell()
+                .as_issue()
+                .emit();
+            }
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports API routes nested more than `max_depth` segments below `/api`
+    /// (like `pages/api/a/b/c/d/e/handler.ts`), which are usually better
+    /// flattened. Enabled with `experimental.maxApiDepth`.
+    #[turbo_tasks::function]
+    pub async fn check_api_depth(
+        self,
+        max_depth: u32,
+        strict_routing: bool,
+    ) -> Result<CompletionVc> {
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            if !entry.is_api {
+                continue;
+            }
+            if let Some(depth) = api_depth_exceeding(&entry.pattern, max_depth as usize) {
+                PagesStructureIssue {
+                    severity: routing_issue_severity(strict_routing).into(),
+                    path: entry.file,
+                    message: StringVc::cell(format!(
+                        "The API route `{}` is nested {depth} segments below `/{API_ROOT_NAME}`, \
+                         more than the configured maximum of {max_depth}. Consider flattening it.",
+                        entry.pattern
+                    )),
+                }
+                .cell()
+                .as_issue()
+                .emit();
+            }
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Returns the route patterns (relative to `router_root`) of all items
+    /// whose file is inside `dir`, i.e. the routes that would disappear if
+    /// `dir` were deleted. Sorted by pattern.
+    #[turbo_tasks::function]
+    pub async fn routes_under_directory(
+        self,
+        dir: FileSystemPathVc,
+        router_root: FileSystemPathVc,
+    ) -> Result<StringsVc> {
+        let root_path = self.await?.directory.await?;
+        let dir_path = dir.await?;
+        let directory = match root_path.get_path_to(&dir_path) {
+            Some(directory) => directory.to_string(),
+            // `dir` contains the whole pages directory.
+            None if dir_path.get_path_to(&root_path).is_some() => String::new(),
+            None => return Ok(StringsVc::cell(vec![])),
+        };
+        let router_root = router_root.await?;
+        let mut routes = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                let item = item.await?;
+                let file = item.file().await?;
+                let url = item.url().await?;
+                if let (Some(file), Some(url)) =
+                    (root_path.get_path_to(&file), router_root.get_path_to(&url))
+                {
+                    routes.push((
+                        file.to_string(),
+                        format_route_pattern(&route_segments_for_url_path(url)),
+                    ));
+                }
+            }
+            queue.extend(structure.children.iter().copied());
+        }
+        Ok(StringsVc::cell(
+            patterns_under_directory(&routes, &directory)
+                .into_iter()
+                .map(ToString::to_string)
+                .collect(),
+        ))
+    }
+
+    /// Returns the pairs of route patterns (relative to `router_root`) that
+    /// compete for the same pathnames, like `/[slug]` and `/about`: they have
+    /// the same number of segments and differ only in static vs dynamic
+    /// segments. Routes with catch-all segments are left out.
+    #[turbo_tasks::function]
+    pub async fn competing_routes(self, router_root: FileSystemPathVc) -> Result<RoutePairsVc> {
+        let router_root = router_root.await?;
+        let mut patterns = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                let url = item.await?.url().await?;
+                if let Some(url) = router_root.get_path_to(&url) {
+                    patterns.push(format_route_pattern(&route_segments_for_url_path(url)));
+                }
+            }
+            queue.extend(structure.children.iter().copied());
+        }
+        let patterns = patterns.iter().map(String::as_str).collect::<Vec<_>>();
+        Ok(RoutePairsVc::cell(
+            find_competing_routes(&patterns)
+                .into_iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect(),
+        ))
+    }
+
+    /// Warns about dynamic directories (like `pages/[slug]/`) that contain no
+    /// routable file anywhere beneath them, which usually means an
+    /// `index.tsx` is missing. This check is opt-in and isn't run as part of
+    /// [PagesStructureVc::routes_changed].
+    #[turbo_tasks::function]
+    pub async fn check_orphaned_dynamic_directories(
+        self,
+        strict_routing: bool,
+    ) -> Result<CompletionVc> {
+        let root = self.await?.directory;
+        let root_path = root.await?;
+        let mut directories = vec![];
+        let mut files = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                if let Some(path) = root_path.get_path_to(&*item.await?.file().await?) {
+                    files.push(path.to_string());
+                }
+            }
+            for child in structure.children.iter() {
+                if let Some(path) = root_path.get_path_to(&*child.await?.directory.await?) {
+                    directories.push(path.to_string());
+                }
+            }
+            queue.extend(structure.children.iter().copied());
+        }
+
+        for directory in find_orphaned_dynamic_directories(&directories, &files) {
+            PagesStructureIssue {
+                severity: routing_issue_severity(strict_routing).into(),
+                path: root.join(directory),
+                message: StringVc::cell(format!(
+                    "The dynamic directory `{directory}` contains no page or API route, so it \
+                     doesn't produce any route. Did you forget to add an `index` file?"
+                )),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Warns about dynamic pages whose `getStaticPaths` returns params with
+    /// keys that don't match the params of the route, like `{ id }` for
+    /// `/blog/[slug]`. Only literal `paths` arrays are checked. This check
+    /// reads the source of every dynamic page, so it only runs with
+    /// `experimental.checkStaticPathsParams`.
+    #[turbo_tasks::function]
+    pub async fn check_static_paths_params(self, strict_routing: bool) -> Result<CompletionVc> {
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            if !entry.is_page() || dynamic_params(&entry.pattern).is_empty() {
+                continue;
+            }
+            let FileContent::Content(file) = &*entry.file.read().await? else {
+                continue;
+            };
+            let Some(mismatch) =
+                static_paths_param_mismatch(&entry.pattern, &file.content().to_str()?)
+            else {
+                continue;
+            };
+            let mut problems = vec![];
+            if !mismatch.unknown.is_empty() {
+                problems.push(format!(
+                    "unknown keys {}",
+                    format_param_keys(&mismatch.unknown)
+                ));
+            }
+            if !mismatch.missing.is_empty() {
+                problems.push(format!(
+                    "missing keys {}",
+                    format_param_keys(&mismatch.missing)
+                ));
+            }
+            PagesStructureIssue {
+                severity: routing_issue_severity(strict_routing).into(),
+                path: entry.file,
+                message: StringVc::cell(format!(
+                    "`getStaticPaths` of the route `{}` returns params with {}. The keys of \
+                     `params` have to match the dynamic segments of the route.",
+                    entry.pattern,
+                    problems.join(" and ")
+                )),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports the dynamic pages that can't be exported with `output:
+    /// 'export'`: those without a `getStaticPaths` returning `fallback:
+    /// false`, since every path has to be known at build time. API routes are
+    /// already reported by the [NextFileClassifier]. Like
+    /// [PagesStructureVc::check_static_paths_params], this reads the source
+    /// of every dynamic page and only understands literal `fallback` values.
+    /// Does nothing without `output: 'export'`.
+    #[turbo_tasks::function]
+    pub async fn check_static_export(self, next_config: NextConfigVc) -> Result<CompletionVc> {
+        if !*next_config.is_static_export().await? {
+            return Ok(CompletionVc::new());
+        }
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            if !entry.is_page() || !is_dynamic_route_pattern(&entry.pattern) {
+                continue;
+            }
+            let FileContent::Content(file) = &*entry.file.read().await? else {
+                continue;
+            };
+            let Some(problem) = static_export_problem(&entry.pattern, &file.content().to_str()?)
+            else {
+                continue;
+            };
+            let message = match problem {
+                StaticExportProblem::MissingStaticPaths => format!(
+                    "The dynamic page `{}` needs `getStaticPaths` with `fallback: false` to be \
+                     used with `output: 'export'`, since all of its paths have to be known at \
+                     build time.",
+                    entry.pattern
+                ),
+                StaticExportProblem::Fallback(fallback) => format!(
+                    "`getStaticPaths` of the dynamic page `{}` returns `fallback: {fallback}`, \
+                     which can't be used with `output: 'export'`. Return `fallback: false`.",
+                    entry.pattern
+                ),
+            };
+            PagesStructureIssue {
+                severity: IssueSeverity::Error.into(),
+                path: entry.file,
+                message: StringVc::cell(message),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports dynamic params named like a query parameter that Next.js adds
+    /// itself (like `[amp]`), see
+    /// [crate::next_config::NextConfig::reserved_param_names]. The param and
+    /// the query parameter would overwrite each other.
+    #[turbo_tasks::function]
+    pub async fn check_reserved_param_names(
+        self,
+        next_config: NextConfigVc,
+    ) -> Result<CompletionVc> {
+        let next_config = next_config.await?;
+        let reserved = next_config.reserved_param_names();
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            for name in reserved_params(&entry.pattern, &reserved) {
+                let message = format!(
+                    "The dynamic param `{name}` of `{}` has the name of a query parameter that \
+                     Next.js uses itself, so they overwrite each other. Rename the param.",
+                    entry.pattern
+                );
+                PagesStructureIssue {
+                    severity: routing_issue_severity(next_config.strict_routing()).into(),
+                    path: entry.file,
+                    message: StringVc::cell(message),
+                }
+                .cell()
+                .as_issue()
+                .emit();
+            }
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Warns about rewrites from the Next.js config that rewrite each other
+    /// in a cycle, which makes requests loop at runtime. A rewrite to one of
+    /// the `targets` ends the chain, see
+    /// [CombinedStructureVc::rewrite_targets].
+    #[turbo_tasks::function]
+    pub async fn check_rewrite_cycles(
+        self,
+        next_config: NextConfigVc,
+        targets: StringsVc,
+    ) -> Result<CompletionVc> {
+        let rewrites = next_config.rewrites().await?;
+        let rewrites = rewrites
+            .before_files
+            .iter()
+            .chain(rewrites.after_files.iter())
+            .chain(rewrites.fallback.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        if rewrites.is_empty() {
+            return Ok(CompletionVc::new());
+        }
+
+        let targets = targets.await?;
+        let is_route = |path: &str| {
+            resolve_route(targets.iter().map(|pattern| pattern.as_str()), path).is_some()
+        };
+
+        let directory = self.await?.directory;
+        let strict_routing = next_config.await?.strict_routing();
+        for cycle in find_rewrite_cycles(&rewrites, is_route) {
+            let path = cycle
+                .iter()
+                .chain(cycle.first())
+                .map(|&index| rewrites[index].source.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            PagesStructureIssue {
+                severity: routing_issue_severity(strict_routing).into(),
+                path: directory,
+                message: StringVc::cell(format!(
+                    "The rewrites in the Next.js config rewrite each other in a cycle, which \
+                     makes requests loop: {path}"
+                )),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Reports rewrites from the Next.js config whose internal destinations
+    /// reference params their source doesn't capture, or don't match any of
+    /// the `targets`, see [CombinedStructureVc::rewrite_targets].
+    #[turbo_tasks::function]
+    pub async fn check_rewrite_destinations(
+        self,
+        next_config: NextConfigVc,
+        targets: StringsVc,
+    ) -> Result<CompletionVc> {
+        let rewrites = next_config.rewrites().await?;
+        let targets = targets.await?;
+        let is_route = |path: &str| {
+            resolve_route(targets.iter().map(|pattern| pattern.as_str()), path).is_some()
+        };
+
+        let directory = self.await?.directory;
+        let strict_routing = next_config.await?.strict_routing();
+        for rewrite in rewrites
+            .before_files
+            .iter()
+            .chain(rewrites.after_files.iter())
+            .chain(rewrites.fallback.iter())
+        {
+            for problem in check_rewrite_destination(rewrite, is_route) {
+                let (severity, message) = match problem {
+                    DestinationProblem::UnknownParam(param) => (
+                        routing_issue_severity(strict_routing),
+                        format!(
+                            "The rewrite from `{}` to `{}` references the param `:{param}`, which \
+                             its source doesn't capture.",
+                            rewrite.source, rewrite.destination
+                        ),
+                    ),
+                    DestinationProblem::NoRoute => (
+                        IssueSeverity::Info,
+                        format!(
+                            "The rewrite from `{}` to `{}` doesn't point at any route, so it \
+                             responds with a 404.",
+                            rewrite.source, rewrite.destination
+                        ),
+                    ),
+                };
+                PagesStructureIssue {
+                    severity: severity.into(),
+                    path: directory,
+                    message: StringVc::cell(message),
+                }
+                .cell()
+                .as_issue()
+                .emit();
+            }
+        }
+        Ok(CompletionVc::new())
+    }
+}
+
+impl PagesStructureVc {
+    /// Returns the routes of the whole tree as compared by
+    /// [PagesStructureVc::structurally_equal].
+    async fn structural_routes(self) -> Result<Vec<StructuralRoute>> {
+        let directory = self.await?.directory.await?;
+        let mut routes = vec![];
+        for entry in self.route_entries().await?.iter() {
+            let entry = entry.await?;
+            let kind = match &entry.kind {
+                Some(kind) => kind.clone(),
+                None if entry.is_api => "api".to_string(),
+                None => "page".to_string(),
+            };
+            let file = entry.file.await?;
+            let path = directory
+                .get_path_to(&file)
+                .map_or_else(|| file.path.clone(), ToString::to_string);
+            let rank = route_rank(&route_segments_from_pattern(&entry.pattern));
+            routes.push((entry.pattern.clone(), kind, rank, path));
+        }
+        Ok(routes)
+    }
+
+    /// Returns the patterns of all routes of the whole tree, relative to
+    /// `router_root`.
+    async fn route_patterns_relative_to(
+        self,
+        router_root: FileSystemPathVc,
+    ) -> Result<Vec<String>> {
+        let router_root = router_root.await?;
+        let mut patterns = vec![];
+        let mut queue = vec![self];
+        while let Some(structure) = queue.pop() {
+            let structure = structure.await?;
+            for item in structure.items.iter() {
+                let url = item.await?.url().await?;
+                if let Some(path) = router_root.get_path_to(&url) {
+                    patterns.push(format_route_pattern(&route_segments_for_url_path(path)));
+                }
+            }
+            queue.extend(structure.children.iter().copied());
+        }
+        Ok(patterns)
+    }
+
+    async fn resolve_route_with_locale(
+        self,
+        pathname: &str,
+        context: &RouteRuleContext,
+    ) -> Result<OptionResolvedRouteVc> {
+        let entries = self.route_entries().await?;
+        let mut patterns = IndexMap::new();
+        for entry in entries.iter() {
+            patterns.insert(entry.await?.pattern.clone(), *entry);
+        }
+        let keys = patterns.keys().map(|pattern| pattern.as_str());
+        let resolved = resolve_route_i18n(keys, pathname, context);
+        Ok(OptionResolvedRouteVc::cell(resolved.map(
+            |(pattern, params, locale)| {
+                ResolvedRoute {
+                    entry: patterns[pattern],
+                    params,
+                    locale,
+                }
+                .cell()
+            },
+        )))
+    }
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionPagesStructure(Option<PagesStructureVc>);
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionPagesStructureItem(Option<PagesStructureItemVc>);
+
+#[turbo_tasks::value(transparent)]
+pub struct PagesStructureItems(Vec<PagesStructureItemVc>);
+
+#[turbo_tasks::value_impl]
+impl OptionPagesStructureVc {
+    #[turbo_tasks::function]
+    pub async fn routes_changed(self) -> Result<CompletionVc> {
+        if let Some(pages_structure) = *self.await? {
+            pages_structure.routes_changed().await?;
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Writes the route params declarations of the pages directory to
+    /// `routes.d.ts` in `types_dir`, see
+    /// [PagesStructureVc::write_route_params_types]. Writes nothing if there
+    /// is no pages directory.
+    #[turbo_tasks::function]
+    pub async fn write_route_params_types(
+        self,
+        types_dir: FileSystemPathVc,
+    ) -> Result<CompletionVc> {
+        if let Some(pages_structure) = *self.await? {
+            pages_structure.write_route_params_types(types_dir).await?;
+        }
+        Ok(CompletionVc::new())
+    }
+
+    /// Returns live route counts, which update whenever a route changes.
+    /// Returns empty counts if there is no pages directory.
+    #[turbo_tasks::function]
+    pub async fn route_stats(self) -> Result<RouteStatsVc> {
+        // Ties the stats to the same invalidation as the other consumers of
+        // the routes, like the router.
+        self.routes_changed().await?;
+        if let Some(pages_structure) = *self.await? {
+            Ok(pages_structure.route_stats())
+        } else {
+            Ok(RouteStats::default().cell())
+        }
+    }
+}
+
+/// The routes of the pages directory and the app directory together.
+#[turbo_tasks::value(shared)]
+pub struct CombinedStructure {
+    pub pages: OptionPagesStructureVc,
+    pub app: OptionAppStructureVc,
+    pub next_config: NextConfigVc,
+}
+
+#[turbo_tasks::value_impl]
+impl CombinedStructureVc {
+    /// Returns a completion that changes when any route in either directory
+    /// changes. Also reports routes that are served by both directories.
+    #[turbo_tasks::function]
+    pub async fn routes_changed(self) -> Result<CompletionVc> {
+        let this = self.await?;
+        this.pages.routes_changed().await?;
+        this.app.routes_changed().await?;
+        check_pages_app_duplicates(this.pages, this.app, this.next_config).await?;
+        Ok(CompletionVc::new())
+    }
+
+    /// Returns the route patterns of both directories in the order they are
+    /// resolved in, see [RouteOrdering::Native]. Route groups of the app
+    /// directory aren't part of the patterns, so grouped routes order like
+    /// ungrouped ones. A pattern served by both directories is listed once.
+    #[turbo_tasks::function]
+    pub async fn sorted_routes(self) -> Result<StringsVc> {
+        let this = self.await?;
+        let mut patterns = vec![];
+        if let Some(pages) = *this.pages.await? {
+            patterns.extend(pages.route_patterns().await?.iter().cloned());
+        }
+        if let Some(app) = *this.app.await? {
+            patterns.extend(
+                app_routes(app)
+                    .await?
+                    .into_iter()
+                    .map(|(pattern, _)| pattern),
+            );
+        }
+        Ok(StringsVc::cell(sort_combined_routes(&patterns)?))
+    }
+
+    /// Returns the patterns that rewrites can point at: the routes of both
+    /// directories (see [CombinedStructureVc::sorted_routes]) and the files
+    /// of the `public_dir`.
+    #[turbo_tasks::function]
+    pub async fn rewrite_targets(self, public_dir: FileSystemPathVc) -> Result<StringsVc> {
+        let mut targets = self.sorted_routes().await?.clone_value();
+        targets.extend(
+            public_file_patterns(public_dir, public_dir)
+                .await?
+                .iter()
+                .cloned(),
+        );
+        Ok(StringsVc::cell(targets))
+    }
+}
+
+/// Returns the route pattern and file of every page and route handler of the
+/// app directory.
+async fn app_routes(app: AppStructureVc) -> Result<Vec<(String, FileSystemPathVc)>> {
+    let mut routes = vec![];
+    let mut queue = vec![app];
+    while let Some(structure) = queue.pop() {
+        let structure = structure.await?;
+        if let Some(item) = structure.item {
+            let (url, file) = match *item.await? {
+                AppStructureItem::Page { url, page, .. } => (url, page),
+                AppStructureItem::Route { url, route, .. } => (url, route),
+            };
+            let url = url.await?;
+            routes.push((
+                format_route_pattern(&route_segments_for_url_path(&url.path)),
+                file,
+            ));
+        }
+        queue.extend(structure.children.iter().copied());
+    }
+    Ok(routes)
+}
+
+/// Returns the route of every file in `directory` and below, relative to the
+/// `public` directory, like `/images/logo.png`.
+#[turbo_tasks::function]
+async fn public_file_patterns(
+    public_dir: FileSystemPathVc,
+    directory: FileSystemPathVc,
+) -> Result<StringsVc> {
+    let public_dir_ref = public_dir.await?;
+    let mut patterns = vec![];
+    if let DirectoryContent::Entries(entries) = &*directory.read_dir().await? {
+        for entry in entries.values() {
+            match *entry {
+                DirectoryEntry::File(file) => {
+                    if let Some(path) = public_dir_ref.get_path_to(&*file.await?) {
+                        patterns.push(format!("/{path}"));
+                    }
+                }
+                DirectoryEntry::Directory(child) => {
+                    patterns.extend(
+                        public_file_patterns(public_dir, child)
+                            .await?
+                            .iter()
+                            .cloned(),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(StringsVc::cell(patterns))
+}
+
+/// Sorts the route patterns of the pages and app directories together, in
+/// the order they are resolved in, without duplicates.
+fn sort_combined_routes(patterns: &[String]) -> Result<Vec<String>> {
+    let mut sorted = sort_routes(patterns.iter().map(String::as_str), RouteOrdering::Native)?;
+    sorted.dedup();
+    Ok(sorted)
+}
+
+/// Combines the pages and app structures, so that routes can be checked
+/// across both of them.
+#[turbo_tasks::function]
+pub fn find_combined_structure(
+    pages: OptionPagesStructureVc,
+    app: OptionAppStructureVc,
+    next_config: NextConfigVc,
+) -> CombinedStructureVc {
+    CombinedStructure {
+        pages,
+        app,
+        next_config,
+    }
+    .cell()
+}
+
+/// Emits an issue for every route that is served by both the pages and the app
+/// directory.
+#[turbo_tasks::function]
+async fn check_pages_app_duplicates(
+    pages: OptionPagesStructureVc,
+    app: OptionAppStructureVc,
+    next_config: NextConfigVc,
+) -> Result<CompletionVc> {
+    let (Some(pages), Some(app)) = (*pages.await?, *app.await?) else {
+        return Ok(CompletionVc::new());
+    };
+
+    let mut pages_routes = vec![];
+    for entry in pages.route_entries().await?.iter() {
+        let entry = entry.await?;
+        pages_routes.push((entry.pattern.clone(), entry.file));
+    }
+
+    let app_routes = app_routes(app).await?;
+
+    let strict_routing = next_config.await?.strict_routing();
+    for (pattern, pages_file, app_file) in find_duplicate_routes(&pages_routes, &app_routes) {
+        PagesStructureIssue {
+            severity: routing_issue_severity(strict_routing).into(),
+            path: pages_file,
+            message: StringVc::cell(format!(
+                "The route `{pattern}` is served by both {} in the pages directory and {} in the \
+                 app directory. The app directory takes precedence, so the page will never be \
+                 served.",
+                pages_file.await?.path,
+                app_file.await?.path,
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+
+    let mut app_root_files = vec![];
+    if let DirectoryContent::Entries(entries) = &*app.await?.directory.read_dir().await? {
+        for (name, entry) in entries.iter() {
+            if let DirectoryEntry::File(file) = entry {
+                app_root_files.push((name.clone(), *file));
+            }
+        }
+    }
+    if let Some((pages_file, app_file)) = find_not_found_conflict(&pages_routes, &app_root_files) {
+        PagesStructureIssue {
+            severity: IssueSeverity::Info.into(),
+            path: pages_file,
+            message: StringVc::cell(format!(
+                "Both {} in the pages directory and {} in the app directory define a not found \
+                 page. Paths served by the app directory use {}, all other paths use {}.",
+                pages_file.await?.path,
+                app_file.await?.path,
+                app_file.await?.path,
+                pages_file.await?.path,
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+
+    if let Some((pages_file, app_file)) =
+        find_global_wrapper_conflict(&pages_routes, &app_root_files)
+    {
+        PagesStructureIssue {
+            severity: IssueSeverity::Info.into(),
+            path: pages_file,
+            message: StringVc::cell(format!(
+                "Both {} in the pages directory and {} in the app directory wrap every page. \
+                 Pages of the pages directory are only wrapped by {}, and routes of the app \
+                 directory only by {}, never by both. Shared providers and styles have to be \
+                 added to both of them while migrating.",
+                pages_file.await?.path,
+                app_file.await?.path,
+                pages_file.await?.path,
+                app_file.await?.path,
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+
+    Ok(CompletionVc::new())
+}
+
+/// Returns the pages `_app` file and the app root `layout` file if both
+/// exist, since each of them wraps the pages of its router. `app_root_files`
+/// are the file names at the root of the app directory.
+fn find_global_wrapper_conflict<T: Copy>(
+    pages_routes: &[(String, T)],
+    app_root_files: &[(String, T)],
+) -> Option<(T, T)> {
+    let pages_file = pages_routes
+        .iter()
+        .find(|(pattern, _)| pattern == "/_app")?
+        .1;
+    let app_file = app_root_files
+        .iter()
+        .find(|(name, _)| matches!(name.rsplit_once('.'), Some(("layout", _))))?
+        .1;
+    Some((pages_file, app_file))
+}
+
+/// Returns the pages `/404` route and the app root `not-found` file if both
+/// exist. `app_root_files` are the file names at the root of the app
+/// directory.
+fn find_not_found_conflict<T: Copy>(
+    pages_routes: &[(String, T)],
+    app_root_files: &[(String, T)],
+) -> Option<(T, T)> {
+    let pages_file = pages_routes
+        .iter()
+        .find(|(pattern, _)| pattern == "/404")?
+        .1;
+    let app_file = app_root_files
+        .iter()
+        .find(|(name, _)| matches!(name.rsplit_once('.'), Some(("not-found", _))))?
+        .1;
+    Some((pages_file, app_file))
+}
+
+/// Returns `(pattern, pages value, app value)` for every route pattern that
+/// appears in both lists.
+fn find_duplicate_routes<T: Copy>(
+    pages: &[(String, T)],
+    app: &[(String, T)],
+) -> Vec<(String, T, T)> {
+    let app = app
+        .iter()
+        .map(|(pattern, value)| (pattern.as_str(), *value))
+        .collect::<BTreeMap<_, _>>();
+    pages
+        .iter()
+        .filter_map(|(pattern, pages_value)| {
+            app.get(pattern.as_str())
+                .map(|app_value| (pattern.clone(), *pages_value, *app_value))
+        })
+        .collect()
+}
+
+/// Returns every route pattern that appears more than once, with the values
+/// of all its routes, in order of first appearance.
+fn find_colliding_routes<T: Copy>(routes: &[(String, T)]) -> Vec<(String, Vec<T>)> {
+    let mut by_pattern = IndexMap::<&str, Vec<T>>::new();
+    for (pattern, value) in routes {
+        by_pattern.entry(pattern.as_str()).or_default().push(*value);
+    }
+    by_pattern
+        .into_iter()
+        .filter(|(_, values)| values.len() > 1)
+        .map(|(pattern, values)| (pattern.to_string(), values))
+        .collect()
+}
+
+/// Returns the values of all API routes serving the API root (`/api`), if
+/// there is more than one.
+fn find_api_root_conflict<T: Copy>(api_routes: &[(String, T)]) -> Option<Vec<T>> {
+    let api_root = format!("/{API_ROOT_NAME}");
+    find_colliding_routes(api_routes)
+        .into_iter()
+        .find(|(pattern, _)| *pattern == api_root)
+        .map(|(_, values)| values)
+}
+
+/// Decides how virtual routes merge with the `scanned` route patterns.
+/// Returns the virtual patterns to add and the scanned patterns they replace.
+fn merge_virtual_routes<'a>(
+    scanned: &[String],
+    virtual_patterns: impl IntoIterator<Item = &'a str>,
+    precedence: VirtualPagesPrecedence,
+) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut added = vec![];
+    let mut replaced = vec![];
+    for pattern in virtual_patterns {
+        if !scanned.iter().any(|scanned| scanned == pattern) {
+            added.push(pattern);
+        } else if precedence == VirtualPagesPrecedence::VirtualPages {
+            added.push(pattern);
+            replaced.push(pattern);
+        }
+    }
+    (added, replaced)
+}
+
+/// Returns the path of the url of a route pattern relative to the router
+/// root, e.g. `blog/[slug]/index.html` for `/blog/[slug]`. This is the
+/// inverse of [route_segments_for_url_path].
+fn url_path_for_route_pattern(pattern: &str) -> String {
+    let path = pattern.trim_matches('/');
+    if path.is_empty() {
+        "index.html".to_string()
+    } else {
+        format!("{path}/index.html")
+    }
+}
+
+/// Returns the first catch-all of a route that is followed by another
+/// segment, and that segment. A catch-all has to be the last segment, e.g.
+/// `/[a]/[...b]` is valid but `/[...a]/[b]` is not.
+fn find_segment_after_catch_all(
+    segments: &[RouteSegment],
+) -> Option<(&RouteSegment, &RouteSegment)> {
+    segments.windows(2).find_map(|pair| match &pair[0] {
+        RouteSegment::CatchAll(_) | RouteSegment::OptionalCatchAll(_) => Some((&pair[0], &pair[1])),
+        _ => None,
+    })
+}
+
+/// Explains why `following` never matches after `catch_all` in the route
+/// `pattern`, depending on the kind of segment, and how to fix the route.
+fn segment_after_catch_all_message(
+    pattern: &str,
+    catch_all: &RouteSegment,
+    following: &RouteSegment,
+) -> String {
+    match following {
+        RouteSegment::Dynamic(_) => format!(
+            "The dynamic segment `{following}` follows the catch-all `{catch_all}` in the route \
+             `{pattern}`. The catch-all matches all remaining segments, so `{following}` never \
+             receives a value. Move `{following}` before the catch-all, e.g. \
+             `/{following}/{catch_all}`."
+        ),
+        RouteSegment::CatchAll(_) | RouteSegment::OptionalCatchAll(_) => format!(
+            "The route `{pattern}` has more than one catch-all. `{catch_all}` matches all \
+             remaining segments, so `{following}` never matches. Use a single catch-all."
+        ),
+        RouteSegment::Static(_) => format!(
+            "The segment `{following}` follows the catch-all `{catch_all}` in the route \
+             `{pattern}`. A catch-all has to be the last segment of a route."
+        ),
+    }
+}
+
+/// Returns the first param that a route uses more than once, like `id` in
+/// `/[id]/[...id]`.
+fn find_repeated_param(segments: &[RouteSegment]) -> Option<&str> {
+    let mut params = vec![];
+    for param in segments.iter().filter_map(RouteSegment::param_name) {
+        if params.contains(&param) {
+            return Some(param);
+        }
+        params.push(param);
+    }
+    None
+}
+
+/// An issue reported while scanning the pages directory, streamed by
+/// [pages_diagnostics].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PagesDiagnostic {
+    /// The path of the file or directory the issue is about.
+    pub path: String,
+    /// The severity, as returned by `IssueSeverity::as_str`.
+    pub severity: String,
+    pub message: String,
+}
+
+/// A step of [pages_diagnostics].
+enum PagesDiagnosticsStep {
+    /// Queues the directories of the scan.
+    Scan(OptionPagesStructureVc),
+    /// Queues the subdirectories of a directory, before reporting its issues.
+    Enter(PagesStructureVc),
+    /// Reports the issues of a directory and its subdirectories.
+    Leave(PagesStructureVc),
+    /// Reports the issues of the whole scan, like those of the checks run on
+    /// the structure.
+    Finish(OptionPagesStructureVc),
+}
+
+/// Streams the issues emitted while scanning the pages directory, directory
+/// by directory, so that a CLI can print them while a large tree is still
+/// being traversed. Subdirectories are reported before their parents, and
+/// the checks run on the whole structure (see
+/// [find_pages_structure_with_options]) last. Every issue is streamed once.
+pub fn pages_diagnostics(
+    pages: OptionPagesStructureVc,
+) -> BoxStream<'static, Result<PagesDiagnostic>> {
+    let steps = vec![PagesDiagnosticsStep::Scan(pages)];
+    stream::try_unfold(
+        (steps, HashSet::new()),
+        |(mut steps, mut seen)| async move {
+            let Some(step) = steps.pop() else {
+                return Ok(None);
+            };
+            let diagnostics = match step {
+                PagesDiagnosticsStep::Scan(pages) => {
+                    steps.push(PagesDiagnosticsStep::Finish(pages));
+                    if let Some(structure) = *pages.await? {
+                        steps.push(PagesDiagnosticsStep::Enter(structure));
+                    }
+                    vec![]
+                }
+                PagesDiagnosticsStep::Enter(structure) => {
+                    steps.push(PagesDiagnosticsStep::Leave(structure));
+                    // Reversed, so that directories are visited in order.
+                    steps.extend(
+                        structure
+                            .await?
+                            .children
+                            .iter()
+                            .rev()
+                            .map(|child| PagesDiagnosticsStep::Enter(*child)),
+                    );
+                    vec![]
+                }
+                PagesDiagnosticsStep::Leave(structure) => {
+                    unseen_diagnostics(structure, &mut seen).await?
+                }
+                PagesDiagnosticsStep::Finish(pages) => unseen_diagnostics(pages, &mut seen).await?,
+            };
+            Ok(Some((
+                stream::iter(diagnostics.into_iter().map(Ok::<_, anyhow::Error>)),
+                (steps, seen),
+            )))
+        },
+    )
+    .try_flatten()
+    .boxed()
+}
+
+/// Returns the issues emitted while computing `source` that aren't in
+/// `seen` yet, and adds them to it.
+async fn unseen_diagnostics<T: CollectiblesSource + Copy>(
+    source: T,
+    seen: &mut HashSet<IssueVc>,
+) -> Result<Vec<PagesDiagnostic>> {
+    let mut diagnostics = vec![];
+    for issue in IssueVc::peek_issues_with_path(source).await?.await?.iter() {
+        let issue = issue.resolve().await?;
+        if !seen.insert(issue) {
+            continue;
+        }
+        diagnostics.push(PagesDiagnostic {
+            path: issue.context().await?.path.clone(),
+            severity: issue.severity().await?.as_str().to_string(),
+            message: issue.description().await?.clone_value(),
+        });
+    }
+    Ok(diagnostics)
+}
+
+/// The suffix of the basename of AMP variants of pages, like `post.amp.tsx`
+/// for `post.tsx`.
+const AMP_SUFFIX: &str = ".amp";
+
+/// Returns true if two different patterns without catch-alls can match the
+/// same pathname, segment by segment.
+fn routes_compete(a: &[RouteSegment], b: &[RouteSegment]) -> bool {
+    a.len() == b.len()
+        && a != b
+        && a.iter().zip(b).all(|pair| match pair {
+            (RouteSegment::Static(a), RouteSegment::Static(b)) => a == b,
+            (
+                RouteSegment::Static(_) | RouteSegment::Dynamic(_),
+                RouteSegment::Static(_) | RouteSegment::Dynamic(_),
+            ) => true,
+            _ => false,
+        })
+}
+
+/// Returns the pairs of route patterns that compete with each other, see
+/// [routes_compete]. Competing isn't transitive: `/blog/[id]` competes with
+/// both `/blog/latest` and `/[slug]/comments`, which don't compete with each
+/// other. Each pair is sorted, pairs are ordered by their first pattern.
+fn find_competing_routes<'a>(patterns: &[&'a str]) -> Vec<(&'a str, &'a str)> {
+    let segments = patterns
+        .iter()
+        .map(|pattern| route_segments_from_pattern(pattern))
+        .collect::<Vec<_>>();
+    let mut pairs = vec![];
+    for i in 0..patterns.len() {
+        for j in i + 1..patterns.len() {
+            if routes_compete(&segments[i], &segments[j]) {
+                let (a, b) = (patterns[i], patterns[j]);
+                pairs.push((a.min(b), a.max(b)));
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+static GET_STATIC_PATHS: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bgetStaticPaths\b").unwrap());
+static PATHS_ARRAY: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bpaths\s*:\s*\[").unwrap());
+static PARAMS_OBJECT: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bparams\s*:\s*\{").unwrap());
+
+/// Returns the contents of the literal `paths: [...]` array returned by
+/// `getStaticPaths`, if the source has one. Arrays built with spreads or
+/// calls (like `.map(...)`) aren't literal and are skipped.
+fn static_paths_array(source: &str) -> Option<&str> {
+    let start = GET_STATIC_PATHS.find(source)?.end();
+    let start = start + PATHS_ARRAY.find(&source[start..])?.end();
+    let mut depth = 0;
+    for (index, c) in source[start..].char_indices() {
+        match c {
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' if depth > 0 => depth -= 1,
+            ']' => {
+                let array = &source[start..start + index];
+                return (!array.contains("...") && !array.contains('(')).then_some(array);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the body of the object literal whose opening `{` precedes
+/// `source`, up to its matching `}`. Nested objects and arrays are part of
+/// the body.
+fn object_literal_body(source: &str) -> Option<&str> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (index, c) in source.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'' | '`') => quote = Some(c),
+            (None, '[' | '{' | '(') => depth += 1,
+            (None, '}') if depth == 0 => return Some(&source[..index]),
+            (None, ']' | '}' | ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the body of an object literal at its top-level commas.
+fn split_object_properties(body: &str) -> Vec<&str> {
+    let mut properties = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (index, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'' | '`') => quote = Some(c),
+            (None, '[' | '{' | '(') => depth += 1,
+            (None, ']' | '}' | ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                properties.push(&body[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    properties.push(&body[start..]);
+    properties
+}
+
+/// Returns the keys of each `params` object in the literal `paths` array
+/// returned by `getStaticPaths`.
+fn static_paths_param_keys(source: &str) -> Option<Vec<Vec<String>>> {
+    let array = static_paths_array(source)?;
+    Some(
+        PARAMS_OBJECT
+            .find_iter(array)
+            .filter_map(|params| object_literal_body(&array[params.end()..]))
+            .map(|params| {
+                split_object_properties(params)
+                    .into_iter()
+                    .filter_map(|property| {
+                        let key = property.split(':').next()?.trim();
+                        let key = key.trim_matches(|c| c == '"' || c == '\'');
+                        (!key.is_empty()).then(|| key.to_string())
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Keys of the params returned by `getStaticPaths` that don't match the
+/// params of the route.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct StaticPathsParamMismatch {
+    /// Keys that aren't params of the route.
+    unknown: Vec<String>,
+    /// Params of the route that some params object lacks. Optional
+    /// catch-alls may be left out.
+    missing: Vec<String>,
+}
+
+/// Compares the keys of the params returned by `getStaticPaths` in `source`
+/// with the params of the route `pattern`.
+fn static_paths_param_mismatch(pattern: &str, source: &str) -> Option<StaticPathsParamMismatch> {
+    let params = dynamic_params(pattern);
+    let mut mismatch = StaticPathsParamMismatch::default();
+    for keys in static_paths_param_keys(source)? {
+        for key in keys.iter() {
+            if !params.iter().any(|param| &param.name == key) && !mismatch.unknown.contains(key) {
+                mismatch.unknown.push(key.clone());
+            }
+        }
+        for param in params.iter() {
+            if !param.optional
+                && !keys.contains(&param.name)
+                && !mismatch.missing.contains(&param.name)
+            {
+                mismatch.missing.push(param.name.clone());
+            }
+        }
+    }
+    (mismatch != StaticPathsParamMismatch::default()).then_some(mismatch)
+}
+
+fn format_param_keys(keys: &[String]) -> String {
+    keys.iter()
+        .map(|key| format!("`{key}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+static HYBRID_AMP_CONFIG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"amp\s*:\s*["']hybrid["']"#).unwrap());
+
+/// Returns true if the source of a page exports `config = { amp: 'hybrid' }`.
+fn is_hybrid_amp_page(source: &str) -> bool {
+    HYBRID_AMP_CONFIG.is_match(source)
+}
+
+/// Returns true if the page `file` exports `config = { amp: 'hybrid' }`.
+async fn is_hybrid_amp_file(file: FileSystemPathVc) -> Result<bool> {
+    Ok(match &*file.read().await? {
+        FileContent::Content(content) => is_hybrid_amp_page(&content.content().to_str()?),
+        FileContent::NotFound => false,
+    })
+}
+
+static STATIC_PATHS_FALLBACK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\bfallback["']?\s*:\s*(true|false|"blocking"|'blocking')"#).unwrap()
+});
+
+/// Why a dynamic page can't be exported with `output: 'export'`.
+#[derive(Debug, PartialEq, Eq)]
+enum StaticExportProblem {
+    /// A dynamic page without `getStaticPaths`.
+    MissingStaticPaths,
+    /// A dynamic page whose `getStaticPaths` returns this `fallback` value
+    /// instead of `false`.
+    Fallback(String),
+}
+
+/// Checks the source of a page for `output: 'export'`. Only dynamic pages
+/// are checked. A `fallback` that isn't a literal can't be checked and
+/// passes.
+fn static_export_problem(pattern: &str, source: &str) -> Option<StaticExportProblem> {
+    if !is_dynamic_route_pattern(pattern) {
+        return None;
+    }
+    let Some(start) = GET_STATIC_PATHS.find(source) else {
+        return Some(StaticExportProblem::MissingStaticPaths);
+    };
+    let fallback = STATIC_PATHS_FALLBACK
+        .captures(&source[start.end()..])?
+        .get(1)?
+        .as_str();
+    (fallback != "false").then(|| StaticExportProblem::Fallback(fallback.to_string()))
+}
+
+static NOT_FOUND_RESULT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\bnotFound["']?\s*:\s*true\b"#).unwrap());
+
+/// Returns true if the source of a page contains `notFound: true`, like a
+/// data fetching function returning `{ notFound: true }`.
+fn returns_not_found(source: &str) -> bool {
+    NOT_FOUND_RESULT.is_match(source)
+}
+
+/// Returns the number of segments of an API route pattern below `/api`, if
+/// it exceeds `max_depth`.
+fn api_depth_exceeding(pattern: &str, max_depth: usize) -> Option<usize> {
+    let mut segments = pattern.split('/').filter(|segment| !segment.is_empty());
+    if segments.next() != Some(API_ROOT_NAME) {
+        return None;
+    }
+    let depth = segments.count();
+    (depth > max_depth).then_some(depth)
+}
+
+/// Returns true if a file name contains no uppercase letters.
+fn is_lowercase_file_name(name: &str) -> bool {
+    !name.chars().any(char::is_uppercase)
+}
+
+/// Returns the dynamic directories (relative to the pages directory) that
+/// none of the routable files are nested in.
+fn find_orphaned_dynamic_directories<'a>(
+    directories: &'a [String],
+    routable_files: &[String],
+) -> Vec<&'a str> {
+    directories
+        .iter()
+        .filter(|directory| {
+            let name = directory.rsplit('/').next().unwrap_or(directory);
+            RouteSegment::parse(name).param_name().is_some()
+                && !routable_files.iter().any(|file| {
+                    file.strip_prefix(directory.as_str())
+                        .map_or(false, |rest| rest.starts_with('/'))
+                })
+        })
+        .map(|directory| directory.as_str())
+        .collect()
+}
+
+/// Returns the patterns of the `(file, pattern)` routes whose file is inside
+/// `directory`, sorted. Paths are relative to the pages directory, which is
+/// `""` itself.
+fn patterns_under_directory<'a>(routes: &'a [(String, String)], directory: &str) -> Vec<&'a str> {
+    let mut patterns = routes
+        .iter()
+        .filter(|(file, _)| {
+            directory.is_empty()
+                || file
+                    .strip_prefix(directory)
+                    .map_or(false, |rest| rest.starts_with('/'))
+        })
+        .map(|(_, pattern)| pattern.as_str())
+        .collect::<Vec<_>>();
+    patterns.sort_unstable();
+    patterns
+}
+
+/// The name of the directory in the pages directory containing API routes.
+const API_ROOT_NAME: &str = "api";
+
+/// Describes what's wrong with a project root of the given type, or returns
+/// `None` if it can contain a pages directory.
+fn project_root_problem(entry_type: &FileSystemEntryType) -> Option<&'static str> {
+    match entry_type {
+        FileSystemEntryType::Directory | FileSystemEntryType::Symlink => None,
+        FileSystemEntryType::NotFound => Some("doesn't exist"),
+        FileSystemEntryType::File => Some("is a file, not a directory"),
+        _ => Some("is not a directory"),
+    }
+}
+
+/// Returns the `pages` or `src/pages` directory of the project if existing,
+/// and whether it is the `src/pages` fallback.
+///
+/// Reports an issue when the project root itself is missing or not a
+/// directory, which usually means a wrong project path was passed in. Its
+/// type is read reactively, so creating the root later recovers.
+async fn find_pages_dir(
+    project_path: FileSystemPathVc,
+) -> Result<Option<(FileSystemPathVc, bool)>> {
+    if let Some(problem) = project_root_problem(&*project_path.get_type().await?) {
+        PagesStructureIssue {
+            severity: IssueSeverity::Error.into(),
+            path: project_path,
+            message: StringVc::cell(format!(
+                "The project root `{}` {problem}, so no pages can be found. Check the project \
+                 path that was passed in.",
+                project_path.to_string().await?
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+        return Ok(None);
+    }
+
+    find_pages_root(project_path).await
+}
+
+/// The pages directories of a project, in the order they are looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PagesRootCandidate {
+    Pages,
+    SrcPages,
+}
+
+/// Picks the pages directory given which candidates are directories. `pages`
+/// wins over `src/pages`.
+fn select_pages_root(pages_is_dir: bool, src_pages_is_dir: bool) -> Option<PagesRootCandidate> {
+    if pages_is_dir {
+        Some(PagesRootCandidate::Pages)
+    } else if src_pages_is_dir {
+        Some(PagesRootCandidate::SrcPages)
+    } else {
+        None
+    }
+}
+
+/// Returns true if `path` is a directory or a symlink to one, like a `pages`
+/// symlink to `content/pages`. The type of a symlink is reported as such, so
+/// the directory is read through the link to tell where it points.
+async fn is_directory(path: FileSystemPathVc) -> Result<bool> {
+    let entry_type = path.get_type().await?;
+    let target_is_dir = *entry_type == FileSystemEntryType::Symlink
+        && matches!(*path.read_dir().await?, DirectoryContent::Entries(_));
+    Ok(resolves_to_directory(&entry_type, target_is_dir))
+}
+
+/// Returns true if an entry of the given type is a directory, following a
+/// symlink to the target whose type is given by `target_is_dir`.
+fn resolves_to_directory(entry_type: &FileSystemEntryType, target_is_dir: bool) -> bool {
+    match entry_type {
+        FileSystemEntryType::Directory => true,
+        FileSystemEntryType::Symlink => target_is_dir,
+        _ => false,
+    }
+}
+
+/// Returns the pages directory of the project and whether it is the
+/// `src/pages` fallback. Doesn't check the project root itself, see
+/// [find_pages_dir].
+async fn find_pages_root(
+    project_path: FileSystemPathVc,
+) -> Result<Option<(FileSystemPathVc, bool)>> {
+    let pages = project_path.join("pages");
+    let src_pages = project_path.join("src/pages");
+    let pages_is_dir = is_directory(pages).await?;
+    // Only look at `src/pages` when needed, so that changes to it don't
+    // invalidate projects with a `pages` directory.
+    let src_pages_is_dir = !pages_is_dir && is_directory(src_pages).await?;
+    let (pages_dir, is_src_fallback) = match select_pages_root(pages_is_dir, src_pages_is_dir) {
+        Some(PagesRootCandidate::Pages) => (pages, false),
+        Some(PagesRootCandidate::SrcPages) => (src_pages, true),
+        None => return Ok(None),
+    };
+    Ok(Some((pages_dir.resolve().await?, is_src_fallback)))
+}
+
+/// Where the pages structure of a project was loaded from, for tooling that
+/// prints e.g. "routes loaded from src/pages (scanned 120ms ago, 87 routes)".
+/// The scan generation and time are kept by a [PagesScanLog].
+#[turbo_tasks::value(shared)]
+#[derive(Debug, Clone)]
+pub struct PagesStructureMetadata {
+    /// The `pages` or `src/pages` directory the structure was scanned from.
+    pub pages_root: FileSystemPathVc,
+    /// Whether `src/pages` was used because there is no `pages` directory.
+    pub is_src_fallback: bool,
+    /// The number of routes, including API routes.
+    pub routes: usize,
+    /// A hash of the pages root and the routes, which only changes when they
+    /// do.
+    pub fingerprint: u64,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionPagesStructureMetadata(Option<PagesStructureMetadataVc>);
+
+/// Returns the [PagesStructureMetadata] of the structure returned by
+/// [find_pages_structure_with_options] for the same arguments, if there is a
+/// pages directory.
+#[turbo_tasks::function]
+pub async fn pages_structure_metadata(
+    project_path: FileSystemPathVc,
+    server_root: FileSystemPathVc,
+    next_config: NextConfigVc,
+    options: PagesScanOptionsVc,
+) -> Result<OptionPagesStructureMetadataVc> {
+    let Some(scan) = *scan_pages_root(project_path, server_root, next_config, options).await?
+    else {
+        return Ok(OptionPagesStructureMetadataVc::cell(None));
+    };
+    let scan = scan.await?;
+    let mut structural_routes = scan.structure.structural_routes().await?;
+    structural_routes.sort();
+    let fingerprint = hash_xxh3_hash64((
+        &scan.pages_root.await?.path,
+        scan.is_src_fallback,
+        &structural_routes,
+    ));
+    Ok(OptionPagesStructureMetadataVc::cell(Some(
+        PagesStructureMetadata {
+            pages_root: scan.pages_root,
+            is_src_fallback: scan.is_src_fallback,
+            routes: structural_routes.len(),
+            fingerprint,
+        }
+        .cell(),
+    )))
+}
+
+/// The latest scan recorded in a [PagesScanLog].
+#[derive(Debug, Clone)]
+pub struct PagesScanStatus {
+    pub metadata: PagesStructureMetadata,
+    /// Increases by one whenever the routes of the project or its pages root
+    /// change, so pollers can cheaply tell whether anything changed since
+    /// they last looked. Starts at 1.
+    pub generation: u64,
+    /// When the current generation was recorded.
+    pub scanned_at: SystemTime,
+}
+
+/// Counts the generations of the pages structure of a project. It is owned
+/// by the embedder, like the dev server, and filled by [record_pages_scan],
+/// so that the counter and the scan times stay out of cached values.
+#[derive(Default)]
+pub struct PagesScanLog {
+    latest: Mutex<Option<PagesScanStatus>>,
+}
+
+impl PagesScanLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a scan with `metadata` at `scanned_at`. Starts the next
+    /// generation if the fingerprint changed, and returns the latest status.
+    pub fn record(
+        &self,
+        metadata: PagesStructureMetadata,
+        scanned_at: SystemTime,
+    ) -> PagesScanStatus {
+        let mut latest = self.latest.lock().unwrap();
+        let status = match latest.take() {
+            Some(status) if status.metadata.fingerprint == metadata.fingerprint => status,
+            Some(status) => PagesScanStatus {
+                metadata,
+                generation: status.generation + 1,
+                scanned_at,
+            },
+            None => PagesScanStatus {
+                metadata,
+                generation: 1,
+                scanned_at,
+            },
+        };
+        *latest = Some(status.clone());
+        status
+    }
+
+    /// Returns the latest recorded scan, if any.
+    pub fn latest(&self) -> Option<PagesScanStatus> {
+        self.latest.lock().unwrap().clone()
+    }
+}
+
+/// A [PagesScanLog] that can be passed to turbo tasks.
+#[turbo_tasks::value(shared, serialization = "none", eq = "manual")]
+pub struct PagesScans(#[turbo_tasks(debug_ignore, trace_ignore)] pub Arc<PagesScanLog>);
+
+impl PartialEq for PagesScans {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PagesScans {}
+
+/// Records the metadata of the pages structure with `scans` every time it
+/// changes.
+#[turbo_tasks::function]
+pub async fn record_pages_scan(
+    metadata: OptionPagesStructureMetadataVc,
+    scans: PagesScansVc,
+) -> Result<CompletionVc> {
+    if let Some(metadata) = *metadata.await? {
+        scans
+            .await?
+            .0
+            .record(metadata.await?.clone_value(), SystemTime::now());
+    }
+    Ok(CompletionVc::new())
+}
+
+/// Options of [find_pages_structure_with_options]. The defaults scan the
+/// pages directory like [find_pages_structure].
+#[turbo_tasks::value(shared)]
+#[derive(Default)]
+pub struct PagesScanOptions {
+    /// Lists directories from a snapshot that was read ahead of time instead
+    /// of reading them one level at a time. The snapshot is the source of
+    /// truth for the directories it lists, which aren't read at all, so pass
+    /// an updated snapshot when they change. Every directory only depends on
+    /// its own listing, so an updated snapshot only rescans the directories
+    /// that changed. Directories missing from the snapshot are read live.
+    pub snapshot: Option<PagesDirectorySnapshotVc>,
+    /// Classifies files and directories instead of the default
+    /// [NextFileClassifier].
+    pub classifier: Option<PagesFileClassifierVc>,
+    /// Rewrites the route pattern of every item (e.g. to prefix all routes).
+    /// Routes that end up with the same pattern are reported.
+    pub transform: Option<PagesRouteTransformVc>,
+    /// Routes generated by plugins, merged into the scanned routes.
+    pub virtual_pages: Option<VirtualPagesVc>,
+    /// Decides whether a virtual route or a file serving the same route wins.
+    pub virtual_pages_precedence: VirtualPagesPrecedence,
+    /// True when scanning for a production build, which leaves out the
+    /// dev-only routes (see
+    /// [crate::next_config::NextConfig::dev_only_routes_prefix]).
+    pub production: bool,
+}
+
+impl PagesScanOptions {
+    pub fn with_snapshot(mut self, snapshot: PagesDirectorySnapshotVc) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    pub fn with_classifier(mut self, classifier: PagesFileClassifierVc) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    pub fn with_transform(mut self, transform: PagesRouteTransformVc) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    pub fn with_production(mut self, production: bool) -> Self {
+        self.production = production;
+        self
+    }
+
+    pub fn with_virtual_pages(
+        mut self,
+        virtual_pages: VirtualPagesVc,
+        precedence: VirtualPagesPrecedence,
+    ) -> Self {
+        self.virtual_pages = Some(virtual_pages);
+        self.virtual_pages_precedence = precedence;
+        self
+    }
+}
+
+/// Routes generated by plugins (e.g. `/healthz` or CMS-driven pages) that
+/// don't exist on disk, keyed by route pattern and served from the given
+/// module assets. Routes below `/api` are API routes.
+#[turbo_tasks::value(transparent)]
+pub struct VirtualPages(IndexMap<String, AssetVc>);
+
+/// Decides which route wins when a virtual route and a file in the pages
+/// directory serve the same route pattern.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, TraceRawVcs)]
+pub enum VirtualPagesPrecedence {
+    /// The file wins and the virtual route is ignored.
+    #[default]
+    RealFiles,
+    /// The virtual route replaces the file.
+    VirtualPages,
+}
+
+/// Finds and returns the [PagesStructure] of the pages directory if existing.
+#[turbo_tasks::function]
+pub fn find_pages_structure(
+    project_path: FileSystemPathVc,
+    server_root: FileSystemPathVc,
+    next_config: NextConfigVc,
+) -> OptionPagesStructureVc {
+    find_pages_structure_with_options(
+        project_path,
+        server_root,
+        next_config,
+        PagesScanOptions::default().cell(),
+    )
+}
+
+/// Finds and returns the [PagesStructure] of the pages directory if existing,
+/// scanned with the given options.
+#[turbo_tasks::function]
+pub async fn find_pages_structure_with_options(
+    project_path: FileSystemPathVc,
+    server_root: FileSystemPathVc,
+    next_config: NextConfigVc,
+    options: PagesScanOptionsVc,
+) -> Result<OptionPagesStructureVc> {
+    let structure = match *scan_pages_root(project_path, server_root, next_config, options).await? {
+        Some(scan) => Some(scan.await?.structure),
+        None => None,
+    };
+    Ok(OptionPagesStructureVc::cell(structure))
+}
+
+/// A [PagesStructure] and the pages root it was scanned from.
+#[turbo_tasks::value(shared)]
+struct PagesRootScan {
+    structure: PagesStructureVc,
+    pages_root: FileSystemPathVc,
+    is_src_fallback: bool,
+}
+
+#[turbo_tasks::value(transparent)]
+struct OptionPagesRootScan(Option<PagesRootScanVc>);
+
+/// Scans the pages directory of a project, see
+/// [find_pages_structure_with_options] and [pages_structure_metadata].
+#[turbo_tasks::function]
+async fn scan_pages_root(
+    project_path: FileSystemPathVc,
+    server_root: FileSystemPathVc,
+    next_config: NextConfigVc,
+    options: PagesScanOptionsVc,
+) -> Result<OptionPagesRootScanVc> {
+    let Some((pages_dir, is_src_fallback)) = find_pages_dir(project_path).await? else {
+        return Ok(OptionPagesRootScanVc::cell(None));
+    };
+    let options = options.await?;
+    let next_config_ref = next_config.await?;
+    let classifier = match options.classifier {
+        Some(classifier) => classifier,
+        None => next_config_file_classifier(next_config),
+    };
+
+    // Resolve the structure before storing it, so that readers never observe
+    // an unresolved Vc, e.g. while the cell is restored from a persistent cache.
+    let pages_structure = get_pages_structure_for_directory(
+        pages_dir,
+        SpecificityVc::exact(),
+        0,
+        server_root,
+        false,
+        classifier,
+        pages_dir,
+        server_root,
+        OptionPagesDirectorySnapshotVc::cell(options.snapshot),
+        next_config_ref.strict_routing(),
+        next_config_ref.max_pages_directory_entries(),
+    )
+    .resolve()
+    .await?;
+    let pages_structure = match next_config_ref.dev_only_routes_prefix() {
+        Some(prefix) if options.production => {
+            exclude_dev_only_routes(pages_structure, prefix)
+                .resolve()
+                .await?
+        }
+        _ => pages_structure,
+    };
+    let pages_structure = match options.transform {
+        Some(transform) => {
+            let transformed = transform_pages_structure(pages_structure, transform)
+                .resolve()
+                .await?;
+            check_route_collisions(transformed).await?;
+            transformed
+        }
+        None => pages_structure,
+    };
+    let pages_structure = match options.virtual_pages {
+        Some(virtual_pages) => {
+            merge_virtual_pages(
+                pages_structure,
+                virtual_pages,
+                Value::new(options.virtual_pages_precedence),
+            )
+            .resolve()
+            .await?
+        }
+        None => pages_structure,
+    };
+    if *next_config.require_lowercase_api_routes().await? {
+        pages_structure
+            .check_lowercase_api_routes(next_config_ref.strict_routing())
+            .await?;
+    }
+    if let Some(max_api_depth) = next_config_ref.max_api_depth() {
+        pages_structure
+            .check_api_depth(max_api_depth, next_config_ref.strict_routing())
+            .await?;
+    }
+    pages_structure
+        .check_api_root_handlers(next_config_ref.strict_routing())
+        .await?;
+    pages_structure
+        .check_segments_after_catch_all(next_config_ref.strict_routing())
+        .await?;
+    pages_structure
+        .check_repeated_params(next_config_ref.strict_routing())
+        .await?;
+    if next_config_ref.amp_suffix_pages_enabled() {
+        pages_structure
+            .check_amp_variants(next_config_ref.strict_routing())
+            .await?;
+    }
+    pages_structure
+        .check_reserved_param_names(next_config)
+        .await?;
+    let rewrites = next_config.rewrites().await?;
+    let has_rewrites = !(rewrites.before_files.is_empty()
+        && rewrites.after_files.is_empty()
+        && rewrites.fallback.is_empty());
+    // Rewrites can point at app routes and public files too. They are only
+    // looked up when there are rewrites, so that other projects don't depend
+    // on them.
+    if has_rewrites {
+        let targets = find_combined_structure(
+            OptionPagesStructureVc::cell(Some(pages_structure)),
+            find_app_structure(project_path, server_root, next_config),
+            next_config,
+        )
+        .rewrite_targets(project_path.join("public"));
+        pages_structure
+            .check_rewrite_cycles(next_config, targets)
+            .await?;
+        pages_structure
+            .check_rewrite_destinations(next_config, targets)
+            .await?;
+    }
+    if *next_config.check_static_paths_params().await? {
+        pages_structure
+            .check_static_paths_params(next_config_ref.strict_routing())
+            .await?;
+    }
+    pages_structure.check_static_export(next_config).await?;
+    Ok(OptionPagesRootScanVc::cell(Some(
+        PagesRootScan {
+            structure: pages_structure,
+            pages_root: pages_dir,
+            is_src_fallback,
+        }
+        .cell(),
+    )))
+}
+
+/// Leaves the top-level directories starting with `prefix` (like
+/// `pages/__debug`) out of a structure scanned for a production build, and
+/// reports which ones were left out.
+#[turbo_tasks::function]
+async fn exclude_dev_only_routes(
+    structure: PagesStructureVc,
+    prefix: &str,
+) -> Result<PagesStructureVc> {
+    let this = structure.await?;
+    let mut children = vec![];
+    let mut excluded = vec![];
+    for child in this.children.iter() {
+        let directory = child.await?.directory.await?;
+        let name = directory.path.rsplit('/').next().unwrap_or(&directory.path);
+        if is_dev_only_directory(name, prefix) {
+            excluded.push(format!("`{name}`"));
+        } else {
+            children.push(*child);
+        }
+    }
+    if excluded.is_empty() {
+        return Ok(structure);
+    }
+    PagesStructureIssue {
+        severity: IssueSeverity::Info.into(),
+        path: this.directory,
+        message: StringVc::cell(format!(
+            "The dev-only directories {} are left out of the production build.",
+            excluded.join(", ")
+        )),
+    }
+    .cell()
+    .as_issue()
+    .emit();
+    Ok(PagesStructure {
+        directory: this.directory,
+        router_root: this.router_root,
+        items: this.items.clone(),
+        middleware: this.middleware.clone(),
+        children,
+    }
+    .cell())
+}
+
+/// Returns true if the top-level directory `name` only exists in development,
+/// i.e. starts with the configured `prefix`.
+fn is_dev_only_directory(name: &str, prefix: &str) -> bool {
+    !prefix.is_empty() && name.starts_with(prefix)
+}
+
+/// Rewrites the route patterns of a structure with `transform`. Items keep
+/// their file and are moved to the transformed url, with the specificity of
+/// the transformed route (see [route_specificity]).
+#[turbo_tasks::function]
+async fn transform_pages_structure(
+    structure: PagesStructureVc,
+    transform: PagesRouteTransformVc,
+) -> Result<PagesStructureVc> {
+    let this = structure.await?;
+    let router_root = this.router_root.await?;
+    let transform_ref = transform.await?;
+
+    let mut items = Vec::with_capacity(this.items.len());
+    for item in this.items.iter() {
+        let mut item_value = item.await?.clone_value();
+        let url = item_value.url().await?;
+        let pattern = match router_root.get_path_to(&url) {
+            Some(path) => format_route_pattern(&route_segments_for_url_path(path)),
+            None => {
+                items.push(*item);
+                continue;
+            }
+        };
+        let transformed = (transform_ref.0)(&pattern);
+        if transformed == pattern {
+            items.push(*item);
+            continue;
+        }
+        *item_value.url_mut() = this
+            .router_root
+            .join(&url_path_for_route_pattern(&transformed));
+        *item_value.specificity_mut() =
+            route_specificity(&route_segments_from_pattern(&transformed));
+        items.push(item_value.cell().resolve().await?);
+    }
+
+    let mut children = Vec::with_capacity(this.children.len());
+    for child in this.children.iter() {
+        children.push(
+            transform_pages_structure(*child, transform)
+                .resolve()
+                .await?,
+        );
+    }
+
+    Ok(PagesStructure {
+        directory: this.directory,
+        router_root: this.router_root,
+        items,
+        middleware: this.middleware.clone(),
+        children,
+    }
+    .cell())
+}
+
+/// Merges virtual routes into a structure. Virtual items are added to the
+/// root of the structure. Depending on `precedence`, a virtual route with the
+/// same pattern as a scanned one is ignored or replaces it.
+#[turbo_tasks::function]
+async fn merge_virtual_pages(
+    structure: PagesStructureVc,
+    virtual_pages: VirtualPagesVc,
+    precedence: Value<VirtualPagesPrecedence>,
+) -> Result<PagesStructureVc> {
+    let scanned = structure.route_patterns().await?;
+    let virtual_pages = virtual_pages.await?;
+    let (added, replaced) = merge_virtual_routes(
+        &scanned,
+        virtual_pages.keys().map(String::as_str),
+        precedence.into_value(),
+    );
+
+    let mut replaced_items = IndexMap::new();
+    let mut queue = vec![structure];
+    while let Some(current) = queue.pop() {
+        let current = current.await?;
+        for item in current.items.iter() {
+            let pattern = item.route_pattern().await?;
+            if replaced.contains(&pattern.as_str()) {
+                replaced_items.insert(pattern.clone_value(), *item);
+            }
+        }
+        queue.extend(current.children.iter().copied());
+    }
+
+    let structure = if replaced.is_empty() {
+        structure
+    } else {
+        without_routes(
+            structure,
+            StringsVc::cell(replaced.into_iter().map(ToString::to_string).collect()),
+        )
+        .resolve()
+        .await?
+    };
+    let this = structure.await?;
+    let mut items = this.items.clone();
+    for pattern in added {
+        let asset = virtual_pages[pattern];
+        let segments = route_segments_from_pattern(pattern);
+        let specificity = route_specificity(&segments);
+        let is_api = segments.first() == Some(&RouteSegment::Static(API_ROOT_NAME.to_string()));
+        let item = PagesStructureItemVc::new(
+            this.router_root.join(&url_path_for_route_pattern(pattern)),
+            specificity,
+            asset.ident().path(),
+            is_api,
+            OptionStringVc::cell(None),
+            Value::new(PagesStructureItemProvenance::Virtual {
+                asset,
+                replaced: replaced_items.get(pattern).copied(),
+            }),
+            OptionStringVc::cell(None),
+        );
+        items.push(item.resolve().await?);
+    }
+
+    Ok(PagesStructure {
+        directory: this.directory,
+        router_root: this.router_root,
+        items,
+        middleware: this.middleware.clone(),
+        children: this.children.clone(),
+    }
+    .cell())
+}
+
+/// Removes the items serving any of `patterns` from a structure.
+#[turbo_tasks::function]
+async fn without_routes(
+    structure: PagesStructureVc,
+    patterns: StringsVc,
+) -> Result<PagesStructureVc> {
+    let this = structure.await?;
+    let patterns_ref = patterns.await?;
+    let mut items = vec![];
+    for item in this.items.iter() {
+        if !patterns_ref.contains(&*item.route_pattern().await?) {
+            items.push(*item);
+        }
+    }
+    let mut children = vec![];
+    for child in this.children.iter() {
+        children.push(without_routes(*child, patterns).resolve().await?);
+    }
+    Ok(PagesStructure {
+        directory: this.directory,
+        router_root: this.router_root,
+        items,
+        middleware: this.middleware.clone(),
+        children,
+    }
+    .cell())
+}
+
+/// Reports route patterns that are served by several files, which can happen
+/// after the patterns were transformed.
+#[turbo_tasks::function]
+async fn check_route_collisions(structure: PagesStructureVc) -> Result<CompletionVc> {
+    let mut routes = vec![];
+    for entry in structure.route_entries().await?.iter() {
+        let entry = entry.await?;
+        routes.push((entry.pattern.clone(), entry.file));
+    }
+    for (pattern, files) in find_colliding_routes(&routes) {
+        let mut paths = vec![];
+        for file in files.iter() {
+            paths.push(file.await?.path.clone());
+        }
+        PagesStructureIssue {
+            severity: IssueSeverity::Error.into(),
+            path: files[0],
+            message: StringVc::cell(format!(
+                "The route `{pattern}` is served by several files after transforming the route \
+                 patterns: {}.",
+                paths.join(", ")
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+    Ok(CompletionVc::new())
+}
+
+/// The type of an entry in a [PagesDirectorySnapshot].
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, TraceRawVcs,
+)]
+pub enum SnapshotEntryType {
+    File,
+    Directory,
+}
+
+/// A listing of the pages directory that was read ahead of time. Maps the
+/// path of every directory relative to the pages directory (`""` for the
+/// pages directory itself) to its entries.
+#[turbo_tasks::value(transparent)]
+pub struct PagesDirectorySnapshot(IndexMap<String, Vec<(String, SnapshotEntryType)>>);
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionPagesDirectorySnapshot(Option<PagesDirectorySnapshotVc>);
+
+#[turbo_tasks::value(transparent)]
+struct OptionSnapshotListing(Option<Vec<(String, SnapshotEntryType)>>);
+
+/// Returns the listing of the directory at `relative` (relative to the pages
+/// directory) in `snapshot`, if the snapshot has one. Reading the listing in
+/// its own task makes every directory depend on its own listing only, instead
+/// of on the whole snapshot.
+#[turbo_tasks::function]
+async fn snapshot_listing(
+    snapshot: PagesDirectorySnapshotVc,
+    relative: &str,
+) -> Result<OptionSnapshotListingVc> {
+    Ok(OptionSnapshotListingVc::cell(
+        snapshot.await?.get(relative).cloned(),
+    ))
+}
+
+/// Returns the entries of a directory listing, or None if there are more
+/// than `max_entries` of them. Huge directories (e.g. created by a runaway
+/// script) are left out before any items are created for them, so they
+/// can't exhaust the memory of the dev server.
+fn capped_entries<T>(entries: &[T], max_entries: usize) -> Option<&[T]> {
+    (entries.len() <= max_entries).then_some(entries)
+}
+
+/// Directories with more entries than this are sorted on a blocking thread.
+const BLOCKING_SORT_THRESHOLD: usize = 10_000;
+
+/// Runs `sort`, which sorts `len` entries, on a blocking thread if there are
+/// more than [BLOCKING_SORT_THRESHOLD] of them. Sorting a directory of
+/// generated pages with tens of thousands of entries would otherwise stall
+/// the executor thread running the task. Smaller directories are sorted
+/// inline, since the thread hop would cost more than the sort.
+async fn run_sort<T: Send + 'static>(
+    len: usize,
+    sort: impl FnOnce() -> T + Send + 'static,
+) -> Result<T> {
+    if len <= BLOCKING_SORT_THRESHOLD {
+        return Ok(sort());
+    }
+    Ok(tokio::task::spawn_blocking(sort).await?)
+}
+
+/// The top level of the pages directory, without any nested directories
+/// being traversed.
+#[turbo_tasks::value]
+pub struct ShallowPagesStructure {
+    pub directory: FileSystemPathVc,
+    pub items: Vec<PagesStructureItemVc>,
+    /// Legacy `_middleware` files at the root, see
+    /// [PagesStructure::middleware].
+    pub middleware: Vec<FileSystemPathVc>,
+    pub child_directories: Vec<String>,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionShallowPagesStructure(Option<ShallowPagesStructureVc>);
+
+/// Finds the pages directory like [find_pages_structure], but only returns
+/// the routes at its root and the names of its child directories. The root is
+/// listed and reported like in a full traversal, see [list_pages_directory].
+/// Use [find_pages_structure] for a full traversal.
+#[turbo_tasks::function]
+pub async fn find_pages_structure_shallow(
+    project_path: FileSystemPathVc,
+    server_root: FileSystemPathVc,
+    next_config: NextConfigVc,
+) -> Result<OptionShallowPagesStructureVc> {
+    let Some((pages_dir, _)) = find_pages_dir(project_path).await? else {
+        return Ok(OptionShallowPagesStructureVc::cell(None));
+    };
+
+    let next_config_ref = next_config.await?;
+    let listing = list_pages_directory(
+        pages_dir,
+        SpecificityVc::exact(),
+        0,
+        server_root,
+        false,
+        next_config_file_classifier(next_config),
+        pages_dir,
+        server_root,
+        OptionPagesDirectorySnapshotVc::cell(None),
+        next_config_ref.strict_routing(),
+        next_config_ref.max_pages_directory_entries(),
+    )
+    .await?;
+
+    Ok(OptionShallowPagesStructureVc::cell(Some(
+        ShallowPagesStructure {
+            directory: pages_dir,
+            items: listing.items,
+            middleware: listing.middleware,
+            child_directories: listing
+                .children
+                .into_iter()
+                .map(|child| child.name)
+                .collect(),
+        }
+        .cell(),
+    )))
+}
+
+/// Parses a directory as pages directory and returns the [PagesStructure].
+/// Use [find_pages_structure_with_options] to scan with the Next.js config or
+/// a custom [FileClassifier].
+#[turbo_tasks::function]
+pub fn get_pages_structure(
+    pages_dir: FileSystemPathVc,
+    server_root: FileSystemPathVc,
+    page_extensions: PageExtensionsVc,
+) -> PagesStructureVc {
+    get_pages_structure_for_directory(
+        pages_dir,
+        SpecificityVc::exact(),
+        0,
+        server_root,
+        false,
+        next_file_classifier(page_extensions, false),
+        pages_dir,
+        server_root,
+        OptionPagesDirectorySnapshotVc::cell(None),
+        false,
+        DEFAULT_MAX_PAGES_DIRECTORY_ENTRIES,
+    )
+}
+
+/// Returns the route pattern (like `/blog/[slug]`) of the file at `rel`,
+/// relative to the pages directory, without reading the file system. Returns
+/// None if the file isn't a route, e.g. because it doesn't have one of the
+/// `page_extensions` or is a special file like `_app`.
+#[turbo_tasks::function]
+pub async fn route_from_relative_path(
+    rel: String,
+    page_extensions: PageExtensionsVc,
+) -> Result<OptionStringVc> {
+    let classifier = next_file_classifier(page_extensions, false).await?;
+    Ok(OptionStringVc::cell(route_for_relative_path(
+        &rel,
+        &*classifier.0,
+    )))
+}
+
+/// Returns the route pattern of the file at `rel` in the pages directory,
+/// classified like the traversal does: `index` files serve their directory,
+/// files in the API root are API routes and AMP variants serve their page.
+fn route_for_relative_path(rel: &str, classifier: &dyn FileClassifier) -> Option<String> {
+    let rel = rel.replace('\\', "/");
+    let (directory, name) = rel.rsplit_once('/').unwrap_or(("", &rel));
+    let directories = directory
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
+    if !directories
+        .iter()
+        .all(|name| classifier.include_directory(name))
+    {
+        return None;
+    }
+    let is_api_dir = directories.first() == Some(&classifier.api_root_name());
+    let basename = match classifier.classify_file(name, is_api_dir, directories.is_empty()) {
+        FileClassification::Page { basename } => classifier
+            .amp_variant_of(&basename)
+            .unwrap_or(&basename)
+            .to_string(),
+        FileClassification::Api { basename } | FileClassification::Custom { basename, .. } => {
+            basename
+        }
+        FileClassification::Special { .. }
+        | FileClassification::Unsupported { .. }
+        | FileClassification::Ignored => return None,
+    };
+    let mut path = directories.join("/");
+    if basename != "index" {
+        path.push('/');
+        path.push_str(&basename);
+    }
+    Some(format_route_pattern(&route_segments_from_pattern(&path)))
+}
+
+/// Returns the files of the pages directory (relative to it) that are
+/// classified differently with the `current` page extensions than with the
+/// `previous` ones, see [PageExtensions::reclassified_files]. This tells
+/// which routes a change of `pageExtensions` adds or removes, e.g. to report
+/// it. The scan doesn't use it: its directories are scanned with a classifier
+/// built from the extensions, so a change rescans all of them.
+#[turbo_tasks::function]
+pub async fn pages_reclassified_by_extensions(
+    pages_dir: FileSystemPathVc,
+    previous: PageExtensionsVc,
+    current: PageExtensionsVc,
+) -> Result<StringsVc> {
+    let mut files = vec![];
+    let mut queue = vec![(pages_dir, String::new())];
+    while let Some((dir, prefix)) = queue.pop() {
+        for (name, entry) in directory_entries(&*dir.read_dir().await?) {
+            match entry {
+                DirectoryEntry::File(_) => files.push(format!("{prefix}{name}")),
+                DirectoryEntry::Directory(child) => queue.push((child, format!("{prefix}{name}/"))),
+                _ => {}
+            }
+        }
+    }
+    files.sort();
+    let current = current.await?;
+    Ok(StringsVc::cell(
+        current
+            .reclassified_files(&previous.await?, files.iter().map(String::as_str))
+            .into_iter()
+            .map(ToString::to_string)
+            .collect(),
+    ))
+}
+
+/// Handles a directory in the pages directory (or the pages directory itself).
+/// Lists it with [list_pages_directory] and calls itself recursively for sub
+/// directories.
+#[allow(clippy::too_many_arguments)]
+#[turbo_tasks::function]
+async fn get_pages_structure_for_directory(
+    input_dir: FileSystemPathVc,
+    specificity: SpecificityVc,
+    position: u32,
+    url: FileSystemPathVc,
+    is_api: bool,
+    classifier: PagesFileClassifierVc,
+    pages_dir: FileSystemPathVc,
+    router_root: FileSystemPathVc,
+    snapshot: OptionPagesDirectorySnapshotVc,
+    strict_routing: bool,
+    max_entries: u32,
+) -> Result<PagesStructureVc> {
+    let listing = list_pages_directory(
+        input_dir,
+        specificity,
+        position,
+        url,
+        is_api,
+        classifier,
+        pages_dir,
+        router_root,
+        snapshot,
+        strict_routing,
+        max_entries,
+    )
+    .await?;
+
+    // Store resolved Vcs only, so that the structure is self-contained when it
+    // is restored from a persistent cache.
+    let mut children = Vec::with_capacity(listing.children.len());
+    for child in listing.children {
+        let structure = get_pages_structure_for_directory(
+            child.directory,
+            child.specificity,
+            position + 1,
+            url.join(&child.name),
+            child.is_api,
+            classifier,
+            pages_dir,
+            router_root,
+            snapshot,
+            strict_routing,
+            max_entries,
+        );
+        children.push(structure.resolve().await?);
+    }
+
+    Ok(PagesStructure {
+        directory: input_dir,
+        router_root,
+        items: listing.items,
+        middleware: listing.middleware,
+        children,
+    }
+    .cell())
+}
+
+/// A sub directory found by [list_pages_directory], with what its scan
+/// starts from.
+struct PagesChildDirectory {
+    name: String,
+    directory: FileSystemPathVc,
+    specificity: SpecificityVc,
+    is_api: bool,
+}
+
+/// The classified entries of a single directory in the pages directory, in
+/// a deterministic order.
+struct PagesDirectoryListing {
+    items: Vec<PagesStructureItemVc>,
+    middleware: Vec<FileSystemPathVc>,
+    children: Vec<PagesChildDirectory>,
+}
+
+/// Lists a directory in the pages directory (or the pages directory itself)
+/// without descending into sub directories, and reports the problems found
+/// in it as issues of the calling task.
+///
+/// `is_api` is true if the directory is inside the API root, which is
+/// decided once per directory instead of once per file. `strict_routing`
+/// selects the severity of routing diagnostics, see [routing_issue_severity].
+/// Directories with more than `max_entries` entries are reported and left
+/// out, see [capped_entries].
+#[allow(clippy::too_many_arguments)]
+async fn list_pages_directory(
+    input_dir: FileSystemPathVc,
+    specificity: SpecificityVc,
+    position: u32,
+    url: FileSystemPathVc,
+    is_api: bool,
+    classifier: PagesFileClassifierVc,
+    pages_dir: FileSystemPathVc,
+    router_root: FileSystemPathVc,
+    snapshot: OptionPagesDirectorySnapshotVc,
+    strict_routing: bool,
+    max_entries: u32,
+) -> Result<PagesDirectoryListing> {
+    let classifier_ref = classifier.await?;
+    let segment_index = router_path_depth(router_root, url).await?;
+
+    let scan = |entries: &[(String, DirectoryEntry)]| {
+        let Some(entries) = capped_entries(entries, max_entries as usize) else {
+            return (vec![], vec![], vec![], vec![], vec![], Some(entries.len()));
+        };
+        let name_collisions =
+            find_unnormalized_name_collisions(entries.iter().map(|(name, _)| name.as_str()))
+                .into_iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect::<Vec<_>>();
+        let mut children = vec![];
+        let mut items = vec![];
+        let mut middleware = vec![];
+        for (name, entry) in entries.iter() {
+            if !check_entry_name(name, entry, strict_routing) {
+                continue;
+            }
+            if position == 0 {
+                check_api_root_collision(
+                    name,
+                    entry,
+                    classifier_ref.0.api_root_name(),
+                    strict_routing,
+                );
+            }
+            let is_file = matches!(entry, DirectoryEntry::File(_));
+            let specificity = segment_specificity(specificity, name, is_file, segment_index);
+            match entry {
+                DirectoryEntry::File(file) => {
+                    if is_legacy_middleware(name, is_api, position == 0, &*classifier_ref.0) {
+                        middleware.push((name.clone(), *file));
+                    } else if let Some((basename, item)) = pages_structure_item_for_file(
+                        name,
+                        *file,
+                        specificity,
+                        url,
+                        is_api,
+                        position == 0,
+                        &*classifier_ref.0,
+                        pages_dir,
+                    ) {
+                        let priority = classifier_ref.0.file_priority(name);
+                        items.push((name.clone(), basename, priority, item));
+                    }
+                }
+                DirectoryEntry::Directory(dir) => {
+                    if !classifier_ref.0.include_directory(name) || !check_param_name(name, *dir) {
+                        continue;
+                    }
+                    children.push(PagesChildDirectory {
+                        name: name.clone(),
+                        directory: *dir,
+                        specificity,
+                        is_api: is_api
+                            || (position == 0 && name == classifier_ref.0.api_root_name()),
+                    });
+                }
+                _ => {}
+            }
+        }
+        let (items, duplicates) = dedupe_by_basename(items);
+        let items = attach_amp_variants(items);
+        (
+            items,
+            children,
+            middleware,
+            duplicates,
+            name_collisions,
+            None,
+        )
+    };
+
+    let snapshot_listing = match *snapshot.await? {
+        Some(snapshot) => {
+            let pages_dir_path = pages_dir.await?;
+            let input_dir_path = input_dir.await?;
+            match pages_dir_path.get_path_to(&input_dir_path) {
+                Some(relative) => snapshot_listing(snapshot, relative).await?.clone_value(),
+                None => None,
+            }
+        }
+        None => None,
+    };
+    let entries = match snapshot_listing {
+        Some(snapshot_listing) => snapshot_listing
+            .into_iter()
+            .map(|(name, entry_type)| {
+                let path = input_dir.join(&name);
+                let entry = match entry_type {
+                    SnapshotEntryType::File => DirectoryEntry::File(path),
+                    SnapshotEntryType::Directory => DirectoryEntry::Directory(path),
+                };
+                (name, entry)
+            })
+            .collect::<Vec<_>>(),
+        None => directory_entries(&*input_dir.read_dir().await?),
+    };
+    let (mut items, mut children, mut middleware, duplicates, name_collisions, truncated) =
+        scan(&entries);
+
+    if let Some(entries) = truncated {
+        PagesStructureIssue {
+            severity: IssueSeverity::Error.into(),
+            path: input_dir,
+            message: StringVc::cell(format!(
+                "The directory has {entries} entries, more than the limit of {max_entries}, so \
+                 its routes are left out. This is usually caused by generated files. Move them \
+                 out of the pages directory, or raise `experimental.maxPagesDirectoryEntries` in \
+                 the Next.js config."
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+
+    for (first, second) in name_collisions {
+        PagesStructureIssue {
+            severity: routing_issue_severity(strict_routing).into(),
+            path: input_dir.join(&second),
+            message: StringVc::cell(format!(
+                "The names {first:?} and {second:?} look the same, but are encoded differently \
+                 (they are equal after Unicode NFC normalization). Depending on the file system \
+                 they are the same entry or two entries serving the same route. Rename one of \
+                 them."
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+
+    for (ignored, used) in duplicates {
+        PagesStructureIssue {
+            severity: routing_issue_severity(strict_routing).into(),
+            path: input_dir.join(&ignored),
+            message: StringVc::cell(format!(
+                "The files `{used}` and `{ignored}` serve the same route. `{used}` is used and \
+                 `{ignored}` is ignored, as decided by the order of `pageExtensions`."
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+
+    // Ensure deterministic order since read_dir is not deterministic
+    let (items, children) = run_sort(items.len() + children.len(), move || {
+        items.sort_by(|(a, _), (b, _)| a.cmp(b));
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        (items, children)
+    })
+    .await?;
+    middleware.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (first, second) in
+        find_conflicting_dynamic_siblings(children.iter().map(|child| child.name.as_str()))
+    {
+        PagesStructureIssue {
+            severity: routing_issue_severity(strict_routing).into(),
+            path: input_dir.join(second),
+            message: StringVc::cell(format!(
+                "The dynamic directories `{first}` and `{second}` match the same paths with \
+                 different param names. You cannot use different slug names for the same dynamic \
+                 path. Merge them into one directory."
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+
+    // Store resolved Vcs only, so that the structure is self-contained when it
+    // is restored from a persistent cache.
+    let mut resolved_items = Vec::with_capacity(items.len());
+    for (_, item) in items {
+        resolved_items.push(item.resolve().await?);
+    }
+    let mut resolved_middleware = Vec::with_capacity(middleware.len());
+    for (_, file) in middleware {
+        resolved_middleware.push(file.resolve().await?);
+    }
+
+    Ok(PagesDirectoryListing {
+        items: resolved_items,
+        middleware: resolved_middleware,
+        children,
+    })
+}
+
+/// Why the name of a directory entry can't be part of a route.
+#[derive(Debug, PartialEq, Eq)]
+enum InvalidEntryName {
+    /// An NTFS alternate data stream like `about.tsx:Zone.Identifier`, which
+    /// some tools expose as a separate entry on Windows.
+    AlternateDataStream,
+    /// Trailing whitespace, which NTFS preserves but other file systems and
+    /// checkouts may not.
+    TrailingWhitespace,
+    /// A lock or partially written file that editors create while saving,
+    /// like `.#about.tsx` (Emacs), `about.tsx.tmp` or `about.tsx.crswap`
+    /// (VS Code).
+    TransientFile,
+}
+
+/// The suffixes of files that editors write next to a file while saving it.
+const TRANSIENT_FILE_SUFFIXES: [&str; 2] = [".tmp", ".crswap"];
+
+/// Checks an entry name so that the routes are the same as in a POSIX
+/// checkout of the same project. `windows` enables the checks for names that
+/// are only produced on Windows.
+fn invalid_entry_name(name: &str, windows: bool) -> Option<InvalidEntryName> {
+    if name.starts_with(".#")
+        || TRANSIENT_FILE_SUFFIXES
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    {
+        Some(InvalidEntryName::TransientFile)
+    } else if windows && name.contains(':') {
+        Some(InvalidEntryName::AlternateDataStream)
+    } else if name.ends_with(char::is_whitespace) {
+        Some(InvalidEntryName::TrailingWhitespace)
+    } else {
+        None
+    }
+}
+
+/// Returns the pairs of sibling names that differ but are equal after NFC
+/// normalization, like `é` written as one code point and as `e` followed by a
+/// combining accent. Names are compared in sorted order.
+fn find_unnormalized_name_collisions<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+) -> Vec<(&'a str, &'a str)> {
+    let mut names = names.into_iter().collect::<Vec<_>>();
+    names.sort_unstable();
+    let mut seen = BTreeMap::<String, &str>::new();
+    let mut collisions = vec![];
+    for name in names {
+        let normalized = name.nfc().collect::<String>();
+        match seen.get(&normalized) {
+            Some(&first) if first != name => collisions.push((first, name)),
+            Some(_) => {}
+            None => {
+                seen.insert(normalized, name);
+            }
+        }
+    }
+    collisions
+}
+
+/// Returns the pairs of sibling directory names that are the same kind of
+/// dynamic segment with different param names, like `[a]` and `[b]`, or
+/// `[...a]` and `[...b]`. Each name is paired with the first one of its kind.
+fn find_conflicting_dynamic_siblings<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+) -> Vec<(&'a str, &'a str)> {
+    let mut first_of_kind: [Option<(&str, String)>; 3] = Default::default();
+    let mut conflicts = vec![];
+    for name in names {
+        let (kind, param) = match RouteSegment::parse(name) {
+            RouteSegment::Dynamic(param) => (0, param),
+            RouteSegment::CatchAll(param) => (1, param),
+            RouteSegment::OptionalCatchAll(param) => (2, param),
+            RouteSegment::Static(_) => continue,
+        };
+        match &first_of_kind[kind] {
+            Some((first, first_param)) if *first_param != param => {
+                conflicts.push((*first, name));
+            }
+            Some(_) => {}
+            None => first_of_kind[kind] = Some((name, param)),
+        }
+    }
+    conflicts
+}
+
+/// Returns the severity of routing diagnostics: route conflicts, name
+/// collisions and invalid entry names. They are warnings, unless
+/// `experimental.strictRouting` upgrades them to errors, which fail the build.
+/// New routing diagnostics should use this instead of a fixed severity.
+pub fn routing_issue_severity(strict_routing: bool) -> IssueSeverity {
+    if strict_routing {
+        IssueSeverity::Error
+    } else {
+        IssueSeverity::Warning
+    }
+}
+
+/// Returns false if a directory entry should be skipped because of its name.
+/// Alternate data streams and transient editor files are skipped silently,
+/// so saving a page doesn't add and remove routes. Other invalid names are
+/// reported.
+fn check_entry_name(name: &str, entry: &DirectoryEntry, strict_routing: bool) -> bool {
+    let path = match entry {
+        DirectoryEntry::File(path) | DirectoryEntry::Directory(path) => *path,
+        _ => return true,
+    };
+    match invalid_entry_name(name, cfg!(windows)) {
+        None => true,
+        Some(InvalidEntryName::AlternateDataStream | InvalidEntryName::TransientFile) => false,
+        Some(InvalidEntryName::TrailingWhitespace) => {
+            PagesStructureIssue {
+                severity: routing_issue_severity(strict_routing).into(),
+                path,
+                message: StringVc::cell(format!(
+                    "The name {name:?} ends with whitespace, which is not preserved on all file \
+                     systems. It will be ignored; rename it to {:?}.",
+                    name.trim_end()
+                )),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+            false
+        }
+    }
+}
+
+/// Returns the entries of a directory, or no entries if it doesn't exist.
+fn directory_entries(content: &DirectoryContent) -> Vec<(String, DirectoryEntry)> {
+    match content {
+        DirectoryContent::Entries(entries) => entries
+            .iter()
+            .map(|(name, entry)| (name.clone(), *entry))
+            .collect(),
+        DirectoryContent::NotFound => vec![],
+    }
+}
+
+/// Returns true if the route name (a directory name or a file name without
+/// extension) only differs from the API root name by case. On case-insensitive
+/// file systems it would collide with the API routes.
+fn collides_with_api_root(name: &str, api_root_name: &str) -> bool {
+    name != api_root_name && name.eq_ignore_ascii_case(api_root_name)
+}
+
+/// Emits an issue if an entry at the root of the pages directory collides with
+/// the API root on case-insensitive file systems.
+fn check_api_root_collision(
+    name: &str,
+    entry: &DirectoryEntry,
+    api_root_name: &str,
+    strict_routing: bool,
+) {
+    let (route_name, path) = match entry {
+        DirectoryEntry::File(file) => (name.rsplit_once('.').map_or(name, |(n, _)| n), *file),
+        DirectoryEntry::Directory(dir) => (name, *dir),
+        _ => return,
+    };
+    if collides_with_api_root(route_name, api_root_name) {
+        PagesStructureIssue {
+            severity: routing_issue_severity(strict_routing).into(),
+            path,
+            message: StringVc::cell(format!(
+                "The route `/{route_name}` only differs from the API routes in `/{api_root_name}` \
+                 by case. They will collide on case-insensitive file systems."
+            )),
+        }
+        .cell()
+        .as_issue()
+        .emit();
+    }
+}
+
+/// Checks that a dynamic route name (a directory name or a file name without
+/// extension) like `[slug]` has a param name. Emits an issue and returns false
+/// for malformed names like `[]` or `[...]`.
+fn check_param_name(name: &str, path: FileSystemPathVc) -> bool {
+    if RouteSegment::parse(name).param_name() != Some("") {
+        return true;
+    }
+    PagesStructureIssue {
+        severity: IssueSeverity::Error.into(),
+        path,
+        message: StringVc::cell(format!(
+            "The dynamic route segment `{name}` has no param name. Use a name like `[slug]` or \
+             `[...slug]` instead. The route will be ignored."
+        )),
+    }
+    .cell()
+    .as_issue()
+    .emit();
+    false
+}
+
+/// Returns the route segment the file or directory `name` adds to its
+/// routes. Files are routed by their basename, so `[...slug].tsx` adds the
+/// same segment as the directory `[...slug]`, and `index` files add none.
+fn entry_route_segment(name: &str, is_file: bool) -> Option<RouteSegment> {
+    let name = if is_file {
+        name.rsplit_once('.').map_or(name, |(basename, _)| basename)
+    } else {
+        name
+    };
+    if is_file && name == "index" {
+        return None;
+    }
+    Some(RouteSegment::parse(name))
+}
+
+/// Returns the specificity of a route through the file or directory `name`.
+/// `position` is the index of the segment of `name` in the router path, so a
+/// dynamic segment after a static one (`/a/[x]`) is more specific than one at
+/// the start of the route (`/[x]`), like in route resolution.
+fn segment_specificity(
+    specificity: SpecificityVc,
+    name: &str,
+    is_file: bool,
+    position: u32,
+) -> SpecificityVc {
+    match entry_route_segment(name, is_file) {
+        Some(RouteSegment::CatchAll(_) | RouteSegment::OptionalCatchAll(_)) => {
+            specificity.with_catch_all(position)
+        }
+        Some(RouteSegment::Dynamic(_)) => specificity.with_dynamic_segment(position),
+        Some(RouteSegment::Static(_)) | None => specificity,
+    }
+}
+
+/// Returns the specificity of a route with the given segments, see
+/// [segment_specificity].
+fn route_specificity(segments: &[RouteSegment]) -> SpecificityVc {
+    segments
+        .iter()
+        .enumerate()
+        .fold(SpecificityVc::exact(), |specificity, (index, segment)| {
+            segment_specificity(specificity, &segment.to_string(), false, index as u32)
+        })
+}
+
+/// Returns the number of segments of `url` below `router_root`, which is the
+/// index in the router path of the entries of the directory served at `url`.
+async fn router_path_depth(router_root: FileSystemPathVc, url: FileSystemPathVc) -> Result<u32> {
+    let router_root = router_root.await?;
+    let url = url.await?;
+    Ok(router_root.get_path_to(&url).map_or(0, |path| {
+        path.split('/')
+            .filter(|segment| !segment.is_empty())
+            .count()
+    }) as u32)
+}
+
+/// Creates the [PagesStructureItem] for a file in a pages (sub)directory, or
+/// returns `None` if the file doesn't have a page extension. Unsupported files
+/// are reported and skipped.
+///
+/// Files in an API directory are API routes, and so is an `api` file at the
+/// root of the pages directory, since it serves `/api`. Like everywhere else,
+/// `index` files serve their directory, so `pages/api/index.ts` is the handler
+/// of `/api` too, while `pages/api/index/index.ts` serves `/api/index`.
+///
+/// An AMP variant (like `post.amp.tsx`, see [FileClassifier::amp_variant_of])
+/// gets the route of its page (`/post`), but keeps its own basename, so that
+/// it isn't deduplicated against the page.
+#[allow(clippy::too_many_arguments)]
+fn pages_structure_item_for_file(
+    name: &str,
+    file: FileSystemPathVc,
+    specificity: SpecificityVc,
+    url: FileSystemPathVc,
+    is_api_dir: bool,
+    is_root: bool,
+    classifier: &dyn FileClassifier,
+    pages_dir: FileSystemPathVc,
+) -> Option<(String, ScannedFile)> {
+    let (basename, is_api, custom_kind) = match classifier.classify_file(name, is_api_dir, is_root)
+    {
+        // Legacy middleware isn't a route, see [is_legacy_middleware].
+        FileClassification::Special { basename } if basename == LEGACY_MIDDLEWARE => return None,
+        FileClassification::Page { basename } | FileClassification::Special { basename } => {
+            (basename, false, None)
+        }
+        FileClassification::Api { basename } => (basename, true, None),
+        FileClassification::Custom { basename, kind } => (basename, false, Some(kind)),
+        FileClassification::Unsupported { message } => {
+            PagesStructureIssue {
+                severity: IssueSeverity::Error.into(),
+                path: file,
+                message: StringVc::cell(message),
+            }
+            .cell()
+            .as_issue()
+            .emit();
+            return None;
+        }
+        FileClassification::Ignored => return None,
+    };
+    let amp_variant_of = if is_api || custom_kind.is_some() {
+        None
+    } else {
+        classifier
+            .amp_variant_of(&basename)
+            .map(ToString::to_string)
+    };
+    let route_basename = amp_variant_of.as_deref().unwrap_or(&basename);
+    if !check_param_name(route_basename, file) {
+        return None;
+    }
+    let url = if route_basename == "index" {
+        url.join("index.html")
+    } else {
+        url.join(route_basename).join("index.html")
+    };
+    let item = PagesStructureItemVc::new(
+        url,
+        specificity,
+        file,
+        is_api,
+        OptionStringVc::cell(custom_kind),
+        Value::new(PagesStructureItemProvenance::Scanned { root: pages_dir }),
+        OptionStringVc::cell(classifier.transform_hint(name)),
+    );
+    Some((
+        basename.clone(),
+        ScannedFile {
+            basename,
+            amp_variant_of,
+            item,
+        },
+    ))
+}
+
+/// Returns true if the file `name` is a legacy `_middleware` file, which is
+/// recorded in [PagesStructure::middleware] instead of being routed.
+fn is_legacy_middleware(
+    name: &str,
+    is_api_dir: bool,
+    is_root: bool,
+    classifier: &dyn FileClassifier,
+) -> bool {
+    matches!(
+        classifier.classify_file(name, is_api_dir, is_root),
+        FileClassification::Special { basename } if basename == LEGACY_MIDDLEWARE
+    )
+}
+
+/// A file of a pages (sub)directory that has a route.
+struct ScannedFile {
+    basename: String,
+    /// The basename of the page this file is the AMP variant of.
+    amp_variant_of: Option<String>,
+    item: PagesStructureItemVc,
+}
+
+/// Attaches the AMP variants among the files of a directory to their page,
+/// e.g. `post.amp.tsx` to `post.tsx`. AMP variants without a page are kept
+/// and serve the route of the page themselves. Takes and returns the files
+/// by name.
+fn attach_amp_variants(files: Vec<(String, ScannedFile)>) -> Vec<(String, PagesStructureItemVc)> {
+    let pages = files
+        .iter()
+        .filter(|(_, file)| file.amp_variant_of.is_none())
+        .map(|(_, file)| file.basename.clone())
+        .collect::<HashSet<_>>();
+    let mut amp_variants = HashMap::new();
+    let mut kept = vec![];
+    for (name, file) in files {
+        match &file.amp_variant_of {
+            Some(page) if pages.contains(page) => {
+                amp_variants.insert(page.clone(), file.item);
+            }
+            _ => kept.push((name, file)),
+        }
+    }
+    kept.into_iter()
+        .map(|(name, file)| {
+            let item = match amp_variants.remove(&file.basename) {
+                Some(amp_variant) => file.item.with_amp_variant(amp_variant),
+                None => file.item,
+            };
+            (name, item)
+        })
+        .collect()
+}
+
+/// Keeps one file per basename of a directory, e.g. only `post.mdx` of
+/// `post.mdx` and `post.tsx`. Takes `(name, basename, priority, item)` for
+/// every file; the file with the lowest priority wins, and ties are broken by
+/// name. Returns the kept items by name and the `(ignored, used)` names of
+/// the dropped files.
+fn dedupe_by_basename<T>(
+    files: Vec<(String, String, usize, T)>,
+) -> (Vec<(String, T)>, Vec<(String, String)>) {
+    let mut kept = IndexMap::<String, (usize, String, T)>::new();
+    let mut ignored = vec![];
+    for (name, basename, priority, item) in files {
+        match kept.get_mut(&basename) {
+            Some(existing) if (priority, &name) < (existing.0, &existing.1) => {
+                let (_, previous, _) = std::mem::replace(existing, (priority, name, item));
+                ignored.push((previous, basename));
+            }
+            Some(_) => ignored.push((name, basename)),
+            None => {
+                kept.insert(basename, (priority, name, item));
+            }
+        }
+    }
+    let duplicates = ignored
+        .into_iter()
+        .map(|(name, basename)| (name, kept[&basename].1.clone()))
+        .collect();
+    let items = kept
+        .into_values()
+        .map(|(_, name, item)| (name, item))
+        .collect();
+    (items, duplicates)
+}
+
+/// How a file in the pages directory is treated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileClassification {
+    /// A page, routed by its basename (the file name without extension).
+    Page { basename: String },
+    /// An API route, routed by its basename.
+    Api { basename: String },
+    /// A special page like `_app` or `_document`.
+    Special { basename: String },
+    /// A route of a custom kind, tagged with `kind`. Frameworks built on top
+    /// of Next.js serve these with their own content source.
+    Custom { basename: String, kind: String },
+    /// A route that can't be served with the current config. It is reported
+    /// as an error with `message` and not part of the routes.
+    Unsupported { message: String },
+    /// Not part of the routes.
+    Ignored,
+}
+
+/// Decides how the files and directories of the pages directory are treated.
+/// Frameworks built on top of Next.js can provide their own implementation
+/// with [PagesScanOptions::with_classifier].
+pub trait FileClassifier: Send + Sync {
+    /// Classifies the file `name` (with extension). `is_api_dir` is true for
+    /// files inside the API root, `is_root` for files at the root of the
+    /// pages directory.
+    fn classify_file(&self, name: &str, is_api_dir: bool, is_root: bool) -> FileClassification;
+
+    /// Returns the name of the directory at the root of the pages directory
+    /// that contains API routes.
+    fn api_root_name(&self) -> &str {
+        API_ROOT_NAME
+    }
+
+    /// Returns false if the directory `name` and everything inside it should
+    /// be skipped.
+    fn include_directory(&self, _name: &str) -> bool {
+        true
+    }
+
+    /// Returns the priority of the file `name` when several files of a
+    /// directory have the same basename. The file with the lowest priority
+    /// serves the route.
+    fn file_priority(&self, _name: &str) -> usize {
+        0
+    }
+
+    /// Returns the name of the loader that transforms the file `name`, which
+    /// is stored on its [PagesStructureItem].
+    fn transform_hint(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// Returns the basename of the page that the page with `basename` is the
+    /// AMP variant of, like `post` for `post.amp`. The variant is attached to
+    /// the page of the same directory, or serves its route itself if there
+    /// isn't one.
+    fn amp_variant_of<'a>(&self, _basename: &'a str) -> Option<&'a str> {
+        None
+    }
+}
+
+/// The special pages at the root of the pages directory.
+const SPECIAL_PAGES: [&str; 3] = ["_app", "_document", "_error"];
+
+/// The legacy (Next.js 12) per-directory middleware, which can appear at any
+/// level of the pages directory.
+const LEGACY_MIDDLEWARE: &str = "_middleware";
+
+/// The kind of a special file of the pages directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, TraceRawVcs)]
+pub enum SpecialKind {
+    App,
+    Document,
+    Error,
+    /// A legacy `_middleware` file.
+    Middleware,
+}
+
+/// Returns the kind of special file with the given basename, if it is one.
+/// `is_root` is true for files at the root of the pages directory.
+fn special_kind(basename: &str, is_root: bool) -> Option<SpecialKind> {
+    match basename {
+        LEGACY_MIDDLEWARE => Some(SpecialKind::Middleware),
+        "_app" if is_root => Some(SpecialKind::App),
+        "_document" if is_root => Some(SpecialKind::Document),
+        "_error" if is_root => Some(SpecialKind::Error),
+        _ => None,
+    }
+}
+
+/// The default [FileClassifier], which routes every file with one of the
+/// configured `pageExtensions`.
+///
+/// With `output: 'export'`, API routes are unsupported. Leaving them out of
+/// the structure also means edits to them don't invalidate the pages.
+pub struct NextFileClassifier {
+    page_extensions: PageExtensions,
+    static_export: bool,
+    amp_suffix_pages: bool,
+    api_root_name: String,
+    /// Compound extensions (like `rpc.ts`) and the kinds of their files.
+    custom_kinds: Vec<(String, String)>,
+}
+
+impl NextFileClassifier {
+    pub fn new(page_extensions: PageExtensions) -> Self {
+        NextFileClassifier {
+            page_extensions,
+            static_export: false,
+            amp_suffix_pages: false,
+            api_root_name: API_ROOT_NAME.to_string(),
+            custom_kinds: vec![],
+        }
+    }
+
+    /// Registers a custom page kind: files with the compound `extension`
+    /// (like `rpc.ts` for `pages/users.rpc.ts`) are classified as
+    /// [FileClassification::Custom] routes tagged with `kind`, instead of as
+    /// pages or API routes. The last part of the extension must still be one
+    /// of the `pageExtensions`.
+    pub fn with_custom_kind(
+        mut self,
+        extension: impl Into<String>,
+        kind: impl Into<String>,
+    ) -> Self {
+        self.custom_kinds.push((extension.into(), kind.into()));
+        self
+    }
+
+    /// Returns the basename and kind of `name` if it has the compound
+    /// extension of a registered custom kind.
+    fn custom_kind(&self, name: &str) -> Option<(String, String)> {
+        self.custom_kinds.iter().find_map(|(extension, kind)| {
+            let basename = name.strip_suffix(extension.as_str())?.strip_suffix('.')?;
+            (!basename.is_empty()).then(|| (basename.to_string(), kind.clone()))
+        })
+    }
+
+    /// Sets whether `output: 'export'` is configured.
+    pub fn with_static_export(mut self, static_export: bool) -> Self {
+        self.static_export = static_export;
+        self
+    }
+
+    /// Sets whether pages like `post.amp.tsx` are the AMP variants of their
+    /// page (`experimental.ampSuffixPages`).
+    pub fn with_amp_suffix_pages(mut self, amp_suffix_pages: bool) -> Self {
+        self.amp_suffix_pages = amp_suffix_pages;
+        self
+    }
+
+    /// Sets the directory containing API routes, instead of `api`.
+    pub fn with_api_root_name(mut self, api_root_name: impl Into<String>) -> Self {
+        self.api_root_name = api_root_name.into();
+        self
+    }
+}
+
+impl FileClassifier for NextFileClassifier {
+    fn classify_file(&self, name: &str, is_api_dir: bool, is_root: bool) -> FileClassification {
+        let Some((basename, _)) = self.page_extensions.matches(name) else {
+            return FileClassification::Ignored;
+        };
+        if let Some((basename, kind)) = self.custom_kind(name) {
+            return FileClassification::Custom { basename, kind };
+        }
+        let basename = basename.to_string();
+        if is_api_dir || (is_root && basename == self.api_root_name) {
+            if self.static_export {
+                FileClassification::Unsupported {
+                    message: format!(
+                        "The API route `{name}` can't be used with `output: 'export'`, since \
+                         exported apps are served as static files. Remove it, or remove `output: \
+                         'export'` from the Next.js config."
+                    ),
+                }
+            } else {
+                FileClassification::Api { basename }
+            }
+        } else if (is_root && SPECIAL_PAGES.contains(&basename.as_str()))
+            || basename == LEGACY_MIDDLEWARE
+        {
+            FileClassification::Special { basename }
+        } else {
+            FileClassification::Page { basename }
+        }
+    }
+
+    fn api_root_name(&self) -> &str {
+        &self.api_root_name
+    }
+
+    fn file_priority(&self, name: &str) -> usize {
+        self.page_extensions.priority(name).unwrap_or(usize::MAX)
+    }
+
+    fn transform_hint(&self, name: &str) -> Option<String> {
+        self.page_extensions.loader(name).map(ToString::to_string)
+    }
+
+    fn amp_variant_of<'a>(&self, basename: &'a str) -> Option<&'a str> {
+        if !self.amp_suffix_pages {
+            return None;
+        }
+        basename
+            .strip_suffix(AMP_SUFFIX)
+            .filter(|page| !page.is_empty())
+    }
+}
+
+/// A [FileClassifier] that can be passed to turbo tasks.
+#[turbo_tasks::value(shared, serialization = "none", eq = "manual")]
+pub struct PagesFileClassifier(
+    #[turbo_tasks(debug_ignore, trace_ignore)] pub Arc<dyn FileClassifier>,
+);
+
+impl PartialEq for PagesFileClassifier {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PagesFileClassifier {}
+
+/// Returns the default [FileClassifier] for the configured `pageExtensions`.
+/// `static_export` is true if `output: 'export'` is configured.
+#[turbo_tasks::function]
+pub async fn next_file_classifier(
+    page_extensions: PageExtensionsVc,
+    static_export: bool,
+) -> Result<PagesFileClassifierVc> {
+    Ok(PagesFileClassifier(Arc::new(
+        NextFileClassifier::new(page_extensions.await?.clone_value())
+            .with_static_export(static_export),
+    ))
+    .cell())
+}
+
+/// Returns the default [FileClassifier] for the Next.js config: its
+/// `pageExtensions`, `output: 'export'` and `experimental.ampSuffixPages`.
+#[turbo_tasks::function]
+pub async fn next_config_file_classifier(
+    next_config: NextConfigVc,
+) -> Result<PagesFileClassifierVc> {
+    let page_extensions = PageExtensionsVc::from_config(next_config).await?;
+    Ok(PagesFileClassifier(Arc::new(
+        NextFileClassifier::new(page_extensions.clone_value())
+            .with_static_export(*next_config.is_static_export().await?)
+            .with_amp_suffix_pages(next_config.await?.amp_suffix_pages_enabled()),
+    ))
+    .cell())
+}
+
+/// Rewrites a route pattern (e.g. `/about` to `/v2/about`) when the pages
+/// structure is built. Must return the same pattern for the same input.
+pub type RoutePatternTransform = dyn Fn(&str) -> String + Send + Sync;
+
+/// A [RoutePatternTransform] that can be passed to turbo tasks.
+#[turbo_tasks::value(shared, serialization = "none", eq = "manual")]
+pub struct PagesRouteTransform(
+    #[turbo_tasks(debug_ignore, trace_ignore)] pub Arc<RoutePatternTransform>,
+);
+
+impl PartialEq for PagesRouteTransform {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PagesRouteTransform {}
+
+#[turbo_tasks::value(shared)]
+pub struct PagesStructureIssue {
+    pub severity: IssueSeverityVc,
+    pub path: FileSystemPathVc,
+    pub message: StringVc,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for PagesStructureIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> IssueSeverityVc {
+        self.severity
+    }
+
+    #[turbo_tasks::function]
+    async fn title(&self) -> Result<StringVc> {
+        Ok(StringVc::cell(
+            "An issue occurred while preparing your Next.js pages".to_string(),
+        ))
+    }
+
+    #[turbo_tasks::function]
+    fn category(&self) -> StringVc {
+        StringVc::cell("next pages".to_string())
+    }
+
+    #[turbo_tasks::function]
+    fn context(&self) -> FileSystemPathVc {
+        self.path
+    }
+
+    #[turbo_tasks::function]
+    fn description(&self) -> StringVc {
+        self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::bail;
+    use turbopack_dev_server::source::specificity::Specificity;
+
+    use super::*;
+    use crate::{
+        app_structure::find_app_structure,
+        next_config::NextConfigBuilder,
+        testing::{
+            assert_routes, project_root, reported_issues, ReportedIssue, TestProject, TestRunner,
+        },
+    };
+
+    #[test]
+    fn parses_catch_all_segments() {
+        assert_eq!(
+            RouteSegment::parse("[...slug]"),
+            RouteSegment::CatchAll("slug".to_string())
+        );
+        assert_eq!(
+            RouteSegment::parse("[[...slug]]"),
+            RouteSegment::OptionalCatchAll("slug".to_string())
+        );
+        assert_eq!(
+            RouteSegment::parse("[slug]"),
+            RouteSegment::Dynamic("slug".to_string())
+        );
+        assert_eq!(
+            RouteSegment::parse("about"),
+            RouteSegment::Static("about".to_string())
+        );
+    }
+
+    #[test]
+    fn detects_empty_param_names() {
+        // `pages/[].tsx` and `pages/[...].tsx`
+        assert_eq!(RouteSegment::parse("[]").param_name(), Some(""));
+        assert_eq!(RouteSegment::parse("[...]").param_name(), Some(""));
+        assert_eq!(RouteSegment::parse("[[...]]").param_name(), Some(""));
+        assert_eq!(RouteSegment::parse("[slug]").param_name(), Some("slug"));
+        assert_eq!(RouteSegment::parse("about").param_name(), None);
+    }
+
+    #[test]
+    fn last_url_segment_distinguishes_catch_all_forms() {
+        assert_eq!(
+            route_segments_for_url_path("docs/[...slug]/index.html").pop(),
+            Some(RouteSegment::CatchAll("slug".to_string()))
+        );
+        assert_eq!(
+            route_segments_for_url_path("docs/[[...slug]]/index.html").pop(),
+            Some(RouteSegment::OptionalCatchAll("slug".to_string()))
+        );
+        assert_eq!(route_segments_for_url_path("index.html").pop(), None);
+    }
+
+    fn pattern(path: &str) -> String {
+        format_route_pattern(&route_segments_for_url_path(path))
+    }
+
+    #[test]
+    fn formats_route_patterns_for_manifests() {
+        assert_eq!(pattern("index.html"), "/");
+        assert_eq!(pattern("a/b/index.html"), "/a/b");
+        assert_eq!(pattern("[x]/index.html"), "/[x]");
+        assert_eq!(pattern("[...x]/index.html"), "/[...x]");
+        assert_eq!(pattern("[[...x]]/index.html"), "/[[...x]]");
+    }
+
+    /// Lists the directory at `dir` and all directories beneath it, like an
+    /// embedder walking the project would.
+    fn read_snapshot(dir: &Path) -> Result<IndexMap<String, Vec<(String, SnapshotEntryType)>>> {
+        let mut snapshot = IndexMap::new();
+        let mut queue = vec![String::new()];
+        while let Some(relative) = queue.pop() {
+            let mut listing = vec![];
+            for entry in std::fs::read_dir(dir.join(&relative))? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.file_type()?.is_dir() {
+                    queue.push(if relative.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{relative}/{name}")
+                    });
+                    listing.push((name, SnapshotEntryType::Directory));
+                } else {
+                    listing.push((name, SnapshotEntryType::File));
+                }
+            }
+            snapshot.insert(relative, listing);
+        }
+        Ok(snapshot)
+    }
+
+    #[test]
+    fn scans_snapshots_like_live_directories() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("pages/blog/[slug].tsx", ""),
+            ("pages/api/users.ts", ""),
+            ("pages/[a]/x.tsx", ""),
+            ("pages/[b]/y.tsx", ""),
+        ])?;
+        let pages_dir = Path::new(&project.root_dir()).join("pages");
+        let runner = TestRunner::new()?;
+        let scan = |snapshot: Option<IndexMap<String, Vec<(String, SnapshotEntryType)>>>| {
+            let root_dir = project.root_dir();
+            runner.run(async move {
+                let scan = scan_project(
+                    &root_dir,
+                    NextConfigBuilder::default(),
+                    |_| match snapshot {
+                        Some(snapshot) => PagesScanOptions::default()
+                            .with_snapshot(PagesDirectorySnapshotVc::cell(snapshot)),
+                        None => PagesScanOptions::default(),
+                    },
+                )
+                .await?;
+                Ok((
+                    scan.pages
+                        .print_tree(PageExtensionsVc::from_config(scan.next_config), true)
+                        .await?
+                        .clone_value(),
+                    reported_issues(scan.structure).await?,
+                ))
+            })
+        };
+
+        let (live_tree, live_issues) = scan(None)?;
+        let snapshot = read_snapshot(&pages_dir)?;
+        let (snapshot_tree, snapshot_issues) = scan(Some(snapshot.clone()))?;
+        assert_eq!(snapshot_tree, live_tree);
+        // The sibling dynamic directories are reported once.
+        assert_eq!(live_issues.len(), 1, "{live_issues:?}");
+        assert_eq!(snapshot_issues, live_issues);
+
+        // The snapshot is the source of truth, so a page added later only
+        // shows up with an updated snapshot.
+        project.write("pages/contact.tsx", "")?;
+        let (stale_tree, _) = scan(Some(snapshot))?;
+        assert_eq!(stale_tree, snapshot_tree);
+        let (updated_tree, _) = scan(Some(read_snapshot(&pages_dir)?))?;
+        assert!(updated_tree.contains("contact"), "{updated_tree}");
+        Ok(())
+    }
+
+    /// A scan of the pages directory of a project, see [scan_project].
+    #[derive(Clone, Copy)]
+    struct ProjectScan {
+        root: FileSystemPathVc,
+        next_config: NextConfigVc,
+        options: PagesScanOptionsVc,
+        /// The result of the scan, which its issues are reported on.
+        structure: OptionPagesStructureVc,
+        pages: PagesStructureVc,
+    }
+
+    /// Scans the pages directory of the project in `root_dir` with the given
+    /// config and options. `options` is called with the project root inside
+    /// of the run, so that it can create Vcs. Call this inside of
+    /// [TestRunner::run] for runs of a watched project, and use [scan_pages]
+    /// otherwise.
+    async fn scan_project(
+        root_dir: &str,
+        next_config: NextConfigBuilder,
+        options: impl FnOnce(FileSystemPathVc) -> PagesScanOptions,
+    ) -> Result<ProjectScan> {
+        scan_project_served_from(root_dir, "", next_config, options).await
+    }
+
+    /// Like [scan_project], with the routes served from `server_root`
+    /// (relative to the project root) instead of the project root.
+    async fn scan_project_served_from(
+        root_dir: &str,
+        server_root: &str,
+        next_config: NextConfigBuilder,
+        options: impl FnOnce(FileSystemPathVc) -> PagesScanOptions,
+    ) -> Result<ProjectScan> {
+        let root = project_root(root_dir);
+        let server_root = if server_root.is_empty() {
+            root
+        } else {
+            root.join(server_root)
+        };
+        let next_config = next_config.cell();
+        let options = options(root).cell();
+        let structure = find_pages_structure_with_options(root, server_root, next_config, options);
+        let Some(pages) = *structure.await? else {
+            bail!("the pages directory wasn't found");
+        };
+        Ok(ProjectScan {
+            root,
+            next_config,
+            options,
+            structure,
+            pages,
+        })
+    }
+
+    /// Scans the pages directory of `project` with the given config and
+    /// returns what `read` reads from the structure.
+    fn scan_pages<T, F>(
+        project: &TestProject,
+        next_config: NextConfigBuilder,
+        read: impl FnOnce(PagesStructureVc) -> F + Send + 'static,
+    ) -> Result<T>
+    where
+        T: Send + 'static,
+        F: std::future::Future<Output = Result<T>> + Send + 'static,
+    {
+        scan_pages_with_options(
+            project,
+            next_config,
+            |_| PagesScanOptions::default(),
+            |scan| read(scan.pages),
+        )
+    }
+
+    /// Like [scan_pages], with the scan options returned by `options` (see
+    /// [scan_project]). `read` gets the whole [ProjectScan].
+    fn scan_pages_with_options<T, F>(
+        project: &TestProject,
+        next_config: NextConfigBuilder,
+        options: impl FnOnce(FileSystemPathVc) -> PagesScanOptions + Send + 'static,
+        read: impl FnOnce(ProjectScan) -> F + Send + 'static,
+    ) -> Result<T>
+    where
+        T: Send + 'static,
+        F: std::future::Future<Output = Result<T>> + Send + 'static,
+    {
+        let root_dir = project.root_dir();
+        TestRunner::new()?.run(async move {
+            let scan = scan_project(&root_dir, next_config, options).await?;
+            read(scan).await
+        })
+    }
+
+    #[test]
+    fn finds_api_routes_at_any_depth() -> Result<()> {
+        let has_api_routes = |files: &[(&str, &str)]| {
+            scan_pages(
+                &TestProject::new(files)?,
+                NextConfigBuilder::default(),
+                |pages| async move { Ok(*pages.has_api_routes().await?) },
+            )
+        };
+        assert!(has_api_routes(&[
+            ("pages/index.tsx", ""),
+            ("pages/api/users.ts", "")
+        ])?);
+        assert!(has_api_routes(&[
+            ("pages/index.tsx", ""),
+            ("pages/api/v1/users/[id].ts", "")
+        ])?);
+        assert!(!has_api_routes(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("pages/api/", "")
+        ])?);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_windows_only_entry_names() {
+        assert_eq!(
+            invalid_entry_name("about.tsx:Zone.Identifier", true),
+            Some(InvalidEntryName::AlternateDataStream)
+        );
+        // A POSIX checkout routes the same files as a Windows one.
+        assert_eq!(invalid_entry_name("about.tsx:Zone.Identifier", false), None);
+        assert_eq!(
+            invalid_entry_name("about.tsx ", true),
+            Some(InvalidEntryName::TrailingWhitespace)
+        );
+        assert_eq!(
+            invalid_entry_name("blog ", false),
+            Some(InvalidEntryName::TrailingWhitespace)
+        );
+        assert_eq!(invalid_entry_name("about.tsx", true), None);
+    }
+
+    #[test]
+    fn skips_transient_editor_files() {
+        let classifier =
+            NextFileClassifier::new(PageExtensions::new(["tsx".to_string(), "ts".to_string()]));
+        let names = [
+            ".#about.tsx",
+            "about.tsx.tmp",
+            "about.tsx.crswap",
+            ".#index.ts",
+        ];
+        let routes = names
+            .into_iter()
+            .filter(|name| invalid_entry_name(name, false).is_none())
+            .filter(|name| {
+                classifier.classify_file(name, false, true) != FileClassification::Ignored
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(routes, Vec::<&str>::new());
+        for name in names {
+            assert_eq!(
+                invalid_entry_name(name, true),
+                Some(InvalidEntryName::TransientFile)
+            );
+        }
+        // Only the `.#` prefix marks a lock file.
+        assert_eq!(invalid_entry_name("#about.tsx", false), None);
+        assert_eq!(invalid_entry_name(".well-known", false), None);
+    }
+
+    #[test]
+    fn skips_invalid_entry_names_of_the_scan() -> Result<()> {
+        let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/about.tsx", "")])?;
+        // A listing as read on Windows, where tooling exposes alternate data
+        // streams and NTFS preserves trailing whitespace.
+        let listing = [
+            ("index.tsx", SnapshotEntryType::File),
+            ("about.tsx", SnapshotEntryType::File),
+            ("about.tsx:Zone.Identifier", SnapshotEntryType::File),
+            ("contact.tsx ", SnapshotEntryType::File),
+            ("blog ", SnapshotEntryType::Directory),
+            (".#about.tsx", SnapshotEntryType::File),
+            ("about.tsx.crswap", SnapshotEntryType::File),
+        ]
+        .map(|(name, entry_type)| (name.to_string(), entry_type))
+        .to_vec();
+        let snapshot = IndexMap::from([
+            (String::new(), listing),
+            (
+                "blog ".to_string(),
+                vec![("index.tsx".to_string(), SnapshotEntryType::File)],
+            ),
+        ]);
+        let issues = scan_pages_with_options(
+            &project,
+            NextConfigBuilder::default(),
+            |_| PagesScanOptions::default().with_snapshot(PagesDirectorySnapshotVc::cell(snapshot)),
+            |scan| async move {
+                // The same routes as a POSIX checkout of the project: on
+                // Windows the alternate data stream is skipped, elsewhere it
+                // isn't a page.
+                assert_routes(scan.pages, &["/", "/about"]).await?;
+                reported_issues(scan.structure).await
+            },
+        )?;
+        // Only the names ending with whitespace are reported.
+        assert_eq!(issues.len(), 2, "{issues:?}");
+        for (issue, name) in issues.iter().zip(["\"blog \"", "\"contact.tsx \""]) {
+            assert_eq!(issue.severity, "warning", "{issue:?}");
+            assert!(
+                issue
+                    .description
+                    .starts_with(&format!("The name {name} ends with whitespace")),
+                "{issue:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn detects_case_insensitive_api_root_collisions() {
+        // `pages/API.tsx` next to `pages/api/foo.ts`
+        assert!(collides_with_api_root("API", "api"));
+        assert!(collides_with_api_root("Api", "api"));
+        assert!(!collides_with_api_root("api", "api"));
+        assert!(!collides_with_api_root("apis", "api"));
+        assert!(collides_with_api_root("rpc", "RPC"));
+    }
+
+    #[test]
+    fn reports_pages_colliding_with_the_api_root_by_case() -> Result<()> {
+        let scan = |api_root_name: &'static str, files: &[(&str, &str)]| {
+            let page_extensions =
+                PageExtensions::from_next_config(&NextConfigBuilder::default().build());
+            scan_pages_with_options(
+                &TestProject::new(files)?,
+                NextConfigBuilder::default(),
+                move |_| {
+                    let classifier = PagesFileClassifier(Arc::new(
+                        NextFileClassifier::new(page_extensions).with_api_root_name(api_root_name),
+                    ));
+                    PagesScanOptions::default().with_classifier(classifier.cell())
+                },
+                |scan| async move {
+                    let mut api_routes = vec![];
+                    for entry in scan.pages.route_entries().await?.iter() {
+                        let entry = entry.await?;
+                        if entry.is_api {
+                            api_routes.push(entry.pattern.clone());
+                        }
+                    }
+                    let issues = reported_issues(scan.structure)
+                        .await?
+                        .into_iter()
+                        .map(|issue| issue.description)
+                        .filter(|description| description.contains("by case"))
+                        .collect::<Vec<_>>();
+                    Ok((api_routes, issues))
+                },
+            )
+        };
+
+        let (api_routes, issues) = scan("api", &[("pages/API.tsx", ""), ("pages/api/foo.ts", "")])?;
+        assert_eq!(api_routes, vec!["/api/foo"]);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("`/API` only differs from the API routes in `/api`"));
+
+        let (api_routes, issues) = scan(
+            "rpc",
+            &[
+                ("pages/RPC.tsx", ""),
+                ("pages/rpc/foo.ts", ""),
+                ("pages/api/bar.ts", ""),
+            ],
+        )?;
+        assert_eq!(api_routes, vec!["/rpc/foo"]);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("`/RPC` only differs from the API routes in `/rpc`"));
+        Ok(())
+    }
+
+    #[test]
+    fn counts_routes() {
+        let before = RouteStats::from_patterns(["/", "/about", "/blog/[slug]"]);
+        assert_eq!((before.total, before.dynamic), (3, 1));
+
+        let after = RouteStats::from_patterns(["/", "/about", "/blog/[slug]", "/docs/[...path]"]);
+        assert_eq!((after.total, after.dynamic), (4, 2));
+    }
+
+    #[test]
+    fn finds_segments_after_catch_all() {
+        let find = |pattern: &str| {
+            let segments = route_segments_from_pattern(pattern);
+            find_segment_after_catch_all(&segments)
+                .map(|(catch_all, following)| (catch_all.to_string(), following.to_string()))
+        };
+        assert_eq!(find("/[a]/[...b]"), None);
+        assert_eq!(find("/[a]/[[...b]]"), None);
+        assert_eq!(find("/docs/[...path]"), None);
+        assert_eq!(
+            find("/[...a]/[b]"),
+            Some(("[...a]".to_string(), "[b]".to_string()))
+        );
+        assert_eq!(
+            find("/[[...a]]/edit"),
+            Some(("[[...a]]".to_string(), "edit".to_string()))
+        );
+
+        let segments = route_segments_from_pattern("/[...a]/[b]");
+        let (catch_all, following) = find_segment_after_catch_all(&segments).unwrap();
+        let message = segment_after_catch_all_message("/[...a]/[b]", catch_all, following);
+        assert!(message.starts_with("The dynamic segment `[b]` follows the catch-all `[...a]`"));
+        assert!(message.contains("`/[b]/[...a]`"));
+    }
+
+    #[test]
+    fn finds_repeated_params() {
+        let find = |pattern: &str| {
+            find_repeated_param(&route_segments_from_pattern(pattern)).map(ToString::to_string)
+        };
+        assert_eq!(find("/[id]/[[...id]]/edit"), Some("id".to_string()));
+        assert_eq!(find("/[a]/b/[a]"), Some("a".to_string()));
+        assert_eq!(find("/[a]/[b]/[...c]"), None);
+    }
+
+    #[test]
+    fn streams_the_issues_of_the_scan() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/[a]/x.tsx", ""),
+            ("pages/[b]/y.tsx", ""),
+            ("pages/api/a/b/handler.ts", ""),
+            ("pages/docs/[...slug]/[id].tsx", ""),
+            ("pages/users/[id]/[id].tsx", ""),
+        ])?;
+        let root_dir = project.root_dir();
+        let (streamed, reported) = TestRunner::new()?.run(async move {
+            let root = project_root(&root_dir);
+            let next_config = NextConfigBuilder::default().max_api_depth(1).cell();
+            let pages = find_pages_structure(root, root, next_config);
+            let streamed = pages_diagnostics(pages).try_collect::<Vec<_>>().await?;
+            Ok((streamed, reported_issues(pages).await?))
+        })?;
+
+        // The sibling dynamic directories, the API route that is too deep, the
+        // segment after a catch-all and the repeated param.
+        assert_eq!(reported.len(), 4, "{reported:?}");
+        let mut streamed_issues = streamed
+            .iter()
+            .map(|diagnostic| ReportedIssue {
+                severity: diagnostic.severity.clone(),
+                description: diagnostic.message.clone(),
+            })
+            .collect::<Vec<_>>();
+        streamed_issues.sort();
+        assert_eq!(streamed_issues, reported);
+        // Found while reading the pages directory, before the checks of the
+        // whole structure.
+        assert!(streamed[0].message.contains("[a]"), "{streamed:?}");
+        let repeated = streamed
+            .iter()
+            .find(|diagnostic| diagnostic.message.contains("used more than once"))
+            .unwrap();
+        assert!(repeated.path.ends_with("pages/users/[id]/[id].tsx"));
+        Ok(())
+    }
+
+    #[test]
+    fn counts_scanned_directories() {
+        // pages, pages/api, pages/blog, pages/blog/[slug], pages/blog/[slug]/comments
+        let stats = PagesScanStats::from_directory_depths([0, 1, 1, 2, 3]);
+        assert_eq!((stats.directories, stats.levels), (5, 4));
+
+        let stats = PagesScanStats::from_directory_depths([0]);
+        assert_eq!((stats.directories, stats.levels), (1, 1));
+    }
+
+    #[test]
+    fn finds_max_route_depth() {
+        let stats = RouteStats::from_patterns([
+            "/",
+            "/about",
+            "/blog/[slug]",
+            "/docs/[lang]/guides/[...path]",
+            "/shop/[[...path]]",
+        ]);
+        assert_eq!(stats.max_depth, 4);
+        assert_eq!(RouteStats::from_patterns(["/"]).max_depth, 0);
+    }
+
+    #[test]
+    fn finds_routes_duplicated_between_pages_and_app() {
+        // `pages/about.tsx` and `app/about/page.tsx`
+        let pages = [
+            ("/".to_string(), "pages/index.tsx"),
+            ("/about".to_string(), "pages/about.tsx"),
+        ];
+        let app = [
+            ("/about".to_string(), "app/about/page.tsx"),
+            ("/blog".to_string(), "app/blog/page.tsx"),
+        ];
+        assert_eq!(
+            find_duplicate_routes(&pages, &app),
+            vec![(
+                "/about".to_string(),
+                "pages/about.tsx",
+                "app/about/page.tsx"
+            )]
+        );
+    }
+
+    #[test]
+    fn orders_pages_and_grouped_app_routes_together() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/about.tsx", ""),
+            ("pages/blog/[slug].tsx", ""),
+            ("pages/[...path].tsx", ""),
+            ("app/(shop)/[category]/page.tsx", ""),
+            ("app/(marketing)/blog/page.tsx", ""),
+            ("app/(shop)/about/page.tsx", ""),
+        ])?;
+        let root_dir = project.root_dir();
+        let routes = TestRunner::new()?.run(async move {
+            let root = project_root(&root_dir);
+            let next_config = NextConfigBuilder::default().app_dir(true).cell();
+            let combined = find_combined_structure(
+                find_pages_structure(root, root, next_config),
+                find_app_structure(root, root, next_config),
+                next_config,
+            );
+            Ok(combined.sorted_routes().await?.clone_value())
+        })?;
+        assert_eq!(
+            routes,
+            vec![
+                "/about",
+                "/blog",
+                "/blog/[slug]",
+                "/[category]",
+                "/[...path]"
+            ]
+        );
+        Ok(())
+    }
+
+    /// Treats `.foo` files as pages, in addition to the default behavior.
+    struct FooClassifier(NextFileClassifier);
+
+    impl FileClassifier for FooClassifier {
+        fn classify_file(&self, name: &str, is_api_dir: bool, is_root: bool) -> FileClassification {
+            match name.strip_suffix(".foo") {
+                Some(basename) => FileClassification::Page {
+                    basename: basename.to_string(),
+                },
+                None => self.0.classify_file(name, is_api_dir, is_root),
+            }
+        }
+    }
+
+    #[test]
+    fn detects_uppercase_api_file_names() {
+        // `pages/api/Users.ts`
+        assert!(!is_lowercase_file_name("Users.ts"));
+        assert!(is_lowercase_file_name("users.ts"));
+        assert!(is_lowercase_file_name("[id].ts"));
+    }
+
+    #[test]
+    fn queries_route_trie() {
+        let trie = RouteTrie::from_patterns([
+            "/",
+            "/about",
+            "/blog/[slug]",
+            "/blog/featured",
+            "/docs/[...path]",
+            "/shop/[[...path]]",
+        ]);
+        assert!(trie.has_routes_under("/blog"));
+        assert!(trie.has_routes_under("/blog/[slug]"));
+        assert!(!trie.has_routes_under("/blog/hello"));
+        assert!(!trie.has_routes_under("/contact"));
+        assert_eq!(
+            trie.routes_under("/blog"),
+            vec!["/blog/[slug]", "/blog/featured"]
+        );
+
+        assert_eq!(trie.match_pathname("/"), Some("/"));
+        assert_eq!(
+            trie.match_pathname("/blog/featured"),
+            Some("/blog/featured")
+        );
+        assert_eq!(trie.match_pathname("/blog/hello"), Some("/blog/[slug]"));
+        assert_eq!(trie.match_pathname("/docs/a/b"), Some("/docs/[...path]"));
+        assert_eq!(trie.match_pathname("/docs"), None);
+        assert_eq!(trie.match_pathname("/shop"), Some("/shop/[[...path]]"));
+        assert_eq!(trie.match_pathname("/contact"), None);
+    }
+
+    #[test]
+    fn classifies_files_with_a_custom_classifier() {
+        let next =
+            NextFileClassifier::new(PageExtensions::new(["tsx".to_string(), "ts".to_string()]));
+        assert_eq!(
+            next.classify_file("about.foo", false, true),
+            FileClassification::Ignored
+        );
+        assert_eq!(
+            next.classify_file("_app.tsx", false, true),
+            FileClassification::Special {
+                basename: "_app".to_string()
+            }
+        );
+
+        let custom = FooClassifier(next);
+        assert_eq!(
+            custom.classify_file("about.foo", false, true),
+            FileClassification::Page {
+                basename: "about".to_string()
+            }
+        );
+        assert_eq!(
+            custom.classify_file("hello.ts", true, false),
+            FileClassification::Api {
+                basename: "hello".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_files_of_custom_kinds() {
+        // `pages/users.rpc.ts`, `pages/api/posts.rpc.ts`, `pages/about.tsx`
+        // and `pages/api/hello.ts`, with `.rpc.ts` registered as `rpc`
+        let classifier =
+            NextFileClassifier::new(PageExtensions::new(["tsx".to_string(), "ts".to_string()]))
+                .with_custom_kind("rpc.ts", "rpc");
+        for (name, is_api_dir) in [("users.rpc.ts", false), ("posts.rpc.ts", true)] {
+            assert_eq!(
+                classifier.classify_file(name, is_api_dir, !is_api_dir),
+                FileClassification::Custom {
+                    basename: name.trim_end_matches(".rpc.ts").to_string(),
+                    kind: "rpc".to_string()
+                }
+            );
+        }
+        assert_eq!(
+            classifier.classify_file("about.tsx", false, true),
+            FileClassification::Page {
+                basename: "about".to_string()
+            }
+        );
+        assert_eq!(
+            classifier.classify_file("hello.ts", true, false),
+            FileClassification::Api {
+                basename: "hello".to_string()
+            }
+        );
+        // Only consulted for files with a page extension.
+        assert_eq!(
+            classifier.classify_file("users.rpc.js", false, true),
+            FileClassification::Ignored
+        );
+    }
+
+    #[test]
+    fn rejects_api_routes_in_static_export() {
+        // `pages/index.tsx`, `pages/api.ts` and `pages/api/hello.ts` with
+        // `output: 'export'`
+        let classifier =
+            NextFileClassifier::new(PageExtensions::new(["tsx".to_string(), "ts".to_string()]))
+                .with_static_export(true);
+        assert_eq!(
+            classifier.classify_file("index.tsx", false, true),
+            FileClassification::Page {
+                basename: "index".to_string()
+            }
+        );
+        for (name, is_api_dir, is_root) in [("api.ts", false, true), ("hello.ts", true, false)] {
+            assert!(
+                matches!(
+                    classifier.classify_file(name, is_api_dir, is_root),
+                    FileClassification::Unsupported { message } if message.contains(name)
+                ),
+                "{name} should be unsupported"
+            );
+        }
+    }
+
+    #[test]
+    fn prefers_files_by_page_extension_order() {
+        let classifier =
+            NextFileClassifier::new(PageExtensions::new(["mdx".to_string(), "tsx".to_string()]));
+        let files = ["post.tsx", "post.mdx", "about.tsx"]
+            .into_iter()
+            .map(|name| {
+                let basename = match classifier.classify_file(name, false, false) {
+                    FileClassification::Page { basename } => basename,
+                    other => panic!("{name} should be a page, got {other:?}"),
+                };
+                (
+                    name.to_string(),
+                    basename,
+                    classifier.file_priority(name),
+                    (),
+                )
+            })
+            .collect();
+        let (items, duplicates) = dedupe_by_basename(files);
+        let names = items
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["post.mdx", "about.tsx"]);
+        assert_eq!(
+            duplicates,
+            vec![("post.tsx".to_string(), "post.mdx".to_string())]
+        );
+
+        // The route doesn't depend on the extension of the file serving it.
+        let segments = entry_route_segment("post.mdx", true)
+            .into_iter()
+            .collect::<Vec<_>>();
+        assert_eq!(format_route_pattern(&segments), "/post");
+    }
+
+    #[test]
+    fn selects_routes_to_prewarm() {
+        let routes = [
+            ("/_app", false),
+            ("/about", false),
+            ("/api/hello", true),
+            ("/blog/[slug]", false),
+            ("/", false),
+            ("/contact", false),
+            ("/_error", false),
+            ("/_document", false),
+        ];
+        assert_eq!(select_prewarm_routes(routes, 3), vec![0, 7, 4]);
+        assert_eq!(select_prewarm_routes(routes, 10), vec![0, 7, 4, 1, 5]);
+        assert!(select_prewarm_routes(routes, 0).is_empty());
+    }
+
+    #[test]
+    fn finds_orphaned_dynamic_directories() {
+        // `pages/[slug]/sub/` without any page file
+        let directories = ["[slug]".to_string(), "[slug]/sub".to_string()];
+        assert_eq!(
+            find_orphaned_dynamic_directories(&directories, &["index.tsx".to_string()]),
+            vec!["[slug]"]
+        );
+        assert!(find_orphaned_dynamic_directories(
+            &directories,
+            &["[slug]/sub/index.tsx".to_string()]
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn resolves_routes_by_priority() {
+        let patterns = ["/", "/about", "/[slug]", "/docs/[...path]"];
+        assert_eq!(resolve_route(patterns, "/about").unwrap().0, "/about");
+        let (pattern, params) = resolve_route(patterns, "/hello").unwrap();
+        assert_eq!(pattern, "/[slug]");
+        assert_eq!(params["slug"], RouteParamValue::Single("hello".to_string()));
+        let (pattern, params) = resolve_route(patterns, "/docs/a/b").unwrap();
+        assert_eq!(pattern, "/docs/[...path]");
+        assert_eq!(
+            params["path"],
+            RouteParamValue::Multiple(vec!["a".to_string(), "b".to_string()])
+        );
+        assert!(resolve_route(patterns, "/docs").is_none());
+    }
+
+    #[test]
+    fn maps_amp_suffix_pages_to_the_route_of_their_page() -> Result<()> {
+        let hybrid = "export const config = { amp: 'hybrid' }";
+        let project = TestProject::new(&[
+            ("pages/post.tsx", ""),
+            ("pages/post.amp.tsx", ""),
+            ("pages/shared.tsx", ""),
+            ("pages/shared.amp.tsx", hybrid),
+            ("pages/about.amp.tsx", ""),
+            ("pages/blog/index.tsx", hybrid),
+            ("pages/blog/index.amp.tsx", ""),
+        ])?;
+        let scan = |amp_suffix_pages: bool| {
+            scan_pages(
+                &project,
+                NextConfigBuilder::default().amp_suffix_pages(amp_suffix_pages),
+                |pages| async move {
+                    let mut routes = vec![];
+                    let mut queue = vec![pages];
+                    while let Some(structure) = queue.pop() {
+                        let structure = structure.await?;
+                        for item in structure.items.iter() {
+                            let amp_variant = match item.await?.amp_variant() {
+                                Some(file) => Some(file.await?.path.clone()),
+                                None => None,
+                            };
+                            routes.push((item.route_pattern().await?.clone_value(), amp_variant));
+                        }
+                        queue.extend(structure.children.iter().copied());
+                    }
+                    routes.sort();
+                    Ok((routes, reported_issues(pages).await?))
+                },
+            )
+        };
+
+        let (routes, issues) = scan(true)?;
+        let amp_variant = |path: &str| Some(path.to_string());
+        assert_eq!(
+            routes,
+            vec![
+                ("/about".to_string(), None),
+                ("/blog".to_string(), amp_variant("pages/blog/index.amp.tsx")),
+                ("/post".to_string(), amp_variant("pages/post.amp.tsx")),
+                ("/shared".to_string(), amp_variant("pages/shared.amp.tsx")),
+            ]
+        );
+        // Only `post` has neither file exporting the hybrid config.
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert!(
+            issues[0]
+                .description
+                .contains("The route `/post` has the AMP variant pages/post.amp.tsx"),
+            "{issues:?}"
+        );
+
+        // Without `experimental.ampSuffixPages`, `.amp` is part of the route.
+        let (routes, issues) = scan(false)?;
+        let patterns = routes
+            .iter()
+            .map(|(pattern, _)| pattern.as_str())
+            .collect::<Vec<_>>();
+        assert!(patterns.contains(&"/post.amp"), "{patterns:?}");
+        assert!(patterns.contains(&"/about.amp"), "{patterns:?}");
+        assert_eq!(issues, vec![]);
+
+        assert!(is_hybrid_amp_page(hybrid));
+        assert!(!is_hybrid_amp_page("export const config = { amp: true }"));
+        Ok(())
+    }
+
+    #[test]
+    fn detects_not_found_results() {
+        assert!(returns_not_found(
+            "export async function getStaticProps({ params }) {\n  const post = await \
+             getPost(params.slug)\n  if (!post) {\n    return { notFound: true }\n  }\n  return { \
+             props: { post } }\n}"
+        ));
+        assert!(returns_not_found(
+            "export const getServerSideProps = () => ({ 'notFound': true })"
+        ));
+        assert!(!returns_not_found(
+            "export async function getStaticProps() {\n  return { props: {} }\n}"
+        ));
+        assert!(!returns_not_found("return { notFound: false }"));
+    }
+
+    /// Route sets of real apps with their native and webpack orderings. Sets
+    /// whose orderings agree have no webpack expectation.
+    const ROUTE_ORDERING_CORPUS: &[(&str, &[&str], &[&str], Option<&[&str]>)] = &[
+        (
+            // The webpack ordering keeps `/api/users` next to the other
+            // routes of `/api`, before `/blog`.
+            "blog",
+            &[
+                "/",
+                "/about",
+                "/blog",
+                "/blog/[slug]",
+                "/[slug]",
+                "/api/users",
+                "/api/users/[id]",
+            ],
+            &[
+                "/",
+                "/about",
+                "/blog",
+                "/api/users",
+                "/api/users/[id]",
+                "/blog/[slug]",
+                "/[slug]",
+            ],
+            Some(&[
+                "/",
+                "/about",
+                "/api/users",
+                "/api/users/[id]",
+                "/blog",
+                "/blog/[slug]",
+                "/[slug]",
+            ]),
+        ),
+        (
+            "docs",
+            &[
+                "/docs",
+                "/docs/[...slug]",
+                "/docs/getting-started",
+                "/[[...path]]",
+            ],
+            &[
+                "/docs",
+                "/docs/getting-started",
+                "/docs/[...slug]",
+                "/[[...path]]",
+            ],
+            None,
+        ),
+        (
+            "shop",
+            &[
+                "/products/[category]/[id]",
+                "/products/[category]",
+                "/products/sale/[id]",
+                "/products/sale",
+                "/cart",
+                "/[locale]/products",
+            ],
+            &[
+                "/cart",
+                "/products/sale",
+                "/products/sale/[id]",
+                "/products/[category]",
+                "/products/[category]/[id]",
+                "/[locale]/products",
+            ],
+            None,
+        ),
+        (
+            // The webpack ordering lists all of `/dashboard` before `/login`.
+            "dashboard",
+            &[
+                "/dashboard",
+                "/dashboard/[team]/settings",
+                "/dashboard/[team]/[project]",
+                "/dashboard/new",
+                "/login",
+            ],
+            &[
+                "/dashboard",
+                "/login",
+                "/dashboard/new",
+                "/dashboard/[team]/settings",
+                "/dashboard/[team]/[project]",
+            ],
+            Some(&[
+                "/dashboard",
+                "/dashboard/new",
+                "/dashboard/[team]/settings",
+                "/dashboard/[team]/[project]",
+                "/login",
+            ]),
+        ),
+        (
+            // Deeper dynamic routes come before shallower static ones in
+            // the webpack ordering, since top-level segments are sorted by
+            // name first.
+            "marketing",
+            &[
+                "/",
+                "/pricing",
+                "/features/[feature]",
+                "/customers/[customer]",
+            ],
+            &[
+                "/",
+                "/pricing",
+                "/customers/[customer]",
+                "/features/[feature]",
+            ],
+            Some(&[
+                "/",
+                "/customers/[customer]",
+                "/features/[feature]",
+                "/pricing",
+            ]),
+        ),
+    ];
+
+    #[test]
+    fn compares_route_orderings() {
+        for (name, routes, native, webpack) in ROUTE_ORDERING_CORPUS.iter().copied() {
+            let sorted_natively =
+                sort_routes(routes.iter().copied(), RouteOrdering::Native).unwrap();
+            let sorted_like_webpack =
+                sort_routes(routes.iter().copied(), RouteOrdering::Webpack).unwrap();
+            assert_eq!(sorted_natively, native, "native ordering of {name}");
+            assert_eq!(
+                sorted_like_webpack,
+                webpack.unwrap_or(native),
+                "webpack ordering of {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn renders_tree_leaves() {
+        let page_extensions =
+            PageExtensions::new(["tsx", "ts", "page.ts"].map(ToString::to_string));
+        let leaf = |pattern, kind, provenance, file_name, verbose| {
+            tree_leaf_line(
+                pattern,
+                kind,
+                provenance,
+                file_name,
+                &page_extensions,
+                verbose,
+            )
+        };
+        assert_eq!(
+            leaf("/blog/[slug]", "page", "scanned", "[slug].tsx", false),
+            "/blog/[slug]"
+        );
+        assert_eq!(
+            leaf("/blog/[slug]", "page", "scanned", "[slug].tsx", true),
+            "/blog/[slug] (page, scanned, .tsx)"
+        );
+        assert_eq!(
+            leaf("/api/users", "api", "virtual", "users.page.ts", true),
+            "/api/users (api, virtual, .page.ts)"
+        );
+        assert_eq!(
+            leaf("/docs/[...slug]", "page", "scanned", "[...slug].tsx", true),
+            "/docs/[...slug] (page, scanned, .tsx)"
+        );
+        assert_eq!(
+            leaf("/[[...path]]", "page", "scanned", "[[...path]].tsx", true),
+            "/[[...path]] (page, scanned, .tsx)"
+        );
+        assert_eq!(
+            leaf("/healthz", "page", "virtual", "healthz", true),
+            "/healthz (page, virtual)"
+        );
+    }
+
+    #[test]
+    fn renders_route_reports() {
+        // `pages/index.tsx`, `pages/about.tsx`, `pages/blog/[slug].tsx`,
+        // `pages/blog/index.tsx`, `pages/api/users.ts` and
+        // `pages/docs/[...path]/index.tsx`
+        let routes = [
+            ("blog", "/blog/[slug]", "page"),
+            ("", "/about", "page"),
+            ("docs/[...path]", "/docs/[...path]", "page"),
+            ("api", "/api/users", "api"),
+            ("blog", "/blog", "page"),
+            ("", "/", "page"),
+        ]
+        .into_iter()
+        .map(|(directory, pattern, kind)| {
+            (directory.to_string(), pattern.to_string(), kind.to_string())
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(
+            format_route_report("pages", routes.clone()),
+            indoc::indoc! {"
+                pages/
+                  /                    page  static
+                  /about               page  static
+                  api/
+                    /api/users         api   static
+                  blog/
+                    /blog              page  static
+                    /blog/[slug]       page  dynamic
+                  docs/
+                    [...path]/
+                      /docs/[...path]  page  dynamic
+            "}
+        );
+        let reversed = routes.iter().rev().cloned().collect();
+        assert_eq!(
+            format_route_report("pages", reversed),
+            format_route_report("pages", routes)
+        );
+    }
+
+    #[test]
+    fn makes_portable_pages_manifests() -> Result<()> {
+        let project = TestProject::new(&[
+            ("src/pages/index.tsx", ""),
+            ("src/pages/blog/[slug].tsx", ""),
+            ("src/pages/api/users.ts", ""),
+        ])?;
+        let manifest = scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            Ok(pages.portable_pages_manifest().await?.clone_value())
+        })?;
+        let json = serde_json::to_string_pretty(&manifest)?;
+        assert_eq!(
+            json,
+            r#"{
+  "/": "index.tsx",
+  "/api/users": "api/users.ts",
+  "/blog/[slug]": "blog/[slug].tsx"
+}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn finds_mismatched_static_paths_params() {
+        let source = r#"
+export async function getStaticPaths() {
+  return {
+    paths: [{ params: { id: '1' } }, { params: { slug: 'hello', 'lang': 'en' } }],
+    fallback: false,
+  }
+}
+"#;
+        assert_eq!(
+            static_paths_param_keys(source),
+            Some(vec![
+                vec!["id".to_string()],
+                vec!["slug".to_string(), "lang".to_string()]
+            ])
+        );
+        assert_eq!(
+            static_paths_param_mismatch("/blog/[slug]", source),
+            Some(StaticPathsParamMismatch {
+                unknown: vec!["id".to_string(), "lang".to_string()],
+                missing: vec!["slug".to_string()],
+            })
+        );
+
+        let matching = "export const getStaticPaths = () => ({ paths: [{ params: { path: ['a', \
+                        'b'] } }], fallback: true })";
+        assert_eq!(
+            static_paths_param_mismatch("/docs/[...path]", matching),
+            None
+        );
+        assert_eq!(
+            static_paths_param_mismatch(
+                "/docs/[[...path]]",
+                "getStaticPaths = { paths: [{ params: {} }] }"
+            ),
+            None
+        );
+
+        let computed = "export async function getStaticPaths() { return { paths: posts.map((post) \
+                        => ({ params: { id: post.id } })) } }";
+        assert_eq!(static_paths_param_mismatch("/blog/[slug]", computed), None);
+
+        // Nested values are part of the params object, not params of their own.
+        let nested = "getStaticPaths = { paths: [{ params: { slug: 'a', meta: { id: 1 } } }] }";
+        assert_eq!(
+            static_paths_param_keys(nested),
+            Some(vec![vec!["slug".to_string(), "meta".to_string()]])
+        );
+    }
+
+    #[test]
+    fn checks_static_paths_params_when_enabled() -> Result<()> {
+        let project = TestProject::new(&[(
+            "pages/blog/[slug].tsx",
+            "export async function getStaticPaths() {\n  return { paths: [{ params: { id: '1' } \
+             }], fallback: false }\n}",
+        )])?;
+        let scan = |check: bool| {
+            scan_pages(
+                &project,
+                NextConfigBuilder::default().check_static_paths_params(check),
+                |pages| async move { reported_issues(pages).await },
+            )
+        };
+        let issues = scan(true)?;
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert!(
+            issues[0]
+                .description
+                .contains("returns params with unknown keys `id` and missing keys `slug`"),
+            "{issues:?}"
+        );
+        assert_eq!(scan(false)?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn finds_routes_that_cant_be_exported() {
+        // `pages/blog/[slug].tsx` without `getStaticPaths`
+        let source = "export default function Post() {}\nexport async function getStaticProps() { \
+                      return { props: {} } }";
+        assert_eq!(
+            static_export_problem("/blog/[slug]", source),
+            Some(StaticExportProblem::MissingStaticPaths)
+        );
+
+        let blocking =
+            "export const getStaticPaths = async () => ({ paths: [], fallback: 'blocking' })";
+        assert_eq!(
+            static_export_problem("/blog/[slug]", blocking),
+            Some(StaticExportProblem::Fallback("'blocking'".to_string()))
+        );
+        let exported =
+            "export async function getStaticPaths() { return { paths: [], fallback: false } }";
+        assert_eq!(static_export_problem("/blog/[slug]", exported), None);
+        let computed = "export async function getStaticPaths() { return { paths: [], fallback } }";
+        assert_eq!(static_export_problem("/blog/[slug]", computed), None);
+
+        // Static pages don't need `getStaticPaths`.
+        assert_eq!(static_export_problem("/about", source), None);
+    }
+
+    #[test]
+    fn reports_each_route_that_cant_be_exported_once() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/blog/[slug].tsx", "export default function Post() {}"),
+            ("pages/api/users.ts", ""),
+        ])?;
+        let issues = scan_pages(
+            &project,
+            NextConfigBuilder::default().static_export(true),
+            |pages| async move { reported_issues(pages).await },
+        )?;
+        assert_eq!(issues.len(), 2, "{issues:?}");
+        assert!(issues[0].description.contains("API route `users.ts`"));
+        assert!(issues[1]
+            .description
+            .contains("`/blog/[slug]` needs `getStaticPaths`"));
+
+        let issues = scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            reported_issues(pages).await
+        })?;
+        assert_eq!(issues, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn finds_competing_routes() {
+        let patterns = [
+            "/",
+            "/about",
+            "/[slug]",
+            "/blog/[id]",
+            "/blog/latest",
+            "/[slug]/comments",
+            "/docs/[...path]",
+            "/shop/cart",
+        ];
+        assert_eq!(
+            find_competing_routes(&patterns),
+            vec![
+                ("/[slug]", "/about"),
+                ("/[slug]/comments", "/blog/[id]"),
+                ("/blog/[id]", "/blog/latest"),
+            ]
+        );
+        assert!(find_competing_routes(&["/a", "/b", "/[...rest]"]).is_empty());
+    }
+
+    #[test]
+    fn lists_only_top_level_routes_in_a_shallow_scan() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("pages/about.js", ""),
+            ("pages/_middleware.ts", ""),
+            ("pages/blog/[slug].tsx", ""),
+            ("pages/api/users.ts", ""),
+        ])?;
+        let shallow_scan = |next_config: NextConfigBuilder| {
+            scan_pages_with_options(
+                &project,
+                next_config,
+                |_| PagesScanOptions::default(),
+                |scan| async move {
+                    let shallow =
+                        find_pages_structure_shallow(scan.root, scan.root, scan.next_config);
+                    let Some(structure) = *shallow.await? else {
+                        bail!("the pages directory wasn't found");
+                    };
+                    let structure = structure.await?;
+                    let mut routes = vec![];
+                    for item in structure.items.iter() {
+                        routes.push(item.route_pattern().await?.clone_value());
+                    }
+                    let mut middleware = vec![];
+                    for file in structure.middleware.iter() {
+                        middleware.push(file.await?.path.clone());
+                    }
+                    Ok((
+                        routes,
+                        middleware,
+                        structure.child_directories.clone(),
+                        reported_issues(shallow).await?,
+                    ))
+                },
+            )
+        };
+
+        // The root is listed like in a full scan: legacy middleware isn't a
+        // route and the ignored duplicate is reported.
+        let (routes, middleware, child_directories, issues) =
+            shallow_scan(NextConfigBuilder::default())?;
+        assert_eq!(routes, vec!["/about", "/"]);
+        assert_eq!(middleware, vec!["pages/_middleware.ts"]);
+        assert_eq!(child_directories, vec!["api", "blog"]);
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert!(
+            issues[0]
+                .description
+                .starts_with("The files `about.tsx` and `about.js` serve the same route."),
+            "{issues:?}"
+        );
+
+        // The root is left out when it has too many entries.
+        let (routes, middleware, child_directories, issues) =
+            shallow_scan(NextConfigBuilder::default().max_pages_directory_entries(5))?;
+        assert!(routes.is_empty() && middleware.is_empty() && child_directories.is_empty());
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!(issues[0].severity, "error");
+        Ok(())
+    }
+
+    #[test]
+    fn stores_the_router_root_it_was_scanned_with() -> Result<()> {
+        let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/blog/[slug].tsx", "")])?;
+        let root_dir = project.root_dir();
+        TestRunner::new()?.run(async move {
+            let scan = scan_project_served_from(
+                &root_dir,
+                ".next/server",
+                NextConfigBuilder::default(),
+                |_| PagesScanOptions::default(),
+            )
+            .await?;
+            assert_eq!(
+                *scan.pages.router_root().await?,
+                *scan.root.join(".next/server").await?
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn finds_directories_without_routes() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/blog/post.tsx", ""),
+            ("pages/drafts/notes.md", ""),
+            ("pages/drafts/old/", ""),
+        ])?;
+        let has_routes = scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            let mut has_routes = vec![(String::new(), *pages.has_routes().await?)];
+            for child in pages.await?.children.iter() {
+                let directory = child.directory().await?;
+                let name = directory.path.rsplit('/').next().unwrap_or_default();
+                has_routes.push((name.to_string(), *child.has_routes().await?));
+            }
+            Ok(has_routes)
+        })?;
+        assert_eq!(
+            has_routes,
+            vec![
+                (String::new(), true),
+                ("blog".to_string(), true),
+                ("drafts".to_string(), false),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn applies_route_specific_tracing_excludes() -> Result<()> {
+        let project = TestProject::new(&[("pages/api/image.ts", ""), ("pages/api/other.ts", "")])?;
+        let next_config = || {
+            NextConfigBuilder::default().output_file_tracing(
+                IndexMap::new(),
+                IndexMap::from([(
+                    "/api/image".to_string(),
+                    vec!["node_modules/sharp/**".to_string()],
+                )]),
+            )
+        };
+        let traces = scan_pages(&project, next_config(), move |pages| async move {
+            let next_config = next_config().cell();
+            let traced = StringsVc::cell(vec![
+                "node_modules/sharp/lib/index.js".to_string(),
+                "node_modules/react/index.js".to_string(),
+            ]);
+            let mut traces = vec![];
+            for entry in pages.route_entries().await?.iter() {
+                traces.push((
+                    entry.await?.pattern.clone(),
+                    entry.trace_files(next_config, traced).await?.clone_value(),
+                ));
+            }
+            traces.sort();
+            Ok(traces)
+        })?;
+        assert_eq!(
+            traces,
+            vec![
+                (
+                    "/api/image".to_string(),
+                    vec!["node_modules/react/index.js".to_string()]
+                ),
+                (
+                    "/api/other".to_string(),
+                    vec![
+                        "node_modules/sharp/lib/index.js".to_string(),
+                        "node_modules/react/index.js".to_string(),
+                    ]
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn updates_route_stats_when_a_route_is_added() -> Result<()> {
+        let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/about.tsx", "")])?;
+        let runner = TestRunner::new()?;
+        runner.watch(&project)?;
+        let root_dir = project.root_dir();
+        let stats = move || {
+            let root_dir = root_dir.clone();
+            async move {
+                let root = project_root(&root_dir);
+                let pages = find_pages_structure(root, root, NextConfigBuilder::default().cell());
+                let stats = pages.route_stats().await?;
+                Ok((stats.total, stats.dynamic))
+            }
+        };
+
+        let initial = runner.run(stats())?;
+        assert_eq!(initial, (2, 0));
+        project.write("pages/blog/[slug].tsx", "")?;
+        assert_eq!(runner.run_until_changed(&initial, stats)?, (3, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn writes_route_params_types() -> Result<()> {
+        let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/blog/[slug].tsx", "")])?;
+        let root_dir = project.root_dir();
+        TestRunner::new()?.run(async move {
+            let root = project_root(&root_dir);
+            find_pages_structure(root, root, NextConfigBuilder::default().cell())
+                .write_route_params_types(root.join(".next/types"))
+                .await?;
+            Ok(())
+        })?;
+        let declarations = std::fs::read_to_string(
+            Path::new(&project.root_dir()).join(".next/types/routes.d.ts"),
+        )?;
+        assert!(declarations.contains(r#""/blog/[slug]": { slug: string };"#));
+        Ok(())
+    }
+
+    #[test]
+    fn finds_competing_routes_in_a_scan() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("pages/[slug].tsx", ""),
+            ("pages/blog/[id].tsx", ""),
+            ("pages/blog/latest.tsx", ""),
+        ])?;
+        let pairs = scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            let router_root = pages.router_root();
+            Ok(pages.competing_routes(router_root).await?.clone_value())
+        })?;
+        assert_eq!(
+            pairs,
+            vec![
+                ("/[slug]".to_string(), "/about".to_string()),
+                ("/blog/[id]".to_string(), "/blog/latest".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn selects_the_pages_root() {
+        assert_eq!(
+            select_pages_root(true, true),
+            Some(PagesRootCandidate::Pages)
+        );
+        assert_eq!(
+            select_pages_root(true, false),
+            Some(PagesRootCandidate::Pages)
+        );
+        assert_eq!(
+            select_pages_root(false, true),
+            Some(PagesRootCandidate::SrcPages)
+        );
+        assert_eq!(select_pages_root(false, false), None);
+    }
+
+    #[test]
+    fn follows_symlinked_pages_directories() {
+        // `pages` is a symlink to `content/pages`.
+        assert!(resolves_to_directory(&FileSystemEntryType::Symlink, true));
+        assert_eq!(
+            select_pages_root(
+                resolves_to_directory(&FileSystemEntryType::Symlink, true),
+                false
+            ),
+            Some(PagesRootCandidate::Pages)
+        );
+        // A dangling symlink or a symlink to a file isn't a pages directory.
+        assert!(!resolves_to_directory(&FileSystemEntryType::Symlink, false));
+        assert!(resolves_to_directory(
+            &FileSystemEntryType::Directory,
+            false
+        ));
+        assert!(!resolves_to_directory(&FileSystemEntryType::File, false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scans_symlinked_pages_directories() -> Result<()> {
+        let project = TestProject::new(&[
+            ("content/pages/index.tsx", ""),
+            ("content/pages/blog/[slug].tsx", ""),
+            ("src/pages/fallback.tsx", ""),
+        ])?;
+        let pages_dir = Path::new(&project.root_dir()).join("pages");
+        std::os::unix::fs::symlink("content/pages", &pages_dir)?;
+        let files = scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            assert_routes(pages, &["/", "/blog/[slug]"]).await?;
+            let mut files = vec![];
+            for entry in pages.route_entries().await?.iter() {
+                files.push(entry.await?.file.await?.path.clone());
+            }
+            files.sort();
+            Ok(files)
+        })?;
+        // The files are found through the link.
+        assert_eq!(files, vec!["pages/blog/[slug].tsx", "pages/index.tsx"]);
+
+        // A dangling link isn't a pages directory.
+        std::fs::remove_file(&pages_dir)?;
+        std::os::unix::fs::symlink("content/missing", &pages_dir)?;
+        scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            assert_routes(pages, &["/fallback"]).await
+        })
+    }
+
+    #[test]
+    fn polls_routes_changed_while_the_structure_is_rebuilt() -> Result<()> {
+        let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/blog/[slug].tsx", "")])?;
+        let runner = TestRunner::new()?;
+        runner.watch(&project)?;
+        let root_dir = project.root_dir();
+        let routes = || {
+            let root_dir = root_dir.clone();
+            async move {
+                let scan = scan_project(&root_dir, NextConfigBuilder::default(), |_| {
+                    PagesScanOptions::default()
+                })
+                .await?;
+                let route_patterns = |structure: PagesStructureVc| async move {
+                    let mut patterns = structure.route_patterns().await?.clone_value();
+                    patterns.sort();
+                    anyhow::Ok(patterns)
+                };
+                // Poll the completion while the routes are read.
+                let (_, routes) = futures::try_join!(
+                    async { scan.structure.routes_changed().await.map(|_| ()) },
+                    route_patterns(scan.pages),
+                )?;
+
+                // Round trip the structure through serialization, like a
+                // restore from a persistent cache, and read it again.
+                let restored: PagesStructure =
+                    serde_json::from_str(&serde_json::to_string(&*scan.pages.await?)?)?;
+                let restored_routes = route_patterns(restored.cell()).await?;
+                if restored_routes != routes {
+                    bail!("restored routes {restored_routes:?} differ from {routes:?}");
+                }
+                Ok(routes)
+            }
+        };
+
+        let mut previous = runner.run(routes())?;
+        assert_eq!(previous, vec!["/", "/blog/[slug]"]);
+        for i in 0..20 {
+            let page = format!("pages/page-{i}.tsx");
+            project.write(&page, "")?;
+            previous = runner.run_until_changed(&previous, routes)?;
+            assert!(previous.contains(&format!("/page-{i}")), "{previous:?}");
+            project.remove(&page)?;
+            previous = runner.run_until_changed(&previous, routes)?;
+            assert_eq!(previous, vec!["/", "/blog/[slug]"]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn records_the_pages_root_and_a_generation_per_structural_change() -> Result<()> {
+        let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/about.tsx", "")])?;
+        let runner = TestRunner::new()?;
+        runner.watch(&project)?;
+        // Records a scan of the project in `root_dir` with `log`, and returns
+        // the pages root, whether it is the fallback, the generation and the
+        // number of routes.
+        let record = |root_dir: String, log: Arc<PagesScanLog>| async move {
+            let scan = scan_project(&root_dir, NextConfigBuilder::default(), |_| {
+                PagesScanOptions::default()
+            })
+            .await?;
+            let metadata =
+                pages_structure_metadata(scan.root, scan.root, scan.next_config, scan.options);
+            record_pages_scan(metadata, PagesScans(log.clone()).cell()).await?;
+            let Some(status) = log.latest() else {
+                bail!("no scan was recorded");
+            };
+            Ok((
+                status.metadata.pages_root.await?.path.clone(),
+                status.metadata.is_src_fallback,
+                status.generation,
+                status.metadata.routes,
+            ))
+        };
+        let root_dir = project.root_dir();
+        let log = Arc::new(PagesScanLog::new());
+        let scan = || record(root_dir.clone(), log.clone());
+
+        let initial = runner.run(scan())?;
+        assert_eq!(initial, ("pages".to_string(), false, 1, 2));
+
+        // Editing a page doesn't change the routes. A new page is the next
+        // generation, not one per recomputation.
+        project.write("pages/about.tsx", "export default function About() {}")?;
+        project.write("pages/contact.tsx", "")?;
+        let added = runner.run_until_changed(&initial, scan)?;
+        assert_eq!(added, ("pages".to_string(), false, 2, 3));
+
+        project.remove("pages/contact.tsx")?;
+        let removed = runner.run_until_changed(&added, scan)?;
+        assert_eq!(removed, ("pages".to_string(), false, 3, 2));
+
+        // Every log counts its own generations.
+        let fallback = TestProject::new(&[("src/pages/index.tsx", "")])?;
+        let fallback_scan =
+            runner.run(record(fallback.root_dir(), Arc::new(PagesScanLog::new())))?;
+        assert_eq!(fallback_scan, ("src/pages".to_string(), true, 1, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn finds_deeply_nested_api_routes() {
+        assert_eq!(api_depth_exceeding("/api/a/b/c/d/e/handler", 3), Some(6));
+        assert_eq!(api_depth_exceeding("/api/a/b/c", 3), None);
+        assert_eq!(api_depth_exceeding("/api", 0), None);
+        assert_eq!(api_depth_exceeding("/a/b/c/d/e", 3), None);
+
+        let config = NextConfigBuilder::default().max_api_depth(3).build();
+        assert_eq!(config.max_api_depth(), Some(3));
+        assert_eq!(NextConfigBuilder::default().build().max_api_depth(), None);
+    }
+
+    #[test]
+    fn finds_the_root_index_route() -> Result<()> {
+        let home_route = |files: &[(&str, &str)]| {
+            scan_pages(
+                &TestProject::new(files)?,
+                NextConfigBuilder::default(),
+                |pages| async move {
+                    Ok(match *pages.home_route().await? {
+                        Some(item) => Some(item.route_pattern().await?.clone_value()),
+                        None => None,
+                    })
+                },
+            )
+        };
+        assert_eq!(
+            home_route(&[("pages/about.tsx", ""), ("pages/index.tsx", "")])?.as_deref(),
+            Some("/")
+        );
+        // Unlike `home_item`, the root optional catch-all isn't the index.
+        assert_eq!(
+            home_route(&[("pages/about.tsx", ""), ("pages/[[...slug]].tsx", "")])?,
+            None
+        );
+        assert_eq!(home_route(&[("pages/blog/index.tsx", "")])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn finds_pages_reclassified_by_removing_an_extension() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/docs/intro.mdx", ""),
+            ("pages/docs/api.tsx", ""),
+            ("pages/blog/hello.mdx", ""),
+            ("pages/notes.txt", ""),
+        ])?;
+        let root_dir = project.root_dir();
+        let reclassified = TestRunner::new()?.run(async move {
+            let root = project_root(&root_dir);
+            let extensions = |extensions: &[&str]| {
+                let config = NextConfigBuilder::default()
+                    .page_extensions(extensions.iter().map(ToString::to_string).collect())
+                    .build();
+                PageExtensions::from_next_config(&config).cell()
+            };
+            Ok(pages_reclassified_by_extensions(
+                root.join("pages"),
+                extensions(&["tsx", "mdx"]),
+                extensions(&["tsx"]),
+            )
+            .await?
+            .clone_value())
+        })?;
+        assert_eq!(reclassified, vec!["blog/hello.mdx", "docs/intro.mdx"]);
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_dev_only_routes_out_of_production() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("pages/__debug/cache.tsx", ""),
+            ("pages/__debug/index.tsx", ""),
+            ("pages/blog/__debug.tsx", ""),
+        ])?;
+        let scan = |dev_only_routes: bool, production: bool| {
+            scan_pages_with_options(
+                &project,
+                NextConfigBuilder::default().dev_only_routes(dev_only_routes),
+                move |_| PagesScanOptions::default().with_production(production),
+                |scan| async move {
+                    let mut routes = scan.pages.route_patterns().await?.clone_value();
+                    routes.sort();
+                    Ok((routes, reported_issues(scan.pages).await?))
+                },
+            )
+        };
+
+        let (dev, dev_issues) = scan(true, false)?;
+        let (production, production_issues) = scan(true, true)?;
+        assert_eq!(
+            dev,
+            vec!["/", "/__debug", "/__debug/cache", "/about", "/blog/__debug"]
+        );
+        assert_eq!(production, vec!["/", "/about", "/blog/__debug"]);
+        assert_eq!(dev_issues, vec![]);
+        assert_eq!(production_issues.len(), 1, "{production_issues:?}");
+        assert_eq!(production_issues[0].severity, "info");
+        assert!(production_issues[0].description.contains("`__debug`"));
+        // Without the opt-in, production builds keep every route.
+        assert_eq!(scan(false, true)?.0, dev);
+        Ok(())
+    }
+
+    #[test]
+    fn applies_the_base_path_to_scanned_routes() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/blog/[slug].tsx", ""),
+            ("pages/api/users.ts", ""),
+        ])?;
+        let next_config = || NextConfigBuilder::default().base_path("/docs");
+        let (urls, resolved) = scan_pages(&project, next_config(), move |pages| async move {
+            let next_config = next_config().cell();
+            let mut urls = vec![];
+            for item in pages.await?.items.iter() {
+                urls.push(item.effective_urls(next_config).await?[0].clone());
+            }
+            for child in pages.await?.children.iter() {
+                for item in child.await?.items.iter() {
+                    urls.push(item.effective_urls(next_config).await?[0].clone());
+                }
+            }
+            urls.sort();
+            let mut resolved = vec![];
+            for pathname in ["/docs/blog/hello", "/blog/hello"] {
+                let route = pages
+                    .resolve_route_with_rewrites(pathname.to_string(), next_config)
+                    .await?;
+                resolved.push(match *route {
+                    Some(route) => Some(route.await?.entry.await?.pattern.clone()),
+                    None => None,
+                });
+            }
+            Ok((urls, resolved))
+        })?;
+        assert_eq!(urls, vec!["/docs", "/docs/api/users", "/docs/blog/[slug]"]);
+        assert_eq!(resolved, vec![Some("/blog/[slug]".to_string()), None]);
+        Ok(())
+    }
+
+    #[test]
+    fn composes_effective_urls() {
+        let config = NextConfigBuilder::default()
+            .base_path("/docs")
+            .i18n(vec!["en".to_string(), "fr".to_string()], "en")
+            .trailing_slash(true)
+            .build();
+        let urls = |pattern: &str, is_api: bool| {
+            effective_urls(
+                pattern,
+                is_api,
+                config.base_path(),
+                config.i18n_locales(),
+                config.trailing_slash(),
+            )
+        };
+        assert_eq!(
+            urls("/", false),
+            vec![
+                "/docs/",
+                "/docs",
+                "/docs/en/",
+                "/docs/en",
+                "/docs/fr/",
+                "/docs/fr"
+            ]
+        );
+        assert_eq!(
+            urls("/blog/[slug]", false),
+            vec![
+                "/docs/blog/[slug]/",
+                "/docs/blog/[slug]",
+                "/docs/en/blog/[slug]/",
+                "/docs/en/blog/[slug]",
+                "/docs/fr/blog/[slug]/",
+                "/docs/fr/blog/[slug]",
+            ]
+        );
+        assert_eq!(
+            urls("/api/users", true),
+            vec!["/docs/api/users/", "/docs/api/users"]
+        );
+        assert_eq!(
+            urls("/shop/[[...path]]", false),
+            vec![
+                "/docs/shop/[[...path]]/",
+                "/docs/shop/[[...path]]",
+                "/docs/shop/",
+                "/docs/shop",
+                "/docs/en/shop/[[...path]]/",
+                "/docs/en/shop/[[...path]]",
+                "/docs/en/shop/",
+                "/docs/en/shop",
+                "/docs/fr/shop/[[...path]]/",
+                "/docs/fr/shop/[[...path]]",
+                "/docs/fr/shop/",
+                "/docs/fr/shop",
+            ]
+        );
+
+        // Without any of the features, the root index is only `/`.
+        assert_eq!(effective_urls("/", false, "", None, false), vec!["/"]);
+        assert_eq!(
+            effective_urls("/[[...slug]]", false, "", None, false),
+            vec!["/[[...slug]]", "/[[...slug]]/", "/"]
+        );
+    }
+
+    #[test]
+    fn finds_reserved_param_names() {
+        let reserved = NextConfigBuilder::default().build().reserved_param_names();
+        assert_eq!(reserved_params("/docs/[amp]", &reserved), vec!["amp"]);
+        assert_eq!(
+            reserved_params("/[_rsc]/[...slug]", &reserved),
+            vec!["_rsc"]
+        );
+        assert!(reserved_params("/blog/[slug]", &reserved).is_empty());
+        assert!(reserved_params("/amp", &reserved).is_empty());
+
+        let reserved = NextConfigBuilder::default()
+            .reserved_param_names(vec!["slug".to_string()])
+            .build()
+            .reserved_param_names();
+        assert_eq!(reserved_params("/blog/[slug]", &reserved), vec!["slug"]);
+        assert!(reserved_params("/docs/[amp]", &reserved).is_empty());
+    }
+
+    #[test]
+    fn finds_routes_of_relative_paths() {
+        let classifier =
+            NextFileClassifier::new(PageExtensions::new(["tsx".to_string(), "ts".to_string()]))
+                .with_amp_suffix_pages(true);
+        for (rel, route) in [
+            ("blog/[slug].tsx", Some("/blog/[slug]")),
+            ("index.tsx", Some("/")),
+            ("blog/index.tsx", Some("/blog")),
+            ("blog/index/index.tsx", Some("/blog/index")),
+            ("api/users/[id].ts", Some("/api/users/[id]")),
+            ("docs\\[...path].tsx", Some("/docs/[...path]")),
+            ("about.md", None),
+            ("_app.tsx", None),
+            ("blog/_middleware.ts", None),
+            ("blog/_app.tsx", Some("/blog/_app")),
+            ("post.amp.tsx", Some("/post")),
+        ] {
+            assert_eq!(
+                route_for_relative_path(rel, &classifier).as_deref(),
+                route,
+                "{rel}"
+            );
+        }
+    }
+
+    #[test]
+    fn caps_huge_directory_listings() {
+        // `pages/tmp` with 11 files next to `pages/blog` with 2, capped at 10
+        let tmp = (0..11).map(|i| format!("{i}.tsx")).collect::<Vec<_>>();
+        let blog = ["[slug].tsx", "index.tsx"];
+        assert_eq!(capped_entries(&tmp, 10), None);
+        assert_eq!(capped_entries(&tmp[..10], 10).map(<[_]>::len), Some(10));
+        assert_eq!(capped_entries(&blog, 10), Some(&blog[..]));
+        assert_eq!(
+            NextConfigBuilder::default()
+                .build()
+                .max_pages_directory_entries(),
+            DEFAULT_MAX_PAGES_DIRECTORY_ENTRIES
+        );
+    }
+
+    #[test]
+    fn caps_huge_directories_of_the_scan() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/blog/index.tsx", ""),
+            ("pages/blog/[slug].tsx", ""),
+        ])?;
+        // A mocked listing of `pages/tmp` with one entry more than the cap,
+        // next to the listing of `pages/blog`.
+        let snapshot = IndexMap::from([
+            (
+                String::new(),
+                vec![
+                    ("index.tsx".to_string(), SnapshotEntryType::File),
+                    ("tmp".to_string(), SnapshotEntryType::Directory),
+                    ("blog".to_string(), SnapshotEntryType::Directory),
+                ],
+            ),
+            (
+                "tmp".to_string(),
+                (0..=100)
+                    .map(|i| (format!("{i}.tsx"), SnapshotEntryType::File))
+                    .collect(),
+            ),
+            (
+                "blog".to_string(),
+                vec![
+                    ("index.tsx".to_string(), SnapshotEntryType::File),
+                    ("[slug].tsx".to_string(), SnapshotEntryType::File),
+                ],
+            ),
+        ]);
+        let issues = scan_pages_with_options(
+            &project,
+            NextConfigBuilder::default().max_pages_directory_entries(100),
+            |_| PagesScanOptions::default().with_snapshot(PagesDirectorySnapshotVc::cell(snapshot)),
+            |scan| async move {
+                assert_routes(scan.pages, &["/", "/blog", "/blog/[slug]"]).await?;
+                reported_issues(scan.structure).await
+            },
+        )?;
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!(issues[0].severity, "error");
+        assert!(
+            issues[0]
+                .description
+                .starts_with("The directory has 101 entries, more than the limit of 100"),
+            "{issues:?}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compares_routes_structurally() {
+        let route = |pattern: &str, kind: &str, path: &str| {
+            (
+                pattern.to_string(),
+                kind.to_string(),
+                route_rank(&route_segments_from_pattern(pattern)),
+                path.to_string(),
+            )
+        };
+        let fixture = || {
+            vec![
+                route("/", "page", "index.tsx"),
+                route("/blog/[slug]", "page", "blog/[slug].tsx"),
+                route("/api/users", "api", "api/users.ts"),
+            ]
+        };
+        assert!(same_routes(fixture(), fixture()));
+        let mut reversed = fixture();
+        reversed.reverse();
+        assert!(same_routes(fixture(), reversed));
+
+        let mut moved = fixture();
+        moved[1] = route("/blog/[slug]", "page", "blog/[slug]/index.tsx");
+        assert!(!same_routes(fixture(), moved));
+        let mut missing = fixture();
+        missing.pop();
+        assert!(!same_routes(fixture(), missing));
+    }
+
+    #[test]
+    fn finds_sibling_routes() {
+        // `pages/docs/setup.tsx`, `pages/docs/intro.tsx` and
+        // `pages/docs/usage.tsx`
+        let patterns = ["/docs/setup", "/docs/intro", "/docs/usage"].map(ToString::to_string);
+        assert_eq!(sibling_indices(&patterns, 0), vec![1, 2]);
+        assert_eq!(sibling_indices(&patterns, 1), vec![0, 2]);
+        assert_eq!(sibling_indices(&patterns, 2), vec![1, 0]);
+        assert_eq!(sibling_indices(&patterns[..1], 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn finds_subtree_directories() {
+        // `pages/blog/[slug].tsx`, `pages/blog/archive/2020.tsx` and
+        // `pages/about.tsx`, served from `/`
+        assert_eq!(subtree_directories("", "/blog"), Some(vec!["blog"]));
+        assert_eq!(
+            subtree_directories("", "/blog/archive/"),
+            Some(vec!["blog", "archive"])
+        );
+        assert_eq!(subtree_directories("", "/"), Some(vec![]));
+        // With the routes of the structure served from `/docs`.
+        assert_eq!(
+            subtree_directories("docs", "/docs/blog"),
+            Some(vec!["blog"])
+        );
+        assert_eq!(subtree_directories("docs", "/blog"), None);
+    }
+
+    #[test]
+    fn extracts_subtrees_of_the_scan() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("pages/blog/index.tsx", ""),
+            ("pages/blog/[slug].tsx", ""),
+            ("pages/blog/archive/2020.tsx", ""),
+        ])?;
+        scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            let router_root = pages.await?.router_root;
+            let Some(blog) = *pages.subtree("/blog", router_root).await? else {
+                bail!("the `/blog` subtree wasn't found");
+            };
+            assert_routes(blog, &["/blog", "/blog/[slug]", "/blog/archive/2020"]).await?;
+            let Some(archive) = *pages.subtree("/blog/archive/", router_root).await? else {
+                bail!("the `/blog/archive` subtree wasn't found");
+            };
+            assert_routes(archive, &["/blog/archive/2020"]).await?;
+            let Some(all) = *pages.subtree("/", router_root).await? else {
+                bail!("the `/` subtree wasn't found");
+            };
+            assert_routes(
+                all,
+                &["/", "/about", "/blog", "/blog/[slug]", "/blog/archive/2020"],
+            )
+            .await?;
+            // Pages aren't subtrees, and neither are missing directories.
+            assert!(pages.subtree("/about", router_root).await?.is_none());
+            assert!(pages.subtree("/shop", router_root).await?.is_none());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn finds_the_home_route() {
+        assert_eq!(
+            find_home_route(["/about", "/", "/[[...slug]]"], true),
+            Some(1)
+        );
+        assert_eq!(find_home_route(["/about", "/[[...slug]]"], true), Some(1));
+        assert_eq!(find_home_route(["/about", "/[[...slug]]"], false), None);
+        assert_eq!(
+            find_home_route(["/about", "/[...slug]", "/blog/[[...slug]]"], true),
+            None
+        );
+    }
+
+    #[test]
+    fn checks_rewrite_destinations_against_app_routes_and_public_files() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("app/layout.tsx", ""),
+            ("app/dashboard/page.tsx", ""),
+            ("public/logo.png", ""),
+        ])?;
+        let rewrite = |source: &str, destination: &str| Rewrite {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            base_path: None,
+            locale: None,
+            has: None,
+            missing: None,
+        };
+        let next_config = NextConfigBuilder::default()
+            .app_dir(true)
+            .rewrites(Rewrites {
+                after_files: vec![
+                    rewrite("/dash", "/dashboard"),
+                    rewrite("/brand", "/logo.png"),
+                    rewrite("/gone", "/missing"),
+                ],
+                ..Default::default()
+            });
+        let issues = scan_pages_with_options(
+            &project,
+            next_config,
+            |_| PagesScanOptions::default(),
+            |scan| async move { reported_issues(scan.structure).await },
+        )?;
+        let rewrite_issues = issues
+            .iter()
+            .filter(|issue| issue.description.starts_with("The rewrite from"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rewrite_issues,
+            vec![&ReportedIssue {
+                severity: "info".to_string(),
+                description: "The rewrite from `/gone` to `/missing` doesn't point at any route, \
+                              so it responds with a 404."
+                    .to_string(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_routes_after_rewrites() {
+        let patterns = ["/", "/about", "/new/[slug]"];
+        let rewrites = Rewrites {
+            after_files: vec![Rewrite {
+                source: "/old/:slug".to_string(),
+                destination: "/new/:slug".to_string(),
+                base_path: None,
+                locale: None,
+                has: None,
+                missing: None,
+            }],
+            ..Default::default()
+        };
+        let context = RouteRuleContext::default();
+
+        let (pattern, params, pathname) =
+            resolve_route_with_rewrites(&patterns, "/old/hello", &rewrites, &context).unwrap();
+        assert_eq!(pattern, "/new/[slug]");
+        assert_eq!(params["slug"], RouteParamValue::Single("hello".to_string()));
+        assert_eq!(pathname, "/new/hello");
+
+        let (pattern, _, pathname) =
+            resolve_route_with_rewrites(&patterns, "/about", &rewrites, &context).unwrap();
+        assert_eq!((pattern, pathname.as_str()), ("/about", "/about"));
+        assert!(resolve_route_with_rewrites(&patterns, "/old", &rewrites, &context).is_none());
+    }
+
+    #[test]
+    fn resolves_routes_after_rewrites_under_the_base_path_and_locales() {
+        let patterns = ["/", "/new/[slug]"];
+        let rewrite = Rewrite {
+            source: "/old/:slug".to_string(),
+            destination: "/new/:slug".to_string(),
+            base_path: None,
+            locale: None,
+            has: None,
+            missing: None,
+        };
+        let rewrites = Rewrites {
+            after_files: vec![
+                rewrite.clone(),
+                Rewrite {
+                    source: "/legacy/:slug".to_string(),
+                    destination: "/docs/new/:slug".to_string(),
+                    base_path: Some(false),
+                    locale: Some(false),
+                    ..rewrite
+                },
+            ],
+            ..Default::default()
+        };
+        let context = RouteRuleContext {
+            base_path: "/docs".to_string(),
+            locales: vec!["en".to_string(), "fr".to_string()],
+            default_locale: Some("en".to_string()),
+        };
+
+        let (pattern, params, pathname) =
+            resolve_route_with_rewrites(&patterns, "/docs/fr/old/hello", &rewrites, &context)
+                .unwrap();
+        assert_eq!(pattern, "/new/[slug]");
+        assert_eq!(params["slug"], RouteParamValue::Single("hello".to_string()));
+        assert_eq!(pathname, "/new/hello");
+
+        let (pattern, ..) =
+            resolve_route_with_rewrites(&patterns, "/legacy/hello", &rewrites, &context).unwrap();
+        assert_eq!(pattern, "/new/[slug]");
+        assert!(
+            resolve_route_with_rewrites(&patterns, "/old/hello", &rewrites, &context).is_none()
+        );
+    }
+
+    #[test]
+    fn resolves_routes_with_locale_prefixes() {
+        let patterns = ["/", "/about"];
+        let context = RouteRuleContext {
+            locales: vec!["en".to_string(), "fr".to_string()],
+            default_locale: Some("en".to_string()),
+            ..Default::default()
+        };
+        let (pattern, _, locale) = resolve_route_i18n(patterns, "/fr/about", &context).unwrap();
+        assert_eq!((pattern, locale.as_deref()), ("/about", Some("fr")));
+        let (pattern, _, locale) = resolve_route_i18n(patterns, "/about", &context).unwrap();
+        assert_eq!((pattern, locale.as_deref()), ("/about", Some("en")));
+        let (pattern, _, locale) = resolve_route_i18n(patterns, "/fr", &context).unwrap();
+        assert_eq!((pattern, locale.as_deref()), ("/", Some("fr")));
+        let (_, _, locale) =
+            resolve_route_i18n(patterns, "/about", &RouteRuleContext::default()).unwrap();
+        assert_eq!(locale, None);
+    }
+
+    #[test]
+    fn finds_not_found_pages_in_both_routers() {
+        // `pages/404.tsx` and `app/not-found.tsx`
+        let pages = [
+            ("/".to_string(), "pages/index.tsx"),
+            ("/404".to_string(), "pages/404.tsx"),
+        ];
+        let app = [
+            ("layout.tsx".to_string(), "app/layout.tsx"),
+            ("not-found.tsx".to_string(), "app/not-found.tsx"),
+        ];
+        assert_eq!(
+            find_not_found_conflict(&pages, &app),
+            Some(("pages/404.tsx", "app/not-found.tsx"))
+        );
+        assert_eq!(find_not_found_conflict(&pages[..1], &app), None);
+        assert_eq!(find_not_found_conflict(&pages, &app[..1]), None);
+    }
+
+    #[test]
+    fn finds_global_wrappers_in_both_routers() {
+        // `pages/_app.tsx` and `app/layout.tsx`
+        let pages = [
+            ("/".to_string(), "pages/index.tsx"),
+            ("/_app".to_string(), "pages/_app.tsx"),
+        ];
+        let app = [
+            ("page.tsx".to_string(), "app/page.tsx"),
+            ("layout.tsx".to_string(), "app/layout.tsx"),
+        ];
+        assert_eq!(
+            find_global_wrapper_conflict(&pages, &app),
+            Some(("pages/_app.tsx", "app/layout.tsx"))
+        );
+        assert_eq!(find_global_wrapper_conflict(&pages[..1], &app), None);
+        assert_eq!(find_global_wrapper_conflict(&pages, &app[..1]), None);
+    }
+
+    #[test]
+    fn groups_api_routes_by_first_segment() {
+        // `api/users/[id].ts`, `api/users/index.ts`, `api/posts/index.ts` and
+        // `api/index.ts`
+        let groups = group_api_routes([
+            ("users/[id]/index.html", "api/users/[id].ts"),
+            ("users/index.html", "api/users/index.ts"),
+            ("posts/index.html", "api/posts/index.ts"),
+            ("index.html", "api/index.ts"),
+        ]);
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            vec!["users", "posts", ""]
+        );
+        assert_eq!(
+            groups["users"],
+            vec!["api/users/[id].ts", "api/users/index.ts"]
+        );
+        assert_eq!(groups["posts"], vec!["api/posts/index.ts"]);
+        assert_eq!(groups[""], vec!["api/index.ts"]);
+    }
+
+    #[test]
+    fn strict_routing_upgrades_routing_diagnostics() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/[a]/x.tsx", ""),
+            ("pages/[b]/y.tsx", ""),
+            ("pages/api/a/b/handler.ts", ""),
+        ])?;
+        let runner = TestRunner::new()?;
+        for (strict_routing, severity) in [(false, "warning"), (true, "error")] {
+            let root_dir = project.root_dir();
+            let issues = runner.run(async move {
+                let root = project_root(&root_dir);
+                let next_config = NextConfigBuilder::default()
+                    .strict_routing(strict_routing)
+                    .max_api_depth(1)
+                    .cell();
+                let structure = find_pages_structure(root, root, next_config);
+                structure.await?;
+                reported_issues(structure).await
+            })?;
+            // The dynamic siblings and the nested API route.
+            assert_eq!(issues.len(), 2, "{issues:?}");
+            for issue in issues {
+                assert_eq!(issue.severity, severity, "{issue:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn attaches_loader_hints() {
+        let classifier = NextFileClassifier::new(PageExtensions::new(
+            ["mdx", "tsx", "page.js"].map(ToString::to_string),
+        ));
+        assert_eq!(
+            classifier.transform_hint("post.mdx").as_deref(),
+            Some("mdx")
+        );
+        assert_eq!(
+            classifier.transform_hint("about.tsx").as_deref(),
+            Some("typescript")
+        );
+        assert_eq!(
+            classifier.transform_hint("about.page.js").as_deref(),
+            Some("ecmascript")
+        );
+        assert_eq!(classifier.transform_hint("styles.css"), None);
+    }
+
+    #[test]
+    fn finds_special_files_at_any_level() {
+        // `pages/_app.tsx`, `pages/blog/_middleware.ts` and `pages/blog/_app.tsx`
+        assert_eq!(special_kind("_app", true), Some(SpecialKind::App));
+        assert_eq!(
+            special_kind("_middleware", false),
+            Some(SpecialKind::Middleware)
+        );
+        assert_eq!(special_kind("_app", false), None);
+        assert_eq!(special_kind("about", true), None);
+
+        let classifier = NextFileClassifier::new(PageExtensions::new(["ts".to_string()]));
+        assert_eq!(
+            classifier.classify_file("_middleware.ts", false, false),
+            FileClassification::Special {
+                basename: "_middleware".to_string()
+            }
+        );
+        assert_eq!(
+            classifier.classify_file("_app.ts", false, false),
+            FileClassification::Page {
+                basename: "_app".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn lists_legacy_middleware_without_routing_it() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/_app.tsx", ""),
+            ("pages/index.tsx", ""),
+            ("pages/blog/_middleware.ts", ""),
+            ("pages/blog/[slug].tsx", ""),
+        ])?;
+        let special_files =
+            scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+                assert_routes(pages, &["/", "/_app", "/blog/[slug]"]).await?;
+                let mut special_files = vec![];
+                for (file, kind) in pages.all_special_files().await?.iter() {
+                    special_files.push((file.await?.path.clone(), *kind));
+                }
+                Ok(special_files)
+            })?;
+        assert_eq!(
+            special_files,
+            vec![
+                ("pages/_app.tsx".to_string(), SpecialKind::App),
+                (
+                    "pages/blog/_middleware.ts".to_string(),
+                    SpecialKind::Middleware
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn merges_virtual_routes() {
+        // `pages/index.tsx` and `pages/about.tsx`, with virtual `/healthz` and
+        // `/about` routes
+        let scanned = vec!["/".to_string(), "/about".to_string()];
+        let virtual_patterns = ["/healthz", "/about"];
+        assert_eq!(
+            merge_virtual_routes(
+                &scanned,
+                virtual_patterns,
+                VirtualPagesPrecedence::default()
+            ),
+            (vec!["/healthz"], vec![])
+        );
+        assert_eq!(
+            merge_virtual_routes(
+                &scanned,
+                virtual_patterns,
+                VirtualPagesPrecedence::VirtualPages
+            ),
+            (vec!["/healthz", "/about"], vec!["/about"])
+        );
+    }
+
+    #[test]
+    fn records_the_provenance_of_merged_virtual_pages() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("plugin/about.tsx", ""),
+            ("plugin/healthz.ts", ""),
+        ])?;
+        let virtual_pages = |root: FileSystemPathVc| {
+            let virtual_pages = VirtualPagesVc::cell(IndexMap::from([
+                (
+                    "/about".to_string(),
+                    SourceAssetVc::new(root.join("plugin/about.tsx")).into(),
+                ),
+                (
+                    "/healthz".to_string(),
+                    SourceAssetVc::new(root.join("plugin/healthz.ts")).into(),
+                ),
+            ]));
+            PagesScanOptions::default()
+                .with_virtual_pages(virtual_pages, VirtualPagesPrecedence::VirtualPages)
+        };
+        scan_pages_with_options(
+            &project,
+            NextConfigBuilder::default(),
+            virtual_pages,
+            |scan| async move {
+                let root = scan.root;
+                let mut provenances = IndexMap::new();
+                for item in scan.pages.await?.items.iter() {
+                    provenances.insert(
+                        item.route_pattern().await?.clone_value(),
+                        *item.provenance().await?,
+                    );
+                }
+                let PagesStructureItemProvenance::Scanned { root: scanned_root } = provenances["/"]
+                else {
+                    bail!("`/` wasn't scanned");
+                };
+                assert_eq!(*scanned_root.await?, *root.join("pages").await?);
+                assert!(matches!(
+                    provenances["/healthz"],
+                    PagesStructureItemProvenance::Virtual { replaced: None, .. }
+                ));
+                let PagesStructureItemProvenance::Virtual {
+                    replaced: Some(loser),
+                    ..
+                } = provenances["/about"]
+                else {
+                    bail!("the virtual `/about` didn't record the page it replaced");
+                };
+                assert_eq!(
+                    *loser.await?.file().await?,
+                    *root.join("pages/about.tsx").await?
+                );
+                assert!(matches!(
+                    *loser.provenance().await?,
+                    PagesStructureItemProvenance::Scanned { .. }
+                ));
+                Ok(())
+            },
+        )
+    }
+
+    #[test]
+    fn serves_the_api_root_from_index_files() {
+        // `pages/api/index.ts` serves `/api`, `pages/api/index/index.ts`
+        // serves `/api/index`.
+        assert_eq!(entry_route_segment("index.ts", true), None);
+        assert_eq!(
+            entry_route_segment("index", false),
+            Some(RouteSegment::Static("index".to_string()))
+        );
+        assert_eq!(
+            format_route_pattern(&route_segments_for_url_path("api/index.html")),
+            "/api"
+        );
+        assert_eq!(
+            format_route_pattern(&route_segments_for_url_path("api/index/index.html")),
+            "/api/index"
+        );
+
+        // `pages/api.ts` and `pages/api/index.ts`
+        let routes = [
+            ("/api".to_string(), "pages/api.ts"),
+            ("/api/users".to_string(), "pages/api/users.ts"),
+            ("/api".to_string(), "pages/api/index.ts"),
+        ];
+        assert_eq!(
+            find_api_root_conflict(&routes),
+            Some(vec!["pages/api.ts", "pages/api/index.ts"])
+        );
+        assert_eq!(find_api_root_conflict(&routes[1..]), None);
+    }
+
+    #[test]
+    fn finds_routes_under_directory() {
+        let routes = [
+            ("index.tsx", "/"),
+            ("blog/index.tsx", "/blog"),
+            ("blog/[slug].tsx", "/blog/[slug]"),
+            ("blog/drafts/index.tsx", "/blog/drafts"),
+            ("blog.tsx", "/blog"),
+            ("blogroll.tsx", "/blogroll"),
+        ]
+        .map(|(file, pattern)| (file.to_string(), pattern.to_string()));
+        assert_eq!(
+            patterns_under_directory(&routes, "blog"),
+            vec!["/blog", "/blog/[slug]", "/blog/drafts"]
+        );
+        assert_eq!(
+            patterns_under_directory(&routes, "blog/drafts"),
+            vec!["/blog/drafts"]
+        );
+        assert_eq!(patterns_under_directory(&routes, "").len(), routes.len());
+        assert!(patterns_under_directory(&routes, "docs").is_empty());
+    }
+
+    #[test]
+    fn detects_unnormalized_name_collisions() {
+        // `pages/café.tsx` twice: precomposed and with a combining accent
+        let listing = vec![
+            ("caf\u{e9}.tsx".to_string(), SnapshotEntryType::File),
+            ("about.tsx".to_string(), SnapshotEntryType::File),
+            ("cafe\u{301}.tsx".to_string(), SnapshotEntryType::File),
+            ("cafe".to_string(), SnapshotEntryType::Directory),
+        ];
+        assert_eq!(
+            find_unnormalized_name_collisions(listing.iter().map(|(name, _)| name.as_str())),
+            vec![("cafe\u{301}.tsx", "caf\u{e9}.tsx")]
+        );
+        assert!(find_unnormalized_name_collisions(["about.tsx", "About.tsx"]).is_empty());
+    }
+
+    #[test]
+    fn detects_dynamic_siblings_with_different_param_names() {
+        // A listing of `pages/` with the dynamic directories `[a]` and `[b]`,
+        // e.g. produced by a group folder.
+        let listing = vec![
+            ("[a]".to_string(), SnapshotEntryType::Directory),
+            ("[b]".to_string(), SnapshotEntryType::Directory),
+            ("[...rest]".to_string(), SnapshotEntryType::Directory),
+            ("[c].tsx".to_string(), SnapshotEntryType::File),
+            ("about".to_string(), SnapshotEntryType::Directory),
+        ];
+        let directories = listing
+            .iter()
+            .filter(|(_, entry_type)| *entry_type == SnapshotEntryType::Directory)
+            .map(|(name, _)| name.as_str());
+        assert_eq!(
+            find_conflicting_dynamic_siblings(directories),
+            vec![("[a]", "[b]")]
+        );
+        assert!(find_conflicting_dynamic_siblings(["[a]", "[...b]", "[[...c]]"]).is_empty());
+        assert_eq!(
+            find_conflicting_dynamic_siblings(["[...a]", "[...b]", "[...a]"]),
+            vec![("[...a]", "[...b]")]
+        );
+    }
+
+    #[test]
+    fn transforms_route_patterns() {
+        let prefix = |pattern: &str| format!("/v2{}", pattern.trim_end_matches('/'));
+        let patterns = ["/", "/about", "/blog/[slug]", "/docs/[[...path]]"];
+        let transformed = patterns
+            .iter()
+            .map(|pattern| {
+                let url_path = url_path_for_route_pattern(&prefix(pattern));
+                format_route_pattern(&route_segments_for_url_path(&url_path))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transformed,
+            vec![
+                "/v2",
+                "/v2/about",
+                "/v2/blog/[slug]",
+                "/v2/docs/[[...path]]"
+            ]
+        );
+        assert_eq!(url_path_for_route_pattern("/"), "index.html");
+
+        // `pages/about.tsx` and `pages/About.tsx` with a lowercasing transform
+        let routes = [
+            ("/about".to_string(), 1),
+            ("/blog".to_string(), 2),
+            ("/about".to_string(), 3),
+        ];
+        assert_eq!(
+            find_colliding_routes(&routes),
+            vec![("/about".to_string(), vec![1, 3])]
+        );
+    }
+
+    #[test]
+    fn transforms_route_patterns_of_the_scan() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/about.tsx", ""),
+            ("pages/About.tsx", ""),
+            ("pages/blog/[slug].tsx", ""),
+        ])?;
+        let transform = |_| {
+            let transform: Arc<RoutePatternTransform> = Arc::new(|pattern: &str| {
+                format!("/v2{}", pattern.to_lowercase().trim_end_matches('/'))
+            });
+            PagesScanOptions::default().with_transform(PagesRouteTransform(transform).cell())
+        };
+        let issues = scan_pages_with_options(
+            &project,
+            NextConfigBuilder::default(),
+            transform,
+            |scan| async move {
+                assert_routes(
+                    scan.pages,
+                    &["/v2", "/v2/about", "/v2/about", "/v2/blog/[slug]"],
+                )
+                .await?;
+                reported_issues(scan.structure).await
+            },
+        )?;
+        let collisions = issues
+            .iter()
+            .filter(|issue| issue.description.contains("after transforming"))
+            .collect::<Vec<_>>();
+        assert_eq!(collisions.len(), 1, "{issues:?}");
+        assert!(collisions[0].description.contains("`/v2/about`"));
+        Ok(())
+    }
+
+    #[test]
+    fn generates_unique_route_keys() {
+        // Used to overflow after 255 generated keys, and to repeat keys after 26.
+        let pattern = (0..300).map(|i| format!("/[{i}]")).collect::<String>();
+        let entry = dynamic_route_manifest_entry(&pattern).unwrap();
+        assert_eq!(entry.route_keys.len(), 300);
+        let keys = entry
+            .route_keys
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(&keys[..3], ["a", "b", "c"]);
+        assert_eq!(&keys[25..28], ["z", "aa", "ab"]);
+        assert_eq!(keys[299], "kn");
+    }
+
+    #[test]
+    fn generates_route_keys_that_dont_collide_with_params() {
+        for (pattern, expected) in [
+            ("/[1]/[a]", vec![("b", "1"), ("a", "a")]),
+            ("/[a-b]/[ab]", vec![("ab", "a-b"), ("a", "ab")]),
+            (
+                "/[a]/[b]/[1]/[2]",
+                vec![("a", "a"), ("b", "b"), ("c", "1"), ("d", "2")],
+            ),
+            ("/[a.b]/[...a-b]", vec![("ab", "a.b"), ("a", "a-b")]),
+        ] {
+            let entry = dynamic_route_manifest_entry(pattern).unwrap();
+            let keys = entry
+                .route_keys
+                .iter()
+                .map(|(key, param)| (key.as_str(), param.as_str()))
+                .collect::<Vec<_>>();
+            assert_eq!(keys, expected, "{pattern}");
+        }
+    }
+
+    #[test]
+    fn derives_route_details_from_the_same_segments() {
+        for (url_path, pattern, regex) in [
+            ("index.html", "/", "^/(?:/)?$"),
+            ("about/index.html", "/about", "^/about(?:/)?$"),
+            (
+                "blog/[slug]/index.html",
+                "/blog/[slug]",
+                "^/blog/([^/]+?)(?:/)?$",
+            ),
+            (
+                "docs/[...path]/index.html",
+                "/docs/[...path]",
+                "^/docs/(.+?)(?:/)?$",
+            ),
+            (
+                "shop/[[...path]]/index.html",
+                "/shop/[[...path]]",
+                "^/shop(?:/(.+?))?(?:/)?$",
+            ),
+        ] {
+            let segments = route_segments_for_url_path(url_path);
+            assert_eq!(segments, route_segments_from_pattern(pattern));
+            assert_eq!(format_route_pattern(&segments), pattern);
+            assert_eq!(route_regexes(&segments).0, regex);
+            assert_eq!(
+                dynamic_params_for_segments(&segments),
+                dynamic_params(pattern)
+            );
+            if let Some(entry) = dynamic_route_manifest_entry(pattern) {
+                assert_eq!(entry.regex, regex);
+            }
+        }
+    }
+
+    #[test]
+    fn builds_dynamic_routes_manifest() {
+        let entries = dynamic_routes_manifest(
+            [
+                "/about",
+                "/shop/[[...path]]",
+                "/blog/[slug]",
+                "/docs/[...path]",
+                "/my-posts/[post-id]",
+            ],
+            ManifestOrder::Specificity,
+        );
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.page.as_str(),
+                    entry.regex.as_str(),
+                    entry.named_regex.as_str(),
+                    entry
+                        .route_keys
+                        .iter()
+                        .map(|(key, param)| format!("{key}={param}"))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "/blog/[slug]",
+                    "^/blog/([^/]+?)(?:/)?$",
+                    "^/blog/(?<slug>[^/]+?)(?:/)?$",
+                    vec!["slug=slug".to_string()],
+                ),
+                (
+                    "/my-posts/[post-id]",
+                    "^/my\\x2dposts/([^/]+?)(?:/)?$",
+                    "^/my\\x2dposts/(?<postid>[^/]+?)(?:/)?$",
+                    vec!["postid=post-id".to_string()],
+                ),
+                (
+                    "/docs/[...path]",
+                    "^/docs/(.+?)(?:/)?$",
+                    "^/docs/(?<path>.+?)(?:/)?$",
+                    vec!["path=path".to_string()],
+                ),
+                (
+                    "/shop/[[...path]]",
+                    "^/shop(?:/(.+?))?(?:/)?$",
+                    "^/shop(?:/(?<path>.+?))?(?:/)?$",
+                    vec!["path=path".to_string()],
+                ),
+            ]
+        );
+
+        let regex = regex::Regex::new(
+            &dynamic_route_manifest_entry("/shop/[[...path]]")
+                .unwrap()
+                .regex,
+        )
+        .unwrap();
+        assert!(regex.is_match("/shop"));
+        assert!(regex.is_match("/shop/a/b/"));
+        assert!(!regex.is_match("/shopping"));
+    }
+
+    #[test]
+    fn orders_manifest_entries() {
+        let patterns = [
+            "/shop/[[...path]]",
+            "/about",
+            "/[slug]",
+            "/blog/[slug]",
+            "/blog",
+            "/docs/[...path]",
+        ];
+        let sorted = |order| {
+            let mut entries = patterns.to_vec();
+            sort_manifest_entries(&mut entries, |pattern| *pattern, order);
+            entries
+        };
+        assert_eq!(
+            sorted(ManifestOrder::Specificity),
+            vec![
+                "/about",
+                "/blog",
+                "/blog/[slug]",
+                "/docs/[...path]",
+                "/shop/[[...path]]",
+                "/[slug]",
+            ]
+        );
+        assert_eq!(
+            sorted(ManifestOrder::Alphabetical),
+            vec![
+                "/[slug]",
+                "/about",
+                "/blog",
+                "/blog/[slug]",
+                "/docs/[...path]",
+                "/shop/[[...path]]",
+            ]
+        );
+        assert_eq!(ManifestOrder::default(), ManifestOrder::Specificity);
+
+        let pages = |order| {
+            dynamic_routes_manifest(patterns, order)
+                .into_iter()
+                .map(|entry| entry.page)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            pages(ManifestOrder::Specificity),
+            vec![
+                "/blog/[slug]",
+                "/docs/[...path]",
+                "/shop/[[...path]]",
+                "/[slug]",
+            ]
+        );
+        assert_eq!(
+            pages(ManifestOrder::Alphabetical),
+            vec![
+                "/[slug]",
+                "/blog/[slug]",
+                "/docs/[...path]",
+                "/shop/[[...path]]",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_project_roots_that_are_not_directories() {
+        assert_eq!(
+            project_root_problem(&FileSystemEntryType::NotFound),
+            Some("doesn't exist")
+        );
+        assert_eq!(
+            project_root_problem(&FileSystemEntryType::File),
+            Some("is a file, not a directory")
+        );
+        assert_eq!(project_root_problem(&FileSystemEntryType::Directory), None);
+    }
+
+    #[test]
+    fn recovers_when_the_project_root_is_created() -> Result<()> {
+        let project = TestProject::new(&[("other.txt", "")])?;
+        let runner = TestRunner::new()?;
+        runner.watch(&project)?;
+        let scan = |path: &'static str| {
+            let root_dir = project.root_dir();
+            move || {
+                let root_dir = root_dir.clone();
+                async move {
+                    let root = project_root(&root_dir).join(path);
+                    let structure =
+                        find_pages_structure(root, root, NextConfigBuilder::default().cell());
+                    let descriptions = reported_issues(structure)
+                        .await?
+                        .into_iter()
+                        .map(|issue| issue.description)
+                        .collect::<Vec<_>>();
+                    Ok((structure.await?.is_some(), descriptions))
+                }
+            }
+        };
+
+        let (found, issues) = runner.run(scan("other.txt")())?;
+        assert!(!found);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("is a file, not a directory"));
+
+        let missing = runner.run(scan("site")())?;
+        assert!(!missing.0);
+        assert_eq!(missing.1.len(), 1);
+        assert!(missing.1[0].contains("doesn't exist"));
+
+        project.write("site/pages/index.tsx", "")?;
+        let created = runner.run_until_changed(&missing, scan("site"))?;
+        assert_eq!(created, (true, vec![]));
+        Ok(())
+    }
+
+    #[test]
+    fn describes_dynamic_params() {
+        let param = |name: &str, catch_all, optional| ParamInfo {
+            name: name.to_string(),
+            catch_all,
+            optional,
+        };
+        assert_eq!(
+            dynamic_params("/[org]/repos/[repo]"),
+            vec![param("org", false, false), param("repo", false, false)]
+        );
+        assert_eq!(
+            dynamic_params("/docs/[...path]"),
+            vec![param("path", true, false)]
+        );
+        assert_eq!(
+            dynamic_params("/shop/[[...path]]"),
+            vec![param("path", true, true)]
+        );
+        assert!(dynamic_params("/about").is_empty());
+    }
+
+    #[test]
+    fn catch_all_files_and_directories_produce_the_same_route() -> Result<()> {
+        // Returns the pattern and specificity of the only route of a project
+        // with the file `path` in its pages directory.
+        let route = |path: &str| -> Result<(String, Specificity)> {
+            let project = TestProject::new(&[(&format!("pages/{path}"), "")])?;
+            scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+                let entries = pages.route_entries().await?;
+                let [entry] = &entries[..] else {
+                    bail!("expected a single route, found {}", entries.len());
+                };
+                let entry = entry.await?;
+                Ok((
+                    entry.pattern.clone(),
+                    entry.specificity.await?.clone_value(),
+                ))
+            })
+        };
+
+        for (file, directory, expected) in [
+            ("[...slug].tsx", "[...slug]/index.tsx", "/[...slug]"),
+            ("[[...slug]].tsx", "[[...slug]]/index.tsx", "/[[...slug]]"),
+            (
+                "docs/[...slug].js",
+                "docs/[...slug]/index.js",
+                "/docs/[...slug]",
+            ),
+        ] {
+            let (pattern, specificity) = route(file)?;
+            assert_eq!(pattern, expected);
+            assert_eq!(route(directory)?, (pattern, specificity));
+        }
+
+        assert_eq!(
+            entry_route_segment("[...slug].tsx", true),
+            entry_route_segment("[...slug]", false)
+        );
+        assert_eq!(
+            entry_route_segment("[...slug]", false),
+            Some(RouteSegment::CatchAll("slug".to_string()))
+        );
+        assert_eq!(entry_route_segment("index.tsx", true), None);
+        assert_eq!(
+            entry_route_segment("index", false),
+            Some(RouteSegment::Static("index".to_string()))
+        );
+    }
+
+    #[test]
+    fn ranks_scanned_routes_like_resolution() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/about.tsx", ""),
+            ("pages/a/[x].tsx", ""),
+            ("pages/[x]/b.tsx", ""),
+            ("pages/[x]/[y].tsx", ""),
+            ("pages/[...rest].tsx", ""),
+        ])?;
+        let mut routes = scan_pages(&project, NextConfigBuilder::default(), |pages| async move {
+            let mut routes = vec![];
+            for entry in pages.route_entries().await?.iter() {
+                let entry = entry.await?;
+                routes.push((
+                    entry.specificity.await?.clone_value(),
+                    entry.pattern.clone(),
+                ));
+            }
+            Ok(routes)
+        })?;
+
+        // `/a/b` is served by `/a/[x]` rather than `/[x]/b`, since its dynamic
+        // segment comes later.
+        let patterns = routes
+            .iter()
+            .map(|(_, pattern)| pattern.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(resolve_route(patterns.clone(), "/a/b").unwrap().0, "/a/[x]");
+
+        // The most specific route comes first, in the same order as the routes
+        // are resolved in.
+        let native = sort_routes(patterns, RouteOrdering::Native)?;
+        routes.sort_by(|(a, _), (b, _)| b.cmp(a));
+        assert_eq!(
+            routes
+                .iter()
+                .map(|(_, pattern)| pattern.as_str())
+                .collect::<Vec<_>>(),
+            native
+        );
+        assert_eq!(
+            native,
+            vec!["/about", "/a/[x]", "/[x]/b", "/[x]/[y]", "/[...rest]"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ranks_dynamic_segments_by_their_index_in_the_router_path() -> Result<()> {
+        let project = TestProject::new(&[("pages/[x].tsx", ""), ("pages/a/[x].tsx", "")])?;
+        // Returns the specificity of every route, and the specificity of a
+        // route with a single dynamic segment at each index.
+        let scan = |prefix: &'static str| {
+            let options = move |_| {
+                let transform: Arc<RoutePatternTransform> =
+                    Arc::new(move |pattern: &str| format!("{prefix}{pattern}"));
+                PagesScanOptions::default().with_transform(PagesRouteTransform(transform).cell())
+            };
+            scan_pages_with_options(
+                &project,
+                NextConfigBuilder::default(),
+                options,
+                |scan| async move {
+                    let mut routes = BTreeMap::new();
+                    for entry in scan.pages.route_entries().await?.iter() {
+                        let entry = entry.await?;
+                        routes.insert(
+                            entry.pattern.clone(),
+                            entry.specificity.await?.clone_value(),
+                        );
+                    }
+                    let mut dynamic_at = vec![];
+                    for index in 0..3 {
+                        dynamic_at.push(
+                            SpecificityVc::exact()
+                                .with_dynamic_segment(index)
+                                .await?
+                                .clone_value(),
+                        );
+                    }
+                    Ok((routes, dynamic_at))
+                },
+            )
+        };
+
+        // `pages/a/[x].tsx` has its dynamic segment at index 1 of the router
+        // path, so it is more specific than `pages/[x].tsx`, and serves `/a/b`.
+        let (routes, dynamic_at) = scan("")?;
+        assert_eq!(routes["/[x]"], dynamic_at[0]);
+        assert_eq!(routes["/a/[x]"], dynamic_at[1]);
+        assert!(routes["/a/[x]"] > routes["/[x]"]);
+        assert_eq!(
+            resolve_route(routes.keys().map(String::as_str), "/a/b")
+                .unwrap()
+                .0,
+            "/a/[x]"
+        );
+
+        // A transformed route is ranked by its index in the transformed path.
+        let (routes, dynamic_at) = scan("/v2")?;
+        assert_eq!(routes["/v2/[x]"], dynamic_at[1]);
+        assert_eq!(routes["/v2/a/[x]"], dynamic_at[2]);
+        Ok(())
+    }
+
+    #[test]
+    fn generates_route_params_types() {
+        assert_eq!(
+            route_params_type_declarations([
+                "/shop/[[...path]]",
+                "/",
+                "/blog/[slug]",
+                "/docs/[...path]",
+                "/[org]/[repo]",
+                "/café/[post-id]",
+                r#"/say-"hi"/[$name]"#,
+            ]),
+            indoc::indoc! {r#"
+                // This file is generated by Next.js and should not be edited.
+
+                export interface RouteParams {
+                  "/[org]/[repo]": { org: string; repo: string };
+                  "/blog/[slug]": { slug: string };
+                  "/café/[post-id]": { "post-id": string };
+                  "/docs/[...path]": { path: string[] };
+                  "/say-\"hi\"/[$name]": { $name: string };
+                  "/shop/[[...path]]": { path: string[] | undefined };
+                }
+            "#}
+        );
     }
-    .cell())
 }
@@ -0,0 +1,619 @@
+//! Static evaluation of the `rewrites` from the Next.js config, used to
+//! check them against the routes of the pages structure. Requests are still
+//! rewritten by the Next.js router at runtime.
+//!
+//! Sources use the subset of the path-to-regexp syntax that can be matched
+//! segment by segment: static segments, `:param` and the `:param?`,
+//! `:param*` and `:param+` modifiers.
+
+use indexmap::IndexMap;
+
+use crate::next_config::{NextConfig, Rewrite, RouteHas};
+
+/// How many path segments a param of a source matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamModifier {
+    /// `:param`, exactly one segment.
+    One,
+    /// `:param?`, zero or one segment.
+    Optional,
+    /// `:param*`, zero or more segments.
+    ZeroOrMore,
+    /// `:param+`, one or more segments.
+    OneOrMore,
+}
+
+/// A segment of the source of a rewrite or redirect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SourceSegment {
+    Static(String),
+    Param {
+        name: String,
+        modifier: ParamModifier,
+    },
+}
+
+/// Parses the source of a rewrite or redirect (e.g. `/blog/:slug*`).
+pub fn parse_source(source: &str) -> Vec<SourceSegment> {
+    source
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            let Some(param) = segment.strip_prefix(':') else {
+                return SourceSegment::Static(segment.to_string());
+            };
+            let (name, modifier) = if let Some(name) = param.strip_suffix('?') {
+                (name, ParamModifier::Optional)
+            } else if let Some(name) = param.strip_suffix('*') {
+                (name, ParamModifier::ZeroOrMore)
+            } else if let Some(name) = param.strip_suffix('+') {
+                (name, ParamModifier::OneOrMore)
+            } else {
+                (param, ParamModifier::One)
+            };
+            SourceSegment::Param {
+                name: name.to_string(),
+                modifier,
+            }
+        })
+        .collect()
+}
+
+/// Returns a route pattern (like `/docs/[[...path]]`) that matches at least
+/// the pathnames a parsed source matches. A `:param?` is widened to an
+/// optional catch-all.
+pub fn source_route_pattern(source: &[SourceSegment]) -> String {
+    let segments = source
+        .iter()
+        .map(|segment| match segment {
+            SourceSegment::Static(name) => name.clone(),
+            SourceSegment::Param { name, modifier } => match modifier {
+                ParamModifier::One => format!("[{name}]"),
+                ParamModifier::OneOrMore => format!("[...{name}]"),
+                ParamModifier::Optional | ParamModifier::ZeroOrMore => format!("[[...{name}]]"),
+            },
+        })
+        .collect::<Vec<_>>();
+    format!("/{}", segments.join("/"))
+}
+
+/// The params captured when matching a source. Params spanning multiple
+/// segments are joined with `/`.
+pub type SourceParams = IndexMap<String, String>;
+
+/// Matches a pathname against a parsed source and returns the captured
+/// params.
+pub fn match_source(source: &[SourceSegment], pathname: &str) -> Option<SourceParams> {
+    let segments = pathname
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let mut params = SourceParams::new();
+    let mut failed = vec![false; (source.len() + 1) * (segments.len() + 1)];
+    match_segments(source, &segments, 0, 0, &mut failed, &mut params).then_some(params)
+}
+
+/// Matches `source[source_index..]` against `path[path_index..]`. Positions
+/// that are known not to match are recorded in `failed`, so that sources with
+/// several repeated params don't backtrack exponentially.
+fn match_segments(
+    source: &[SourceSegment],
+    path: &[&str],
+    source_index: usize,
+    path_index: usize,
+    failed: &mut [bool],
+    params: &mut SourceParams,
+) -> bool {
+    let state = source_index * (path.len() + 1) + path_index;
+    if failed[state] {
+        return false;
+    }
+    let matched = match source.get(source_index) {
+        None => path_index == path.len(),
+        Some(SourceSegment::Static(name)) => {
+            path.get(path_index) == Some(&name.as_str())
+                && match_segments(
+                    source,
+                    path,
+                    source_index + 1,
+                    path_index + 1,
+                    failed,
+                    params,
+                )
+        }
+        Some(SourceSegment::Param { name, modifier }) => {
+            let remaining = path.len() - path_index;
+            let (min, max) = match modifier {
+                ParamModifier::One => (1, 1),
+                ParamModifier::Optional => (0, 1),
+                ParamModifier::ZeroOrMore => (0, remaining),
+                ParamModifier::OneOrMore => (1, remaining),
+            };
+            // Prefer longer matches, like path-to-regexp's greedy quantifiers.
+            (min..=max.min(remaining)).rev().any(|len| {
+                let end = path_index + len;
+                let matched = match_segments(source, path, source_index + 1, end, failed, params);
+                if matched {
+                    params.insert(name.clone(), path[path_index..end].join("/"));
+                }
+                matched
+            })
+        }
+    };
+    if !matched {
+        failed[state] = true;
+    }
+    matched
+}
+
+/// Replaces `:param` references in a destination with the captured params.
+/// References to params that weren't captured are kept as they are.
+pub fn interpolate_destination(destination: &str, params: &SourceParams) -> String {
+    let mut result = String::with_capacity(destination.len());
+    let mut rest = destination;
+    while let Some(index) = rest.find(':') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        match params.get(&after[..name_len]) {
+            Some(value) if name_len > 0 => {
+                result.push_str(value);
+                rest = after[name_len..]
+                    .strip_prefix(['*', '+'])
+                    .unwrap_or(&after[name_len..]);
+            }
+            _ => {
+                result.push(':');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Returns true if a destination points outside of the app.
+pub fn is_external_destination(destination: &str) -> bool {
+    !destination.starts_with('/')
+}
+
+/// Returns the names of the params a destination references with `:param`,
+/// in order and without duplicates.
+pub fn destination_params(destination: &str) -> Vec<&str> {
+    let mut params = vec![];
+    let mut rest = destination;
+    while let Some(index) = rest.find(':') {
+        let after = &rest[index + 1..];
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..name_len];
+        if !name.is_empty() && !params.contains(&name) {
+            params.push(name);
+        }
+        rest = &after[name_len..];
+    }
+    params
+}
+
+/// Returns the names of the params a rule captures: the params of its source,
+/// the keys of its `has` conditions and the named groups of their values.
+fn captured_params(source: &str, has: Option<&[RouteHas]>) -> Vec<String> {
+    let mut params = parse_source(source)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            SourceSegment::Param { name, .. } => Some(name),
+            SourceSegment::Static(_) => None,
+        })
+        .collect::<Vec<_>>();
+    for condition in has.into_iter().flatten() {
+        let (key, value) = match condition {
+            RouteHas::Header { key, value }
+            | RouteHas::Cookie { key, value }
+            | RouteHas::Query { key, value } => (Some(key), value.as_ref()),
+            RouteHas::Host { value } => (None, Some(value)),
+        };
+        params.extend(key.cloned());
+        let mut rest = value.map_or("", String::as_str);
+        while let Some(index) = rest.find("(?<") {
+            let after = &rest[index + 3..];
+            let Some(end) = after.find('>') else {
+                break;
+            };
+            params.push(after[..end].to_string());
+            rest = &after[end..];
+        }
+    }
+    params
+}
+
+/// A problem with the destination of a rewrite.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DestinationProblem {
+    /// The destination references a param the rule doesn't capture, which
+    /// usually is a typo like `:slg` for `:slug`.
+    UnknownParam(String),
+    /// The internal destination doesn't match any route (`is_route`), so it
+    /// responds with a 404. Destinations with params aren't checked.
+    NoRoute,
+}
+
+/// Checks the destination of a rewrite against its source and the routes of
+/// the app. External destinations are skipped.
+pub fn check_rewrite_destination(
+    rewrite: &Rewrite,
+    is_route: impl Fn(&str) -> bool,
+) -> Vec<DestinationProblem> {
+    let destination = &rewrite.destination;
+    if is_external_destination(destination) {
+        return vec![];
+    }
+    let captured = captured_params(&rewrite.source, rewrite.has.as_deref());
+    let params = destination_params(destination);
+    let mut problems = params
+        .iter()
+        .filter(|param| !captured.iter().any(|captured| captured == *param))
+        .map(|param| DestinationProblem::UnknownParam(param.to_string()))
+        .collect::<Vec<_>>();
+    let path = destination.split(['?', '#']).next().unwrap_or(destination);
+    if !path.contains(':') && !is_route(path) {
+        problems.push(DestinationProblem::NoRoute);
+    }
+    problems
+}
+
+/// The parts of the config that change which pathname a rule's source is
+/// matched against.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouteRuleContext {
+    pub base_path: String,
+    pub locales: Vec<String>,
+    pub default_locale: Option<String>,
+}
+
+impl RouteRuleContext {
+    pub fn from_config(config: &NextConfig) -> Self {
+        let (locales, default_locale) = match config.i18n_locales() {
+            Some((locales, default_locale)) => (locales.to_vec(), Some(default_locale.to_string())),
+            None => (vec![], None),
+        };
+        RouteRuleContext {
+            base_path: config.base_path().to_string(),
+            locales,
+            default_locale,
+        }
+    }
+
+    /// Strips the base path from a pathname, or returns `None` if the
+    /// pathname is outside of the base path.
+    pub fn strip_base_path<'a>(&self, pathname: &'a str) -> Option<&'a str> {
+        if self.base_path.is_empty() {
+            return Some(pathname);
+        }
+        let rest = pathname.strip_prefix(self.base_path.as_str())?;
+        if rest.is_empty() {
+            Some("/")
+        } else if rest.starts_with('/') {
+            Some(rest)
+        } else {
+            None
+        }
+    }
+
+    /// Strips a leading locale from a pathname and returns it, falling back
+    /// to the default locale.
+    pub fn strip_locale<'a>(&self, pathname: &'a str) -> (Option<&str>, &'a str) {
+        let trimmed = pathname.trim_start_matches('/');
+        let (first, rest) = trimmed.split_once('/').unwrap_or((trimmed, ""));
+        match self.locales.iter().find(|locale| *locale == first) {
+            Some(locale) => (
+                Some(locale.as_str()),
+                if rest.is_empty() {
+                    "/"
+                } else {
+                    &pathname[pathname.len() - rest.len() - 1..]
+                },
+            ),
+            None => (self.default_locale.as_deref(), pathname),
+        }
+    }
+}
+
+/// Prefixes a pathname, e.g. with the base path or a locale.
+fn join_prefix(prefix: &str, pathname: &str) -> String {
+    if prefix.is_empty() {
+        pathname.to_string()
+    } else if pathname == "/" {
+        prefix.to_string()
+    } else {
+        format!("{prefix}{pathname}")
+    }
+}
+
+/// Applies a single rule to a requested pathname, following Next.js'
+/// semantics: unless a rule sets `basePath: false` or `locale: false`, its
+/// source is matched after stripping the base path and locale from the
+/// pathname, and both are added back to internal destinations.
+fn apply_route_rule(
+    source: &str,
+    destination: &str,
+    base_path: Option<bool>,
+    locale: Option<bool>,
+    pathname: &str,
+    context: &RouteRuleContext,
+) -> Option<String> {
+    let use_base_path = base_path != Some(false);
+    let use_locale = locale != Some(false) && !context.locales.is_empty();
+
+    let mut path = pathname;
+    if use_base_path {
+        path = context.strip_base_path(path)?;
+    }
+    let mut matched_locale = None;
+    if use_locale {
+        let (locale, rest) = context.strip_locale(path);
+        matched_locale = locale;
+        path = rest;
+    }
+
+    let params = match_source(&parse_source(source), path)?;
+    let mut destination = interpolate_destination(destination, &params);
+    if is_external_destination(&destination) {
+        return Some(destination);
+    }
+    if let Some(locale) = matched_locale {
+        destination = join_prefix(&format!("/{locale}"), &destination);
+    }
+    if use_base_path {
+        destination = join_prefix(&context.base_path, &destination);
+    }
+    Some(destination)
+}
+
+/// Returns the destination a rewrite rewrites the requested pathname to, or
+/// `None` if the rewrite doesn't apply.
+pub fn rewrite_pathname(
+    rewrite: &Rewrite,
+    pathname: &str,
+    context: &RouteRuleContext,
+) -> Option<String> {
+    apply_route_rule(
+        &rewrite.source,
+        &rewrite.destination,
+        rewrite.base_path,
+        rewrite.locale,
+        pathname,
+        context,
+    )
+}
+
+/// Returns the rewrite that a rewrite's destination is rewritten by again,
+/// if that can be proven statically. Destinations that are external, still
+/// contain params, or are served by a route (`is_route`) end the chain, and
+/// so do rules with `has` or `missing` conditions.
+fn next_rewrite(
+    rewrites: &[Rewrite],
+    index: usize,
+    is_route: &impl Fn(&str) -> bool,
+) -> Option<usize> {
+    let destination = &rewrites[index].destination;
+    if is_external_destination(destination) {
+        return None;
+    }
+    let path = destination.split(['?', '#']).next().unwrap_or(destination);
+    if path.contains(':') || is_route(path) {
+        return None;
+    }
+    let next = rewrites
+        .iter()
+        .position(|rewrite| match_source(&parse_source(&rewrite.source), path).is_some())?;
+    let rewrite = &rewrites[next];
+    (rewrite.has.is_none() && rewrite.missing.is_none()).then_some(next)
+}
+
+/// Finds cycles of rewrites whose destinations are rewritten again, e.g.
+/// `/a -> /b` and `/b -> /a`. Each cycle is returned once, as the indices of
+/// its rewrites in order. Base path and locale prefixes are ignored, as they
+/// are added back to every internal destination.
+pub fn find_rewrite_cycles(
+    rewrites: &[Rewrite],
+    is_route: impl Fn(&str) -> bool,
+) -> Vec<Vec<usize>> {
+    let next = (0..rewrites.len())
+        .map(|index| next_rewrite(rewrites, index, &is_route))
+        .collect::<Vec<_>>();
+    let mut visited = vec![false; rewrites.len()];
+    let mut cycles = vec![];
+    for start in 0..rewrites.len() {
+        let mut path = vec![];
+        let mut current = Some(start);
+        while let Some(index) = current {
+            if visited[index] {
+                if let Some(position) = path.iter().position(|&i| i == index) {
+                    cycles.push(path[position..].to_vec());
+                }
+                break;
+            }
+            visited[index] = true;
+            path.push(index);
+            current = next[index];
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(source: &str, destination: &str) -> Rewrite {
+        Rewrite {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            base_path: None,
+            locale: None,
+            has: None,
+            missing: None,
+        }
+    }
+
+    fn context(base_path: &str, locales: &[&str]) -> RouteRuleContext {
+        RouteRuleContext {
+            base_path: base_path.to_string(),
+            locales: locales.iter().map(|l| l.to_string()).collect(),
+            default_locale: locales.first().map(|l| l.to_string()),
+        }
+    }
+
+    #[test]
+    fn matches_params() {
+        let source = parse_source("/blog/:slug/:rest*");
+        let params = match_source(&source, "/blog/hello/a/b").unwrap();
+        assert_eq!(params["slug"], "hello");
+        assert_eq!(params["rest"], "a/b");
+        assert!(match_source(&source, "/docs/hello").is_none());
+        assert_eq!(
+            interpolate_destination("/posts/:slug?rest=:rest*", &params),
+            "/posts/hello?rest=a/b"
+        );
+    }
+
+    #[test]
+    fn matches_repeated_params_without_backtracking() {
+        let source = parse_source(&"/:a*".repeat(32));
+        let pathname = format!("{}/end", "/x".repeat(32));
+        assert!(match_source(&source, &pathname).is_some());
+        let source = parse_source(&format!("{}/end", "/:a*".repeat(32)));
+        assert!(match_source(&source, &"/x".repeat(32)).is_none());
+    }
+
+    #[test]
+    fn base_path_false_rule_matches_unprefixed_pathname() {
+        let context = context("/docs", &[]);
+        let rule = Rewrite {
+            base_path: Some(false),
+            ..rewrite("/old", "/new")
+        };
+        assert_eq!(
+            rewrite_pathname(&rule, "/old", &context).as_deref(),
+            Some("/new")
+        );
+        assert_eq!(rewrite_pathname(&rule, "/docs/old", &context), None);
+
+        let rule = rewrite("/old", "/new");
+        assert_eq!(rewrite_pathname(&rule, "/old", &context), None);
+        assert_eq!(
+            rewrite_pathname(&rule, "/docs/old", &context).as_deref(),
+            Some("/docs/new")
+        );
+    }
+
+    #[test]
+    fn locale_false_rule_matches_localized_pathname() {
+        let context = context("", &["en", "fr"]);
+        let rule = Rewrite {
+            locale: Some(false),
+            ..rewrite("/fr/about", "/about-fr")
+        };
+        assert_eq!(
+            rewrite_pathname(&rule, "/fr/about", &context).as_deref(),
+            Some("/about-fr")
+        );
+
+        let rule = rewrite("/fr/about", "/about-fr");
+        assert_eq!(rewrite_pathname(&rule, "/fr/about", &context), None);
+    }
+
+    #[test]
+    fn finds_rewrite_cycles() {
+        let is_route = |path: &str| path == "/about";
+
+        let rewrites = vec![rewrite("/a", "/b"), rewrite("/b", "/a")];
+        assert_eq!(find_rewrite_cycles(&rewrites, is_route), vec![vec![0, 1]]);
+
+        let rewrites = vec![rewrite("/self", "/self?from=self")];
+        assert_eq!(find_rewrite_cycles(&rewrites, is_route), vec![vec![0]]);
+
+        // `/c` is rewritten to `/about`, which is served by a route.
+        let rewrites = vec![
+            rewrite("/b", "/c"),
+            rewrite("/c", "/about"),
+            rewrite("/about", "/b"),
+        ];
+        assert!(find_rewrite_cycles(&rewrites, is_route).is_empty());
+
+        // The destination of a param can't be proven to loop.
+        let rewrites = vec![
+            rewrite("/x/:slug", "/y/:slug"),
+            rewrite("/y/:slug", "/x/:slug"),
+        ];
+        assert!(find_rewrite_cycles(&rewrites, is_route).is_empty());
+
+        let rewrites = vec![
+            rewrite("/x/:slug", "/y/static"),
+            rewrite("/y/:slug", "/x/static"),
+        ];
+        assert_eq!(find_rewrite_cycles(&rewrites, is_route), vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn checks_rewrite_destinations() {
+        let is_route = |path: &str| path == "/about" || path.starts_with("/blog/");
+
+        assert_eq!(
+            check_rewrite_destination(&rewrite("/posts/:slug", "/blog/:slg"), is_route),
+            vec![DestinationProblem::UnknownParam("slg".to_string())]
+        );
+        assert!(
+            check_rewrite_destination(&rewrite("/posts/:slug", "/blog/:slug"), is_route).is_empty()
+        );
+        assert_eq!(
+            check_rewrite_destination(&rewrite("/team", "/abuot"), is_route),
+            vec![DestinationProblem::NoRoute]
+        );
+        assert!(
+            check_rewrite_destination(&rewrite("/team", "/about?tab=team"), is_route).is_empty()
+        );
+        assert!(check_rewrite_destination(
+            &rewrite("/docs/:path*", "https://docs.example.com/:path*"),
+            is_route
+        )
+        .is_empty());
+        assert!(check_rewrite_destination(
+            &rewrite("/proxy/:path*", "https://example.com/:other"),
+            is_route
+        )
+        .is_empty());
+
+        let rule = Rewrite {
+            has: Some(vec![RouteHas::Query {
+                key: "page".to_string(),
+                value: None,
+            }]),
+            ..rewrite("/list", "/blog/:page")
+        };
+        assert!(check_rewrite_destination(&rule, is_route).is_empty());
+    }
+
+    #[test]
+    fn destination_keeps_stripped_prefixes() {
+        let context = context("/docs", &["en", "fr"]);
+        let rule = rewrite("/blog/:slug", "/posts/:slug");
+        assert_eq!(
+            rewrite_pathname(&rule, "/docs/fr/blog/hello", &context).as_deref(),
+            Some("/docs/fr/posts/hello")
+        );
+        assert_eq!(
+            rewrite_pathname(&rule, "/docs/blog/hello", &context).as_deref(),
+            Some("/docs/en/posts/hello")
+        );
+
+        let rule = rewrite("/blog/:slug", "https://example.com/:slug");
+        assert_eq!(
+            rewrite_pathname(&rule, "/docs/blog/hello", &context).as_deref(),
+            Some("https://example.com/hello")
+        );
+    }
+}
@@ -0,0 +1,825 @@
+//! Reports changes of the route table in dev as `tracing` events, so they can
+//! be correlated with other events (e.g. slow builds).
+//!
+//! Every recomputation of the route table is recorded, but events are only
+//! emitted once the routes have settled for a while, so a burst of file
+//! operations produces a single event.
+//!
+//! Embedders that act on every added or removed route can subscribe to
+//! [RouteEvents] instead, which keeps the changes until they are pulled.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use tokio::sync::Notify;
+use turbo_tasks::{CompletionVc, TransientInstance};
+use turbo_tasks_fs::FileSystemPathVc;
+use turbo_tasks_hash::hash_xxh3_hash64;
+
+use crate::{
+    app_structure::{find_app_structure, OptionAppStructureVc},
+    next_config::NextConfigVc,
+    pages_structure::{
+        find_combined_structure, find_pages_structure_with_options, OptionPagesStructureVc,
+        PagesScanOptions,
+    },
+    rewrites::{parse_source, source_route_pattern},
+};
+
+/// The `tracing` target of route table events.
+pub const ROUTE_TABLE_EVENT_TARGET: &str = "next_core::route_table";
+
+/// A route pattern and the path of the file serving it.
+pub type RouteTableEntry = (String, String);
+
+/// How a route table changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RouteTableDiff {
+    pub added: usize,
+    pub removed: usize,
+    /// Routes served by a renamed file: either the route stayed and its file
+    /// changed, or a route was replaced by one served from the same directory.
+    pub renamed: usize,
+}
+
+impl RouteTableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.renamed == 0
+    }
+}
+
+fn parent_directory(file: &str) -> &str {
+    file.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// Compares two route tables.
+pub fn diff_route_tables(
+    previous: &[RouteTableEntry],
+    current: &[RouteTableEntry],
+) -> RouteTableDiff {
+    let previous = previous.iter().cloned().collect::<IndexMap<_, _>>();
+    let current = current.iter().cloned().collect::<IndexMap<_, _>>();
+
+    let mut diff = RouteTableDiff::default();
+    let mut removed = vec![];
+    for (pattern, file) in previous.iter() {
+        match current.get(pattern) {
+            Some(current_file) if current_file != file => diff.renamed += 1,
+            Some(_) => {}
+            None => removed.push(file.as_str()),
+        }
+    }
+    let mut added = current
+        .iter()
+        .filter(|(pattern, _)| !previous.contains_key(*pattern))
+        .map(|(_, file)| Some(file.as_str()))
+        .collect::<Vec<_>>();
+
+    for file in removed {
+        let directory = parent_directory(file);
+        let renamed_to = added.iter_mut().find(
+            |candidate| matches!(candidate, Some(file) if parent_directory(file) == directory),
+        );
+        match renamed_to {
+            Some(added) => {
+                *added = None;
+                diff.renamed += 1;
+            }
+            None => diff.removed += 1,
+        }
+    }
+    diff.added = added.iter().flatten().count();
+    diff
+}
+
+/// A route that was added to or removed from the route table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouteEvent {
+    Added(RouteTableEntry),
+    Removed(RouteTableEntry),
+}
+
+/// Returns the routes removed from and added to a route table, removed ones
+/// first. A route whose file changed is removed and added again, so unlike
+/// [diff_route_tables] renames aren't told apart.
+pub fn route_table_events(
+    previous: &[RouteTableEntry],
+    current: &[RouteTableEntry],
+) -> Vec<RouteEvent> {
+    let removed = previous
+        .iter()
+        .filter(|entry| !current.contains(entry))
+        .cloned()
+        .map(RouteEvent::Removed);
+    let added = current
+        .iter()
+        .filter(|entry| !previous.contains(entry))
+        .cloned()
+        .map(RouteEvent::Added);
+    removed.chain(added).collect()
+}
+
+/// Returns a fingerprint of a route table that doesn't depend on the order
+/// of the routes.
+pub fn route_table_fingerprint(routes: &[RouteTableEntry]) -> String {
+    let mut routes = routes.iter().collect::<Vec<_>>();
+    routes.sort();
+    format!("{:016x}", hash_xxh3_hash64(routes))
+}
+
+/// The route patterns of the app, sorted, with a fingerprint that changes
+/// whenever they do. This is what the SWC link validation transform
+/// (`experimental.validateLinks`) checks links against, so it serializes to
+/// its `{ fingerprint, patterns }` config.
+#[turbo_tasks::value(shared)]
+#[derive(Debug, Clone, Default)]
+pub struct RouteTableSnapshot {
+    pub fingerprint: String,
+    pub patterns: Vec<String>,
+}
+
+impl RouteTableSnapshot {
+    pub fn new(mut patterns: Vec<String>) -> Self {
+        patterns.sort();
+        patterns.dedup();
+        RouteTableSnapshot {
+            fingerprint: format!("{:016x}", hash_xxh3_hash64(&patterns)),
+            patterns,
+        }
+    }
+
+    /// Returns the config of the SWC link validation transform.
+    pub fn link_validation_config(&self) -> next_transform_link_validation::Config {
+        next_transform_link_validation::Config {
+            fingerprint: self.fingerprint.clone(),
+            patterns: self.patterns.clone(),
+        }
+    }
+}
+
+/// Routes served by Next.js itself, like its chunks under `/_next/static`.
+const INTERNAL_ROUTE_PATTERNS: [&str; 1] = ["/_next/[...path]"];
+
+/// Returns the [RouteTableSnapshot] of the app: the routes of the pages and
+/// app directories, the sources of the rewrites, the files of the `public`
+/// directory and the routes served by Next.js itself. It is recomputed
+/// whenever any of them change.
+#[turbo_tasks::function]
+pub async fn route_table_snapshot(
+    pages_structure: OptionPagesStructureVc,
+    app_structure: OptionAppStructureVc,
+    project_path: FileSystemPathVc,
+    next_config: NextConfigVc,
+) -> Result<RouteTableSnapshotVc> {
+    let mut patterns = find_combined_structure(pages_structure, app_structure, next_config)
+        .rewrite_targets(project_path.join("public"))
+        .await?
+        .clone_value();
+    let rewrites = next_config.rewrites().await?;
+    patterns.extend(
+        rewrites
+            .before_files
+            .iter()
+            .chain(rewrites.after_files.iter())
+            .chain(rewrites.fallback.iter())
+            .map(|rewrite| source_route_pattern(&parse_source(&rewrite.source))),
+    );
+    patterns.extend(INTERNAL_ROUTE_PATTERNS.map(ToString::to_string));
+    Ok(RouteTableSnapshot::new(patterns).cell())
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionRouteTableSnapshot(Option<RouteTableSnapshotVc>);
+
+/// Returns the [RouteTableSnapshot] of the project at `project_path` that
+/// links are validated against, if `experimental.validateLinks` is enabled.
+/// Dev-only routes are part of it, as links to them work in development.
+#[turbo_tasks::function]
+pub async fn link_validation_routes(
+    project_path: FileSystemPathVc,
+    next_config: NextConfigVc,
+) -> Result<OptionRouteTableSnapshotVc> {
+    if !next_config.await?.validate_links() {
+        return Ok(OptionRouteTableSnapshotVc::cell(None));
+    }
+    let pages_structure = find_pages_structure_with_options(
+        project_path,
+        project_path,
+        next_config,
+        PagesScanOptions::default().with_production(false).cell(),
+    );
+    let app_structure = find_app_structure(project_path, project_path, next_config);
+    let snapshot = route_table_snapshot(pages_structure, app_structure, project_path, next_config);
+    Ok(OptionRouteTableSnapshotVc::cell(Some(
+        snapshot.resolve().await?,
+    )))
+}
+
+#[derive(Default)]
+struct RouteTableEventsState {
+    /// The route table of the last emitted event.
+    reported: Vec<RouteTableEntry>,
+    /// The latest route table, if it changed since the last event.
+    pending: Option<Vec<RouteTableEntry>>,
+    /// The total wall time of the recomputations since the last event.
+    wall_time: Duration,
+    last_recorded: Option<Instant>,
+}
+
+/// Collects route table recomputations and emits one event per burst of
+/// changes.
+pub struct RouteTableEvents {
+    quiet_period: Duration,
+    state: Mutex<RouteTableEventsState>,
+}
+
+impl RouteTableEvents {
+    /// Creates a collector that emits an event once the route table didn't
+    /// change for `quiet_period`.
+    pub fn new(quiet_period: Duration) -> Self {
+        RouteTableEvents {
+            quiet_period,
+            state: Mutex::new(RouteTableEventsState::default()),
+        }
+    }
+
+    /// Returns how long the route table has to be unchanged before an event
+    /// is emitted.
+    pub fn quiet_period(&self) -> Duration {
+        self.quiet_period
+    }
+
+    /// Records a recomputation of the route table that took `wall_time`.
+    pub fn record(&self, routes: Vec<RouteTableEntry>, wall_time: Duration, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.pending = Some(routes);
+        state.wall_time += wall_time;
+        state.last_recorded = Some(now);
+    }
+
+    /// Emits an event if the route table changed and then didn't change for
+    /// the quiet period. Returns true if an event was emitted.
+    pub fn flush(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let settled = state
+            .last_recorded
+            .map_or(false, |last| now.duration_since(last) >= self.quiet_period);
+        if !settled {
+            return false;
+        }
+        Self::emit(&mut state)
+    }
+
+    /// Emits an event if the route table changed, without waiting for the
+    /// quiet period. Called on shutdown, so the last burst isn't lost.
+    pub fn finish(&self) -> bool {
+        Self::emit(&mut self.state.lock().unwrap())
+    }
+
+    fn emit(state: &mut RouteTableEventsState) -> bool {
+        let Some(routes) = state.pending.take() else {
+            return false;
+        };
+        let wall_time = std::mem::take(&mut state.wall_time);
+        let diff = diff_route_tables(&state.reported, &routes);
+        let fingerprint = route_table_fingerprint(&routes);
+        state.reported = routes;
+        if diff.is_empty() {
+            return false;
+        }
+        tracing::info!(
+            target: ROUTE_TABLE_EVENT_TARGET,
+            fingerprint = %fingerprint,
+            added = diff.added,
+            removed = diff.removed,
+            renamed = diff.renamed,
+            wall_time_ms = wall_time.as_millis() as u64,
+            "route table changed"
+        );
+        true
+    }
+}
+
+/// Returns the route table of the pages directory.
+async fn route_table(pages_structure: OptionPagesStructureVc) -> Result<Vec<RouteTableEntry>> {
+    let mut routes = vec![];
+    if let Some(pages_structure) = *pages_structure.await? {
+        for entry in pages_structure.route_entries().await?.iter() {
+            let entry = entry.await?;
+            routes.push((entry.pattern.clone(), entry.file.await?.path.clone()));
+        }
+    }
+    Ok(routes)
+}
+
+/// Records the route table of the pages directory with `events`. This is
+/// recomputed, and so records again, every time the routes change.
+#[turbo_tasks::function]
+pub async fn report_route_table(
+    pages_structure: OptionPagesStructureVc,
+    events: TransientInstance<RouteTableEvents>,
+) -> Result<CompletionVc> {
+    let start = Instant::now();
+    let routes = route_table(pages_structure).await?;
+    events.record(routes, start.elapsed(), Instant::now());
+    Ok(CompletionVc::new())
+}
+
+/// A position in the changes of a [RouteEventLog]. It points after the
+/// changes a consumer has already received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RouteEventsCursor(u64);
+
+/// The events of one change of the route table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteTableChange {
+    /// Numbers the changes of a log, starting at 0.
+    pub sequence: u64,
+    pub events: Vec<RouteEvent>,
+}
+
+/// The changes returned by [RouteEventLog::next_events].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteEventsBatch {
+    /// The changes after the requested cursor, oldest first.
+    pub changes: Vec<RouteTableChange>,
+    /// The number of changes after the requested cursor that were dropped
+    /// from the retention window before they were pulled. A consumer that
+    /// missed changes should start over from [RouteEventLog::subscribe].
+    pub missed: u64,
+    /// The cursor to pass to the next call.
+    pub cursor: RouteEventsCursor,
+}
+
+#[derive(Default)]
+struct RouteEventLogState {
+    /// The latest recorded route table.
+    routes: Vec<RouteTableEntry>,
+    /// The retained changes, oldest first.
+    changes: VecDeque<RouteTableChange>,
+    next_sequence: u64,
+}
+
+/// Keeps the last changes of the route table, so consumers can pull them at
+/// their own pace. Unlike [RouteTableEvents], no change is lost as long as
+/// it is pulled before `retention` newer changes are recorded.
+pub struct RouteEventLog {
+    retention: usize,
+    state: Mutex<RouteEventLogState>,
+    changed: Notify,
+}
+
+impl RouteEventLog {
+    /// Creates a log that retains the last `retention` changes.
+    pub fn new(retention: usize) -> Self {
+        RouteEventLog {
+            retention: retention.max(1),
+            state: Mutex::new(RouteEventLogState::default()),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Returns the current route table and a cursor pointing after it, so
+    /// that the next changes apply on top of the returned routes.
+    pub fn subscribe(&self) -> (Vec<RouteTableEntry>, RouteEventsCursor) {
+        let state = self.state.lock().unwrap();
+        (state.routes.clone(), RouteEventsCursor(state.next_sequence))
+    }
+
+    /// Records the latest route table. Returns true if it changed.
+    pub fn record(&self, routes: Vec<RouteTableEntry>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let events = route_table_events(&state.routes, &routes);
+        state.routes = routes;
+        if events.is_empty() {
+            return false;
+        }
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        state
+            .changes
+            .push_back(RouteTableChange { sequence, events });
+        while state.changes.len() > self.retention {
+            state.changes.pop_front();
+        }
+        drop(state);
+        self.changed.notify_waiters();
+        true
+    }
+
+    /// Returns the changes after `cursor` if there are any.
+    pub fn try_next_events(&self, cursor: RouteEventsCursor) -> Option<RouteEventsBatch> {
+        let state = self.state.lock().unwrap();
+        if cursor.0 >= state.next_sequence {
+            return None;
+        }
+        let first_retained = state.next_sequence - state.changes.len() as u64;
+        Some(RouteEventsBatch {
+            changes: state
+                .changes
+                .iter()
+                .filter(|change| change.sequence >= cursor.0)
+                .cloned()
+                .collect(),
+            missed: first_retained.saturating_sub(cursor.0),
+            cursor: RouteEventsCursor(state.next_sequence),
+        })
+    }
+
+    /// Waits until there are changes after `cursor` and returns them.
+    pub async fn next_events(&self, cursor: RouteEventsCursor) -> RouteEventsBatch {
+        loop {
+            // Created before checking, so a change recorded in between wakes
+            // it up.
+            let changed = self.changed.notified();
+            if let Some(batch) = self.try_next_events(cursor) {
+                return batch;
+            }
+            changed.await;
+        }
+    }
+}
+
+/// A [RouteEventLog] that can be passed to turbo tasks.
+#[turbo_tasks::value(shared, serialization = "none", eq = "manual")]
+pub struct RouteEvents(#[turbo_tasks(debug_ignore, trace_ignore)] pub Arc<RouteEventLog>);
+
+impl PartialEq for RouteEvents {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for RouteEvents {}
+
+impl RouteEvents {
+    /// Creates a log that retains the last `retention` changes.
+    pub fn new(retention: usize) -> Self {
+        RouteEvents(Arc::new(RouteEventLog::new(retention)))
+    }
+}
+
+/// Records the route table of the pages directory with `events` every time
+/// the routes change.
+#[turbo_tasks::function]
+pub async fn record_route_events(
+    pages_structure: OptionPagesStructureVc,
+    events: RouteEventsVc,
+) -> Result<CompletionVc> {
+    pages_structure.routes_changed().await?;
+    let routes = route_table(pages_structure).await?;
+    events.await?.0.record(routes);
+    Ok(CompletionVc::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fmt, sync::Arc};
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    use futures::{executor::block_on, FutureExt};
+
+    use super::*;
+    use crate::{
+        app_structure::find_app_structure,
+        next_config::{NextConfigBuilder, Rewrite, Rewrites},
+        pages_structure::find_pages_structure,
+        testing::{project_root, TestProject, TestRunner},
+    };
+
+    type Fields = HashMap<String, String>;
+
+    /// Collects the fields of every event.
+    #[derive(Clone, Default)]
+    struct CollectingSubscriber(Arc<Mutex<Vec<Fields>>>);
+
+    struct FieldsVisitor<'a>(&'a mut Fields);
+
+    impl Visit for FieldsVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    impl Subscriber for CollectingSubscriber {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields::new();
+            event.record(&mut FieldsVisitor(&mut fields));
+            self.0.lock().unwrap().push(fields);
+        }
+
+        fn enter(&self, _span: &span::Id) {}
+
+        fn exit(&self, _span: &span::Id) {}
+    }
+
+    fn routes(routes: &[(&str, &str)]) -> Vec<RouteTableEntry> {
+        routes
+            .iter()
+            .map(|(pattern, file)| (pattern.to_string(), file.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn diffs_route_tables() {
+        let previous = routes(&[("/", "pages/index.tsx"), ("/a", "pages/a.tsx")]);
+        assert!(diff_route_tables(&previous, &previous).is_empty());
+        assert_eq!(
+            diff_route_tables(
+                &previous,
+                &routes(&[("/", "pages/index.tsx"), ("/b", "pages/b.tsx")])
+            ),
+            RouteTableDiff {
+                renamed: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            diff_route_tables(
+                &previous,
+                &routes(&[("/", "pages/index.tsx"), ("/a", "pages/a/index.tsx")])
+            ),
+            RouteTableDiff {
+                renamed: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            diff_route_tables(&previous, &routes(&[("/x/y", "pages/x/y.tsx")])),
+            RouteTableDiff {
+                added: 1,
+                removed: 2,
+                renamed: 0
+            }
+        );
+        assert_eq!(
+            route_table_fingerprint(&previous),
+            route_table_fingerprint(&previous.iter().rev().cloned().collect::<Vec<_>>())
+        );
+    }
+
+    #[test]
+    fn snapshots_route_patterns() {
+        let snapshot = RouteTableSnapshot::new(vec!["/blog/[slug]".to_string(), "/".to_string()]);
+        assert_eq!(snapshot.patterns, vec!["/", "/blog/[slug]"]);
+        assert_eq!(
+            snapshot.fingerprint,
+            RouteTableSnapshot::new(vec!["/".to_string(), "/blog/[slug]".to_string()]).fingerprint
+        );
+        assert_ne!(
+            snapshot.fingerprint,
+            RouteTableSnapshot::new(vec!["/".to_string()]).fingerprint
+        );
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["patterns"], serde_json::json!(["/", "/blog/[slug]"]));
+        assert_eq!(json["fingerprint"], snapshot.fingerprint);
+    }
+
+    #[test]
+    fn snapshots_every_route_links_can_point_to() -> Result<()> {
+        let project = TestProject::new(&[
+            ("pages/index.tsx", ""),
+            ("pages/blog/[slug].tsx", ""),
+            ("app/dashboard/page.tsx", ""),
+            ("public/favicon.ico", ""),
+            ("public/images/logo.png", ""),
+        ])?;
+        let root_dir = project.root_dir();
+        let snapshot = TestRunner::new()?.run(async move {
+            let root = project_root(&root_dir);
+            let next_config = NextConfigBuilder::default()
+                .app_dir(true)
+                .rewrites(Rewrites {
+                    after_files: vec![Rewrite {
+                        source: "/docs/:path*".to_string(),
+                        destination: "https://docs.example.com/:path*".to_string(),
+                        base_path: None,
+                        locale: None,
+                        has: None,
+                        missing: None,
+                    }],
+                    ..Default::default()
+                })
+                .cell();
+            Ok(route_table_snapshot(
+                find_pages_structure(root, root, next_config),
+                find_app_structure(root, root, next_config),
+                root,
+                next_config,
+            )
+            .await?
+            .clone_value())
+        })?;
+        assert_eq!(
+            snapshot.patterns,
+            vec![
+                "/",
+                "/_next/[...path]",
+                "/blog/[slug]",
+                "/dashboard",
+                "/docs/[[...path]]",
+                "/favicon.ico",
+                "/images/logo.png",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn emits_one_event_per_burst_of_changes() {
+        let subscriber = CollectingSubscriber::default();
+        let events = subscriber.0.clone();
+        let recorder = RouteTableEvents::new(Duration::from_millis(100));
+        let ms = Duration::from_millis;
+        let start = Instant::now();
+
+        let initial = routes(&[("/", "pages/index.tsx")]);
+        let added = routes(&[("/", "pages/index.tsx"), ("/a", "pages/a.tsx")]);
+        let renamed = routes(&[("/", "pages/index.tsx"), ("/b", "pages/b.tsx")]);
+        let added_again = routes(&[
+            ("/", "pages/index.tsx"),
+            ("/b", "pages/b.tsx"),
+            ("/c", "pages/c.tsx"),
+        ]);
+        let renamed_again = routes(&[
+            ("/", "pages/index.tsx"),
+            ("/b", "pages/b.tsx"),
+            ("/d", "pages/d.tsx"),
+        ]);
+
+        let added_before_shutdown = routes(&[
+            ("/", "pages/index.tsx"),
+            ("/b", "pages/b.tsx"),
+            ("/d", "pages/d.tsx"),
+            ("/e", "pages/e.tsx"),
+        ]);
+        tracing::subscriber::with_default(subscriber, || {
+            recorder.record(initial.clone(), ms(20), start);
+            assert!(recorder.flush(start + ms(100)));
+
+            // Adding a page and renaming it right away is a single burst.
+            recorder.record(added, ms(5), start + ms(200));
+            recorder.record(renamed.clone(), ms(7), start + ms(250));
+            assert!(!recorder.flush(start + ms(300)));
+            assert!(recorder.flush(start + ms(350)));
+
+            // Settled changes are reported separately.
+            recorder.record(added_again.clone(), ms(3), start + ms(400));
+            assert!(recorder.flush(start + ms(500)));
+            recorder.record(renamed_again, ms(4), start + ms(600));
+            assert!(recorder.flush(start + ms(700)));
+
+            // Nothing changed since the last event.
+            assert!(!recorder.flush(start + ms(800)));
+
+            // Shutting down reports the pending burst right away.
+            recorder.record(added_before_shutdown, ms(2), start + ms(900));
+            assert!(!recorder.flush(start + ms(950)));
+            assert!(recorder.finish());
+            assert!(!recorder.finish());
+        });
+
+        let events = events.lock().unwrap();
+        let summary = events
+            .iter()
+            .map(|fields| {
+                (
+                    fields["added"].as_str(),
+                    fields["removed"].as_str(),
+                    fields["renamed"].as_str(),
+                    fields["wall_time_ms"].as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("1", "0", "0", "20"),
+                ("1", "0", "0", "12"),
+                ("1", "0", "0", "3"),
+                ("0", "0", "1", "4"),
+                ("1", "0", "0", "2"),
+            ]
+        );
+        assert_eq!(events[1]["fingerprint"], route_table_fingerprint(&renamed));
+        assert_eq!(events[0]["message"], "route table changed");
+    }
+
+    #[test]
+    fn keeps_route_events_for_slow_consumers() {
+        let log = RouteEventLog::new(3);
+        log.record(routes(&[("/", "pages/index.tsx")]));
+        let (initial, cursor) = log.subscribe();
+        assert_eq!(initial, routes(&[("/", "pages/index.tsx")]));
+        assert!(log.next_events(cursor).now_or_never().is_none());
+
+        // Three changes happen before the consumer pulls.
+        let a = ("/a".to_string(), "pages/a.tsx".to_string());
+        let b = ("/b".to_string(), "pages/b.tsx".to_string());
+        let index = ("/".to_string(), "pages/index.tsx".to_string());
+        assert!(log.record(vec![index.clone(), a.clone()]));
+        assert!(!log.record(vec![index.clone(), a.clone()]));
+        assert!(log.record(vec![index.clone(), b.clone()]));
+        assert!(log.record(vec![b.clone()]));
+
+        let batch = block_on(log.next_events(cursor));
+        assert_eq!(batch.missed, 0);
+        assert_eq!(
+            batch
+                .changes
+                .iter()
+                .map(|change| (change.sequence, change.events.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, vec![RouteEvent::Added(a.clone())]),
+                (
+                    2,
+                    vec![RouteEvent::Removed(a.clone()), RouteEvent::Added(b.clone())]
+                ),
+                (3, vec![RouteEvent::Removed(index.clone())]),
+            ]
+        );
+        assert!(log.try_next_events(batch.cursor).is_none());
+
+        // Changes older than the retention window are reported as missed.
+        let stale = cursor;
+        log.record(vec![]);
+        let batch = log.try_next_events(stale).unwrap();
+        assert_eq!(batch.missed, 1);
+        assert_eq!(
+            batch
+                .changes
+                .iter()
+                .map(|change| change.sequence)
+                .collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn records_route_events_when_the_pages_change() -> Result<()> {
+        let project = TestProject::new(&[("pages/index.tsx", "")])?;
+        let runner = TestRunner::new()?;
+        runner.watch(&project)?;
+        let log = Arc::new(RouteEventLog::new(8));
+        let root_dir = project.root_dir();
+        let record = || {
+            let root_dir = root_dir.clone();
+            let log = log.clone();
+            async move {
+                let root = project_root(&root_dir);
+                let next_config = NextConfigBuilder::default().cell();
+                record_route_events(
+                    find_pages_structure(root, root, next_config),
+                    RouteEvents(log.clone()).cell(),
+                )
+                .await?;
+                Ok(log.subscribe().0)
+            }
+        };
+
+        let initial = runner.run(record())?;
+        assert_eq!(initial, routes(&[("/", "pages/index.tsx")]));
+        let (_, cursor) = log.subscribe();
+
+        project.write("pages/about.tsx", "")?;
+        runner.run_until_changed(&initial, record)?;
+        let batch = log.try_next_events(cursor).unwrap();
+        assert_eq!(batch.missed, 0);
+        assert_eq!(
+            batch
+                .changes
+                .into_iter()
+                .flat_map(|change| change.events)
+                .collect::<Vec<_>>(),
+            vec![RouteEvent::Added((
+                "/about".to_string(),
+                "pages/about.tsx".to_string()
+            ))]
+        );
+        Ok(())
+    }
+}
@@ -12,7 +12,7 @@ use turbo_tasks_bytes::{Bytes, Stream};
 use turbo_tasks_fs::{json::parse_json_with_source_context, to_sys_path, File, FileSystemPathVc};
 use turbopack::{evaluate_context::node_evaluate_asset_context, transition::TransitionsByNameVc};
 use turbopack_core::{
-    asset::AssetVc,
+    asset::{Asset, AssetVc},
     changed::any_content_changed,
     chunk::ChunkingContext,
     context::{AssetContext, AssetContextVc},
@@ -44,7 +44,8 @@ use crate::{
     },
     next_import_map::get_next_build_import_map,
     next_server::context::{get_server_module_options_context, ServerContextType},
-    util::{parse_config_from_source, NextSourceConfigVc},
+    page_extensions::PageExtensionsVc,
+    util::parse_config_from_source,
 };
 
 #[turbo_tasks::function]
@@ -58,15 +59,11 @@ fn next_configs() -> StringsVc {
 }
 
 #[turbo_tasks::function]
-async fn middleware_files(page_extensions: StringsVc) -> Result<StringsVc> {
-    let extensions = page_extensions.await?;
-    let files = ["middleware.", "src/middleware."]
+async fn middleware_files(page_extensions: PageExtensionsVc) -> Result<StringsVc> {
+    let page_extensions = page_extensions.await?;
+    let files = ["middleware", "src/middleware"]
         .into_iter()
-        .flat_map(|f| {
-            extensions
-                .iter()
-                .map(move |ext| String::from(f) + ext.as_str())
-        })
+        .flat_map(|f| page_extensions.file_names(f))
         .collect();
     Ok(StringsVc::cell(files))
 }
@@ -171,29 +168,64 @@ async fn next_config_changed(
     })
 }
 
+/// The middleware of a project, together with the matchers statically
+/// extracted from its `config` export.
+#[turbo_tasks::value(shared)]
+pub struct Middleware {
+    pub path: FileSystemPathVc,
+    pub module: EcmascriptModuleAssetVc,
+    /// The `config.matcher` patterns. `None` if the middleware applies to
+    /// all routes.
+    pub matcher: Option<Vec<String>>,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionMiddleware(Option<MiddlewareVc>);
+
+/// Finds the `middleware` file of the project and extracts its matchers.
+#[turbo_tasks::function]
+pub async fn find_middleware(
+    context: AssetContextVc,
+    project_path: FileSystemPathVc,
+    page_extensions: PageExtensionsVc,
+) -> Result<OptionMiddlewareVc> {
+    let module = get_config(context, project_path, middleware_files(page_extensions));
+    let Some(module) = *module.await? else {
+        return Ok(OptionMiddlewareVc::cell(None));
+    };
+    let config = parse_config_from_source(module.as_asset()).await?;
+    Ok(OptionMiddlewareVc::cell(Some(
+        Middleware {
+            path: module.ident().path(),
+            module,
+            matcher: config.matcher.clone(),
+        }
+        .cell(),
+    )))
+}
+
 #[turbo_tasks::function]
 async fn config_assets(
     context: AssetContextVc,
     project_path: FileSystemPathVc,
-    page_extensions: StringsVc,
+    page_extensions: PageExtensionsVc,
 ) -> Result<InnerAssetsVc> {
-    let middleware_config =
-        get_config(context, project_path, middleware_files(page_extensions)).await?;
+    let middleware = find_middleware(context, project_path, page_extensions).await?;
 
     // The router.ts file expects a manifest of chunks for the middleware. If there
     // is no middleware file, then we need to generate a default empty manifest
     // and we cannot process it with the next-edge transition because it
     // requires a real file for some reason.
-    let (manifest, config) = match &*middleware_config {
-        Some(c) => {
+    let (manifest, matcher) = match &*middleware {
+        Some(middleware) => {
+            let middleware = middleware.await?;
             let manifest = context.with_transition("next-edge").process(
-                c.as_asset(),
+                middleware.module.as_asset(),
                 Value::new(ReferenceType::EcmaScriptModules(
                     EcmaScriptModulesReferenceSubType::Undefined,
                 )),
             );
-            let config = parse_config_from_source(c.as_asset());
-            (manifest, config)
+            (manifest, middleware.matcher.clone())
         }
         None => {
             let manifest = as_es_module_asset(
@@ -205,19 +237,14 @@ async fn config_assets(
                 context,
             )
             .as_asset();
-            let config = NextSourceConfigVc::default();
-            (manifest, config)
+            (manifest, None)
         }
     };
 
     let config_asset = as_es_module_asset(
         VirtualAssetVc::new(
             project_path.join("middleware_config.js"),
-            File::from(format!(
-                "export default {};",
-                json!({ "matcher": &config.await?.matcher })
-            ))
-            .into(),
+            File::from(format!("export default {};", json!({ "matcher": matcher }))).into(),
         )
         .as_asset(),
         context,
@@ -350,7 +377,11 @@ async fn route_internal(
         )),
     );
 
-    let configs = config_assets(context, project_path, next_config.page_extensions());
+    let configs = config_assets(
+        context,
+        project_path,
+        PageExtensionsVc::from_config(next_config),
+    );
     let router_asset = route_executor(context, configs);
 
     // This invalidates the router when the next config changes
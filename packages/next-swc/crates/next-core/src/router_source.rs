@@ -1,7 +1,7 @@
 use anyhow::{anyhow, bail, Context, Result};
 use futures::stream::StreamExt;
 use indexmap::IndexSet;
-use turbo_tasks::{primitives::StringVc, CompletionVc, CompletionsVc, Value};
+use turbo_tasks::{primitives::StringVc, CompletionVc, Value};
 use turbopack_core::{
     environment::ServerAddrVc,
     introspect::{Introspectable, IntrospectableChildrenVc, IntrospectableVc},
@@ -15,7 +15,7 @@ use turbopack_node::execution_context::ExecutionContextVc;
 use crate::{
     app_structure::OptionAppStructureVc,
     next_config::NextConfigVc,
-    pages_structure::OptionPagesStructureVc,
+    pages_structure::{find_combined_structure, OptionPagesStructureVc},
     router::{route, RouterRequest, RouterResult},
 };
 
@@ -74,11 +74,9 @@ fn need_data(source: ContentSourceVc, path: &str) -> ContentSourceResultVc {
 fn routes_changed(
     app_structure: OptionAppStructureVc,
     pages_structure: OptionPagesStructureVc,
+    next_config: NextConfigVc,
 ) -> CompletionVc {
-    CompletionsVc::all(vec![
-        app_structure.routes_changed(),
-        pages_structure.routes_changed(),
-    ])
+    find_combined_structure(pages_structure, app_structure, next_config).routes_changed()
 }
 
 #[turbo_tasks::value_impl]
@@ -122,7 +120,7 @@ impl ContentSource for NextRouterContentSource {
             request,
             this.next_config,
             this.server_addr,
-            routes_changed(this.app_structure, this.pages_structure),
+            routes_changed(this.app_structure, this.pages_structure, this.next_config),
         );
 
         let res = res
@@ -0,0 +1,325 @@
+//! Summarizes the routing diagnostics of a project, so the dev server can
+//! print them once at startup instead of as scattered log lines.
+//!
+//! The report only reads the Issues already emitted while computing the pages
+//! structure, the app structure and the Next.js config, so it doesn't scan
+//! anything again.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use mime::APPLICATION_JSON;
+use serde::Serialize;
+use turbo_tasks::primitives::StringVc;
+use turbo_tasks_fs::File;
+use turbopack_core::{asset::AssetContentVc, issue::IssueVc};
+use turbopack_dev_server::source::{
+    ContentSource, ContentSourceContentVc, ContentSourceData, ContentSourceResultVc,
+};
+
+use crate::{
+    app_structure::OptionAppStructureVc, next_config::NextConfigVc,
+    pages_structure::OptionPagesStructureVc,
+};
+
+/// Called with the text of the [RoutingReport] of the project.
+pub type RoutingReportHook = Box<dyn Fn(&str) + Send + Sync>;
+
+/// How many issues the report lists.
+pub const ROUTING_REPORT_TOP_ISSUES: usize = 10;
+
+/// Severities from most to least severe, as returned by
+/// `IssueSeverity::as_str`.
+const SEVERITIES: [&str; 8] = [
+    "bug",
+    "fatal",
+    "error",
+    "warning",
+    "hint",
+    "note",
+    "suggestion",
+    "info",
+];
+
+fn severity_rank(severity: &str) -> usize {
+    SEVERITIES
+        .iter()
+        .position(|s| *s == severity)
+        .unwrap_or(SEVERITIES.len())
+}
+
+/// An Issue as listed in the report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RoutingReportIssue {
+    pub severity: String,
+    pub category: String,
+    /// The file the issue is about.
+    pub path: String,
+    pub title: String,
+}
+
+/// The routing diagnostics of a project.
+#[turbo_tasks::value(shared, serialization = "none")]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutingReport {
+    /// The number of routes in the pages directory.
+    pub page_routes: usize,
+    pub total_issues: usize,
+    /// Issue counts by severity, from most to least severe.
+    pub by_severity: IndexMap<String, usize>,
+    /// Issue counts by category, sorted by category.
+    pub by_category: IndexMap<String, usize>,
+    /// The most severe issues, ordered by severity and then by path.
+    #[turbo_tasks(trace_ignore)]
+    pub top_issues: Vec<RoutingReportIssue>,
+}
+
+impl RoutingReport {
+    /// Builds the report from the collected issues, listing at most `top`
+    /// of them.
+    pub fn new(page_routes: usize, mut issues: Vec<RoutingReportIssue>, top: usize) -> Self {
+        issues.sort_by(|a, b| {
+            severity_rank(&a.severity)
+                .cmp(&severity_rank(&b.severity))
+                .then_with(|| a.path.cmp(&b.path))
+                .then_with(|| a.title.cmp(&b.title))
+        });
+
+        let mut by_severity = IndexMap::new();
+        let mut by_category = IndexMap::new();
+        for issue in issues.iter() {
+            *by_severity.entry(issue.severity.clone()).or_insert(0) += 1;
+            *by_category.entry(issue.category.clone()).or_insert(0) += 1;
+        }
+        by_category.sort_keys();
+
+        let total_issues = issues.len();
+        issues.truncate(top);
+        RoutingReport {
+            page_routes,
+            total_issues,
+            by_severity,
+            by_category,
+            top_issues: issues,
+        }
+    }
+
+    /// Renders the report for the terminal.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if self.total_issues == 0 {
+            writeln!(text, "routing: {} page routes, no issues", self.page_routes).unwrap();
+            return text;
+        }
+
+        let counts = |counts: &IndexMap<String, usize>| {
+            counts
+                .iter()
+                .map(|(name, count)| format!("{count} {name}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(
+            text,
+            "routing: {} page routes, {} issues ({})",
+            self.page_routes,
+            self.total_issues,
+            counts(&self.by_severity)
+        )
+        .unwrap();
+        writeln!(text, "  by category: {}", counts(&self.by_category)).unwrap();
+        for issue in self.top_issues.iter() {
+            writeln!(
+                text,
+                "  {} [{}] {}: {}",
+                issue.severity, issue.category, issue.path, issue.title
+            )
+            .unwrap();
+        }
+        let omitted = self.total_issues - self.top_issues.len();
+        if omitted > 0 {
+            writeln!(text, "  ... and {omitted} more").unwrap();
+        }
+        text
+    }
+
+    /// Renders the report as JSON, for the debug endpoint.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl RoutingReportVc {
+    #[turbo_tasks::function]
+    pub async fn to_text(self) -> Result<StringVc> {
+        Ok(StringVc::cell(self.await?.to_text()))
+    }
+
+    #[turbo_tasks::function]
+    pub async fn to_json(self) -> Result<StringVc> {
+        Ok(StringVc::cell(self.await?.to_json()?))
+    }
+}
+
+async fn report_issue(issue: IssueVc) -> Result<RoutingReportIssue> {
+    Ok(RoutingReportIssue {
+        severity: issue.severity().await?.as_str().to_string(),
+        category: issue.category().await?.clone_value(),
+        path: issue.context().to_string().await?.clone_value(),
+        title: issue.title().await?.clone_value(),
+    })
+}
+
+/// Summarizes the Issues emitted while computing `structure`, `app_structure`
+/// and `config`.
+#[turbo_tasks::function]
+pub async fn routing_report(
+    structure: OptionPagesStructureVc,
+    app_structure: OptionAppStructureVc,
+    config: NextConfigVc,
+) -> Result<RoutingReportVc> {
+    let mut issues = vec![];
+    for captured in [
+        IssueVc::peek_issues_with_path(structure).await?,
+        IssueVc::peek_issues_with_path(app_structure).await?,
+        IssueVc::peek_issues_with_path(config).await?,
+    ] {
+        for issue in captured.await?.iter() {
+            issues.push(report_issue(issue).await?);
+        }
+    }
+
+    let page_routes = match *structure.await? {
+        Some(structure) => structure.route_entries().await?.len(),
+        None => 0,
+    };
+    Ok(RoutingReport::new(page_routes, issues, ROUTING_REPORT_TOP_ISSUES).cell())
+}
+
+/// A content source which serves the [RoutingReport] as JSON, for
+/// development tooling. It's only part of the dev server.
+#[turbo_tasks::value(shared)]
+pub struct DevRoutingReportContentSource {
+    pub report: RoutingReportVc,
+}
+
+#[turbo_tasks::value_impl]
+impl ContentSource for DevRoutingReportContentSource {
+    #[turbo_tasks::function]
+    async fn get(
+        &self,
+        path: &str,
+        _data: turbo_tasks::Value<ContentSourceData>,
+    ) -> Result<ContentSourceResultVc> {
+        if !path.is_empty() {
+            return Ok(ContentSourceResultVc::not_found());
+        }
+
+        let report = File::from(self.report.to_json().await?.clone_value())
+            .with_content_type(APPLICATION_JSON);
+
+        Ok(ContentSourceResultVc::exact(
+            ContentSourceContentVc::static_content(AssetContentVc::from(report).into()).into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app_structure::find_app_structure,
+        next_config::NextConfigBuilder,
+        pages_structure::find_pages_structure,
+        testing::{project_root, TestProject, TestRunner},
+    };
+
+    /// Returns the report of a scan of a project with `files`.
+    fn scan_report(files: &[(&str, &str)]) -> Result<RoutingReport> {
+        let project = TestProject::new(files)?;
+        let root_dir = project.root_dir();
+        TestRunner::new()?.run(async move {
+            let root = project_root(&root_dir);
+            let next_config = NextConfigBuilder::default().cell();
+            let report = routing_report(
+                find_pages_structure(root, root, next_config),
+                find_app_structure(root, root, next_config),
+                next_config,
+            );
+            Ok(report.await?.clone_value())
+        })
+    }
+
+    /// A project whose dynamic directories compete with different param
+    /// names.
+    fn competing_params_report() -> Result<RoutingReport> {
+        scan_report(&[
+            ("pages/index.tsx", ""),
+            ("pages/[id]/edit.tsx", ""),
+            ("pages/[other]/list.tsx", ""),
+            ("pages/[slug]/view.tsx", ""),
+        ])
+    }
+
+    #[test]
+    fn renders_the_text_of_a_scan() -> Result<()> {
+        let report = competing_params_report()?;
+        let title = "An issue occurred while preparing your Next.js pages";
+        assert_eq!(
+            report.to_text(),
+            format!(
+                "\
+routing: 4 page routes, 2 issues (2 warning)
+  by category: 2 next pages
+  warning [next pages] [project]/pages/[other]: {title}
+  warning [next pages] [project]/pages/[slug]: {title}
+"
+            )
+        );
+
+        // Issues past the top ones are only counted.
+        let truncated = RoutingReport::new(report.page_routes, report.top_issues, 1);
+        assert!(truncated.to_text().ends_with("  ... and 1 more\n"));
+
+        assert_eq!(
+            scan_report(&[("pages/index.tsx", "")])?.to_text(),
+            "routing: 1 page routes, no issues\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn renders_the_json_of_a_scan() -> Result<()> {
+        assert_eq!(
+            competing_params_report()?.to_json()?,
+            r#"{
+  "pageRoutes": 4,
+  "totalIssues": 2,
+  "bySeverity": {
+    "warning": 2
+  },
+  "byCategory": {
+    "next pages": 2
+  },
+  "topIssues": [
+    {
+      "severity": "warning",
+      "category": "next pages",
+      "path": "[project]/pages/[other]",
+      "title": "An issue occurred while preparing your Next.js pages"
+    },
+    {
+      "severity": "warning",
+      "category": "next pages",
+      "path": "[project]/pages/[slug]",
+      "title": "An issue occurred while preparing your Next.js pages"
+    }
+  ]
+}"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+//! A port of `getSortedRoutes` from Next.js' `sorted-routes.ts`, the route
+//! ordering of the webpack pipeline.
+//!
+//! Turbopack orders routes by their specificity instead (see
+//! [RouteOrdering](crate::pages_structure::RouteOrdering)), which differs for
+//! routes of different depths. This port exists so that migrating apps can
+//! compare both orderings before switching.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+const SLUG: &str = "[]";
+const REST_SLUG: &str = "[...]";
+const OPTIONAL_REST_SLUG: &str = "[[...]]";
+
+/// A node of the route trie, named after `UrlNode` in `sorted-routes.ts`.
+#[derive(Default)]
+struct UrlNode {
+    /// Whether a route ends at this node.
+    is_route: bool,
+    /// Children keyed by static segment, or by [SLUG], [REST_SLUG] and
+    /// [OPTIONAL_REST_SLUG] for dynamic segments. The keys are sorted by code
+    /// unit, like `Array.prototype.sort` does for ASCII segments.
+    children: BTreeMap<String, UrlNode>,
+    slug_name: Option<String>,
+    rest_slug_name: Option<String>,
+    optional_rest_slug_name: Option<String>,
+}
+
+fn strip_non_word_chars(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
+
+/// Records `next` as the param name of a dynamic segment at a level that
+/// already uses `previous`.
+fn handle_slug(previous: &Option<String>, next: &str, slug_names: &mut Vec<String>) -> Result<()> {
+    if let Some(previous) = previous {
+        if previous != next {
+            bail!(
+                "You cannot use different slug names for the same dynamic path ('{previous}' !== \
+                 '{next}')."
+            );
+        }
+    }
+    for slug in slug_names.iter() {
+        if slug == next {
+            bail!(
+                "You cannot have the same slug name \"{next}\" repeat within a single dynamic path"
+            );
+        }
+        if strip_non_word_chars(slug) == strip_non_word_chars(next) {
+            bail!(
+                "You cannot have the slug names \"{slug}\" and \"{next}\" differ only by non-word \
+                 symbols within a single dynamic path"
+            );
+        }
+    }
+    slug_names.push(next.to_string());
+    Ok(())
+}
+
+impl UrlNode {
+    fn insert(
+        &mut self,
+        segments: &[&str],
+        slug_names: &mut Vec<String>,
+        is_catch_all: bool,
+    ) -> Result<()> {
+        let Some((&segment, rest)) = segments.split_first() else {
+            self.is_route = true;
+            return Ok(());
+        };
+        if is_catch_all {
+            bail!("Catch-all must be the last part of the URL.");
+        }
+
+        let (key, is_catch_all) = match segment.strip_prefix('[').and_then(|s| s.strip_suffix(']'))
+        {
+            Some(name) => {
+                let (name, is_optional) =
+                    match name.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                        Some(name) => (name, true),
+                        None => (name, false),
+                    };
+                let (name, catch_all) = match name.strip_prefix("...") {
+                    Some(name) => (name, true),
+                    None => (name, false),
+                };
+                if name.starts_with('[') || name.ends_with(']') {
+                    bail!("Segment names may not start or end with extra brackets ('{name}').");
+                }
+                if name.starts_with('.') {
+                    bail!("Segment names may not start with erroneous periods ('{name}').");
+                }
+
+                if catch_all && is_optional {
+                    if let Some(rest_slug_name) = &self.rest_slug_name {
+                        bail!(
+                            "You cannot use both an required and optional catch-all route at the \
+                             same level (\"[...{rest_slug_name}]\" and \"{segment}\" )."
+                        );
+                    }
+                    handle_slug(&self.optional_rest_slug_name, name, slug_names)?;
+                    self.optional_rest_slug_name = Some(name.to_string());
+                    (OPTIONAL_REST_SLUG, true)
+                } else if catch_all {
+                    if let Some(optional_rest_slug_name) = &self.optional_rest_slug_name {
+                        bail!(
+                            "You cannot use both an optional and required catch-all route at the \
+                             same level (\"[[...{optional_rest_slug_name}]]\" and \"{segment}\")."
+                        );
+                    }
+                    handle_slug(&self.rest_slug_name, name, slug_names)?;
+                    self.rest_slug_name = Some(name.to_string());
+                    (REST_SLUG, true)
+                } else {
+                    if is_optional {
+                        bail!("Optional route parameters are not yet supported (\"{segment}\").");
+                    }
+                    handle_slug(&self.slug_name, name, slug_names)?;
+                    self.slug_name = Some(name.to_string());
+                    (SLUG, false)
+                }
+            }
+            None => (segment, false),
+        };
+        self.children
+            .entry(key.to_string())
+            .or_default()
+            .insert(rest, slug_names, is_catch_all)
+    }
+
+    fn smoosh(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut routes = vec![];
+        if self.is_route {
+            let route = if prefix == "/" {
+                "/"
+            } else {
+                &prefix[..prefix.len() - 1]
+            };
+            if let Some(optional_rest_slug_name) = &self.optional_rest_slug_name {
+                bail!(
+                    "You cannot define a route with the same specificity as a optional catch-all \
+                     route (\"{route}\" and \"{route}[[...{optional_rest_slug_name}]]\")."
+                );
+            }
+            routes.push(route.to_string());
+        }
+        for (segment, child) in self.children.iter() {
+            if !matches!(segment.as_str(), SLUG | REST_SLUG | OPTIONAL_REST_SLUG) {
+                routes.extend(child.smoosh(&format!("{prefix}{segment}/"))?);
+            }
+        }
+        let dynamic_children = [
+            (SLUG, &self.slug_name, "[", "]"),
+            (REST_SLUG, &self.rest_slug_name, "[...", "]"),
+            (
+                OPTIONAL_REST_SLUG,
+                &self.optional_rest_slug_name,
+                "[[...",
+                "]]",
+            ),
+        ];
+        for (key, name, open, close) in dynamic_children {
+            if let Some(name) = name {
+                routes.extend(self.children[key].smoosh(&format!("{prefix}{open}{name}{close}/"))?);
+            }
+        }
+        Ok(routes)
+    }
+}
+
+/// Sorts route patterns like `getSortedRoutes` does: at each level the route
+/// itself comes first, then static segments in code unit order, then the
+/// dynamic segment, the catch-all and the optional catch-all. Fails with the
+/// same messages for invalid route sets, like different param names at the
+/// same level.
+pub fn get_sorted_routes<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<Vec<String>> {
+    let mut root = UrlNode::default();
+    for pattern in patterns {
+        let segments = pattern
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        root.insert(&segments, &mut vec![], false)?;
+    }
+    root.smoosh("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_like_get_sorted_routes() {
+        assert_eq!(
+            get_sorted_routes([
+                "/[[...path]]",
+                "/docs/[...slug]",
+                "/docs",
+                "/blog/[slug]",
+                "/blog/new",
+                "/about",
+            ])
+            .unwrap(),
+            vec![
+                "/about",
+                "/blog/new",
+                "/blog/[slug]",
+                "/docs",
+                "/docs/[...slug]",
+                "/[[...path]]",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_route_sets() {
+        assert_eq!(
+            get_sorted_routes(["/[a]/x", "/[b]/y"])
+                .unwrap_err()
+                .to_string(),
+            "You cannot use different slug names for the same dynamic path ('a' !== 'b')."
+        );
+        assert_eq!(
+            get_sorted_routes(["/", "/[[...slug]]"])
+                .unwrap_err()
+                .to_string(),
+            "You cannot define a route with the same specificity as a optional catch-all route \
+             (\"/\" and \"/[[...slug]]\")."
+        );
+        assert_eq!(
+            get_sorted_routes(["/[...a]/b"]).unwrap_err().to_string(),
+            "Catch-all must be the last part of the URL."
+        );
+    }
+}
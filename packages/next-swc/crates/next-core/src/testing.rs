@@ -0,0 +1,240 @@
+//! Helpers for tests of crates using the pages structure, and a harness
+//! that runs turbo-tasks functions against a project written to a temporary
+//! directory, so that tests can cover whole scans instead of their helpers
+//! only.
+
+use std::{
+    fmt::Write,
+    fs,
+    future::Future,
+    sync::{Arc, Once},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+use turbo_tasks::{CollectiblesSource, TurboTasks};
+use turbo_tasks_fs::{DiskFileSystemVc, FileSystemPathVc, FileSystemVc};
+use turbo_tasks_memory::MemoryBackend;
+use turbopack_core::issue::IssueVc;
+
+use crate::pages_structure::PagesStructureVc;
+
+/// Asserts that `structure` has exactly the routes in `expected`, in any
+/// order. On mismatch, panics with a diff of the sorted routes.
+pub async fn assert_routes(structure: PagesStructureVc, expected: &[&str]) -> Result<()> {
+    assert_route_list(structure.route_patterns().await?.clone_value(), expected);
+    Ok(())
+}
+
+/// Asserts that `actual` has exactly the routes in `expected`, in any order.
+/// On mismatch, panics with a diff of the sorted routes.
+pub fn assert_route_list(actual: Vec<String>, expected: &[&str]) {
+    if let Some(diff) = route_diff(actual, expected) {
+        panic!("routes don't match (- expected, + actual):\n{diff}");
+    }
+}
+
+/// Returns a line diff of the sorted routes, or `None` if they are the same.
+/// Missing routes are prefixed with `-`, unexpected ones with `+`.
+pub fn route_diff(mut actual: Vec<String>, expected: &[&str]) -> Option<String> {
+    actual.sort();
+    let mut expected = expected.to_vec();
+    expected.sort_unstable();
+    if actual.iter().eq(expected.iter()) {
+        return None;
+    }
+
+    let mut diff = String::new();
+    let mut actual = actual.iter().map(String::as_str).peekable();
+    let mut expected = expected.into_iter().peekable();
+    loop {
+        let (prefix, route) = match (actual.peek(), expected.peek()) {
+            (Some(a), Some(e)) if a == e => {
+                expected.next();
+                (' ', actual.next().unwrap())
+            }
+            (Some(a), Some(e)) if e < a => ('-', expected.next().unwrap()),
+            (Some(_), _) => ('+', actual.next().unwrap()),
+            (None, Some(_)) => ('-', expected.next().unwrap()),
+            (None, None) => break,
+        };
+        writeln!(diff, "{prefix} {route}").unwrap();
+    }
+    Some(diff)
+}
+
+/// How long to wait for the file watcher to pick up a change.
+const MAX_UPDATE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A project on disk, removed when dropped.
+pub struct TestProject {
+    dir: TempDir,
+}
+
+impl TestProject {
+    /// Creates a project with the given files, relative to the project root.
+    /// A path ending with `/` creates an empty directory.
+    pub fn new(files: &[(&str, &str)]) -> Result<Self> {
+        let project = TestProject {
+            dir: tempfile::tempdir()?,
+        };
+        for (path, content) in files {
+            project.write(path, content)?;
+        }
+        Ok(project)
+    }
+
+    /// Writes a file (or creates a directory if `path` ends with `/`),
+    /// creating its parent directories.
+    pub fn write(&self, path: &str, content: &str) -> Result<()> {
+        let full_path = self.dir.path().join(path);
+        if path.ends_with('/') {
+            fs::create_dir_all(full_path)?;
+        } else {
+            if let Some(parent) = full_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(full_path, content)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, path: &str) -> Result<()> {
+        let full_path = self.dir.path().join(path);
+        if full_path.is_dir() {
+            fs::remove_dir_all(full_path)?;
+        } else {
+            fs::remove_file(full_path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the directory of the project, to be passed to [project_root]
+    /// inside of [TestRunner::run].
+    pub fn root_dir(&self) -> String {
+        self.dir.path().to_string_lossy().to_string()
+    }
+}
+
+/// Returns the root of the file system of the project in `root_dir`. The file
+/// system is the same for every call, so tasks are cached across runs.
+pub fn project_root(root_dir: &str) -> FileSystemPathVc {
+    let fs: FileSystemVc =
+        DiskFileSystemVc::new("project".to_string(), root_dir.to_string()).into();
+    fs.root()
+}
+
+/// A turbo-tasks instance with this crate registered. Tasks are cached
+/// between runs of the same runner, like in a dev server.
+pub struct TestRunner {
+    runtime: Runtime,
+    tt: Arc<TurboTasks<MemoryBackend>>,
+}
+
+impl TestRunner {
+    pub fn new() -> Result<Self> {
+        static REGISTER: Once = Once::new();
+        REGISTER.call_once(crate::register);
+
+        let runtime = Runtime::new()?;
+        let tt = runtime.block_on(async { TurboTasks::new(MemoryBackend::default()) });
+        Ok(TestRunner { runtime, tt })
+    }
+
+    /// Runs `future` as a root task and returns its result.
+    pub fn run<T, F>(&self, future: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        self.runtime.block_on(self.tt.run_once(future))
+    }
+
+    /// Starts watching `project`, so that changes written afterwards
+    /// invalidate the tasks that read them.
+    pub fn watch(&self, project: &TestProject) -> Result<()> {
+        let root = project.root_dir();
+        self.run(async move {
+            DiskFileSystemVc::new("project".to_string(), root)
+                .await?
+                .start_watching()?;
+            Ok(())
+        })
+    }
+
+    /// Runs `future` until it returns a value other than `previous`, e.g.
+    /// after a change to a watched project has been picked up.
+    pub fn run_until_changed<T, F>(&self, previous: &T, future: impl Fn() -> F) -> Result<T>
+    where
+        T: PartialEq + Send + 'static,
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let start = Instant::now();
+        loop {
+            let value = self.run(future())?;
+            if value != *previous {
+                return Ok(value);
+            }
+            if start.elapsed() > MAX_UPDATE_TIMEOUT {
+                bail!("the change wasn't picked up");
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// An issue emitted while computing a Vc, with its severity (as returned by
+/// `IssueSeverity::as_str`) and description.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReportedIssue {
+    pub severity: String,
+    pub description: String,
+}
+
+/// Returns the issues emitted while computing `source`, sorted.
+pub async fn reported_issues<T: CollectiblesSource + Copy>(
+    source: T,
+) -> Result<Vec<ReportedIssue>> {
+    let mut issues = vec![];
+    for issue in IssueVc::peek_issues_with_path(source).await?.await?.iter() {
+        issues.push(ReportedIssue {
+            severity: issue.severity().await?.as_str().to_string(),
+            description: issue.description().await?.clone_value(),
+        });
+    }
+    issues.sort();
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes(routes: &[&str]) -> Vec<String> {
+        routes.iter().map(|route| route.to_string()).collect()
+    }
+
+    #[test]
+    fn diffs_sorted_routes() {
+        assert_eq!(
+            route_diff(routes(&["/b", "/", "/a"]), &["/", "/a", "/b"]),
+            None
+        );
+        assert_eq!(
+            route_diff(
+                routes(&["/blog/[slug]", "/", "/about-us"]),
+                &["/about", "/", "/blog/[slug]"]
+            )
+            .as_deref(),
+            Some("  /\n- /about\n+ /about-us\n  /blog/[slug]\n")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "routes don't match (- expected, + actual):\n  /\n- /about\n")]
+    fn panics_with_a_diff() {
+        assert_route_list(routes(&["/"]), &["/", "/about"]);
+    }
+}
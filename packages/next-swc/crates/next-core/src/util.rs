@@ -1,6 +1,6 @@
 use anyhow::{anyhow, bail, Context, Result};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use swc_core::ecma::ast::Program;
+use swc_core::ecma::ast::{Expr, Lit, Program};
 use turbo_tasks::{primitives::StringVc, trace::TraceRawVcs, Value, ValueToString};
 use turbo_tasks_fs::{json::parse_json_rope_with_source_context, FileContent, FileSystemPathVc};
 use turbopack::condition::ContextCondition;
@@ -92,6 +92,36 @@ pub enum NextRuntime {
     Edge,
 }
 
+/// The `fetchCache` segment config option, which sets the default caching of
+/// `fetch` requests in a route.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, TraceRawVcs, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NextFetchCache {
+    Auto,
+    DefaultCache,
+    OnlyCache,
+    ForceCache,
+    DefaultNoStore,
+    OnlyNoStore,
+    ForceNoStore,
+}
+
+impl NextFetchCache {
+    /// Parses a value like `force-cache`.
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value {
+            "auto" => NextFetchCache::Auto,
+            "default-cache" => NextFetchCache::DefaultCache,
+            "only-cache" => NextFetchCache::OnlyCache,
+            "force-cache" => NextFetchCache::ForceCache,
+            "default-no-store" => NextFetchCache::DefaultNoStore,
+            "only-no-store" => NextFetchCache::OnlyNoStore,
+            "force-no-store" => NextFetchCache::ForceNoStore,
+            _ => return None,
+        })
+    }
+}
+
 #[turbo_tasks::value]
 #[derive(Default)]
 pub struct NextSourceConfig {
@@ -99,6 +129,13 @@ pub struct NextSourceConfig {
 
     /// Middleware router matchers
     pub matcher: Option<Vec<String>>,
+
+    /// The maximum duration in seconds, from `export const maxDuration`
+    pub max_duration: Option<u32>,
+
+    /// The default caching of `fetch` requests, from `export const
+    /// fetchCache`
+    pub fetch_cache: Option<NextFetchCache>,
 }
 
 #[turbo_tasks::value_impl]
@@ -162,6 +199,9 @@ pub async fn parse_config_from_source(module_asset: AssetVc) -> Result<NextSourc
             ..
         } = &*ecmascript_asset.parse().await?
         {
+            let mut config = None;
+            let mut max_duration = None;
+            let mut fetch_cache = None;
             for item in &module.body {
                 if let Some(decl) = item
                     .as_module_decl()
@@ -169,37 +209,68 @@ pub async fn parse_config_from_source(module_asset: AssetVc) -> Result<NextSourc
                     .and_then(|export_decl| export_decl.decl.as_var())
                 {
                     for decl in &decl.decls {
-                        if decl
-                            .name
-                            .as_ident()
-                            .map(|ident| &*ident.sym == "config")
-                            .unwrap_or_default()
-                        {
-                            if let Some(init) = decl.init.as_ref() {
-                                let value = eval_context.eval(init);
-                                return Ok(parse_config_from_js_value(module_asset, &value).cell());
-                            } else {
-                                NextSourceConfigParsingIssue {
-                                    ident: module_asset.ident(),
-                                    detail: StringVc::cell(
-                                        "The exported config object must contain an variable \
-                                         initializer."
-                                            .to_string(),
-                                    ),
+                        match decl.name.as_ident().map(|ident| &*ident.sym) {
+                            Some("config") if config.is_none() => {
+                                if let Some(init) = decl.init.as_ref() {
+                                    let value = eval_context.eval(init);
+                                    config = Some(parse_config_from_js_value(module_asset, &value));
+                                } else {
+                                    NextSourceConfigParsingIssue {
+                                        ident: module_asset.ident(),
+                                        detail: StringVc::cell(
+                                            "The exported config object must contain an variable \
+                                             initializer."
+                                                .to_string(),
+                                        ),
+                                    }
+                                    .cell()
+                                    .as_issue()
+                                    .emit()
                                 }
-                                .cell()
-                                .as_issue()
-                                .emit()
                             }
+                            Some("maxDuration") => {
+                                max_duration = decl.init.as_deref().and_then(parse_max_duration);
+                            }
+                            Some("fetchCache") => {
+                                fetch_cache = decl.init.as_deref().and_then(parse_fetch_cache);
+                            }
+                            _ => {}
                         }
                     }
                 }
             }
+            let mut config = config.unwrap_or_default();
+            config.max_duration = max_duration;
+            config.fetch_cache = fetch_cache;
+            return Ok(config.cell());
         }
     }
     Ok(NextSourceConfigVc::default())
 }
 
+/// Parses the `maxDuration` export of a route module. Only literal
+/// non-negative integers are supported.
+fn parse_max_duration(init: &Expr) -> Option<u32> {
+    let Expr::Lit(Lit::Num(number)) = init else {
+        return None;
+    };
+    let value = number.value;
+    if value.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&value) {
+        Some(value as u32)
+    } else {
+        None
+    }
+}
+
+/// Parses the `fetchCache` export of a route module. Only string literals
+/// are supported.
+fn parse_fetch_cache(init: &Expr) -> Option<NextFetchCache> {
+    let Expr::Lit(Lit::Str(value)) = init else {
+        return None;
+    };
+    NextFetchCache::parse(&value.value)
+}
+
 fn parse_config_from_js_value(module_asset: AssetVc, value: &JsValue) -> NextSourceConfig {
     let mut config = NextSourceConfig::default();
     let invalid_config = |detail: &str, value: &JsValue| {
@@ -248,38 +319,23 @@ fn parse_config_from_js_value(module_asset: AssetVc, value: &JsValue) -> NextSou
                             }
                         }
                         if key == "matcher" {
-                            let mut matchers = vec![];
-                            match value {
-                                JsValue::Constant(matcher) => {
-                                    if let Some(matcher) = matcher.as_str() {
-                                        matchers.push(matcher.to_string());
-                                    } else {
+                            match parse_matcher(value) {
+                                Some(matcher) => {
+                                    for entry in matcher.invalid {
                                         invalid_config(
-                                            "The matcher property must be a string or array of \
-                                             strings",
-                                            value,
+                                            "Each matcher must be a string or an object with a \
+                                             source string. The matcher is ignored.",
+                                            entry,
                                         );
                                     }
+                                    config.matcher = Some(matcher.matchers);
                                 }
-                                JsValue::Array { items, .. } => {
-                                    for item in items {
-                                        if let Some(matcher) = item.as_str() {
-                                            matchers.push(matcher.to_string());
-                                        } else {
-                                            invalid_config(
-                                                "The matcher property must be a string or array \
-                                                 of strings",
-                                                value,
-                                            );
-                                        }
-                                    }
-                                }
-                                _ => invalid_config(
-                                    "The matcher property must be a string or array of strings",
+                                None => invalid_config(
+                                    "The matcher property must be a string, an object with a \
+                                     source string or an array of them",
                                     value,
                                 ),
                             }
-                            config.matcher = Some(matchers);
                         }
                     } else {
                         invalid_config(
@@ -300,6 +356,54 @@ fn parse_config_from_js_value(module_asset: AssetVc, value: &JsValue) -> NextSou
     config
 }
 
+/// The statically extracted `matcher` of a config export.
+struct ParsedMatcher<'a> {
+    matchers: Vec<String>,
+    /// The entries of a matcher array that aren't matchers, which are left
+    /// out of `matchers`.
+    invalid: Vec<&'a JsValue>,
+}
+
+/// Statically extracts the `matcher` of a config export, which is a matcher
+/// or an array of matchers. Returns `None` if `value` is neither.
+fn parse_matcher(value: &JsValue) -> Option<ParsedMatcher<'_>> {
+    match value {
+        JsValue::Array { items, .. } => {
+            let mut matcher = ParsedMatcher {
+                matchers: vec![],
+                invalid: vec![],
+            };
+            for item in items {
+                match matcher_source(item) {
+                    Some(source) => matcher.matchers.push(source),
+                    None => matcher.invalid.push(item),
+                }
+            }
+            Some(matcher)
+        }
+        _ => Some(ParsedMatcher {
+            matchers: vec![matcher_source(value)?],
+            invalid: vec![],
+        }),
+    }
+}
+
+/// Returns the path pattern of a single matcher, which is either a string or
+/// an object like `{ source: '/about', has: [...] }`. The `has`, `missing`
+/// and `locale` conditions of objects are ignored, so the middleware runs for
+/// every request matching `source`.
+fn matcher_source(matcher: &JsValue) -> Option<String> {
+    match matcher {
+        JsValue::Object { parts, .. } => parts.iter().find_map(|part| match part {
+            ObjectPart::KeyValue(key, value) if key.as_str() == Some("source") => {
+                value.as_str().map(ToString::to_string)
+            }
+            _ => None,
+        }),
+        _ => matcher.as_str().map(ToString::to_string),
+    }
+}
+
 pub async fn load_next_json<T: DeserializeOwned>(
     context: FileSystemPathVc,
     path: &str,
@@ -342,3 +446,88 @@ pub async fn load_next_json<T: DeserializeOwned>(
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use swc_core::{
+        common::DUMMY_SP,
+        ecma::ast::{Number, Str},
+    };
+    use turbopack_ecmascript::analyzer::ConstantValue;
+
+    use super::*;
+
+    fn number(value: f64) -> Expr {
+        Expr::Lit(Lit::Num(Number {
+            span: DUMMY_SP,
+            value,
+            raw: None,
+        }))
+    }
+
+    #[test]
+    fn parses_max_duration() {
+        assert_eq!(parse_max_duration(&number(30.0)), Some(30));
+        assert_eq!(parse_max_duration(&number(1.5)), None);
+        assert_eq!(parse_max_duration(&number(-1.0)), None);
+        assert_eq!(NextSourceConfig::default().max_duration, None);
+    }
+
+    fn string(value: &str) -> Expr {
+        Expr::Lit(Lit::Str(Str {
+            span: DUMMY_SP,
+            value: value.into(),
+            raw: None,
+        }))
+    }
+
+    #[test]
+    fn parses_fetch_cache() {
+        for (value, fetch_cache) in [
+            ("auto", NextFetchCache::Auto),
+            ("default-cache", NextFetchCache::DefaultCache),
+            ("only-cache", NextFetchCache::OnlyCache),
+            ("force-cache", NextFetchCache::ForceCache),
+            ("default-no-store", NextFetchCache::DefaultNoStore),
+            ("only-no-store", NextFetchCache::OnlyNoStore),
+            ("force-no-store", NextFetchCache::ForceNoStore),
+        ] {
+            assert_eq!(parse_fetch_cache(&string(value)), Some(fetch_cache));
+        }
+        assert_eq!(parse_fetch_cache(&string("no-store")), None);
+        assert_eq!(parse_fetch_cache(&number(1.0)), None);
+        assert_eq!(NextSourceConfig::default().fetch_cache, None);
+    }
+
+    #[test]
+    fn parses_matcher() {
+        let matchers = |value: &JsValue| {
+            parse_matcher(value).map(|matcher| (matcher.matchers, matcher.invalid.len()))
+        };
+        assert_eq!(
+            matchers(&JsValue::array(vec!["/api/:path*".into()])),
+            Some((vec!["/api/:path*".to_string()], 0))
+        );
+        assert_eq!(
+            matchers(&"/about".into()),
+            Some((vec!["/about".to_string()], 0))
+        );
+
+        // `{ source: '/dashboard', has: [...] }`
+        let object = JsValue::object(vec![
+            ObjectPart::KeyValue("source".into(), "/dashboard".into()),
+            ObjectPart::KeyValue("has".into(), JsValue::array(vec![])),
+        ]);
+        assert_eq!(
+            matchers(&JsValue::array(vec![
+                "/a".into(),
+                object.clone(),
+                JsValue::Constant(ConstantValue::True),
+                JsValue::object(vec![]),
+            ])),
+            Some((vec!["/a".to_string(), "/dashboard".to_string()], 2))
+        );
+        assert_eq!(matchers(&object), Some((vec!["/dashboard".to_string()], 0)));
+        assert_eq!(matchers(&JsValue::Constant(ConstantValue::True)), None);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use next_core::{
+    next_config::NextConfigBuilder,
+    route_events::link_validation_routes,
+    testing::{project_root, TestProject, TestRunner},
+};
+use next_transform_link_validation::{link_validation, Config};
+use swc_core::{
+    common::{
+        errors::{Handler, HANDLER},
+        sync::Lrc,
+        FileName, Globals, SourceMap, GLOBALS,
+    },
+    ecma::{
+        ast::EsVersion,
+        parser::{parse_file_as_module, EsConfig, Syntax},
+        visit::FoldWith,
+    },
+};
+
+/// Collects the diagnostics emitted by a transform.
+#[derive(Clone, Default)]
+struct Diagnostics(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Diagnostics {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the config the client link validation transform gets for the
+/// project in `root_dir`, if any.
+async fn link_validation_config(root_dir: String, validate_links: bool) -> Result<Option<Config>> {
+    let next_config = NextConfigBuilder::default()
+        .validate_links(validate_links)
+        .cell();
+    let routes = *link_validation_routes(project_root(&root_dir), next_config).await?;
+    Ok(match routes {
+        Some(routes) => Some(routes.await?.link_validation_config()),
+        None => None,
+    })
+}
+
+/// Runs the link validation transform with `config` over `source`, and
+/// returns the diagnostics it emitted.
+fn validate_links(config: Config, source: &str) -> String {
+    let cm = Lrc::new(SourceMap::default());
+    let fm = cm.new_source_file(FileName::Anon, source.to_string());
+    let diagnostics = Diagnostics::default();
+    let handler = Handler::with_emitter_writer(Box::new(diagnostics.clone()), Some(cm.clone()));
+    GLOBALS.set(&Globals::new(), || {
+        HANDLER.set(&handler, || {
+            let module = parse_file_as_module(
+                &fm,
+                Syntax::Es(EsConfig {
+                    jsx: true,
+                    ..Default::default()
+                }),
+                EsVersion::latest(),
+                None,
+                &mut vec![],
+            )
+            .unwrap();
+            module.fold_with(&mut link_validation(config, true));
+        })
+    });
+    let output = diagnostics.0.lock().unwrap().clone();
+    String::from_utf8(output).unwrap()
+}
+
+const PAGE: &str = r#"
+import Link from "next/link";
+
+export default function Home() {
+    return (
+        <>
+            <Link href="/about">About</Link>
+            <Link href="/missing">Missing</Link>
+        </>
+    );
+}
+"#;
+
+#[test]
+fn warns_about_dead_links_when_validate_links_is_set() -> Result<()> {
+    let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/about.tsx", "")])?;
+    let runner = TestRunner::new()?;
+    let root_dir = project.root_dir();
+
+    // Without the flag, the client transforms don't validate links.
+    assert!(runner
+        .run(link_validation_config(root_dir.clone(), false))?
+        .is_none());
+
+    let config = runner
+        .run(link_validation_config(root_dir, true))?
+        .expect("links are validated with experimental.validateLinks");
+    let diagnostics = validate_links(config, PAGE);
+    assert!(
+        diagnostics.contains("The link `/missing` doesn't match any route of the app."),
+        "{diagnostics}"
+    );
+    assert!(!diagnostics.contains("`/about`"), "{diagnostics}");
+    Ok(())
+}
@@ -0,0 +1,87 @@
+#![feature(min_specialization)]
+
+use std::sync::{Arc, Mutex, Once};
+
+use anyhow::{bail, Result};
+use next_core::{
+    next_config::NextConfigBuilder,
+    pages_structure::find_pages_structure,
+    prewarm_page_routes,
+    testing::{project_root, TestProject, TestRunner},
+    PrewarmRoutesHook,
+};
+use turbo_tasks::{TransientInstance, Value};
+use turbopack_dev_server::source::{
+    ContentSource, ContentSourceData, ContentSourceResultVc, ContentSourceVc,
+};
+
+fn register() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        include!(concat!(env!("OUT_DIR"), "/register_test_prewarm.rs"));
+    });
+}
+
+/// The paths requested from [CompilingSource], once per compiled route.
+static COMPILED: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// Stands in for the pages content source, recording the routes compiled
+/// through it.
+#[turbo_tasks::value(shared)]
+struct CompilingSource {}
+
+#[turbo_tasks::value_impl]
+impl ContentSource for CompilingSource {
+    #[turbo_tasks::function]
+    fn get(&self, path: &str, _data: Value<ContentSourceData>) -> ContentSourceResultVc {
+        COMPILED.lock().unwrap().push(path.to_string());
+        ContentSourceResultVc::not_found()
+    }
+}
+
+#[test]
+fn prewarms_the_configured_number_of_routes() -> Result<()> {
+    let project = TestProject::new(&[
+        ("pages/_app.tsx", ""),
+        ("pages/_document.tsx", ""),
+        ("pages/_error.tsx", ""),
+        ("pages/index.tsx", ""),
+        ("pages/about.tsx", ""),
+        ("pages/contact.tsx", ""),
+        ("pages/blog/[slug].tsx", ""),
+        ("pages/api/users.ts", ""),
+    ])?;
+    let runner = TestRunner::new()?;
+    register();
+    let root_dir = project.root_dir();
+    let hooked = Arc::new(Mutex::new(vec![]));
+    let on_prewarm: PrewarmRoutesHook = {
+        let hooked = hooked.clone();
+        Box::new(move |routes| hooked.lock().unwrap().push(routes.to_vec()))
+    };
+    runner.run(async move {
+        let root = project_root(&root_dir);
+        let next_config = NextConfigBuilder::default().cell();
+        let Some(pages) = *find_pages_structure(root, root, next_config).await? else {
+            bail!("the pages directory wasn't found");
+        };
+        let source: ContentSourceVc = CompilingSource {}.cell().into();
+        prewarm_page_routes(
+            source,
+            pages.prewarm_routes(4),
+            TransientInstance::new(on_prewarm),
+        )
+        .await?;
+        Ok(())
+    })?;
+
+    assert_eq!(
+        *COMPILED.lock().unwrap(),
+        vec!["_app", "_document", "", "about"]
+    );
+    assert_eq!(
+        *hooked.lock().unwrap(),
+        vec![vec!["/_app", "/_document", "/", "/about"]]
+    );
+    Ok(())
+}
@@ -0,0 +1,88 @@
+#![feature(min_specialization)]
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Once,
+};
+
+use anyhow::Result;
+use next_core::{
+    next_config::{NextConfigBuilder, NextConfigVc},
+    pages_structure::{find_pages_structure, OptionPagesStructureVc},
+    testing::{project_root, reported_issues, TestProject, TestRunner},
+};
+use turbo_tasks::CompletionVc;
+
+fn register() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        include!(concat!(env!("OUT_DIR"), "/register_test_static_export.rs"));
+    });
+}
+
+/// How often [page_consumer] has been executed.
+static PAGE_CONSUMER_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+#[turbo_tasks::function]
+fn static_export_config() -> NextConfigVc {
+    NextConfigBuilder::default().static_export(true).cell()
+}
+
+/// Stands in for the page-side consumers of the routes, like the router.
+#[turbo_tasks::function]
+async fn page_consumer(pages: OptionPagesStructureVc) -> Result<CompletionVc> {
+    PAGE_CONSUMER_RUNS.fetch_add(1, Ordering::SeqCst);
+    if let Some(pages) = *pages.await? {
+        pages.routes_changed().await?;
+    }
+    Ok(CompletionVc::new())
+}
+
+/// Returns how often the page consumer has run and the reported issues.
+async fn scan_pages(root_dir: String) -> Result<(usize, Vec<String>)> {
+    let root = project_root(&root_dir);
+    let pages = find_pages_structure(root, root, static_export_config());
+    page_consumer(pages).await?;
+    let issues = reported_issues(pages)
+        .await?
+        .into_iter()
+        .map(|issue| issue.description)
+        .collect();
+    Ok((PAGE_CONSUMER_RUNS.load(Ordering::SeqCst), issues))
+}
+
+#[test]
+fn api_changes_dont_rebuild_pages_with_static_export() -> Result<()> {
+    let project = TestProject::new(&[("pages/index.tsx", ""), ("pages/api/users.ts", "")])?;
+    let runner = TestRunner::new()?;
+    register();
+    runner.watch(&project)?;
+    let root_dir = project.root_dir();
+    let scan = || scan_pages(root_dir.clone());
+
+    let initial = runner.run(scan())?;
+    assert_eq!(initial.0, 1);
+    assert_eq!(initial.1.len(), 1, "{:?}", initial.1);
+
+    // Edits to an API route aren't read by the scan at all, and a new API
+    // route is reported without invalidating the pages.
+    project.write("pages/api/users.ts", "export default function handler() {}")?;
+    project.write("pages/api/posts.ts", "")?;
+    let (runs, issues) = runner.run_until_changed(&initial, scan)?;
+    assert_eq!(runs, 1);
+    assert_eq!(issues.len(), 2, "{issues:?}");
+    assert!(
+        issues
+            .iter()
+            .all(|issue| issue.contains("output: 'export'")),
+        "{issues:?}"
+    );
+
+    // A new page still rebuilds them.
+    project.write("pages/about.tsx", "")?;
+    let previous = (runs, issues);
+    let (runs, issues) = runner.run_until_changed(&previous, scan)?;
+    assert_eq!(runs, 2);
+    assert_eq!(issues.len(), 2, "{issues:?}");
+    Ok(())
+}
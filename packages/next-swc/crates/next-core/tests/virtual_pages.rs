@@ -0,0 +1,167 @@
+#![feature(min_specialization)]
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Once,
+};
+
+use anyhow::{bail, Result};
+use indexmap::IndexMap;
+use next_core::{
+    next_config::NextConfigBuilder,
+    pages_structure::{
+        find_pages_structure_with_options, OptionPagesStructureVc, PagesScanOptions,
+        VirtualPagesPrecedence, VirtualPagesVc,
+    },
+    testing::{project_root, TestProject, TestRunner},
+};
+use turbo_tasks::CompletionVc;
+use turbo_tasks_fs::{DirectoryContent, DirectoryEntry, FileSystemPathVc};
+use turbopack_core::{asset::Asset, source_asset::SourceAssetVc};
+
+fn register() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        include!(concat!(env!("OUT_DIR"), "/register_test_virtual_pages.rs"));
+    });
+}
+
+/// Stands in for a codegen plugin: every file in `dir` is a virtual route,
+/// e.g. `plugin/healthz.ts` serves `/healthz`.
+#[turbo_tasks::function]
+async fn plugin_pages(dir: FileSystemPathVc) -> Result<VirtualPagesVc> {
+    let mut pages = IndexMap::new();
+    if let DirectoryContent::Entries(entries) = &*dir.read_dir().await? {
+        for (name, entry) in entries.iter() {
+            if let (DirectoryEntry::File(file), Some((stem, _))) = (entry, name.rsplit_once('.')) {
+                pages.insert(format!("/{stem}"), SourceAssetVc::new(*file).into());
+            }
+        }
+    }
+    pages.sort_keys();
+    Ok(VirtualPagesVc::cell(pages))
+}
+
+/// Scans the pages directory merged with the routes of [plugin_pages], with
+/// real files winning.
+#[turbo_tasks::function]
+fn pages_with_plugin(root: FileSystemPathVc) -> OptionPagesStructureVc {
+    let options = PagesScanOptions::default().with_virtual_pages(
+        plugin_pages(root.join("plugin")),
+        VirtualPagesPrecedence::RealFiles,
+    );
+    find_pages_structure_with_options(
+        root,
+        root,
+        NextConfigBuilder::default().cell(),
+        options.cell(),
+    )
+}
+
+/// How often [route_consumer] has been executed.
+static ROUTE_CONSUMER_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Stands in for the consumers of the routes, like the router.
+#[turbo_tasks::function]
+async fn route_consumer(pages: OptionPagesStructureVc) -> Result<CompletionVc> {
+    ROUTE_CONSUMER_RUNS.fetch_add(1, Ordering::SeqCst);
+    pages.routes_changed().await?;
+    Ok(CompletionVc::new())
+}
+
+/// Returns how often the route consumer has run, and every route with the
+/// path of the asset the pages content source serves it from.
+async fn scan_pages(root_dir: String) -> Result<(usize, Vec<(String, String)>)> {
+    let root = project_root(&root_dir);
+    let pages = pages_with_plugin(root);
+    route_consumer(pages).await?;
+    let Some(structure) = *pages.await? else {
+        bail!("the pages directory wasn't found");
+    };
+
+    let mut routes = vec![];
+    let mut queue = vec![structure];
+    while let Some(current) = queue.pop() {
+        let current = current.await?;
+        for item in current.items.iter() {
+            let served = item.await?.asset().ident().path().await?;
+            routes.push((
+                item.route_pattern().await?.clone_value(),
+                served.path.clone(),
+            ));
+        }
+        queue.extend(current.children.iter().copied());
+    }
+    routes.sort();
+    Ok((ROUTE_CONSUMER_RUNS.load(Ordering::SeqCst), routes))
+}
+
+fn routes(routes: &[(&str, &str)]) -> Vec<(String, String)> {
+    routes
+        .iter()
+        .map(|(pattern, path)| (pattern.to_string(), path.to_string()))
+        .collect()
+}
+
+#[test]
+fn merges_serves_and_invalidates_virtual_pages() -> Result<()> {
+    let project = TestProject::new(&[
+        ("pages/index.tsx", ""),
+        ("pages/about.tsx", ""),
+        ("plugin/about.tsx", ""),
+        ("plugin/healthz.ts", ""),
+    ])?;
+    let runner = TestRunner::new()?;
+    register();
+    runner.watch(&project)?;
+    let root_dir = project.root_dir();
+    let scan = || scan_pages(root_dir.clone());
+
+    // The virtual `/about` is shadowed by `pages/about.tsx`.
+    let initial = runner.run(scan())?;
+    assert_eq!(
+        initial,
+        (
+            1,
+            routes(&[
+                ("/", "pages/index.tsx"),
+                ("/about", "pages/about.tsx"),
+                ("/healthz", "plugin/healthz.ts"),
+            ])
+        )
+    );
+
+    // A route added to the virtual set invalidates the consumers of the
+    // routes.
+    project.write("plugin/status.ts", "")?;
+    let added = runner.run_until_changed(&initial, scan)?;
+    assert_eq!(
+        added,
+        (
+            2,
+            routes(&[
+                ("/", "pages/index.tsx"),
+                ("/about", "pages/about.tsx"),
+                ("/healthz", "plugin/healthz.ts"),
+                ("/status", "plugin/status.ts"),
+            ])
+        )
+    );
+
+    // Removing the shadowing file serves the virtual route instead.
+    project.remove("pages/about.tsx")?;
+    let unshadowed = runner.run_until_changed(&added, scan)?;
+    assert_eq!(
+        unshadowed,
+        (
+            3,
+            routes(&[
+                ("/", "pages/index.tsx"),
+                ("/about", "plugin/about.tsx"),
+                ("/healthz", "plugin/healthz.ts"),
+                ("/status", "plugin/status.ts"),
+            ])
+        )
+    );
+    Ok(())
+}
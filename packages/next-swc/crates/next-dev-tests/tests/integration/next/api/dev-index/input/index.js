@@ -0,0 +1,10 @@
+it("should list API routes in the dev index", async () => {
+  const res = await fetch("/__nextjs_api_routes");
+  const json = await res.json();
+  expect(json).toEqual({
+    routes: [
+      { route: "/api/hello", file: "api/hello.ts", dynamic: false },
+      { route: "/api/users/[id]", file: "api/users/[id].ts", dynamic: true },
+    ],
+  });
+});
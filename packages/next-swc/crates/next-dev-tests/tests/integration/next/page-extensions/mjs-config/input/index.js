@@ -0,0 +1,10 @@
+it("should route files matching pageExtensions from next.config.mjs", async () => {
+  const res = await fetch("/api/included");
+  const json = await res.json();
+  expect(json).toEqual({ included: true });
+});
+
+it("should not route files excluded by pageExtensions from next.config.mjs", async () => {
+  const res = await fetch("/api/excluded");
+  expect(res.status).toBe(404);
+});
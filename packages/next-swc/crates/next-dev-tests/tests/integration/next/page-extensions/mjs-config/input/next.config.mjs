@@ -0,0 +1,3 @@
+export default {
+  pageExtensions: ["ts"],
+};
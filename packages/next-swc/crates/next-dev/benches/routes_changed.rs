@@ -0,0 +1,240 @@
+//! Measures the invalidation fan-out of `routes_changed`.
+//!
+//! Builds a large pages directory, attaches synthetic dependent tasks to
+//! `routes_changed` at a given granularity (the root structure, each child
+//! structure or each item), adds a single page and reports how many of the
+//! dependents re-executed and how long the update took.
+//!
+//! Run with `cargo bench -p next-dev --bench routes_changed`. The size of the
+//! app and the number of dependents can be configured with
+//! `TURBOPACK_BENCH_ROUTE_DIRS`, `TURBOPACK_BENCH_ROUTE_PAGES` and
+//! `TURBOPACK_BENCH_ROUTE_DEPENDENTS`.
+
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use next_core::{
+    page_extensions::PageExtensions,
+    pages_structure::{get_pages_structure, PagesStructureVc},
+};
+use turbo_tasks::{primitives::U32Vc, util::FormatDuration, CompletionVc, TurboTasks, Value};
+use turbo_tasks_fs::{DiskFileSystemVc, FileSystemVc};
+use turbo_tasks_memory::MemoryBackend;
+
+fn register() {
+    next_core::register();
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/register_bench_routes_changed.rs"
+    ));
+}
+
+/// How long to wait for the file watcher to pick up the added page.
+const MAX_UPDATE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many times the synthetic dependents have executed.
+static EXECUTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Where the synthetic dependents subscribe to `routes_changed`.
+#[turbo_tasks::value(serialization = "auto_for_input")]
+#[derive(Debug, Copy, Clone, Hash, PartialOrd, Ord)]
+enum Granularity {
+    /// Every dependent subscribes to the root structure.
+    Root,
+    /// Dependents are spread over the top-level child structures.
+    PerChild,
+    /// Dependents are spread over all items of the tree.
+    PerItem,
+}
+
+impl Granularity {
+    const ALL: [Granularity; 3] = [
+        Granularity::Root,
+        Granularity::PerChild,
+        Granularity::PerItem,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Granularity::Root => "root",
+            Granularity::PerChild => "per-child",
+            Granularity::PerItem => "per-item",
+        }
+    }
+}
+
+/// A task that only depends on `completion`.
+#[turbo_tasks::function]
+async fn dependent(completion: CompletionVc, _index: u32) -> Result<CompletionVc> {
+    completion.await?;
+    EXECUTIONS.fetch_add(1, Ordering::SeqCst);
+    Ok(CompletionVc::new())
+}
+
+/// Attaches `dependents` dependent tasks to the `routes_changed` completions
+/// of `structure` at the given granularity. Returns the number of routes, so
+/// the caller can tell when an added page has been picked up.
+#[turbo_tasks::function]
+async fn fan_out(
+    structure: PagesStructureVc,
+    granularity: Value<Granularity>,
+    dependents: u32,
+) -> Result<U32Vc> {
+    let mut routes = 0;
+    let mut item_completions = vec![];
+    let mut queue = vec![structure];
+    while let Some(structure) = queue.pop() {
+        let structure = structure.await?;
+        routes += structure.items.len() as u32;
+        item_completions.extend(structure.items.iter().map(|item| item.routes_changed()));
+        queue.extend(structure.children.iter().copied());
+    }
+
+    let completions = match granularity.into_value() {
+        Granularity::Root => vec![structure.routes_changed()],
+        Granularity::PerChild => {
+            let structure = structure.await?;
+            let mut completions = structure
+                .children
+                .iter()
+                .map(|child| child.routes_changed())
+                .collect::<Vec<_>>();
+            completions.extend(structure.items.iter().map(|item| item.routes_changed()));
+            completions
+        }
+        Granularity::PerItem => item_completions,
+    };
+    if completions.is_empty() {
+        bail!("the pages structure is empty");
+    }
+    for index in 0..dependents {
+        dependent(completions[index as usize % completions.len()], index).await?;
+    }
+    Ok(U32Vc::cell(routes))
+}
+
+#[turbo_tasks::function]
+async fn project_fs(project_dir: &str) -> Result<FileSystemVc> {
+    let disk_fs = DiskFileSystemVc::new("project".to_string(), project_dir.to_string());
+    disk_fs.await?.start_watching()?;
+    Ok(disk_fs.into())
+}
+
+/// Creates `dirs` directories with `pages` pages each.
+fn create_app(root: &Path, dirs: usize, pages: usize) -> Result<()> {
+    for dir in 0..dirs {
+        let dir = root.join("pages").join(format!("section{dir}"));
+        fs::create_dir_all(&dir)?;
+        for page in 0..pages {
+            fs::write(
+                dir.join(format!("page{page}.tsx")),
+                "export default function Page() { return null }\n",
+            )?;
+        }
+    }
+    Ok(())
+}
+
+struct FanOut {
+    reexecuted: usize,
+    duration: Duration,
+}
+
+async fn measure(
+    granularity: Granularity,
+    dirs: usize,
+    pages: usize,
+    dependents: u32,
+) -> Result<FanOut> {
+    let app = tempfile::tempdir()?;
+    create_app(app.path(), dirs, pages)?;
+    let root = app.path().to_string_lossy().to_string();
+
+    let tt = TurboTasks::new(MemoryBackend::default());
+    let routes = move || {
+        let root = root.clone();
+        async move {
+            let fs = project_fs(&root);
+            let structure = get_pages_structure(
+                fs.root().join("pages"),
+                fs.root(),
+                PageExtensions::default().cell(),
+            );
+            let routes = fan_out(structure, Value::new(granularity), dependents)
+                .strongly_consistent()
+                .await?;
+            Ok(*routes)
+        }
+    };
+
+    let initial_routes = tt.run_once(routes()).await?;
+    let executions = EXECUTIONS.load(Ordering::SeqCst);
+
+    // Add a single page, which only changes one child structure.
+    let start = Instant::now();
+    fs::write(
+        app.path().join("pages").join("section0").join("added.tsx"),
+        "export default function Added() { return null }\n",
+    )?;
+    while tt.run_once(routes()).await? == initial_routes {
+        if start.elapsed() > MAX_UPDATE_TIMEOUT {
+            bail!("the added page wasn't picked up");
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    Ok(FanOut {
+        reexecuted: EXECUTIONS.load(Ordering::SeqCst) - executions,
+        duration: start.elapsed(),
+    })
+}
+
+fn read_env_usize(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn read_env_list(name: &str, default: &[u32]) -> Vec<u32> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .split(',')
+            .filter_map(|value| value.trim().parse().ok())
+            .collect(),
+        Err(_) => default.to_vec(),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    register();
+
+    let dirs = read_env_usize("TURBOPACK_BENCH_ROUTE_DIRS", 100);
+    let pages = read_env_usize("TURBOPACK_BENCH_ROUTE_PAGES", 10);
+    let dependents = read_env_list("TURBOPACK_BENCH_ROUTE_DEPENDENTS", &[100, 1000, 10000]);
+
+    println!("routes_changed fan-out, {dirs} directories with {pages} pages each");
+    println!(
+        "{:<10} {:>10} {:>12} {:>12}",
+        "attached", "dependents", "re-executed", "time"
+    );
+    for granularity in Granularity::ALL {
+        for dependents in dependents.iter().copied() {
+            let fan_out = measure(granularity, dirs, pages, dependents).await?;
+            println!(
+                "{:<10} {:>10} {:>12} {:>12}",
+                granularity.name(),
+                dependents,
+                fan_out.reexecuted,
+                FormatDuration(fan_out.duration).to_string()
+            );
+        }
+    }
+    Ok(())
+}
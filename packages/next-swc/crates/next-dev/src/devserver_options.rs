@@ -48,6 +48,13 @@ pub struct DevServerOptions {
     #[cfg_attr(feature = "serializable", serde(default))]
     pub eager_compile: bool,
 
+    /// Compile up to this many page routes (`_app`, `_document`, the index and
+    /// other static pages) in the background right after startup, before
+    /// they are first requested.
+    #[cfg_attr(feature = "cli", clap(long, default_value_t = 0))]
+    #[cfg_attr(feature = "serializable", serde(default))]
+    pub prewarm_routes: u32,
+
     /// Display version of the binary. Noop if used in library mode.
     #[cfg_attr(feature = "cli", clap(long))]
     #[cfg_attr(feature = "serializable", serde(default))]
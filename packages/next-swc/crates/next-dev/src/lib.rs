@@ -20,24 +20,40 @@ use anyhow::{Context, Result};
 use devserver_options::DevServerOptions;
 use dunce::canonicalize;
 use next_core::{
-    app_structure::find_app_structure, create_app_source, create_page_source,
-    create_web_entry_source, env::load_env, manifest::DevManifestContentSource,
-    next_config::load_next_config, next_image::NextImageContentSourceVc,
-    pages_structure::find_pages_structure, router_source::NextRouterContentSourceVc,
+    app_structure::find_app_structure,
+    create_app_source, create_page_source, create_web_entry_source,
+    env::load_env,
+    manifest::{DevApiRoutesContentSource, DevManifestContentSource},
+    next_config::{load_next_config, NextConfigVc},
+    next_image::NextImageContentSourceVc,
+    pages_structure::{
+        find_pages_structure_with_options, pages_structure_metadata, record_pages_scan,
+        OptionPagesStructureMetadataVc, OptionPagesStructureVc, PagesScanLog, PagesScanOptions,
+        PagesScans,
+    },
+    prewarm_page_routes,
+    route_events::{
+        record_route_events, report_route_table, RouteEventLog, RouteEvents, RouteTableEvents,
+    },
+    router_source::NextRouterContentSourceVc,
+    routing_report::{
+        routing_report, DevRoutingReportContentSource, RoutingReportHook, RoutingReportVc,
+    },
     source_map::NextSourceMapTraceContentSourceVc,
+    PrewarmRoutesHook,
 };
 use owo_colors::OwoColorize;
 use turbo_malloc::TurboMalloc;
 use turbo_tasks::{
     util::{FormatBytes, FormatDuration},
-    StatsType, TransientInstance, TurboTasks, TurboTasksBackendApi, Value,
+    CompletionVc, StatsType, TransientInstance, TurboTasks, TurboTasksBackendApi, Value,
 };
-use turbo_tasks_fs::{DiskFileSystemVc, FileSystem, FileSystemVc};
+use turbo_tasks_fs::{DiskFileSystemVc, FileSystem, FileSystemPathVc, FileSystemVc};
 use turbo_tasks_memory::MemoryBackend;
 use turbopack::evaluate_context::node_build_environment;
 use turbopack_cli_utils::issue::{ConsoleUiVc, LogOptions};
 use turbopack_core::{
-    environment::ServerAddr,
+    environment::{ServerAddr, ServerAddrVc},
     issue::{IssueReporterVc, IssueSeverity},
     resolve::{parse::RequestVc, pattern::QueryMapVc},
     server_fs::ServerFileSystemVc,
@@ -55,6 +71,41 @@ use turbopack_dev_server::{
 };
 use turbopack_node::execution_context::ExecutionContextVc;
 
+/// How long the routes have to be unchanged before a route table event is
+/// emitted.
+const ROUTE_EVENTS_QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Flushes the route table events once per quiet period for as long as it's
+/// alive. Dropping it, when the server shuts down, stops the flushing and
+/// emits the last burst of changes.
+struct RouteEventsFlusher {
+    route_events: Arc<RouteTableEvents>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl RouteEventsFlusher {
+    fn spawn(route_events: Arc<RouteTableEvents>) -> Self {
+        let task = {
+            let route_events = route_events.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(route_events.quiet_period());
+                loop {
+                    interval.tick().await;
+                    route_events.flush(Instant::now());
+                }
+            })
+        };
+        RouteEventsFlusher { route_events, task }
+    }
+}
+
+impl Drop for RouteEventsFlusher {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.route_events.finish();
+    }
+}
+
 #[derive(Clone)]
 pub enum EntryRequest {
     Relative(String),
@@ -67,6 +118,11 @@ pub struct NextDevServerBuilder {
     root_dir: String,
     entry_requests: Vec<EntryRequest>,
     eager_compile: bool,
+    prewarm_routes: u32,
+    on_prewarm: Option<PrewarmRoutesHook>,
+    on_routing_report: Option<RoutingReportHook>,
+    route_event_log: Option<Arc<RouteEventLog>>,
+    pages_scan_log: Option<Arc<PagesScanLog>>,
     hostname: Option<IpAddr>,
     issue_reporter: Option<Box<dyn IssueReporterProvider>>,
     port: Option<u16>,
@@ -89,6 +145,11 @@ impl NextDevServerBuilder {
             root_dir,
             entry_requests: vec![],
             eager_compile: false,
+            prewarm_routes: 0,
+            on_prewarm: None,
+            on_routing_report: None,
+            route_event_log: None,
+            pages_scan_log: None,
             hostname: None,
             issue_reporter: None,
             port: None,
@@ -112,6 +173,44 @@ impl NextDevServerBuilder {
         self
     }
 
+    /// Compiles up to `prewarm_routes` page routes (`_app`, `_document`, the
+    /// index and other static pages) in the background after startup.
+    pub fn prewarm_routes(mut self, prewarm_routes: u32) -> NextDevServerBuilder {
+        self.prewarm_routes = prewarm_routes;
+        self
+    }
+
+    /// Sets a hook that is called with the routes being prewarmed.
+    pub fn on_prewarm(mut self, on_prewarm: PrewarmRoutesHook) -> NextDevServerBuilder {
+        self.on_prewarm = Some(on_prewarm);
+        self
+    }
+
+    /// Sets a hook that is called once with the text of the routing report
+    /// of the project, after startup.
+    pub fn on_routing_report(
+        mut self,
+        on_routing_report: RoutingReportHook,
+    ) -> NextDevServerBuilder {
+        self.on_routing_report = Some(on_routing_report);
+        self
+    }
+
+    /// Records every change of the routes in `route_event_log`, so that an
+    /// embedder can pull them with [RouteEventLog::next_events].
+    pub fn route_event_log(mut self, route_event_log: Arc<RouteEventLog>) -> NextDevServerBuilder {
+        self.route_event_log = Some(route_event_log);
+        self
+    }
+
+    /// Records every scan of the pages directory in `pages_scan_log`, so that
+    /// an embedder can read the pages root and scan generation with
+    /// [PagesScanLog::latest].
+    pub fn pages_scan_log(mut self, pages_scan_log: Arc<PagesScanLog>) -> NextDevServerBuilder {
+        self.pages_scan_log = Some(pages_scan_log);
+        self
+    }
+
     pub fn hostname(mut self, hostname: IpAddr) -> NextDevServerBuilder {
         self.hostname = Some(hostname);
         self
@@ -205,6 +304,13 @@ impl NextDevServerBuilder {
         let project_dir = self.project_dir;
         let root_dir = self.root_dir;
         let eager_compile = self.eager_compile;
+        let prewarm_routes = self.prewarm_routes;
+        let on_prewarm: Arc<PrewarmRoutesHook> =
+            Arc::new(self.on_prewarm.unwrap_or_else(|| Box::new(|_| {})));
+        let route_event_log = Arc::new(self.route_event_log);
+        let pages_scan_log = Arc::new(self.pages_scan_log);
+        let route_events = Arc::new(RouteTableEvents::new(ROUTE_EVENTS_QUIET_PERIOD));
+        let route_events_flusher = RouteEventsFlusher::spawn(route_events.clone());
         let show_all = self.show_all;
         let log_detail = self.log_detail;
         let browserslist_query = self.browserslist_query;
@@ -223,12 +329,78 @@ impl NextDevServerBuilder {
             Box::new(move || ConsoleUiVc::new(log_options.clone().into()).into())
         });
 
+        if prewarm_routes > 0 {
+            let tasks = tasks.clone();
+            let prewarm = {
+                let root_dir = root_dir.clone();
+                let project_dir = project_dir.clone();
+                let browserslist_query = browserslist_query.clone();
+                let server_addr = server_addr.clone();
+                move || {
+                    prewarm(
+                        root_dir,
+                        project_dir,
+                        browserslist_query,
+                        server_addr.into(),
+                        prewarm_routes,
+                        on_prewarm.into(),
+                    )
+                }
+            };
+            // Awaited in a task of its own, so that serving doesn't wait for
+            // it and failures are reported.
+            tokio::spawn(async move {
+                let prewarmed = tasks
+                    .run_once(async move {
+                        prewarm().await?;
+                        Ok(())
+                    })
+                    .await;
+                if let Err(err) = prewarmed {
+                    println!("{} - failed to prewarm routes: {err:?}", "warn ".yellow());
+                }
+            });
+        }
+
+        if let Some(on_routing_report) = self.on_routing_report {
+            let tasks = tasks.clone();
+            let root_dir = root_dir.clone();
+            let project_dir = project_dir.clone();
+            let browserslist_query = browserslist_query.clone();
+            let server_addr = server_addr.clone();
+            // Computed once instead of from the source, which would print the
+            // report again whenever an issue changes. The issues themselves
+            // are reported as they change by the issue reporter.
+            tokio::spawn(async move {
+                let report = tasks
+                    .run_once(async move {
+                        let report = dev_routing_report(
+                            root_dir,
+                            project_dir,
+                            browserslist_query,
+                            server_addr.into(),
+                        );
+                        Ok(report.to_text().await?.clone_value())
+                    })
+                    .await;
+                match report {
+                    Ok(report) => on_routing_report(&report),
+                    Err(err) => {
+                        println!("{} - failed to report routing: {err:?}", "warn ".yellow())
+                    }
+                }
+            });
+        }
+
         let source = move || {
             source(
                 root_dir.clone(),
                 project_dir.clone(),
                 entry_requests.clone().into(),
                 eager_compile,
+                route_events.clone().into(),
+                route_event_log.clone().into(),
+                pages_scan_log.clone().into(),
                 turbo_tasks.clone().into(),
                 browserslist_query.clone(),
                 server_addr.clone().into(),
@@ -236,7 +408,14 @@ impl NextDevServerBuilder {
         };
 
         let issue_reporter_arc = Arc::new(move || issue_provider.get_issue_reporter());
-        Ok(server.serve(tasks, source, issue_reporter_arc))
+        let mut server = server.serve(tasks, source, issue_reporter_arc);
+        // The flusher lives as long as the server does.
+        let future = server.future;
+        server.future = Box::pin(async move {
+            let _route_events_flusher = route_events_flusher;
+            future.await
+        });
+        Ok(server)
     }
 }
 
@@ -255,17 +434,32 @@ async fn output_fs(project_dir: &str) -> Result<FileSystemVc> {
     Ok(disk_fs.into())
 }
 
-#[allow(clippy::too_many_arguments)]
+/// The project served by the dev server and its pages, shared by [source]
+/// and [prewarm].
+#[turbo_tasks::value]
+struct DevPages {
+    project_path: FileSystemPathVc,
+    execution_context: ExecutionContextVc,
+    next_config: NextConfigVc,
+    server_addr: ServerAddrVc,
+    dev_server_root: FileSystemPathVc,
+    output_root: FileSystemPathVc,
+    /// Where generated TypeScript declarations (like `routes.d.ts`) are
+    /// written.
+    types_root: FileSystemPathVc,
+    pages_structure: OptionPagesStructureVc,
+    /// Where [DevPages::pages_structure] was loaded from.
+    pages_metadata: OptionPagesStructureMetadataVc,
+    page_source: ContentSourceVc,
+}
+
 #[turbo_tasks::function]
-async fn source(
+async fn dev_pages(
     root_dir: String,
     project_dir: String,
-    entry_requests: TransientInstance<Vec<EntryRequest>>,
-    eager_compile: bool,
-    turbo_tasks: TransientInstance<TurboTasks<MemoryBackend>>,
     browserslist_query: String,
     server_addr: TransientInstance<SocketAddr>,
-) -> Result<ContentSourceVc> {
+) -> Result<DevPagesVc> {
     let output_fs = output_fs(&project_dir);
     let fs = project_fs(&root_dir);
     let project_relative = project_dir.strip_prefix(&root_dir).unwrap();
@@ -292,10 +486,109 @@ async fn source(
     let next_config = load_next_config(execution_context.with_layer("next_config"));
 
     let output_root = output_fs.root().join(".next/server");
+    let types_root = output_fs.root().join(".next/types");
     let server_addr = ServerAddr::new(*server_addr).cell();
 
     let dev_server_fs = ServerFileSystemVc::new().as_file_system();
     let dev_server_root = dev_server_fs.root();
+
+    // The dev server serves dev-only routes (like `pages/__debug`), which
+    // production builds leave out.
+    let pages_scan_options = PagesScanOptions::default().with_production(false).cell();
+    let pages_structure = find_pages_structure_with_options(
+        project_path,
+        dev_server_root,
+        next_config,
+        pages_scan_options,
+    );
+    let pages_metadata = pages_structure_metadata(
+        project_path,
+        dev_server_root,
+        next_config,
+        pages_scan_options,
+    );
+    let page_source = create_page_source(
+        pages_structure,
+        project_path,
+        execution_context,
+        output_root.join("pages"),
+        dev_server_root,
+        env,
+        &browserslist_query,
+        next_config,
+        server_addr,
+    );
+    Ok(DevPages {
+        project_path,
+        execution_context,
+        next_config,
+        server_addr,
+        dev_server_root,
+        output_root,
+        types_root,
+        pages_structure,
+        pages_metadata,
+        page_source,
+    }
+    .cell())
+}
+
+/// Compiles up to `prewarm_routes` page routes, see [prewarm_page_routes].
+#[turbo_tasks::function]
+async fn prewarm(
+    root_dir: String,
+    project_dir: String,
+    browserslist_query: String,
+    server_addr: TransientInstance<SocketAddr>,
+    prewarm_routes: u32,
+    on_prewarm: TransientInstance<PrewarmRoutesHook>,
+) -> Result<CompletionVc> {
+    let pages = dev_pages(root_dir, project_dir, browserslist_query, server_addr).await?;
+    if let Some(pages_structure) = *pages.pages_structure.await? {
+        prewarm_page_routes(
+            pages.page_source,
+            pages_structure.prewarm_routes(prewarm_routes),
+            on_prewarm,
+        )
+        .await?;
+    }
+    Ok(CompletionVc::new())
+}
+
+#[allow(clippy::too_many_arguments)]
+#[turbo_tasks::function]
+async fn source(
+    root_dir: String,
+    project_dir: String,
+    entry_requests: TransientInstance<Vec<EntryRequest>>,
+    eager_compile: bool,
+    route_events: TransientInstance<RouteTableEvents>,
+    route_event_log: TransientInstance<Option<Arc<RouteEventLog>>>,
+    pages_scan_log: TransientInstance<Option<Arc<PagesScanLog>>>,
+    turbo_tasks: TransientInstance<TurboTasks<MemoryBackend>>,
+    browserslist_query: String,
+    server_addr: TransientInstance<SocketAddr>,
+) -> Result<ContentSourceVc> {
+    let DevPages {
+        project_path,
+        execution_context,
+        next_config,
+        server_addr,
+        dev_server_root,
+        output_root,
+        types_root,
+        pages_structure,
+        pages_metadata,
+        page_source,
+    } = *dev_pages(
+        root_dir,
+        project_dir,
+        browserslist_query.clone(),
+        server_addr,
+    )
+    .await?;
+    let env = load_env(project_path);
+
     let entry_requests = entry_requests
         .iter()
         .map(|r| match r {
@@ -316,19 +609,18 @@ async fn source(
         &browserslist_query,
         next_config,
     );
-    let pages_structure = find_pages_structure(project_path, dev_server_root, next_config);
-    let page_source = create_page_source(
-        pages_structure,
-        project_path,
-        execution_context,
-        output_root.join("pages"),
-        dev_server_root,
-        env,
-        &browserslist_query,
-        next_config,
-        server_addr,
-    );
+    // Not awaited, so it's recomputed in the background whenever the routes
+    // change.
+    report_route_table(pages_structure, route_events);
+    pages_structure.write_route_params_types(types_root);
+    if let Some(route_event_log) = &*route_event_log {
+        record_route_events(pages_structure, RouteEvents(route_event_log.clone()).cell());
+    }
+    if let Some(pages_scan_log) = &*pages_scan_log {
+        record_pages_scan(pages_metadata, PagesScans(pages_scan_log.clone()).cell());
+    }
     let app_structure = find_app_structure(project_path, dev_server_root, next_config);
+    let routing_report = routing_report(pages_structure, app_structure, next_config);
     let app_source = create_app_source(
         app_structure,
         project_path,
@@ -353,6 +645,12 @@ async fn source(
     }
     .cell()
     .into();
+    let api_routes_source = DevApiRoutesContentSource { pages_structure }.cell().into();
+    let routing_report_source = DevRoutingReportContentSource {
+        report: routing_report,
+    }
+    .cell()
+    .into();
     let main_source = CombinedContentSourceVc::new(vec![
         manifest_source,
         static_source,
@@ -384,6 +682,8 @@ async fn source(
     let source = RouterContentSource {
         routes: vec![
             ("__turbopack__/".to_string(), introspect),
+            ("__nextjs_api_routes".to_string(), api_routes_source),
+            ("__nextjs_routing_report".to_string(), routing_report_source),
             ("__turbo_tasks__/".to_string(), viz),
             (
                 "__nextjs_original-stack-frame".to_string(),
@@ -401,6 +701,25 @@ async fn source(
     Ok(source)
 }
 
+/// Returns the routing report of the project, the same one that
+/// [source] serves.
+#[turbo_tasks::function]
+async fn dev_routing_report(
+    root_dir: String,
+    project_dir: String,
+    browserslist_query: String,
+    server_addr: TransientInstance<SocketAddr>,
+) -> Result<RoutingReportVc> {
+    let pages = dev_pages(root_dir, project_dir, browserslist_query, server_addr).await?;
+    let app_structure =
+        find_app_structure(pages.project_path, pages.dev_server_root, pages.next_config);
+    Ok(routing_report(
+        pages.pages_structure,
+        app_structure,
+        pages.next_config,
+    ))
+}
+
 pub fn register() {
     next_core::register();
     include!(concat!(env!("OUT_DIR"), "/register.rs"));
@@ -446,10 +765,22 @@ pub async fn start_server(options: &DevServerOptions) -> Result<()> {
 
     let tt_clone = tt.clone();
 
+    // Prewarmed routes and the routing report are printed with the other
+    // events below, instead of from the tasks that compute them.
+    let (prewarm_tx, mut prewarm_rx) = tokio::sync::mpsc::unbounded_channel::<Vec<String>>();
+    let (report_tx, mut report_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+
     #[allow(unused_mut)]
     let mut server = NextDevServerBuilder::new(tt, dir, root_dir)
         .entry_request(EntryRequest::Relative("src/index".into()))
         .eager_compile(options.eager_compile)
+        .prewarm_routes(options.prewarm_routes)
+        .on_prewarm(Box::new(move |routes| {
+            let _ = prewarm_tx.send(routes.to_vec());
+        }))
+        .on_routing_report(Box::new(move |report| {
+            let _ = report_tx.send(report.to_string());
+        }))
         .hostname(options.hostname)
         .port(options.port)
         .log_detail(options.log_detail)
@@ -499,6 +830,19 @@ pub async fn start_server(options: &DevServerOptions) -> Result<()> {
 
         let mut progress_counter = 0;
         loop {
+            while let Ok(routes) = prewarm_rx.try_recv() {
+                if !routes.is_empty() {
+                    println!(
+                        "\x1b[2K{event_type} - prewarming {routes}",
+                        event_type = "event".purple(),
+                        routes = routes.join(", ")
+                    );
+                }
+            }
+            while let Ok(report) = report_rx.try_recv() {
+                print!("\x1b[2K{report}");
+            }
+
             let update_future = profile_timeout(
                 tt_clone.as_ref(),
                 tt_clone.aggregated_update_info(Duration::from_millis(100), Duration::MAX),
@@ -0,0 +1,267 @@
+use serde::Deserialize;
+use swc_core::{
+    common::{errors::HANDLER, Span},
+    ecma::{
+        ast::{
+            CallExpr, Callee, Expr, Id, ImportDecl, ImportSpecifier, JSXAttrName, JSXAttrOrSpread,
+            JSXAttrValue, JSXElementName, JSXExpr, JSXOpeningElement, Lit, MemberProp,
+            ModuleExportName, ObjectLit, Pat, Prop, PropName, PropOrSpread, Str, Tpl,
+            VarDeclarator,
+        },
+        transforms::base::pass::Optional,
+        visit::{noop_fold_type, Fold, FoldWith},
+    },
+};
+
+/// The route table of the app, as handed over by next-core.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// Changes whenever the routes change.
+    #[serde(default)]
+    pub fingerprint: String,
+    /// The route patterns, e.g. `/blog/[slug]`.
+    pub patterns: Vec<String>,
+}
+
+/// Warns about `next/link` hrefs and `router.push`/`router.replace` calls
+/// with string literals that don't match any route. Hrefs that can't be
+/// verified (interpolations, external or relative urls) are skipped.
+pub fn link_validation(config: Config, enabled: bool) -> impl Fold {
+    Optional::new(
+        LinkValidation {
+            patterns: config
+                .patterns
+                .iter()
+                .map(|pattern| split_path(pattern).map(PatternSegment::parse).collect())
+                .collect(),
+            link_bindings: vec![],
+            router_bindings: vec![],
+            use_router_bindings: vec![],
+        },
+        enabled,
+    )
+}
+
+enum PatternSegment {
+    Static(String),
+    Dynamic,
+    CatchAll,
+    OptionalCatchAll,
+}
+
+impl PatternSegment {
+    fn parse(segment: &str) -> Self {
+        if segment.starts_with("[[...") {
+            PatternSegment::OptionalCatchAll
+        } else if segment.starts_with("[...") {
+            PatternSegment::CatchAll
+        } else if segment.starts_with('[') {
+            PatternSegment::Dynamic
+        } else {
+            PatternSegment::Static(segment.to_string())
+        }
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn matches_pattern(pattern: &[PatternSegment], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((PatternSegment::OptionalCatchAll, _)) => true,
+        Some((PatternSegment::CatchAll, _)) => !segments.is_empty(),
+        Some((segment, rest)) => match segments.split_first() {
+            Some((first, segments)) => {
+                let matches = match segment {
+                    PatternSegment::Static(name) => name == first,
+                    _ => true,
+                };
+                matches && matches_pattern(rest, segments)
+            }
+            None => false,
+        },
+    }
+}
+
+struct LinkValidation {
+    patterns: Vec<Vec<PatternSegment>>,
+    link_bindings: Vec<Id>,
+    /// The default import of `next/router` and variables holding the result
+    /// of `useRouter()`.
+    router_bindings: Vec<Id>,
+    /// Imports of `useRouter` from `next/router`.
+    use_router_bindings: Vec<Id>,
+}
+
+impl LinkValidation {
+    fn is_known_route(&self, href: &str) -> bool {
+        let path = href.split(|c| c == '?' || c == '#').next().unwrap_or(href);
+        let segments = split_path(path).collect::<Vec<_>>();
+        self.patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &segments))
+    }
+
+    fn check_href(&self, href: &str, span: Span) {
+        // Only absolute paths can be checked against the route table.
+        if !href.starts_with('/') || href.starts_with("//") || self.is_known_route(href) {
+            return;
+        }
+        HANDLER.with(|handler| {
+            handler
+                .struct_span_warn(
+                    span,
+                    &format!("The link `{href}` doesn't match any route of the app."),
+                )
+                .emit()
+        });
+    }
+
+    /// Returns true if `expr` is a router of `next/router`: its default
+    /// import, a variable assigned from `useRouter()` or a call of it.
+    /// Other values named `router` aren't checked.
+    fn is_router(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Ident(ident) => self.router_bindings.contains(&ident.to_id()),
+            Expr::Paren(paren) => self.is_router(&paren.expr),
+            _ => self.is_use_router_call(expr),
+        }
+    }
+
+    fn is_use_router_call(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Call(CallExpr {
+                callee: Callee::Expr(callee),
+                ..
+            }) => matches!(
+                &**callee,
+                Expr::Ident(ident) if self.use_router_bindings.contains(&ident.to_id())
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the href of a string literal, a template literal without
+/// interpolations or a `{ pathname }` object with either of them.
+fn literal_href(expr: &Expr) -> Option<(String, Span)> {
+    match expr {
+        Expr::Lit(Lit::Str(Str { value, span, .. })) => Some((value.to_string(), *span)),
+        Expr::Tpl(Tpl {
+            exprs,
+            quasis,
+            span,
+        }) if exprs.is_empty() => Some((quasis[0].raw.to_string(), *span)),
+        Expr::Object(ObjectLit { props, .. }) => props.iter().find_map(|prop| match prop {
+            PropOrSpread::Prop(prop) => match &**prop {
+                Prop::KeyValue(prop)
+                    if matches!(&prop.key, PropName::Ident(key) if &*key.sym == "pathname") =>
+                {
+                    literal_href(&prop.value)
+                }
+                _ => None,
+            },
+            _ => None,
+        }),
+        Expr::Paren(paren) => literal_href(&paren.expr),
+        _ => None,
+    }
+}
+
+impl Fold for LinkValidation {
+    noop_fold_type!();
+
+    fn fold_import_decl(&mut self, decl: ImportDecl) -> ImportDecl {
+        let is_router = match &*decl.src.value {
+            "next/link" => false,
+            "next/router" => true,
+            _ => return decl,
+        };
+        for specifier in decl.specifiers.iter() {
+            match specifier {
+                ImportSpecifier::Default(default_specifier) => {
+                    let bindings = if is_router {
+                        &mut self.router_bindings
+                    } else {
+                        &mut self.link_bindings
+                    };
+                    bindings.push(default_specifier.local.to_id());
+                }
+                ImportSpecifier::Named(named) if is_router => {
+                    let imported = match &named.imported {
+                        Some(ModuleExportName::Ident(imported)) => &*imported.sym,
+                        Some(ModuleExportName::Str(imported)) => &*imported.value,
+                        None => &*named.local.sym,
+                    };
+                    if imported == "useRouter" {
+                        self.use_router_bindings.push(named.local.to_id());
+                    }
+                }
+                _ => {}
+            }
+        }
+        decl
+    }
+
+    fn fold_var_declarator(&mut self, declarator: VarDeclarator) -> VarDeclarator {
+        if let (Pat::Ident(name), Some(init)) = (&declarator.name, &declarator.init) {
+            if self.is_use_router_call(init) {
+                self.router_bindings.push(name.id.to_id());
+            }
+        }
+        declarator.fold_children_with(self)
+    }
+
+    fn fold_jsx_opening_element(&mut self, element: JSXOpeningElement) -> JSXOpeningElement {
+        if let JSXElementName::Ident(name) = &element.name {
+            if self.link_bindings.contains(&name.to_id()) {
+                for attr in element.attrs.iter() {
+                    let attr = match attr {
+                        JSXAttrOrSpread::JSXAttr(attr) => attr,
+                        _ => continue,
+                    };
+                    if !matches!(&attr.name, JSXAttrName::Ident(attr_name) if &*attr_name.sym == "href")
+                    {
+                        continue;
+                    }
+                    let href = match &attr.value {
+                        Some(JSXAttrValue::Lit(Lit::Str(href))) => {
+                            Some((href.value.to_string(), href.span))
+                        }
+                        Some(JSXAttrValue::JSXExprContainer(container)) => match &container.expr {
+                            JSXExpr::Expr(href) => literal_href(href),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let Some((href, span)) = href {
+                        self.check_href(&href, span);
+                    }
+                }
+            }
+        }
+        element.fold_children_with(self)
+    }
+
+    fn fold_call_expr(&mut self, expr: CallExpr) -> CallExpr {
+        if let Callee::Expr(callee) = &expr.callee {
+            if let Expr::Member(member) = &**callee {
+                let is_navigation = matches!(
+                    &member.prop,
+                    MemberProp::Ident(prop) if &*prop.sym == "push" || &*prop.sym == "replace"
+                );
+                if is_navigation && self.is_router(&member.obj) {
+                    if let Some((href, span)) =
+                        expr.args.first().and_then(|arg| literal_href(&arg.expr))
+                    {
+                        self.check_href(&href, span);
+                    }
+                }
+            }
+        }
+        expr.fold_children_with(self)
+    }
+}